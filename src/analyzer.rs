@@ -0,0 +1,272 @@
+//! `--resolver rust-analyzer` — name resolution by a `rust-analyzer` process
+//! spoken to over LSP (stdio, JSON-RPC), instead of syn's textual matching.
+//! * Re-exports, glob imports, renames and macro-generated paths resolve the
+//!   way the compiler sees them.
+//! * The binary is `$RUST_ANALYZER`, else `rust-analyzer` on `PATH`
+//!   (`rustup component add rust-analyzer`).
+//! * Requests wait for the initial indexing (the `serverStatus` extension's
+//!   `quiescent` flag), which takes a while on large workspaces.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use proc_macro2::LineColumn;
+use serde_json::{json, Value};
+
+/// Longest wait for indexing, or for any single response.
+const TIMEOUT: Duration = Duration::from_secs(300);
+
+pub struct Analyzer {
+    root: PathBuf,
+    /// `root` as rust-analyzer reports it
+    canonical_root: PathBuf,
+    child: Child,
+    stdin: ChildStdin,
+    messages: Receiver<Value>,
+    next_id: u64,
+}
+
+/// A source position rust-analyzer reported; paths under the root are
+/// relative to it as given to `start`.
+pub struct Location {
+    pub path: PathBuf,
+    /// 1-based.
+    pub line: usize,
+    /// UTF-16 units, as LSP counts them.
+    character: u64,
+}
+
+impl Analyzer {
+    /// Starts rust-analyzer on `root` and waits until it has indexed it.
+    pub fn start(root: &Path) -> Result<Self> {
+        let bin = std::env::var_os("RUST_ANALYZER").unwrap_or_else(|| "rust-analyzer".into());
+        let mut child = Command::new(&bin)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("failed to run rust-analyzer (rustup component add rust-analyzer)")?;
+        let stdin = child.stdin.take().context("rust-analyzer has no stdin")?;
+        let stdout = child.stdout.take().context("rust-analyzer has no stdout")?;
+        let (tx, messages) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Ok(Some(msg)) = read_message(&mut reader) {
+                if tx.send(msg).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut ra = Analyzer {
+            root: root.to_path_buf(),
+            canonical_root: root.canonicalize()?,
+            child,
+            stdin,
+            messages,
+            next_id: 0,
+        };
+
+        let uri = file_uri(root);
+        ra.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": uri,
+                "workspaceFolders": [{ "uri": uri, "name": "root" }],
+                "capabilities": {
+                    "experimental": { "serverStatusNotification": true },
+                    "textDocument": { "definition": { "linkSupport": false } },
+                },
+            }),
+        )?;
+        ra.notify("initialized", json!({}))?;
+        ra.wait_quiescent()?;
+        Ok(ra)
+    }
+
+    /// Definition sites of whatever is at `at` in `path`.
+    pub fn definition(&mut self, path: &Path, src: &str, at: LineColumn) -> Result<Vec<Location>> {
+        let line = src.lines().nth(at.line - 1).unwrap_or("");
+        // LSP columns count UTF-16 units, proc-macro2's count chars
+        let character: usize = line.chars().take(at.column).map(char::len_utf16).sum();
+        let result = self.request(
+            "textDocument/definition",
+            json!({
+                "textDocument": { "uri": file_uri(path) },
+                "position": { "line": at.line - 1, "character": character },
+            }),
+        )?;
+        Ok(self.locations(result))
+    }
+
+    /// Workspace items named exactly `name`.
+    pub fn symbols(&mut self, name: &str) -> Result<Vec<Location>> {
+        let result = self.request("workspace/symbol", json!({ "query": name }))?;
+        let exact = result
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|s| s["name"] == name)
+            .map(|s| s["location"].clone())
+            .collect();
+        Ok(self.locations(Value::Array(exact)))
+    }
+
+    /// Every use of the item at `at`, its declaration included.
+    pub fn references(&mut self, at: &Location) -> Result<Vec<Location>> {
+        let result = self.request(
+            "textDocument/references",
+            json!({
+                "textDocument": { "uri": file_uri(&at.path) },
+                "position": { "line": at.line - 1, "character": at.character },
+                "context": { "includeDeclaration": true },
+            }),
+        )?;
+        Ok(self.locations(result))
+    }
+
+    /// `Location`, `Location[]` or `LocationLink[]` results.
+    fn locations(&self, result: Value) -> Vec<Location> {
+        let list = match result {
+            Value::Array(list) => list,
+            Value::Null => Vec::new(),
+            single => vec![single],
+        };
+        list.iter()
+            .filter_map(|l| {
+                let uri = l.get("uri").or_else(|| l.get("targetUri"))?.as_str()?;
+                let range = l.get("range").or_else(|| l.get("targetSelectionRange"))?;
+                let path = uri_path(uri)?;
+                let path = match path.strip_prefix(&self.canonical_root) {
+                    Ok(rel) => self.root.join(rel),
+                    Err(_) => path,
+                };
+                Some(Location {
+                    path,
+                    line: range["start"]["line"].as_u64()? as usize + 1,
+                    character: range["start"]["character"].as_u64()?,
+                })
+            })
+            .collect()
+    }
+
+    fn send(&mut self, msg: Value) -> Result<()> {
+        let body = msg.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Result<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))?;
+        loop {
+            let msg = self.recv()?;
+            if msg.get("method").is_some() {
+                continue;
+            }
+            if msg["id"].as_u64() != Some(id) {
+                continue;
+            }
+            if let Some(err) = msg.get("error") {
+                anyhow::bail!("rust-analyzer {method} failed: {}", err["message"]);
+            }
+            return Ok(msg.get("result").cloned().unwrap_or(Value::Null));
+        }
+    }
+
+    fn wait_quiescent(&mut self) -> Result<()> {
+        loop {
+            let msg = self.recv()?;
+            if msg["method"] == "experimental/serverStatus" && msg["params"]["quiescent"] == true {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Next message from the server; requests it sends us get a null reply.
+    fn recv(&mut self) -> Result<Value> {
+        let msg = self
+            .messages
+            .recv_timeout(TIMEOUT)
+            .context("rust-analyzer stopped responding")?;
+        if let (Some(id), Some(_)) = (msg.get("id"), msg.get("method")) {
+            let reply = json!({ "jsonrpc": "2.0", "id": id, "result": null });
+            self.send(reply)?;
+        }
+        Ok(msg)
+    }
+}
+
+impl Drop for Analyzer {
+    fn drop(&mut self) {
+        if self.request("shutdown", Value::Null).is_ok() {
+            let _ = self.notify("exit", Value::Null);
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// One `Content-Length`-framed message, or `None` at end of stream.
+fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(n) = header.strip_prefix("Content-Length:") {
+            len = Some(n.trim().parse::<usize>()?);
+        }
+    }
+    let mut body = vec![0; len.context("LSP message without Content-Length")?];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    let raw = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        if raw[i] == b'%' && i + 2 < raw.len() {
+            let hex = std::str::from_utf8(&raw[i + 1..i + 3]).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            bytes.push(raw[i]);
+            i += 1;
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
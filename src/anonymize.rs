@@ -0,0 +1,392 @@
+//! `--anonymize` — neutral names for what identifies the codebase: workspace
+//! crate names become `crate_a`, `crate_b`, …, module names (from the
+//! selected files' paths) `mod_a`, … and the `[anonymize] identifiers` of
+//! `.cargo-qp.toml` `ServiceA`, `ServiceB`, …
+//! * Applied to every emitted path, crate label, body and section.
+//! * Crate names are replaced as whole words and configured identifiers
+//!   also inside longer names (`AcmeClient` → `ServiceAClient`). Module
+//!   names only where they name a module: `mod name`, `use` lists, the first
+//!   segment of `name::…`, a segment after `crate::`, `self::`, `super::` or
+//!   another replaced name, and file paths (`src/name.rs`); `std::io`, a
+//!   local `config` or `"error"` are left alone.
+//! * The mapping is kept in `qp-anonymize.json` (or `[anonymize] mapping`)
+//!   so tokens stay stable across snapshots. Anonymized output carries a
+//!   `# qp-anonymized` section, and `cargo qp apply` reverses the mapping
+//!   (tokens as whole words, paths included) only in answers that kept it.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, Ctx};
+
+/// Where the mapping lives without `[anonymize] mapping`.
+pub const DEFAULT_FILE: &str = "qp-anonymize.json";
+
+/// Title of the section that marks anonymized output.
+pub const MARKER: &str = "qp-anonymized";
+
+/// Body of the `MARKER` section.
+pub const MARKER_NOTE: &str = "Crate, module and some type names are neutral tokens \
+(`crate_a`, `mod_a`, `ServiceA`). Keep them, and this section, in the answer: \
+`cargo qp apply` turns them back only then.\n";
+
+/// Path segments a module can follow.
+const PATH_ROOTS: &[&str] = &["crate", "self", "super"];
+
+/// File stems that name no module of their own.
+const NOT_MODULES: &[&str] = &["lib", "main", "mod", "build"];
+
+/// The persisted mapping, original → token, per kind.
+#[derive(Default, Serialize, Deserialize)]
+struct Mapping {
+    #[serde(default)]
+    crates: BTreeMap<String, String>,
+    #[serde(default)]
+    modules: BTreeMap<String, String>,
+    #[serde(default)]
+    identifiers: BTreeMap<String, String>,
+}
+
+/// Replacement tables for both directions.
+pub struct Anonymizer {
+    forward: Table,
+    backward: Table,
+}
+
+/// `(from, to, how)` entries, longest `from` first, indexed by its first
+/// byte.
+struct Table {
+    by_first: HashMap<u8, Vec<(String, String, Match)>>,
+}
+
+/// Where an entry's `from` counts.
+#[derive(Clone, Copy, PartialEq)]
+enum Match {
+    Word,
+    /// A word in a module position (see `Table::is_module`).
+    Module,
+    Anywhere,
+}
+
+impl Anonymizer {
+    /// Extends the mapping with the selection's crates and modules and the
+    /// configured identifiers, saving it when it grew.
+    pub fn build(ctx: &Ctx, config: &Config) -> Result<Self> {
+        let path = mapping_path(&ctx.root, config);
+        let mut mapping = load(&path)?;
+        let before = serde_json::to_string(&mapping)?;
+
+        let mut crates: Vec<&str> = ctx
+            .crates
+            .iter()
+            .filter(|(dir, _)| dir.starts_with(&ctx.root))
+            .map(|(_, (name, _))| name.as_str())
+            .collect();
+        crates.sort();
+        for name in crates {
+            if mapping.crates.contains_key(&name.replace('-', "_")) {
+                continue;
+            }
+            let used: HashSet<String> = mapping
+                .crates
+                .values()
+                .map(|t| t.replace('-', "_"))
+                .collect();
+            let token = format!("crate_{}", letters(used.len()));
+            add_crate(&mut mapping.crates, name, &token);
+        }
+        for path in ctx.selected()? {
+            for module in modules(ctx, &path) {
+                let n = mapping.modules.len();
+                let taken = mapping.crates.contains_key(&module);
+                if !taken {
+                    mapping
+                        .modules
+                        .entry(module)
+                        .or_insert_with(|| format!("mod_{}", letters(n)));
+                }
+            }
+        }
+        for ident in config.anonymize.iter().flat_map(|a| &a.identifiers) {
+            let n = mapping.identifiers.len();
+            mapping
+                .identifiers
+                .entry(ident.clone())
+                .or_insert_with(|| format!("Service{}", letters(n).to_uppercase()));
+        }
+
+        if serde_json::to_string(&mapping)? != before {
+            let json = serde_json::to_string_pretty(&mapping)?;
+            std::fs::write(&path, json + "\n")
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+        Ok(Self::new(&mapping))
+    }
+
+    /// The saved mapping, for reversing an answer; `None` without one.
+    pub fn saved(root: &Path, config: &Config) -> Result<Option<Self>> {
+        let path = mapping_path(root, config);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(Self::new(&load(&path)?)))
+    }
+
+    fn new(mapping: &Mapping) -> Self {
+        let entries: Vec<(&String, &String, Match)> = (mapping.crates.iter())
+            .map(|(from, to)| (from, to, Match::Word))
+            .chain(
+                mapping
+                    .modules
+                    .iter()
+                    .map(|(from, to)| (from, to, Match::Module)),
+            )
+            .chain(
+                mapping
+                    .identifiers
+                    .iter()
+                    .map(|(from, to)| (from, to, Match::Anywhere)),
+            )
+            .collect();
+        Anonymizer {
+            forward: Table::new(entries.iter().copied()),
+            backward: Table::new(entries.iter().map(|&(from, to, how)| {
+                let how = if how == Match::Module {
+                    Match::Word
+                } else {
+                    how
+                };
+                (to, from, how)
+            })),
+        }
+    }
+
+    /// `text` with original names replaced by tokens.
+    pub fn apply(&self, text: &str) -> String {
+        self.forward.replace(text)
+    }
+
+    /// `text` with tokens replaced by the original names, when it carries
+    /// the `MARKER` section; unchanged otherwise.
+    pub fn reverse(&self, text: &str) -> String {
+        if !text.contains(MARKER) {
+            return text.to_string();
+        }
+        self.backward.replace(text)
+    }
+}
+
+impl Table {
+    fn new<'a>(entries: impl Iterator<Item = (&'a String, &'a String, Match)>) -> Self {
+        let mut by_first: HashMap<u8, Vec<(String, String, Match)>> = HashMap::new();
+        for (from, to, how) in entries {
+            if let Some(&first) = from.as_bytes().first() {
+                by_first
+                    .entry(first)
+                    .or_default()
+                    .push((from.clone(), to.clone(), how));
+            }
+        }
+        for entries in by_first.values_mut() {
+            entries.sort_by_key(|(from, ..)| std::cmp::Reverse(from.len()));
+        }
+        Table { by_first }
+    }
+
+    fn replace(&self, text: &str) -> String {
+        let b = text.as_bytes();
+        let mut out = String::with_capacity(text.len());
+        let (mut i, mut last) = (0, 0);
+        while i < b.len() {
+            let before = i.checked_sub(1).map(|j| b[j]);
+            let hit = self.by_first.get(&b[i]).and_then(|entries| {
+                entries.iter().find(|(from, _, how)| {
+                    let after = b.get(i + from.len()).copied();
+                    let word =
+                        before.is_none_or(|c| !is_word(c)) && after.is_none_or(|c| !is_word(c));
+                    b[i..].starts_with(from.as_bytes())
+                        && match how {
+                            Match::Anywhere => true,
+                            Match::Word => word,
+                            Match::Module => word && self.is_module(b, i, i + from.len()),
+                        }
+                })
+            });
+            match hit {
+                Some((from, to, _)) => {
+                    out.push_str(&text[last..i]);
+                    out.push_str(to);
+                    i += from.len();
+                    last = i;
+                }
+                None => i += 1,
+            }
+        }
+        out.push_str(&text[last..]);
+        out
+    }
+
+    /// Whether the word at `b[start..end]` names a module: declared by
+    /// `mod`, listed by `use`, leading or continuing a path, or a path
+    /// component.
+    fn is_module(&self, b: &[u8], start: usize, end: usize) -> bool {
+        let (before, after) = (&b[..start], &b[end..]);
+        if let Some(rest) = before.strip_suffix(b"::") {
+            let prev = &rest[rest.len() - word_back(rest)..];
+            return PATH_ROOTS.iter().any(|r| r.as_bytes() == prev) || self.is_name(prev);
+        }
+        if after.starts_with(b"::") {
+            return true;
+        }
+        if before.ends_with(b"/") && (after.starts_with(b"/") || after.starts_with(b".rs")) {
+            return true;
+        }
+        let trimmed = before.trim_ascii_end();
+        let keyword = &trimmed[trimmed.len() - word_back(trimmed)..];
+        let declared = trimmed.len() < before.len() && (keyword == b"mod" || keyword == b"use");
+        declared || in_use_list(before)
+    }
+
+    /// Whether `word` is one of the table's crate or module names.
+    fn is_name(&self, word: &[u8]) -> bool {
+        word.first()
+            .and_then(|c| self.by_first.get(c))
+            .is_some_and(|entries| {
+                entries
+                    .iter()
+                    .any(|(from, _, how)| *how != Match::Anywhere && from.as_bytes() == word)
+            })
+    }
+}
+
+/// Length of the word `b` ends with.
+fn word_back(b: &[u8]) -> usize {
+    b.iter().rev().take_while(|&&c| is_word(c)).count()
+}
+
+/// Whether `before` ends inside the braces of a `use` item, right after
+/// `{` or `,`.
+fn in_use_list(before: &[u8]) -> bool {
+    if !matches!(before.trim_ascii_end().last(), Some(b'{' | b',')) {
+        return false;
+    }
+    let item = |c: &u8| is_word(*c) || b":{},* \t\r\n".contains(c);
+    let run = before.iter().rev().take_while(|c| item(c)).count();
+    let words = before[before.len() - run..]
+        .split(|c| !is_word(*c))
+        .filter(|w| !w.is_empty());
+    words.into_iter().any(|w| w == b"use")
+}
+
+fn is_word(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// `a`, `b`, …, `z`, `aa`, `ab`, …
+fn letters(mut n: usize) -> String {
+    let mut out = Vec::new();
+    loop {
+        out.push(b'a' + (n % 26) as u8);
+        if n < 26 {
+            break;
+        }
+        n = n / 26 - 1;
+    }
+    out.reverse();
+    String::from_utf8(out).unwrap_or_default()
+}
+
+/// `my-crate` gets `crate-x` as well as `my_crate` → `crate_x`.
+fn add_crate(crates: &mut BTreeMap<String, String>, name: &str, token: &str) {
+    crates.insert(name.replace('-', "_"), token.to_string());
+    if name.contains('-') {
+        crates.insert(name.to_string(), token.replace('_', "-"));
+    }
+}
+
+/// Module names along `path` below its crate's `src/`.
+fn modules(ctx: &Ctx, path: &Path) -> Vec<String> {
+    if path.extension().is_none_or(|x| x != "rs") {
+        return Vec::new();
+    }
+    let Some(rel) = ctx
+        .crate_dir(path)
+        .and_then(|dir| path.strip_prefix(dir.join("src")).ok())
+    else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = rel
+        .parent()
+        .into_iter()
+        .flat_map(Path::components)
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if let Some(stem) = rel.file_stem() {
+        names.push(stem.to_string_lossy().into_owned());
+    }
+    names.retain(|n| !NOT_MODULES.contains(&n.as_str()) && n != "bin");
+    names
+}
+
+fn mapping_path(root: &Path, config: &Config) -> PathBuf {
+    let file = config
+        .anonymize
+        .as_ref()
+        .and_then(|a| a.mapping.clone())
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_FILE));
+    root.join(file)
+}
+
+fn load(path: &Path) -> Result<Mapping> {
+    if !path.exists() {
+        return Ok(Mapping::default());
+    }
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("invalid {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anonymizer() -> Anonymizer {
+        let mut mapping = Mapping::default();
+        add_crate(&mut mapping.crates, "acme-core", "crate_a");
+        for (module, token) in [("io", "mod_a"), ("config", "mod_b")] {
+            mapping.modules.insert(module.into(), token.into());
+        }
+        Anonymizer::new(&mapping)
+    }
+
+    #[test]
+    fn modules_only_in_module_positions() {
+        let text = "mod io;\nuse crate::{config, io::Sink};\nuse std::io;\n\
+            let config = io::read(\"config\"); // the config\n";
+        assert_eq!(
+            anonymizer().apply(text),
+            "mod mod_a;\nuse crate::{mod_b, mod_a::Sink};\nuse std::io;\n\
+            let config = mod_a::read(\"config\"); // the config\n"
+        );
+        assert_eq!(
+            anonymizer().apply("acme-core/src/config.rs acme_core::config::X"),
+            "crate-a/src/mod_b.rs crate_a::mod_b::X"
+        );
+    }
+
+    #[test]
+    fn reverse_needs_the_marker() {
+        let answer = "use crate_a::mod_b;\n";
+        assert_eq!(anonymizer().reverse(answer), answer);
+        let marked = format!("=== # {MARKER} ===\n{answer}");
+        assert_eq!(
+            anonymizer().reverse(&marked),
+            format!("=== # {MARKER} ===\nuse acme_core::config;\n")
+        );
+    }
+}
@@ -0,0 +1,381 @@
+//! `cargo qp api-diff [BASE] [HEAD]` — what changed in each library crate's
+//! public API between two revisions, as one section per crate listing added,
+//! removed and changed items; `--api-diff[=BASE]` appends the same sections
+//! to a snapshot (against `--rev`, or the working tree).
+//! * BASE defaults to the latest tag, HEAD to the working tree.
+//! * The API is what `src/lib.rs` exposes through `pub` modules: `pub`
+//!   items, fields, variants, trait items and inherent methods, `pub use`
+//!   re-exports, exported macros and trait impls, keyed by path (`a::b::C`,
+//!   `C.field`, `C::method`). Signatures are compared with whitespace
+//!   normalized and bodies ignored; `#[doc(hidden)]` items are left out.
+//! * A syn walk like `syntax::api`, so it needs neither rustdoc JSON nor
+//!   `cargo public-api`; `pub` items of private modules are not reported
+//!   even when re-exported under another name.
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use proc_macro2::LineColumn;
+use syn::spanned::Spanned;
+
+use crate::{git, syntax::LineIndex, Ctx};
+
+/// One API entry: its kind and normalized declaration.
+#[derive(PartialEq)]
+struct Entry {
+    kind: &'static str,
+    decl: String,
+}
+
+type Api = BTreeMap<String, Entry>;
+
+pub fn compose(
+    ctx: &mut Ctx,
+    out: &mut dyn Write,
+    base: Option<&str>,
+    head: Option<&str>,
+) -> Result<()> {
+    let base = match base {
+        Some(base) => base.to_string(),
+        None => latest_tag(&ctx.root, ctx.history())?,
+    };
+    let range = format!("{base}..{}", head.unwrap_or("working tree"));
+
+    let mut crates: Vec<(String, PathBuf)> = ctx
+        .crates
+        .iter()
+        .filter(|(dir, _)| dir.starts_with(&ctx.root) && ctx.selected_member(dir))
+        .map(|(dir, (name, _))| (name.clone(), dir.clone()))
+        .collect();
+    crates.sort();
+    let mut changed = false;
+    for (name, dir) in crates {
+        let before = api(ctx, &dir, &name, Some(&base));
+        let after = api(ctx, &dir, &name, head);
+        let report = report(&before, &after);
+        if !report.is_empty() {
+            changed = true;
+            ctx.push_section(out, &format!("api diff :: {name} {range}"), &report)?;
+        }
+    }
+    if !changed {
+        ctx.push_section(
+            out,
+            &format!("api diff :: {range}"),
+            "no public API changes\n",
+        )?;
+    }
+    Ok(())
+}
+
+/// The most recent tag reachable from `head` (HEAD for the working tree).
+fn latest_tag(root: &Path, head: Option<&str>) -> Result<String> {
+    let tag = git::run(
+        root,
+        &["describe", "--tags", "--abbrev=0", head.unwrap_or("HEAD")],
+    )
+    .context("no tag to compare against; pass a base revision")?;
+    Ok(tag.trim().to_string())
+}
+
+/// `added`, `removed` and `changed` blocks; empty when the APIs match.
+fn report(before: &Api, after: &Api) -> String {
+    let added: Vec<_> = after
+        .iter()
+        .filter(|(k, _)| !before.contains_key(*k))
+        .collect();
+    let removed: Vec<_> = before
+        .iter()
+        .filter(|(k, _)| !after.contains_key(*k))
+        .collect();
+    let changed: Vec<_> = before
+        .iter()
+        .filter_map(|(k, old)| {
+            after
+                .get(k)
+                .filter(|new| *new != old)
+                .map(|new| (k, old, new))
+        })
+        .collect();
+
+    let mut out = String::new();
+    for (title, entries) in [("added", &added), ("removed", &removed)] {
+        if entries.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("{title} ({})\n", entries.len()));
+        for (path, entry) in entries {
+            out.push_str(&format!("  {} {path}: {}\n", entry.kind, entry.decl));
+        }
+    }
+    if !changed.is_empty() {
+        out.push_str(&format!("changed ({})\n", changed.len()));
+        for (path, old, new) in changed {
+            out.push_str(&format!(
+                "  {} {path}\n    before: {}\n    after:  {}\n",
+                new.kind, old.decl, new.decl
+            ));
+        }
+    }
+    out
+}
+
+/// The public API of the library crate in `dir` at `rev` (the working tree
+/// for `None`); empty without `src/lib.rs`.
+fn api(ctx: &Ctx, dir: &Path, name: &str, rev: Option<&str>) -> Api {
+    let mut walk = Walk {
+        ctx,
+        rev,
+        api: Api::new(),
+    };
+    let lib = dir.join("src/lib.rs");
+    walk.file(&lib, &name.replace('-', "_"), true);
+    walk.api
+}
+
+struct Walk<'a> {
+    ctx: &'a Ctx,
+    rev: Option<&'a str>,
+    api: Api,
+}
+
+impl Walk<'_> {
+    fn read(&self, path: &Path) -> Option<String> {
+        match self.rev {
+            Some(rev) => {
+                let rel = self.ctx.rel(path).to_string_lossy().replace('\\', "/");
+                git::show(&self.ctx.root, rev, &rel).ok()
+            }
+            None => std::fs::read_to_string(path).ok(),
+        }
+    }
+
+    /// The items of the module file `path`, whose path is `module`;
+    /// `mod_rs` files (the crate root, `mod.rs`) keep submodules beside them.
+    fn file(&mut self, path: &Path, module: &str, mod_rs: bool) {
+        let Some(src) = self.read(path) else {
+            return;
+        };
+        let Ok(file) = syn::parse_file(&src) else {
+            return;
+        };
+        let (Some(parent), Some(stem)) = (path.parent(), path.file_stem()) else {
+            return;
+        };
+        let dir = if mod_rs {
+            parent.to_path_buf()
+        } else {
+            parent.join(stem)
+        };
+        let lines = LineIndex::new(&src);
+        self.items(&file.items, &lines, module, parent, &dir);
+    }
+
+    /// `parent` holds the current file, `dir` its submodules.
+    fn items(
+        &mut self,
+        items: &[syn::Item],
+        lines: &LineIndex,
+        module: &str,
+        parent: &Path,
+        dir: &Path,
+    ) {
+        let text = |from: LineColumn, to: LineColumn| normalized(lines.slice(from, to));
+        let whole = |node: &dyn Spanned| text(node.span().start(), node.span().end());
+        for item in items {
+            match item {
+                syn::Item::Mod(m) if public(&m.vis) && !hidden(&m.attrs) => {
+                    let path = format!("{module}::{}", m.ident);
+                    self.add(&path, "mod", format!("pub mod {}", m.ident));
+                    match &m.content {
+                        Some((_, inner)) => {
+                            let dir = dir.join(m.ident.to_string());
+                            self.items(inner, lines, &path, parent, &dir);
+                        }
+                        None => {
+                            let (file, mod_rs) = match path_attr(&m.attrs) {
+                                Some(to) => (parent.join(to), true),
+                                None => {
+                                    let flat = dir.join(format!("{}.rs", m.ident));
+                                    if self.read(&flat).is_some() {
+                                        (flat, false)
+                                    } else {
+                                        (dir.join(m.ident.to_string()).join("mod.rs"), true)
+                                    }
+                                }
+                            };
+                            self.file(&file, &path, mod_rs);
+                        }
+                    }
+                }
+                syn::Item::Fn(f) if public(&f.vis) && !hidden(&f.attrs) => {
+                    let decl = text(f.vis.span().start(), f.block.span().start());
+                    self.add(&format!("{module}::{}", f.sig.ident), "fn", decl);
+                }
+                syn::Item::Struct(s) if public(&s.vis) && !hidden(&s.attrs) => {
+                    let path = format!("{module}::{}", s.ident);
+                    let decl = match &s.fields {
+                        syn::Fields::Unit => whole(s),
+                        fields => text(s.vis.span().start(), fields.span().start()),
+                    };
+                    self.add(&path, "struct", decl);
+                    self.fields(&path, &s.fields, &whole);
+                }
+                syn::Item::Union(u) if public(&u.vis) && !hidden(&u.attrs) => {
+                    let path = format!("{module}::{}", u.ident);
+                    let decl = text(u.vis.span().start(), u.fields.span().start());
+                    self.add(&path, "union", decl);
+                    let fields = syn::Fields::Named(u.fields.clone());
+                    self.fields(&path, &fields, &whole);
+                }
+                syn::Item::Enum(e) if public(&e.vis) && !hidden(&e.attrs) => {
+                    let path = format!("{module}::{}", e.ident);
+                    let decl = text(e.vis.span().start(), e.brace_token.span.open().start());
+                    self.add(&path, "enum", decl);
+                    for v in e.variants.iter().filter(|v| !hidden(&v.attrs)) {
+                        let decl = text(v.ident.span().start(), v.span().end());
+                        self.add(&format!("{path}::{}", v.ident), "variant", decl);
+                    }
+                }
+                syn::Item::Trait(t) if public(&t.vis) && !hidden(&t.attrs) => {
+                    let path = format!("{module}::{}", t.ident);
+                    let decl = text(t.vis.span().start(), t.brace_token.span.open().start());
+                    self.add(&path, "trait", decl);
+                    for member in &t.items {
+                        let (ident, kind, decl) = match member {
+                            syn::TraitItem::Fn(f) if !hidden(&f.attrs) => {
+                                let end = match &f.default {
+                                    Some(block) => block.span().start(),
+                                    None => f.span().end(),
+                                };
+                                (&f.sig.ident, "trait fn", text(f.sig.span().start(), end))
+                            }
+                            syn::TraitItem::Type(ty) if !hidden(&ty.attrs) => {
+                                (&ty.ident, "trait type", whole(ty))
+                            }
+                            syn::TraitItem::Const(c) if !hidden(&c.attrs) => {
+                                let end = c.ty.span().end();
+                                (
+                                    &c.ident,
+                                    "trait const",
+                                    text(c.const_token.span.start(), end),
+                                )
+                            }
+                            _ => continue,
+                        };
+                        self.add(&format!("{path}::{ident}"), kind, decl);
+                    }
+                }
+                syn::Item::Impl(i) if !hidden(&i.attrs) => {
+                    let header = text(i.impl_token.span.start(), i.brace_token.span.open().start());
+                    if i.trait_.is_some() {
+                        self.add(&format!("{module}::{{{header}}}"), "impl", header);
+                        continue;
+                    }
+                    let self_ty = text(i.self_ty.span().start(), i.self_ty.span().end());
+                    for member in &i.items {
+                        let syn::ImplItem::Fn(f) = member else {
+                            continue;
+                        };
+                        if public(&f.vis) && !hidden(&f.attrs) {
+                            let decl = text(f.vis.span().start(), f.block.span().start());
+                            let path = format!("{module}::{self_ty}::{}", f.sig.ident);
+                            self.add(&path, "method", decl);
+                        }
+                    }
+                }
+                syn::Item::Type(syn::ItemType {
+                    vis, attrs, ident, ..
+                })
+                | syn::Item::Const(syn::ItemConst {
+                    vis, attrs, ident, ..
+                })
+                | syn::Item::Static(syn::ItemStatic {
+                    vis, attrs, ident, ..
+                }) if public(vis) && !hidden(attrs) => {
+                    let kind = match item {
+                        syn::Item::Type(_) => "type",
+                        syn::Item::Const(_) => "const",
+                        _ => "static",
+                    };
+                    let decl = text(vis.span().start(), item.span().end());
+                    self.add(&format!("{module}::{ident}"), kind, decl);
+                }
+                syn::Item::Use(u) if public(&u.vis) && !hidden(&u.attrs) => {
+                    let decl = whole(u);
+                    let tree = text(u.tree.span().start(), u.tree.span().end());
+                    self.add(&format!("{module}::{{{tree}}}"), "use", decl);
+                }
+                syn::Item::Macro(m)
+                    if m.attrs.iter().any(|a| a.path().is_ident("macro_export")) =>
+                {
+                    if let Some(ident) = &m.ident {
+                        let root = module.split("::").next().unwrap_or(module);
+                        self.add(
+                            &format!("{root}::{ident}"),
+                            "macro",
+                            format!("macro_rules! {ident}"),
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The `pub` fields of `path`, as `path.name` (`path.0` when unnamed).
+    fn fields(&mut self, path: &str, fields: &syn::Fields, whole: &dyn Fn(&dyn Spanned) -> String) {
+        for (i, field) in fields.iter().enumerate() {
+            if !public(&field.vis) || hidden(&field.attrs) {
+                continue;
+            }
+            let name = field
+                .ident
+                .as_ref()
+                .map_or(i.to_string(), |id| id.to_string());
+            self.add(&format!("{path}.{name}"), "field", whole(field));
+        }
+    }
+
+    fn add(&mut self, path: &str, kind: &'static str, decl: String) {
+        self.api.insert(path.to_string(), Entry { kind, decl });
+    }
+}
+
+fn public(vis: &syn::Visibility) -> bool {
+    matches!(vis, syn::Visibility::Public(_))
+}
+
+/// `#[doc(hidden)]`
+fn hidden(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|a| {
+        a.path().is_ident("doc") && a.parse_args::<syn::Ident>().is_ok_and(|id| id == "hidden")
+    })
+}
+
+/// `#[path = "…"]`
+fn path_attr(attrs: &[syn::Attribute]) -> Option<String> {
+    attrs.iter().find_map(|a| {
+        let syn::Meta::NameValue(nv) = &a.meta else {
+            return None;
+        };
+        let syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(s),
+            ..
+        }) = &nv.value
+        else {
+            return None;
+        };
+        nv.path.is_ident("path").then(|| s.value())
+    })
+}
+
+/// `text` on one line, runs of whitespace collapsed and no trailing `;`/`,`.
+fn normalized(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    text.trim_end_matches([';', ',']).trim_end().to_string()
+}
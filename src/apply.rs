@@ -0,0 +1,614 @@
+//! `cargo qp apply [INPUT]` — write files from a model's answer back into the
+//! repository.
+//! * Input comes from the clipboard, a file, or stdin (`-`).
+//! * Understands cargo-qp's own `=== crate :: path ===` sections (a
+//!   `DELETED` label removes the file; `=== # title ===` sections such as
+//!   `--log` are not files) and fenced code blocks preceded by (or labelled
+//!   with) a path.
+//! * Tokens of an `--anonymize` mapping are turned back into the original
+//!   names first, in answers that kept the `# qp-anonymized` section.
+//! * Unified diffs in the answer take precedence and are applied hunk by hunk
+//!   (see `patch`); hunks that cannot be located are reported per file.
+//!   Diffs under cargo-qp's own tagged or `#` headers (`--since-manifest`,
+//!   `cargo qp diff`) describe the snapshot, not the answer, and are left
+//!   alone, so pasting a snapshot back writes nothing.
+//! * Whole-file bodies for files edited since the snapshot (their content no
+//!   longer matches the blob in the manifest) are merged three ways — the
+//!   snapshot, the local file and the answer — leaving conflict markers where
+//!   both changed the same lines; such files are never deleted.
+//! * `--interactive` asks about each hunk before writing (see `review`).
+//! * `--dry-run` prints a unified diff per file instead of writing; otherwise
+//!   the previous content is copied to `target/qp-backup/<unix-time>/` first.
+//! * Paths outside the repository, inside `.git` (in any case) or matching
+//!   the sensitive globs are refused, whatever the answer says.
+
+use std::{
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use arboard::Clipboard;
+
+use crate::{
+    anonymize::Anonymizer,
+    git,
+    manifest::Manifest,
+    patch::{self, FilePatch},
+    review::{Reviewer, Verdict},
+    textdiff, Ctx,
+};
+
+/// One file body found in the pasted text; `None` deletes the file.
+pub struct Incoming {
+    pub rel: PathBuf,
+    pub body: Option<String>,
+}
+
+pub fn run(
+    ctx: &Ctx,
+    anonymizer: Option<&Anonymizer>,
+    input: Option<&Path>,
+    dry_run: bool,
+    interactive: bool,
+    manifest: &Path,
+) -> Result<()> {
+    let text = read_input(input)?;
+    let mut out = std::io::stdout().lock();
+    apply_text(
+        ctx,
+        anonymizer,
+        text,
+        dry_run,
+        interactive,
+        manifest,
+        &mut out,
+    )?;
+    Ok(())
+}
+
+/// Applies a model's answer; `--dry-run` diffs go to `out`, progress to
+/// stderr. Returns how many files were written or deleted.
+pub fn apply_text(
+    ctx: &Ctx,
+    anonymizer: Option<&Anonymizer>,
+    mut text: String,
+    dry_run: bool,
+    interactive: bool,
+    manifest: &Path,
+    out: &mut dyn Write,
+) -> Result<usize> {
+    let manifest = if manifest.exists() {
+        Some(Manifest::load(manifest)?)
+    } else {
+        None
+    };
+    if let Some(anonymizer) = anonymizer {
+        text = anonymizer.reverse(&text);
+    }
+    let patches = patch::parse(&answer_text(&text));
+    let (files, rejected) = if patches.is_empty() {
+        (parse(&text), 0)
+    } else {
+        from_patches(ctx, &patches)?
+    };
+    if files.is_empty() && rejected == 0 {
+        anyhow::bail!(
+            "no unified diffs, `=== crate :: path ===` sections or path-labelled code blocks found"
+        );
+    }
+
+    let backup = ctx.root.join("target").join("qp-backup").join(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+            .to_string(),
+    );
+    let mut reviewer = if interactive {
+        Some(Reviewer::new()?)
+    } else {
+        None
+    };
+    let mut written = 0;
+    let mut conflicts = 0;
+    for file in &files {
+        let rel = checked_rel(&file.rel)?;
+        anyhow::ensure!(
+            !ctx.sensitive.is_match(&rel),
+            "refusing to write a sensitive path: {}",
+            rel.display()
+        );
+        let path = ctx.root.join(&rel);
+        let old = std::fs::read_to_string(&path).ok();
+        // the snapshot's version, when the local file drifted from it
+        let base = manifest
+            .as_ref()
+            .and_then(|m| m.entry(&rel.to_string_lossy().replace('\\', "/")))
+            .and_then(|entry| git::cat_blob(&ctx.root, &entry.blob).ok())
+            .filter(|base| old.as_ref().is_some_and(|old| old != base));
+        let mut file_conflicts = 0;
+        let merged = match (&base, &old, &file.body) {
+            // patched bodies already build on the local file
+            (Some(base), Some(old), Some(body)) if patches.is_empty() => {
+                let (text, n) = textdiff::merge3(base, old, body, ("local", "answer"));
+                if n > 0 {
+                    eprintln!(
+                        "conflicts  {} ({n}, changed locally since the snapshot)",
+                        rel.display()
+                    );
+                } else {
+                    eprintln!(
+                        "merged     {} (changed locally since the snapshot)",
+                        rel.display()
+                    );
+                }
+                file_conflicts = n;
+                Some(text)
+            }
+            _ => None,
+        };
+        let body = merged.as_ref().or(file.body.as_ref());
+        if old.as_ref() == body {
+            eprintln!("unchanged  {}", rel.display());
+            continue;
+        }
+        if body.is_none() && base.is_some() {
+            eprintln!(
+                "kept       {} (changed locally since the snapshot)",
+                rel.display()
+            );
+            continue;
+        }
+        let reviewed;
+        let body = match &mut reviewer {
+            Some(reviewer) => {
+                match reviewer.file(&rel, old.as_deref(), body.map(String::as_str))? {
+                    Verdict::Write(body) => {
+                        reviewed = body;
+                        reviewed.as_ref()
+                    }
+                    Verdict::Skip => {
+                        eprintln!("skipped    {}", rel.display());
+                        continue;
+                    }
+                    Verdict::Quit => break,
+                }
+            }
+            None => body,
+        };
+        conflicts += file_conflicts;
+        let Some(body) = body else {
+            if dry_run {
+                writeln!(out, "delete     {}", rel.display())?;
+            } else if let Some(old) = &old {
+                save_backup(&backup, &rel, old)?;
+                std::fs::remove_file(&path)?;
+                eprintln!("deleted  {}", rel.display());
+                written += 1;
+            }
+            continue;
+        };
+
+        if dry_run {
+            match &old {
+                Some(old) => {
+                    let name = rel.display().to_string();
+                    write!(
+                        out,
+                        "{}",
+                        textdiff::unified(&format!("a/{name}"), &format!("b/{name}"), old, body)
+                    )?;
+                }
+                None => writeln!(
+                    out,
+                    "new file   {} ({} lines)",
+                    rel.display(),
+                    body.lines().count()
+                )?,
+            }
+            continue;
+        }
+
+        if let Some(old) = &old {
+            save_backup(&backup, &rel, old)?;
+        } else if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, body)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        eprintln!(
+            "{}  {}",
+            if old.is_some() { "updated" } else { "created" },
+            rel.display()
+        );
+        written += 1;
+    }
+    if written > 0 && backup.exists() {
+        eprintln!("previous versions saved under {}", backup.display());
+    }
+    if rejected > 0 {
+        anyhow::bail!("{rejected} hunk(s) rejected");
+    }
+    if conflicts > 0 && !dry_run {
+        anyhow::bail!("{conflicts} merge conflict(s) left in the files");
+    }
+    Ok(written)
+}
+
+fn save_backup(backup: &Path, rel: &Path, old: &str) -> Result<()> {
+    let saved = backup.join(rel);
+    std::fs::create_dir_all(saved.parent().unwrap())?;
+    Ok(std::fs::write(&saved, old)?)
+}
+
+/// Applies each file patch in memory; returns the results and how many
+/// hunks were rejected (printed to stderr as they are found).
+fn from_patches(ctx: &Ctx, patches: &[FilePatch]) -> Result<(Vec<Incoming>, usize)> {
+    let mut files = Vec::new();
+    let mut rejected = 0;
+    for fp in patches {
+        let Some(rel) = fp.target() else {
+            continue;
+        };
+        let rel = checked_rel(rel)?;
+        let original = match &fp.old {
+            Some(_) => std::fs::read_to_string(ctx.root.join(&rel)).unwrap_or_default(),
+            None => String::new(),
+        };
+        let outcome = patch::apply(&original, fp);
+        eprintln!(
+            "patch      {} ({}/{} hunks)",
+            rel.display(),
+            outcome.applied,
+            fp.hunks.len()
+        );
+        for &idx in &outcome.rejected {
+            eprintln!(
+                "rejected hunk {} in {}:\n{}",
+                idx + 1,
+                rel.display(),
+                fp.hunks[idx].render()
+            );
+        }
+        rejected += outcome.rejected.len();
+        if outcome.applied > 0 {
+            let body = fp.new.is_some().then_some(outcome.text);
+            files.push(Incoming { rel, body });
+        }
+    }
+    Ok((files, rejected))
+}
+
+pub fn read_input(input: Option<&Path>) -> Result<String> {
+    match input {
+        Some(p) if p == Path::new("-") => {
+            let mut s = String::new();
+            std::io::stdin().read_to_string(&mut s)?;
+            Ok(s)
+        }
+        Some(p) => {
+            std::fs::read_to_string(p).with_context(|| format!("failed to read {}", p.display()))
+        }
+        None => Clipboard::new()
+            .and_then(|mut c| c.get_text())
+            .context("failed to read the clipboard (pass a file or `-` for stdin)"),
+    }
+}
+
+/// Relative, non-escaping path outside `.git`, or an error.
+pub fn checked_rel(rel: &Path) -> Result<PathBuf> {
+    let ok = rel
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+    if !ok || rel.as_os_str().is_empty() {
+        anyhow::bail!(
+            "refusing to write outside the repository: {}",
+            rel.display()
+        );
+    }
+    // case-insensitive file systems (macOS, Windows) also take `.GIT`, and
+    // Windows drops trailing dots and spaces
+    let git_dir = rel.components().any(|c| {
+        let name = c.as_os_str().to_string_lossy();
+        name.trim_end_matches(['.', ' '])
+            .eq_ignore_ascii_case(".git")
+    });
+    if git_dir {
+        anyhow::bail!("refusing to write into .git: {}", rel.display());
+    }
+    Ok(rel.to_path_buf())
+}
+
+//──────────────────────── parsing ────────────────────────────────────────────
+
+/// Both formats; qp sections win when present.
+pub fn parse(text: &str) -> Vec<Incoming> {
+    let sections = parse_sections(text);
+    if sections.is_empty() {
+        parse_fences(text)
+    } else {
+        sections
+    }
+}
+
+/// `=== crate vX :: path ===` sections. Tagged headers (`[diff]`,
+/// `[signatures]`, …) are not full bodies and are skipped.
+fn parse_sections(text: &str) -> Vec<Incoming> {
+    let mut out = Vec::new();
+    let mut current: Option<(Section, String)> = None;
+    let mut flush = |cur: Option<(Section, String)>| match cur {
+        Some((Section::Full(rel), mut body)) => {
+            // the blank separator cargo-qp adds after each body
+            if body.ends_with("\n\n") {
+                body.pop();
+            }
+            out.push(Incoming {
+                rel,
+                body: Some(body),
+            });
+        }
+        Some((Section::Deleted(rel), _)) => out.push(Incoming { rel, body: None }),
+        _ => {}
+    };
+    for line in text.split_inclusive('\n') {
+        if let Some(section) = section_header(line.trim_end()) {
+            flush(current.take());
+            current = Some((section, String::new()));
+        } else if let Some((_, body)) = current.as_mut() {
+            body.push_str(line);
+        }
+    }
+    flush(current);
+    out
+}
+
+/// `text` without the bodies of sections that are not files to write
+/// (tagged or `#` headers), whose diffs are context rather than edits.
+fn answer_text(text: &str) -> String {
+    let mut kept = String::new();
+    let mut skip = false;
+    for line in text.split_inclusive('\n') {
+        if let Some(section) = section_header(line.trim_end()) {
+            skip = matches!(section, Section::Partial | Section::Other);
+        }
+        if !skip {
+            kept.push_str(line);
+        }
+    }
+    kept
+}
+
+enum Section {
+    Full(PathBuf),
+    /// `=== DELETED … :: path ===` from a follow-up snapshot
+    Deleted(PathBuf),
+    /// tagged header: a diff, signatures or other partial body
+    Partial,
+    /// `=== # title ===`: a section that is not a file (`--log`, a summary, …)
+    Other,
+}
+
+fn section_header(line: &str) -> Option<Section> {
+    let inner = line.strip_prefix("=== ")?.strip_suffix(" ===")?;
+    if inner.starts_with("# ") {
+        return Some(Section::Other);
+    }
+    let (label, path) = inner.split_once(" :: ")?;
+    if path.ends_with(']') && path.contains(" [") {
+        return Some(Section::Partial);
+    }
+    let path = PathBuf::from(path.trim());
+    if label == "DELETED" || label.starts_with("DELETED ") {
+        return Some(Section::Deleted(path));
+    }
+    Some(Section::Full(path))
+}
+
+/// Fenced blocks whose info string (```` ```rust src/lib.rs ````,
+/// ```` ```rust:src/lib.rs ````) or preceding line names a path.
+fn parse_fences(text: &str) -> Vec<Incoming> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let trimmed = lines[i].trim_start();
+        let Some(fence) = fence_marker(trimmed) else {
+            i += 1;
+            continue;
+        };
+        let info = trimmed[fence.len()..].trim();
+        let label = info.split([' ', ':']).find_map(path_like).or_else(|| {
+            lines[..i]
+                .iter()
+                .rev()
+                .find(|l| !l.trim().is_empty())
+                .and_then(|l| l.split_whitespace().rev().find_map(path_like))
+        });
+
+        let start = i + 1;
+        let mut end = start;
+        while end < lines.len() && !lines[end].trim_start().starts_with(fence) {
+            end += 1;
+        }
+        if let Some(rel) = label {
+            let mut body = lines[start..end.min(lines.len())].join("\n");
+            body.push('\n');
+            out.push(Incoming {
+                rel,
+                body: Some(body),
+            });
+        }
+        i = end + 1;
+    }
+    out
+}
+
+fn fence_marker(line: &str) -> Option<&str> {
+    let ch = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let n = line.chars().take_while(|c| *c == ch).count();
+    (n >= 3).then(|| &line[..n])
+}
+
+/// Token that looks like a relative file path: has an extension or a slash,
+/// after stripping markdown decoration.
+fn path_like(token: &str) -> Option<PathBuf> {
+    let t = token.trim_matches(|c: char| "*`'\"#:()[],".contains(c));
+    let looks = !t.is_empty()
+        && !t.contains("://")
+        && t.chars().all(|c| c.is_alphanumeric() || "/._-".contains(c))
+        && (t.contains('/')
+            || t.rsplit_once('.')
+                .is_some_and(|(stem, ext)| !stem.is_empty() && !ext.is_empty()))
+        && !t.ends_with('.');
+    looks.then(|| PathBuf::from(t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{format, manifest, schema};
+
+    /// A file as `emit_file` renders it in `--format text`.
+    fn file(path: &str, status: Option<&str>, tag: Option<&str>, body: &str) -> String {
+        let file = schema::File {
+            path: path.to_string(),
+            crate_name: "a".to_string(),
+            version: "0.1.0".to_string(),
+            status: status.map(String::from),
+            tag: tag.map(String::from),
+            crates_io: None,
+            note: None,
+            last_commit: None,
+            language: None,
+            lines: body.lines().count(),
+            tokens: 0,
+            transforms: Vec::new(),
+            body: String::new(),
+        };
+        format!("{}{body}\n", format::text_header(&file, false))
+    }
+
+    /// A section as `push_section` renders it in `--format text`.
+    fn section(title: &str, text: &str) -> String {
+        format!("{}{text}\n", format::text_section_header(title))
+    }
+
+    fn bodies(text: &str) -> Vec<(String, Option<String>)> {
+        parse(text)
+            .into_iter()
+            .map(|i| (i.rel.to_string_lossy().into_owned(), i.body))
+            .collect()
+    }
+
+    #[test]
+    fn sections_are_not_files() {
+        let text = [
+            section("git log :: last 2 commits", "1df312b a: init\n"),
+            section("crate :: a v0.1.0", "edition 2021\n"),
+            file("a/src/lib.rs", None, None, "pub fn a() {}\n"),
+            file("a/src/x.rs", None, None, "fn x() {}\n\nfn y() {}\n"),
+            section(
+                "Cargo.lock :: 1df312b..worktree :: 1 package(s) changed",
+                "added syn\n",
+            ),
+        ]
+        .concat();
+        assert_eq!(
+            bodies(&text),
+            [
+                ("a/src/lib.rs".into(), Some("pub fn a() {}\n".into())),
+                ("a/src/x.rs".into(), Some("fn x() {}\n\nfn y() {}\n".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn follow_up_round_trip() {
+        let text = [
+            section(&manifest::follow_up_title(1, 0, 1), ""),
+            file("a/src/lib.rs", Some("UPDATED"), None, "pub fn b() {}\n"),
+            file("a/src/old.rs", Some("DELETED"), None, ""),
+        ]
+        .concat();
+        assert_eq!(
+            bodies(&text),
+            [
+                ("a/src/lib.rs".into(), Some("pub fn b() {}\n".into())),
+                ("a/src/old.rs".into(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn partial_bodies_are_skipped() {
+        let text = [
+            file("a/src/lib.rs", None, Some("signatures"), "pub fn a();\n"),
+            file("a/src/x.rs", None, Some("identical to a/src/y.rs"), ""),
+        ]
+        .concat();
+        assert!(bodies(&text).is_empty());
+    }
+
+    #[test]
+    fn since_manifest_snapshot_writes_nothing() {
+        let patch = textdiff::unified(
+            "a/a/src/lib.rs",
+            "b/a/src/lib.rs",
+            "pub fn a() {}\n",
+            "pub fn a() {}\npub fn b() {}\n",
+        );
+        let text = [
+            section(
+                "changes since manifest :: 2 changed/new, 1 deleted (was ~40 tokens)",
+                "",
+            ),
+            file("a/src/lib.rs", None, Some("diff"), &patch),
+            file("a/src/new.rs", None, Some("new"), "fn n() {}\n"),
+            section("deleted :: a/src/old.rs", ""),
+        ]
+        .concat();
+        assert!(bodies(&text).is_empty());
+        assert!(patch::parse(&answer_text(&text)).is_empty());
+        // the same diff as an answer is applied
+        assert_eq!(patch::parse(&patch).len(), 1);
+    }
+
+    #[test]
+    fn last_line_kept_without_separator() {
+        let text = "=== a v0.1.0 :: a/src/lib.rs ===\nfn a() {}\nfn b() {}";
+        assert_eq!(
+            bodies(text),
+            [("a/src/lib.rs".into(), Some("fn a() {}\nfn b() {}".into()))]
+        );
+        let text = "=== a v0.1.0 :: a/src/lib.rs ===\nfn a() {}\n";
+        assert_eq!(
+            bodies(text),
+            [("a/src/lib.rs".into(), Some("fn a() {}\n".into()))]
+        );
+    }
+
+    #[test]
+    fn checked_rel_refuses_git_and_escapes() {
+        for rel in [
+            "../x",
+            "/etc/passwd",
+            "",
+            ".git/config",
+            ".git/hooks/pre-commit",
+            "a/.GIT/config",
+            ".Git./hooks/x",
+        ] {
+            assert!(checked_rel(Path::new(rel)).is_err(), "{rel}");
+        }
+        for rel in [
+            "src/lib.rs",
+            "./a/b.rs",
+            ".github/workflows/ci.yml",
+            ".gitignore",
+        ] {
+            assert!(checked_rel(Path::new(rel)).is_ok(), "{rel}");
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! `cargo qp auto "<question>"` — picks the files worth sending for a
+//! question and fits them to a token budget.
+//! * Files are scored by BM25 over identifier words (`parse_config`,
+//!   `ParseConfig` and `parse-config` all give `parse` and `config`), with
+//!   matches in the path counting triple; files matching nothing are left
+//!   out.
+//! * Best first (by score, or by `--rank-by`), each file goes in whole while
+//!   it fits, else as its signatures (Rust), else not at all. The manifests
+//!   of the crates picked, and `--extra` files, are always sent.
+//! * The budget is `--max-tokens` (or `max_tokens` in the config), else
+//!   three quarters of `--model`'s context window (default `gpt-4o`), the
+//!   rest being left for the conversation.
+//! * `--prompt` appends the question as the last section.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{
+    order,
+    rank::{self, RankBy},
+    tokens, Ctx,
+};
+
+/// Files left out for the budget that are still listed in the summary.
+const LISTED_OVER_BUDGET: usize = 10;
+
+pub fn compose(
+    ctx: &mut Ctx,
+    out: &mut dyn Write,
+    question: &str,
+    model: Option<&str>,
+    prompt: bool,
+) -> Result<()> {
+    let budget = match (ctx.max_tokens, model) {
+        (Some(max), _) => max,
+        (None, model) => {
+            let model = model.unwrap_or(tokens::MODELS[0].0);
+            let window = tokens::context_window(model).ok_or_else(|| {
+                let known: Vec<&str> = tokens::MODELS.iter().map(|(n, _)| *n).collect();
+                anyhow::anyhow!(
+                    "unknown --model `{model}` (expected a name starting with one of {})",
+                    known.join(", ")
+                )
+            })?;
+            window / 4 * 3
+        }
+    };
+    let terms = rank::terms(question);
+    anyhow::ensure!(!terms.is_empty(), "the question has no searchable words");
+
+    let paths = ctx.selected()?;
+    let bodies = ctx.read_all(&paths)?;
+    let files: Vec<(PathBuf, String)> = paths.into_iter().zip(bodies).collect();
+    let scores = rank::relevance(ctx, &terms, &files);
+    let is_manifest = |p: &Path| p.file_name() == Some("Cargo.toml".as_ref());
+    let candidates: Vec<usize> = rank::priority(
+        ctx,
+        ctx.rank_by.unwrap_or(RankBy::Relevance),
+        &files,
+        Some(&scores),
+    )
+    .into_iter()
+    .filter(|&i| (scores[i] > 0.0 || ctx.extra.contains(&files[i].0)) && !is_manifest(&files[i].0))
+    .collect();
+    anyhow::ensure!(
+        !candidates.is_empty(),
+        "nothing in the selection matches the question"
+    );
+
+    // manifests first: they are small and say what every crate is
+    let crates: HashSet<Option<&Path>> = candidates
+        .iter()
+        .map(|&i| ctx.crate_dir(&files[i].0))
+        .collect();
+    let mut used = 0;
+    let mut manifests = Vec::new();
+    for (i, (path, body)) in files.iter().enumerate() {
+        let dir = path.parent();
+        if is_manifest(path) && (dir == Some(ctx.root.as_path()) || crates.contains(&dir)) {
+            used += rank::cost(ctx, path, body);
+            manifests.push(i);
+        }
+    }
+    let mut refs = vec![None; files.len()];
+    let (mut kept, used) = rank::fit(ctx, &files, &candidates, &mut refs, used, budget);
+
+    let mut lines = Vec::new();
+    let over = candidates.iter().filter(|&&i| !kept[i]).count();
+    let mut listed_over = 0;
+    for &i in &candidates {
+        let verdict = match (kept[i], &refs[i]) {
+            (true, _) if ctx.extra.contains(&files[i].0) => "pinned",
+            (true, Some((tag, _))) => tag.as_str(),
+            (true, None) => "full",
+            (false, _) if listed_over < LISTED_OVER_BUDGET => {
+                listed_over += 1;
+                "over budget"
+            }
+            (false, _) => continue,
+        };
+        lines.push(format!(
+            "{:>6.2}  {}  {verdict}",
+            scores[i],
+            ctx.rel(&files[i].0).display()
+        ));
+    }
+    if over > listed_over {
+        lines.push(format!("        … {} more over budget", over - listed_over));
+    }
+    for i in manifests {
+        kept[i] = true;
+    }
+    let summary = format!(
+        "auto :: {} files for ~{} of {} tokens",
+        kept.iter().filter(|k| **k).count(),
+        tokens::human(used),
+        tokens::human(budget)
+    );
+    ctx.push_section(out, &summary, &format!("{}\n", lines.join("\n")))?;
+    let mut picked: Vec<(PathBuf, String)> = Vec::new();
+    let mut tags = HashMap::new();
+    for ((file, reference), keep) in files.into_iter().zip(refs).zip(kept) {
+        if !keep {
+            continue;
+        }
+        match reference {
+            Some((tag, text)) => {
+                tags.insert(file.0.clone(), tag);
+                picked.push((file.0, text));
+            }
+            None => picked.push(file),
+        }
+    }
+    order::apply(ctx, ctx.order, &mut picked);
+    for (path, text) in &picked {
+        ctx.push_file(out, path, tags.get(path).map(String::as_str), text)?;
+    }
+    if prompt {
+        ctx.push_section(out, "question", &format!("{}\n", question.trim()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,224 @@
+//! `--benches-for <PATH|SYMBOL>` — restrict the snapshot to code and the
+//! criterion benches that measure it, then the latest results.
+//! * Bench files are `.rs` files under a crate's `benches/`, or anywhere
+//!   naming `criterion`.
+//! * A PATH (an existing file, relative to the root) is kept with the bench
+//!   files naming its crate and module (just the crate, for a crate root).
+//!   A SYMBOL is kept as the files defining it, with the bench files where
+//!   the identifier occurs.
+//! * Results come from `target/criterion` (or `$CARGO_TARGET_DIR`): for
+//!   each benchmark whose group or function id is a string literal in an
+//!   emitted bench file, the mean, its standard deviation and the change
+//!   since the run before, from criterion's `new/` and `change/` estimates.
+//! * The result narrows the selection like `--users-of`; filters still
+//!   apply.
+
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::{index, syntax, users, Ctx};
+
+pub fn select(ctx: &Ctx, target: &str) -> Result<HashSet<PathBuf>> {
+    let sources = users::sources(ctx)?;
+    let texts: Vec<(PathBuf, String)> = sources
+        .into_iter()
+        .filter_map(|p| ctx.read(&p).ok().map(|text| (p, text)))
+        .collect();
+    let (benches, code): (Vec<_>, Vec<_>) =
+        texts.iter().partition(|(p, text)| is_bench(ctx, p, text));
+
+    let path = ctx.root.join(target);
+    let mut selected = HashSet::new();
+    if path.is_file() {
+        selected.insert(path.clone());
+        if let Some(dir) = ctx.crate_dir(&path) {
+            let krate = ctx.owner(&path).0.replace('-', "_");
+            let module = users::module_name(dir, &path);
+            for (bench, text) in &benches {
+                if syntax::mentions(text, &krate)
+                    && module.is_none_or(|m| syntax::mentions(text, m))
+                {
+                    selected.insert(bench.clone());
+                }
+            }
+        }
+    } else {
+        for (file, text) in &code {
+            let Ok(parsed) = syn::parse_file(text) else {
+                continue;
+            };
+            if index::definitions(&parsed, String::new())
+                .iter()
+                .any(|d| d.name == target)
+            {
+                selected.insert(file.clone());
+            }
+        }
+        for (bench, text) in &benches {
+            if syntax::mentions(text, target) {
+                selected.insert(bench.clone());
+            }
+        }
+    }
+    if !benches.iter().any(|(bench, _)| selected.contains(bench)) {
+        eprintln!("warning: no criterion benches found for --benches-for {target}");
+    }
+    Ok(selected)
+}
+
+fn is_bench(ctx: &Ctx, path: &Path, text: &str) -> bool {
+    ctx.crate_dir(path)
+        .is_some_and(|dir| path.starts_with(dir.join("benches")))
+        || syntax::mentions(text, "criterion")
+}
+
+/// The `criterion` section for the bench files emitted so far.
+pub fn append(ctx: &mut Ctx, out: &mut dyn Write) -> Result<()> {
+    let mut literals = HashSet::new();
+    for e in &ctx.emitted {
+        let Ok(text) = ctx.read(&e.path) else {
+            continue;
+        };
+        if is_bench(ctx, &e.path, &text) {
+            literals.extend(string_literals(&text));
+        }
+    }
+    if literals.is_empty() {
+        return Ok(());
+    }
+    let target = match std::env::var_os("CARGO_TARGET_DIR") {
+        Some(dir) => ctx.root.join(dir),
+        None => ctx.root.join("target"),
+    };
+    let mut results = Vec::new();
+    collect(&target.join("criterion"), &mut results);
+    results.retain(|r| {
+        literals.contains(&r.group) || r.function.as_ref().is_some_and(|f| literals.contains(f))
+    });
+    results.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let text = if results.is_empty() {
+        format!(
+            "no results under {}; run `cargo bench` first\n",
+            target.join("criterion").display()
+        )
+    } else {
+        let width = results.iter().map(|r| r.id.len()).max().unwrap_or(0);
+        let mut text = String::new();
+        for r in &results {
+            let _ = write!(
+                text,
+                "{:<width$}  {:>10} ± {}",
+                r.id,
+                duration(r.mean),
+                duration(r.std_dev)
+            );
+            if let Some(change) = r.change {
+                let _ = write!(text, "  ({:+.1}%)", change * 100.0);
+            }
+            text.push('\n');
+        }
+        text
+    };
+    let title = format!("criterion :: {} benchmark(s)", results.len());
+    ctx.push_section(out, &title, &text)?;
+    Ok(())
+}
+
+/// One benchmark's latest estimates.
+struct Estimate {
+    id: String,
+    group: String,
+    function: Option<String>,
+    /// nanoseconds
+    mean: f64,
+    std_dev: f64,
+    /// relative change of the mean since the previous run
+    change: Option<f64>,
+}
+
+/// Every `<dir>/**/new/benchmark.json` with its estimates.
+fn collect(dir: &Path, out: &mut Vec<Estimate>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() || path.file_name() == Some("report".as_ref()) {
+            continue;
+        }
+        if path.file_name() == Some("new".as_ref()) {
+            if let Some(result) = read(&path) {
+                out.push(result);
+            }
+        } else {
+            collect(&path, out);
+        }
+    }
+}
+
+fn read(new: &Path) -> Option<Estimate> {
+    let json = |path: PathBuf| -> Option<Value> {
+        serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+    };
+    let benchmark = json(new.join("benchmark.json"))?;
+    let estimates = json(new.join("estimates.json"))?;
+    let point = |v: &Value, key: &str| v.get(key)?.get("point_estimate")?.as_f64();
+    let change = new
+        .parent()
+        .and_then(|dir| json(dir.join("change").join("estimates.json")))
+        .and_then(|c| point(&c, "mean"));
+    Some(Estimate {
+        id: benchmark.get("full_id")?.as_str()?.to_string(),
+        group: benchmark.get("group_id")?.as_str()?.to_string(),
+        function: benchmark
+            .get("function_id")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        mean: point(&estimates, "mean")?,
+        std_dev: point(&estimates, "std_dev")?,
+        change,
+    })
+}
+
+/// String literals in a Rust source, by a plain scan.
+fn string_literals(src: &str) -> HashSet<String> {
+    let mut found = HashSet::new();
+    let mut rest = src;
+    while let Some(start) = rest.find('"') {
+        let after = &rest[start + 1..];
+        let mut end = None;
+        let mut escaped = false;
+        for (i, c) in after.char_indices() {
+            match c {
+                '\\' if !escaped => escaped = true,
+                '"' if !escaped => {
+                    end = Some(i);
+                    break;
+                }
+                _ => escaped = false,
+            }
+        }
+        let Some(end) = end else { break };
+        found.insert(after[..end].to_string());
+        rest = &after[end + 1..];
+    }
+    found
+}
+
+/// `1234.5` (ns) → `1.23 µs`.
+fn duration(ns: f64) -> String {
+    match ns {
+        n if n >= 1e9 => format!("{:.2} s", n / 1e9),
+        n if n >= 1e6 => format!("{:.2} ms", n / 1e6),
+        n if n >= 1e3 => format!("{:.2} µs", n / 1e3),
+        n => format!("{n:.2} ns"),
+    }
+}
@@ -0,0 +1,189 @@
+//! Content-hash cache under `target/qp-cache/`.
+//! * `index.json` maps each path to its last seen mtime/size, blake3 hash and
+//!   token count, so unchanged files keep their hash without re-hashing. The
+//!   hash is of the transformed body, so each entry records the pipeline
+//!   (`Stages::identity`) it came from and only that pipeline reuses it.
+//! * Raw file bytes stay in memory for the life of the process: `watch` and
+//!   the daemon only re-read files whose mtime or size moved. Bytes, not
+//!   bodies, so every request decodes and transforms them with its own
+//!   flags.
+//! * Transformed content (signatures-only views, …) is stored on disk as
+//!   `<hash>.<transform>` and reused by later runs.
+
+use std::{
+    collections::HashMap,
+    fs::Metadata,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::tokens;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Stamp {
+    modified_ns: u128,
+    len: u64,
+    /// `Stages::identity` of the pipeline `hash` and `tokens` describe the
+    /// output of.
+    #[serde(default)]
+    pipeline: String,
+    hash: String,
+    tokens: usize,
+}
+
+impl Stamp {
+    fn matches(&self, meta: &Metadata) -> bool {
+        self.len == meta.len() && self.modified_ns == modified_ns(meta)
+    }
+}
+
+pub struct Cache {
+    dir: PathBuf,
+    index: HashMap<String, Stamp>,
+    /// `Stages::identity` of the current run (`set_pipeline`).
+    pipeline: String,
+    /// path → stamp and raw bytes, for files read by this process
+    /// (`keep_bodies`)
+    bodies: HashMap<String, (Stamp, Vec<u8>)>,
+    keep_bodies: bool,
+    dirty: bool,
+}
+
+impl Cache {
+    /// Loads `<root>/target/qp-cache/index.json`; a missing or unreadable
+    /// index starts empty.
+    pub fn load(root: &Path) -> Self {
+        let dir = root.join("target").join("qp-cache");
+        let index = std::fs::read(dir.join("index.json"))
+            .ok()
+            .and_then(|b| serde_json::from_slice(&b).ok())
+            .unwrap_or_default();
+        Cache {
+            dir,
+            index,
+            pipeline: String::new(),
+            bodies: HashMap::new(),
+            keep_bodies: false,
+            dirty: false,
+        }
+    }
+
+    /// Keep file bytes in memory so unchanged files are not read again;
+    /// worth it for long-running modes only.
+    pub fn keep_bodies(&mut self) {
+        self.keep_bodies = true;
+    }
+
+    /// Sets the pipeline later bodies come from; stamps of another pipeline
+    /// are not reused.
+    pub fn set_pipeline(&mut self, identity: String) {
+        self.pipeline = identity;
+    }
+
+    /// Whether `insert` wants the raw bytes.
+    pub fn keeps_bodies(&self) -> bool {
+        self.keep_bodies
+    }
+
+    /// Cached raw bytes of `rel`, if the file has not changed since it was
+    /// read.
+    pub fn bytes(&self, rel: &str, meta: &Metadata) -> Option<Vec<u8>> {
+        let (_, bytes) = self.bodies.get(rel).filter(|(s, _)| s.matches(meta))?;
+        Some(bytes.clone())
+    }
+
+    /// Records a freshly read body, and its raw `bytes` when kept.
+    pub fn insert(&mut self, rel: &str, meta: &Metadata, body: &str, bytes: Option<Vec<u8>>) {
+        let fresh = |s: &Stamp| s.matches(meta) && s.pipeline == self.pipeline;
+        if !self.index.get(rel).is_some_and(fresh) {
+            let stamp = Stamp {
+                modified_ns: modified_ns(meta),
+                len: meta.len(),
+                pipeline: self.pipeline.clone(),
+                hash: blake3::hash(body.as_bytes()).to_hex().to_string(),
+                tokens: tokens::estimate(body),
+            };
+            self.index.insert(rel.to_string(), stamp);
+            self.dirty = true;
+        }
+        if let (true, Some(bytes)) = (self.keep_bodies, bytes) {
+            self.bodies
+                .insert(rel.to_string(), (self.index[rel].clone(), bytes));
+        }
+    }
+
+    /// `transform(body)`, computed once per content hash and kept on disk.
+    pub fn transformed(
+        &self,
+        body: &str,
+        name: &str,
+        transform: impl FnOnce(&str) -> Option<String>,
+    ) -> Option<String> {
+        let hash = blake3::hash(body.as_bytes()).to_hex();
+        let file = self.dir.join(format!("{hash}.{name}"));
+        if let Ok(cached) = std::fs::read_to_string(&file) {
+            return Some(cached);
+        }
+        let result = transform(body)?;
+        if std::fs::create_dir_all(&self.dir).is_ok() {
+            let _ = std::fs::write(&file, &result);
+        }
+        Some(result)
+    }
+
+    /// Writes the index back if anything changed.
+    pub fn save(&mut self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(
+            self.dir.join("index.json"),
+            serde_json::to_vec(&self.index)?,
+        )?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl Drop for Cache {
+    fn drop(&mut self) {
+        let _ = self.save();
+    }
+}
+
+fn modified_ns(meta: &Metadata) -> u128 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamps_are_per_pipeline() {
+        let dir = std::env::temp_dir().join(format!("qp-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("a.rs");
+        std::fs::write(&file, "a\r\n").unwrap();
+        let meta = file.metadata().unwrap();
+        let mut cache = Cache::load(&dir);
+        cache.insert("a.rs", &meta, "a\r\n", None);
+        let raw = cache.index["a.rs"].hash.clone();
+        cache.insert("a.rs", &meta, "a\n", None);
+        assert_eq!(cache.index["a.rs"].hash, raw);
+        cache.set_pipeline("normalize-eol".into());
+        cache.insert("a.rs", &meta, "a\n", None);
+        assert_ne!(cache.index["a.rs"].hash, raw);
+        assert_eq!(cache.index["a.rs"].pipeline, "normalize-eol");
+        cache.dirty = false;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+//! `--per-crate-cap N` — no crate takes more than N tokens of full bodies,
+//! so one big crate can't crowd the others out of the budget.
+//! * `per-crate-cap` in `.cargo-qp.toml` (or the flag, which wins) sets the
+//!   default; `[crate-caps]` overrides it per crate name.
+//! * Files are taken in `--order` sequence, or best first by `--rank-by`.
+//!   From the first one that no longer fits, a crate's remaining `.rs`
+//!   files are emitted signatures-only and its other files as a placeholder.
+//! * Dedupe copies of a capped file are capped alike (see `dedupe::rebase`).
+//! * Files outside every crate (the workspace manifest, …) are never capped,
+//!   nor are `--extra` files.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::{dedupe, rank, tokens, Config, Ctx, Opts};
+
+/// Token caps per crate name.
+pub struct Caps {
+    default: Option<usize>,
+    by_crate: BTreeMap<String, usize>,
+}
+
+impl Caps {
+    /// `None` when no cap is configured.
+    pub fn new(config: &Config, opts: &Opts) -> Option<Self> {
+        let caps = Caps {
+            default: opts.per_crate_cap.or(config.per_crate_cap),
+            by_crate: config.crate_caps.clone(),
+        };
+        (caps.default.is_some() || !caps.by_crate.is_empty()).then_some(caps)
+    }
+
+    fn of(&self, name: &str) -> Option<usize> {
+        self.by_crate.get(name).copied().or(self.default)
+    }
+}
+
+/// Replaces the bodies of over-cap files in `refs` (see `dedupe::references`)
+/// with their signatures or a placeholder.
+pub fn limit(
+    ctx: &Ctx,
+    files: &[(PathBuf, String)],
+    mut refs: Vec<Option<(String, String)>>,
+) -> Vec<Option<(String, String)>> {
+    let Some(caps) = &ctx.caps else {
+        return refs;
+    };
+    // tokens used per crate, `None` once the crate is over its cap
+    let mut used: HashMap<String, Option<usize>> = HashMap::new();
+    let order = match ctx.rank_by {
+        Some(rank_by) => rank::priority(ctx, rank_by, files, None),
+        None => (0..files.len()).collect(),
+    };
+    for i in order {
+        let (path, body) = &files[i];
+        let reference = &mut refs[i];
+        if ctx.extra.contains(path) {
+            continue;
+        }
+        let Some(dir) = ctx.crate_dir(path) else {
+            continue;
+        };
+        let name = ctx.owner(dir).0;
+        let Some(cap) = caps.of(&name) else {
+            continue;
+        };
+        let emitted = reference
+            .as_ref()
+            .map_or(body.as_str(), |(_, b)| b.as_str());
+        let cost = tokens::estimate(emitted);
+        let slot = used.entry(name).or_insert(Some(0));
+        if let Some(total) = slot.filter(|total| total + cost <= cap) {
+            *slot = Some(total + cost);
+            continue;
+        }
+        *slot = None;
+        *reference = Some(capped(ctx, path, body));
+    }
+    // copies of a capped original are capped alike, rather than pointing at
+    // a body that isn't there
+    let all = vec![true; files.len()];
+    loop {
+        let promoted = dedupe::rebase(ctx, files, &mut refs, &all);
+        if promoted.is_empty() {
+            break;
+        }
+        for (i, _) in promoted {
+            let (path, body) = &files[i];
+            refs[i] = Some(capped(ctx, path, body));
+        }
+    }
+    refs
+}
+
+/// Signatures of an over-cap `.rs` file, else a placeholder.
+fn capped(ctx: &Ctx, path: &Path, body: &str) -> (String, String) {
+    let sigs = path
+        .extension()
+        .is_some_and(|x| x == "rs")
+        .then(|| ctx.signatures(body))
+        .flatten();
+    match sigs {
+        Some(sigs) => ("signatures".into(), sigs),
+        None => (
+            "over crate cap".into(),
+            format!(
+                "(~{} tokens left out by --per-crate-cap)\n",
+                tokens::human(tokens::estimate(body))
+            ),
+        ),
+    }
+}
@@ -0,0 +1,512 @@
+//! `--target <TRIPLE>` / `--cfg-prune` — drops code gated behind `#[cfg]`
+//! predicates that are false for one target (the host for `--cfg-prune`).
+//! * The target's cfg set comes from `rustc --print cfg --target <TRIPLE>`;
+//!   only target-determined names (`unix`, `windows`, `target_*` except
+//!   `target_feature`) are decided. Features, `test`, `debug_assertions` and
+//!   custom cfgs stay unknown, and anything depending on them is kept.
+//! * Removes items, associated and foreign items, fields, variants, match
+//!   arms and `let` statements together with their attributes and docs.
+//! * Files whose `#![cfg]` is false, or whose `mod` declaration was pruned
+//!   (`#[cfg(windows)] mod windows;`), leave the snapshot altogether; these
+//!   are read from the working tree.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use syn::{
+    punctuated::Punctuated,
+    spanned::Spanned,
+    visit::{self, Visit},
+    Meta, Token,
+};
+
+use crate::{
+    syntax::LineIndex,
+    transform::{FileEntry, Filter, Transform},
+};
+
+/// What `rustc` reports for a target.
+pub struct Target {
+    /// `name` and `name="value"` cfgs, as `(name, value)`.
+    set: HashSet<(String, Option<String>)>,
+}
+
+impl Target {
+    /// Asks `$RUSTC` (else `rustc`) for the cfg set of `triple`.
+    pub fn load(triple: Option<&str>) -> Result<Self> {
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let mut cmd = Command::new(&rustc);
+        cmd.args(["--print", "cfg"]);
+        if let Some(triple) = triple {
+            cmd.args(["--target", triple]);
+        }
+        let out = cmd
+            .output()
+            .with_context(|| format!("failed to run `{rustc} --print cfg`"))?;
+        if !out.status.success() {
+            anyhow::bail!(
+                "`{rustc} --print cfg` failed: {}",
+                String::from_utf8_lossy(&out.stderr).trim()
+            );
+        }
+        let set = String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .map(|line| match line.split_once('=') {
+                Some((name, value)) => {
+                    (name.to_string(), Some(value.trim_matches('"').to_string()))
+                }
+                None => (line.to_string(), None),
+            })
+            .collect();
+        Ok(Target { set })
+    }
+
+    /// Whether `rustc`'s answer for `name` holds whatever the build flags.
+    fn decides(name: &str) -> bool {
+        matches!(name, "unix" | "windows")
+            || (name.starts_with("target_") && name != "target_feature")
+    }
+
+    /// The value of a cfg predicate; `None` when it depends on something the
+    /// target doesn't decide.
+    fn eval(&self, meta: &Meta) -> Option<bool> {
+        match meta {
+            Meta::Path(path) => {
+                let name = path.get_ident()?.to_string();
+                Self::decides(&name).then(|| self.set.contains(&(name, None)))
+            }
+            Meta::NameValue(nv) => {
+                let name = nv.path.get_ident()?.to_string();
+                let syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(value),
+                    ..
+                }) = &nv.value
+                else {
+                    return None;
+                };
+                Self::decides(&name).then(|| self.set.contains(&(name, Some(value.value()))))
+            }
+            Meta::List(list) => {
+                let args = list
+                    .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                    .ok()?;
+                let values: Vec<Option<bool>> = args.iter().map(|m| self.eval(m)).collect();
+                match list.path.get_ident()?.to_string().as_str() {
+                    "all" if values.contains(&Some(false)) => Some(false),
+                    "all" => values.iter().all(Option::is_some).then_some(true),
+                    "any" if values.contains(&Some(true)) => Some(true),
+                    "any" => values.iter().all(Option::is_some).then_some(false),
+                    "not" if values.len() == 1 => values[0].map(|v| !v),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// Whether `attrs` carry a `#[cfg]` that is false for the target.
+    fn excludes(&self, attrs: &[syn::Attribute]) -> bool {
+        attrs.iter().any(|attr| {
+            attr.path().is_ident("cfg")
+                && attr
+                    .parse_args::<Meta>()
+                    .is_ok_and(|meta| self.eval(&meta) == Some(false))
+        })
+    }
+}
+
+/// `src` without the code `target` excludes; `None` when nothing was
+/// pruned or the file does not parse.
+fn prune(src: &str, target: &Target) -> Option<String> {
+    let file = syn::parse_file(src).ok()?;
+    let mut pruned = Pruned {
+        target,
+        spans: Vec::new(),
+    };
+    pruned.visit_file(&file);
+    if pruned.spans.is_empty() {
+        return None;
+    }
+
+    let lines = LineIndex::new(src);
+    let b = src.as_bytes();
+    let mut out = String::with_capacity(src.len());
+    let mut last = 0;
+    for span in pruned.spans {
+        let (mut start, mut end) = (lines.offset(span.start()), lines.offset(span.end()));
+        if start < last {
+            continue;
+        }
+        // a trailing comma, then the rest of the line and its indentation
+        // when the node had the line to itself
+        let gap = |at: usize| {
+            b[at..]
+                .iter()
+                .take_while(|c| matches!(c, b' ' | b'\t'))
+                .count()
+        };
+        if b.get(end + gap(end)) == Some(&b',') {
+            end += gap(end) + 1;
+        }
+        let line_start = src[..start].rfind('\n').map_or(0, |i| i + 1);
+        let after = end + gap(end);
+        let alone = src[line_start..start].trim().is_empty()
+            && matches!(b.get(after), None | Some(b'\n' | b'\r'));
+        if alone {
+            start = line_start.max(last);
+            end = src[after..].find('\n').map_or(b.len(), |i| after + i + 1);
+            // one blank line is enough between the neighbours
+            let blank_before = src[..start].ends_with("\n\n") || start == 0;
+            if blank_before && src[end..].starts_with('\n') {
+                end += 1;
+            }
+        }
+        out.push_str(&src[last..start]);
+        last = end;
+    }
+    out.push_str(&src[last..]);
+    Some(out)
+}
+
+/// Spans of the nodes to remove, outermost only, in source order.
+struct Pruned<'t> {
+    target: &'t Target,
+    spans: Vec<proc_macro2::Span>,
+}
+
+impl Pruned<'_> {
+    /// Records `node` when its attributes exclude it; `false` then, so its
+    /// contents aren't visited.
+    fn keep(&mut self, attrs: &[syn::Attribute], node: &impl Spanned) -> bool {
+        if self.target.excludes(attrs) {
+            self.spans.push(node.span());
+            return false;
+        }
+        true
+    }
+}
+
+impl<'ast> Visit<'ast> for Pruned<'_> {
+    fn visit_item(&mut self, i: &'ast syn::Item) {
+        let attrs = match i {
+            syn::Item::Const(x) => &x.attrs,
+            syn::Item::Enum(x) => &x.attrs,
+            syn::Item::ExternCrate(x) => &x.attrs,
+            syn::Item::Fn(x) => &x.attrs,
+            syn::Item::ForeignMod(x) => &x.attrs,
+            syn::Item::Impl(x) => &x.attrs,
+            syn::Item::Macro(x) => &x.attrs,
+            syn::Item::Mod(x) => &x.attrs,
+            syn::Item::Static(x) => &x.attrs,
+            syn::Item::Struct(x) => &x.attrs,
+            syn::Item::Trait(x) => &x.attrs,
+            syn::Item::TraitAlias(x) => &x.attrs,
+            syn::Item::Type(x) => &x.attrs,
+            syn::Item::Union(x) => &x.attrs,
+            syn::Item::Use(x) => &x.attrs,
+            _ => return visit::visit_item(self, i),
+        };
+        if self.keep(attrs, i) {
+            visit::visit_item(self, i);
+        }
+    }
+
+    fn visit_impl_item(&mut self, i: &'ast syn::ImplItem) {
+        let attrs = match i {
+            syn::ImplItem::Const(x) => &x.attrs,
+            syn::ImplItem::Fn(x) => &x.attrs,
+            syn::ImplItem::Type(x) => &x.attrs,
+            syn::ImplItem::Macro(x) => &x.attrs,
+            _ => return visit::visit_impl_item(self, i),
+        };
+        if self.keep(attrs, i) {
+            visit::visit_impl_item(self, i);
+        }
+    }
+
+    fn visit_trait_item(&mut self, i: &'ast syn::TraitItem) {
+        let attrs = match i {
+            syn::TraitItem::Const(x) => &x.attrs,
+            syn::TraitItem::Fn(x) => &x.attrs,
+            syn::TraitItem::Type(x) => &x.attrs,
+            syn::TraitItem::Macro(x) => &x.attrs,
+            _ => return visit::visit_trait_item(self, i),
+        };
+        if self.keep(attrs, i) {
+            visit::visit_trait_item(self, i);
+        }
+    }
+
+    fn visit_foreign_item(&mut self, i: &'ast syn::ForeignItem) {
+        let attrs = match i {
+            syn::ForeignItem::Fn(x) => &x.attrs,
+            syn::ForeignItem::Static(x) => &x.attrs,
+            syn::ForeignItem::Type(x) => &x.attrs,
+            syn::ForeignItem::Macro(x) => &x.attrs,
+            _ => return visit::visit_foreign_item(self, i),
+        };
+        if self.keep(attrs, i) {
+            visit::visit_foreign_item(self, i);
+        }
+    }
+
+    fn visit_field(&mut self, f: &'ast syn::Field) {
+        if self.keep(&f.attrs, f) {
+            visit::visit_field(self, f);
+        }
+    }
+
+    fn visit_variant(&mut self, v: &'ast syn::Variant) {
+        if self.keep(&v.attrs, v) {
+            visit::visit_variant(self, v);
+        }
+    }
+
+    fn visit_arm(&mut self, a: &'ast syn::Arm) {
+        if self.keep(&a.attrs, a) {
+            visit::visit_arm(self, a);
+        }
+    }
+
+    fn visit_local(&mut self, l: &'ast syn::Local) {
+        if self.keep(&l.attrs, l) {
+            visit::visit_local(self, l);
+        }
+    }
+}
+
+/// The `--target`/`--cfg-prune` stage: a filter for whole files and a
+/// transform for the code inside them.
+pub struct CfgPrune {
+    root: PathBuf,
+    target: Target,
+    /// relative path → whether the file leaves the snapshot
+    dropped: Mutex<HashMap<PathBuf, bool>>,
+}
+
+impl CfgPrune {
+    pub fn new(root: &Path, target: Target) -> Self {
+        CfgPrune {
+            root: root.to_path_buf(),
+            target,
+            dropped: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether the module in `rel` is compiled out: by its own `#![cfg]`,
+    /// its declaration, or its parent's.
+    fn dropped(&self, rel: &Path) -> bool {
+        if rel.extension().is_none_or(|x| x != "rs") {
+            return false;
+        }
+        if let Some(&known) = self.dropped.lock().unwrap().get(rel) {
+            return known;
+        }
+        let dropped = self
+            .parse(rel)
+            .is_some_and(|file| self.target.excludes(&file.attrs) || self.declaration_dropped(rel));
+        self.dropped
+            .lock()
+            .unwrap()
+            .insert(rel.to_path_buf(), dropped);
+        dropped
+    }
+
+    /// Finds `mod <name>;` in the files that may declare `rel`'s module.
+    fn declaration_dropped(&self, rel: &Path) -> bool {
+        let (Some(parent), Some(stem)) = (rel.parent(), rel.file_stem()) else {
+            return false;
+        };
+        let (dir, name) = if stem == "mod" {
+            match (parent.parent(), parent.file_name()) {
+                (Some(dir), Some(name)) => (dir, name),
+                _ => return false,
+            }
+        } else {
+            (parent, stem)
+        };
+        let mut declaring = vec![dir.join("mod.rs"), dir.with_extension("rs")];
+        if dir.file_name() == Some("src".as_ref()) {
+            declaring = vec![dir.join("lib.rs"), dir.join("main.rs")];
+        }
+        for candidate in declaring.iter().filter(|c| c.as_path() != rel) {
+            let Some(file) = self.parse(candidate) else {
+                continue;
+            };
+            let decl = file.items.iter().find_map(|item| match item {
+                syn::Item::Mod(m) if m.content.is_none() && m.ident == name.to_string_lossy() => {
+                    Some(m)
+                }
+                _ => None,
+            });
+            if let Some(decl) = decl {
+                return self.target.excludes(&decl.attrs) || self.dropped(candidate);
+            }
+        }
+        false
+    }
+
+    fn parse(&self, rel: &Path) -> Option<syn::File> {
+        let src = std::fs::read_to_string(self.root.join(rel)).ok()?;
+        syn::parse_file(&src).ok()
+    }
+}
+
+impl Filter for CfgPrune {
+    fn name(&self) -> &str {
+        "cfg-prune"
+    }
+
+    fn keep(&self, rel: &Path) -> Result<bool> {
+        Ok(!self.dropped(rel))
+    }
+}
+
+impl Transform for CfgPrune {
+    fn name(&self) -> &str {
+        "cfg-prune"
+    }
+
+    fn identity(&self) -> String {
+        let mut set: Vec<_> = self.target.set.iter().collect();
+        set.sort();
+        format!("cfg-prune {set:?}")
+    }
+
+    fn apply(&self, file: &mut FileEntry) -> Result<()> {
+        if file.path.extension().is_some_and(|x| x == "rs") {
+            if let Some(body) = prune(&file.body, &self.target) {
+                file.body = body;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn linux() -> Target {
+        let set = [
+            ("unix", None),
+            ("target_os", Some("linux")),
+            ("target_pointer_width", Some("64")),
+            ("debug_assertions", None),
+        ];
+        Target {
+            set: set
+                .into_iter()
+                .map(|(n, v)| (n.to_string(), v.map(String::from)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn predicates() {
+        let target = linux();
+        let eval = |src: &str| target.eval(&syn::parse_str::<Meta>(src).unwrap());
+        assert_eq!(eval("unix"), Some(true));
+        assert_eq!(eval("windows"), Some(false));
+        assert_eq!(eval("target_os = \"macos\""), Some(false));
+        assert_eq!(eval("not(target_os = \"linux\")"), Some(false));
+        assert_eq!(eval("any(windows, feature = \"x\")"), None);
+        assert_eq!(eval("all(windows, feature = \"x\")"), Some(false));
+        assert_eq!(eval("any(unix, feature = \"x\")"), Some(true));
+        assert_eq!(eval("debug_assertions"), None);
+        assert_eq!(eval("target_feature = \"sse2\""), None);
+    }
+
+    #[test]
+    fn prunes_excluded_nodes_only() {
+        let src = "\
+use std::fs;
+
+#[cfg(windows)]
+mod windows;
+
+/// Unix only.
+#[cfg(unix)]
+fn both() {}
+
+/// Not here.
+#[cfg(target_os = \"macos\")]
+fn mac() {}
+
+#[cfg(feature = \"x\")]
+fn feature() {}
+
+struct S {
+    a: u8,
+    #[cfg(windows)] b: u8,
+}
+
+fn arms(x: u8) {
+    match x {
+        #[cfg(windows)]
+        0 => {}
+        _ => {}
+    }
+}
+";
+        let expected = "\
+use std::fs;
+
+/// Unix only.
+#[cfg(unix)]
+fn both() {}
+
+#[cfg(feature = \"x\")]
+fn feature() {}
+
+struct S {
+    a: u8,
+}
+
+fn arms(x: u8) {
+    match x {
+        _ => {}
+    }
+}
+";
+        assert_eq!(prune(src, &linux()).as_deref(), Some(expected));
+        assert_eq!(prune("#[cfg(unix)]\nfn f() {}\n", &linux()), None);
+        assert_eq!(prune("fn {", &linux()), None);
+    }
+
+    #[test]
+    fn compiled_out_modules_are_dropped() {
+        let root = std::env::temp_dir().join(format!("qp-cfg-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("src/win")).unwrap();
+        let files = [
+            (
+                "src/lib.rs",
+                "#[cfg(windows)]\nmod win;\nmod host;\nmod mac;\n",
+            ),
+            ("src/win/mod.rs", "mod inner;\n"),
+            ("src/win/inner.rs", "fn f() {}\n"),
+            ("src/host.rs", "fn f() {}\n"),
+            ("src/mac.rs", "#![cfg(target_os = \"macos\")]\nfn f() {}\n"),
+        ];
+        for (rel, text) in files {
+            std::fs::write(root.join(rel), text).unwrap();
+        }
+        let prune = CfgPrune::new(&root, linux());
+        for (rel, dropped) in [
+            ("src/lib.rs", false),
+            ("src/win/mod.rs", true),
+            ("src/win/inner.rs", true),
+            ("src/host.rs", false),
+            ("src/mac.rs", true),
+            ("README.md", false),
+        ] {
+            assert_eq!(prune.dropped(Path::new(rel)), dropped, "{rel}");
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
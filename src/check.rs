@@ -0,0 +1,62 @@
+//! `--check` — CI gate: compose the snapshot without emitting it and fail when
+//! it breaks the repository's rules.
+//! * Over the token budget (`--max-tokens`, or `max-tokens` in
+//!   `.cargo-qp.toml`): exit 3.
+//! * A selected file matches a `deny` glob or looks like it contains a
+//!   secret (see `secrets`): exit 6.
+//! * Findings go to stderr, the budget overrun included; nothing is written
+//!   to stdout or the clipboard.
+//! * Secrets are looked for in the bodies as the snapshot emitted them, so
+//!   no file is read twice.
+
+use std::{path::Path, process::ExitCode};
+
+use anyhow::Result;
+
+use crate::{default_mode, exit, tokens, Ctx, Opts};
+
+pub fn run(ctx: &mut Ctx, opts: &Opts) -> Result<ExitCode> {
+    ctx.secrets = Some(Vec::new());
+    default_mode(ctx, opts, &mut std::io::sink())?;
+    let total: usize = ctx.emitted.iter().map(|e| e.tokens).sum();
+
+    let mut violations = 0;
+    let denied: Vec<&Path> = (ctx.emitted.iter())
+        .map(|e| ctx.rel(&e.path))
+        .filter(|rel| ctx.deny.is_match(rel))
+        .collect();
+    for rel in &denied {
+        eprintln!("denied   {}", rel.display());
+        violations += 1;
+    }
+    for (path, line, kind) in ctx.secrets.iter().flatten() {
+        let rel = ctx.rel(path);
+        if !denied.contains(&rel) {
+            eprintln!("secret   {}:{line} ({kind})", rel.display());
+            violations += 1;
+        }
+    }
+
+    let over = ctx.max_tokens.filter(|&max| total > max);
+    let budget = match (ctx.max_tokens, over) {
+        (Some(max), Some(_)) => format!(
+            " / {} budget (over by ~{})",
+            tokens::human(max),
+            tokens::human(total - max)
+        ),
+        (Some(max), None) => format!(" / {} budget", tokens::human(max)),
+        (None, _) => String::new(),
+    };
+    eprintln!(
+        "checked {} files, ~{} tokens{budget}, {violations} violation(s)",
+        ctx.emitted.len(),
+        tokens::human(total)
+    );
+    if violations > 0 {
+        return Ok(ExitCode::from(exit::VIOLATIONS));
+    }
+    if over.is_some() {
+        return Ok(ExitCode::from(exit::OVER_BUDGET));
+    }
+    Ok(ExitCode::SUCCESS)
+}
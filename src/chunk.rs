@@ -0,0 +1,122 @@
+//! Splitting one file into pieces that each fit a token budget.
+//! * `.rs` files are cut only between top-level items (via `syn`), so no
+//!   chunk starts mid-function; comments directly above an item stay with
+//!   it. An `impl`, `trait` or inline `mod` too big for one chunk is cut
+//!   between its inner items; a single item bigger than the budget becomes
+//!   an oversized chunk rather than being cut.
+//! * Other files, and Rust that does not parse, are cut between lines.
+
+use std::path::Path;
+
+use crate::{syntax, tokens};
+
+/// A run of whole lines of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    /// First and last line, 1-based.
+    pub lines: (usize, usize),
+    pub text: String,
+    /// Estimated tokens of `text`.
+    pub tokens: usize,
+}
+
+/// Splits `src` (the body of `path`) into chunks of at most `max_tokens`
+/// each, in order; together they are exactly `src`.
+pub fn chunk_file(path: &Path, src: &str, max_tokens: usize) -> Vec<Chunk> {
+    let starts: Vec<usize> = std::iter::once(0)
+        .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+        .filter(|&i| i < src.len())
+        .collect();
+    let text = |from: usize, to: usize| {
+        let end = starts.get(to).copied().unwrap_or(src.len());
+        &src[starts[from]..end]
+    };
+    let lines: Vec<&str> = (0..starts.len()).map(|i| text(i, i + 1)).collect();
+
+    // pieces: [start, end) line ranges, each with the cuts allowed inside it
+    let items = path
+        .extension()
+        .is_some_and(|x| x == "rs")
+        .then(|| syntax::item_lines(src))
+        .flatten();
+    let mut pieces: Vec<(usize, usize, Vec<usize>)> = match items {
+        Some(items) => {
+            let mut cuts: Vec<(usize, Vec<usize>)> = items
+                .into_iter()
+                .map(|(first, inner)| {
+                    let inner = inner.into_iter().map(|l| lead(&lines, l - 1)).collect();
+                    (lead(&lines, first - 1), inner)
+                })
+                .collect();
+            // the file's preamble (inner docs, `use`s) joins the first item
+            match cuts.first_mut() {
+                Some(first) => first.0 = 0,
+                None => cuts.push((0, Vec::new())),
+            }
+            let ends: Vec<usize> = cuts
+                .iter()
+                .skip(1)
+                .map(|(start, _)| *start)
+                .chain([lines.len()])
+                .collect();
+            cuts.into_iter()
+                .zip(ends)
+                .map(|((start, inner), end)| (start, end, inner))
+                .collect()
+        }
+        None => (0..lines.len()).map(|i| (i, i + 1, Vec::new())).collect(),
+    };
+    pieces.retain(|(start, end, _)| start < end);
+
+    let mut chunks = Vec::new();
+    let mut open: Option<(usize, usize)> = None;
+    let mut push = |start: usize, end: usize, chunks: &mut Vec<Chunk>| {
+        if let Some((from, _)) = open {
+            if tokens::estimate(text(from, end)) <= max_tokens {
+                open = Some((from, end));
+                return;
+            }
+        }
+        if let Some((from, to)) = open.take() {
+            chunks.push(make(text(from, to), from, to));
+        }
+        open = Some((start, end));
+    };
+    for (start, end, inner) in pieces {
+        if tokens::estimate(text(start, end)) <= max_tokens || inner.is_empty() {
+            push(start, end, &mut chunks);
+            continue;
+        }
+        let mut cuts: Vec<usize> = inner
+            .into_iter()
+            .filter(|&l| l > start && l < end)
+            .collect();
+        cuts.dedup();
+        let bounds: Vec<usize> = std::iter::once(start).chain(cuts).chain([end]).collect();
+        for pair in bounds.windows(2) {
+            push(pair[0], pair[1], &mut chunks);
+        }
+    }
+    if let Some((from, to)) = open {
+        chunks.push(make(text(from, to), from, to));
+    }
+    chunks
+}
+
+fn make(text: &str, from: usize, to: usize) -> Chunk {
+    Chunk {
+        lines: (from + 1, to),
+        text: text.to_string(),
+        tokens: tokens::estimate(text),
+    }
+}
+
+/// Moves a cut at line `at` (0-based) up over the `//` comments directly
+/// above it.
+fn lead(lines: &[&str], at: usize) -> usize {
+    let mut at = at.min(lines.len());
+    while at > 0 && lines[at - 1].trim_start().starts_with("//") {
+        at -= 1;
+    }
+    at
+}
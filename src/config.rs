@@ -0,0 +1,251 @@
+//! `.cargo-qp.toml` — per-repository defaults, read from the snapshot root.
+//! * Command-line flags win over config values.
+//! * `[[transform]]` and `[[plugin]]` tables declare external transform hooks
+//!   and WebAssembly plugins (see `transform`); `[preamble]` a local-model
+//!   hook for crate summaries (see `preamble`); `[licenses]` the policy for
+//!   copying dependency source (see `licenses`); `[anonymize]` extra names
+//!   for `--anonymize` (see `anonymize`).
+//! * Without this file, a `repomix.config.json` is read instead (see
+//!   `repomix`).
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Deserializer};
+
+use crate::{repomix, tokens};
+
+pub const FILE_NAME: &str = ".cargo-qp.toml";
+
+/// Paths never included, whatever git or the extension filter say, unless
+/// `--allow-sensitive`; `sensitive` globs extend the list.
+pub const SENSITIVE: &[&str] = &[
+    "**/.env*",
+    "**/*.pem",
+    "**/*.key",
+    "**/*.p12",
+    "**/*.pfx",
+    "**/id_rsa*",
+    "**/id_dsa*",
+    "**/id_ecdsa*",
+    "**/id_ed25519*",
+    "**/secrets/**",
+];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// Extensions to include when none are given on the command line.
+    pub exts: Option<Vec<String>>,
+    /// Globs (relative to the root) that replace the extension filter:
+    /// only matching files, plus manifests, are included.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Globs (relative to the root) that are never included.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Files (relative to the root) always included, like `--extra`.
+    #[serde(default)]
+    pub extra: Vec<PathBuf>,
+    /// Default `--output` for snapshots.
+    pub output: Option<PathBuf>,
+    /// Files larger than this many bytes are skipped.
+    pub max_file_size: Option<u64>,
+    /// Default token budget (`--max-tokens`); token counts here take a
+    /// number or a string like the flags (`"30k"`).
+    #[serde(default, deserialize_with = "token_count")]
+    pub max_tokens: Option<usize>,
+    /// Default `--per-crate-cap`: tokens of full bodies per crate.
+    #[serde(default, deserialize_with = "token_count")]
+    pub per_crate_cap: Option<usize>,
+    /// Per-crate-name overrides of `per-crate-cap` (`[crate-caps]`).
+    #[serde(default, deserialize_with = "token_counts")]
+    pub crate_caps: BTreeMap<String, usize>,
+    /// Ask before copying snapshots larger than this many bytes to the
+    /// clipboard (default 400 KB; 0 never asks).
+    pub confirm_size: Option<usize>,
+    /// Globs that must never reach a snapshot; `--check` fails on them.
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Globs added to the built-in sensitive-path list (`SENSITIVE`).
+    #[serde(default)]
+    pub sensitive: Vec<String>,
+    /// External transforms (`[[transform]]`), run in order.
+    #[serde(default)]
+    pub transform: Vec<TransformHook>,
+    /// WebAssembly filter/transform plugins (`[[plugin]]`), run in order.
+    #[serde(default)]
+    pub plugin: Vec<PluginConfig>,
+    /// Local-model hook for `--preamble` crate summaries (`[preamble]`).
+    pub preamble: Option<PreambleHook>,
+    /// Which dependency licenses `--dep-docs` may copy (`[licenses]`).
+    pub licenses: Option<LicensePolicy>,
+    /// Names `--anonymize` also replaces, and where it keeps the mapping
+    /// (`[anonymize]`).
+    pub anonymize: Option<AnonymizeConfig>,
+}
+
+/// A token count as written in the config: `30000` or `"30k"`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TokenCount {
+    Number(usize),
+    Text(String),
+}
+
+impl TokenCount {
+    fn value<E: serde::de::Error>(self) -> Result<usize, E> {
+        match self {
+            TokenCount::Number(n) => Ok(n),
+            TokenCount::Text(s) => tokens::parse(&s).map_err(E::custom),
+        }
+    }
+}
+
+fn token_count<'de, D: Deserializer<'de>>(d: D) -> Result<Option<usize>, D::Error> {
+    Option::<TokenCount>::deserialize(d)?
+        .map(TokenCount::value)
+        .transpose()
+}
+
+fn token_counts<'de, D: Deserializer<'de>>(d: D) -> Result<BTreeMap<String, usize>, D::Error> {
+    BTreeMap::<String, TokenCount>::deserialize(d)?
+        .into_iter()
+        .map(|(name, n)| Ok((name, n.value()?)))
+        .collect()
+}
+
+/// One `[[transform]]` table.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TransformHook {
+    /// Recorded in the manifest; defaults to the command.
+    pub name: Option<String>,
+    /// Executable, relative to the root if it contains a path separator.
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Only files matching these globs are transformed (all when empty).
+    #[serde(default)]
+    pub include: Vec<String>,
+}
+
+/// The `[preamble]` table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PreambleHook {
+    /// Reads a prompt on stdin, prints the summary; relative to the root if
+    /// it contains a path separator.
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// The `[licenses]` table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LicensePolicy {
+    /// SPDX identifiers whose source may be copied; defaults to
+    /// `licenses::PERMISSIVE`.
+    pub allow: Option<Vec<String>>,
+    #[serde(default)]
+    pub on_violation: Violation,
+}
+
+/// The `[anonymize]` table.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AnonymizeConfig {
+    /// Product or company names, replaced wherever they occur.
+    #[serde(default)]
+    pub identifiers: Vec<String>,
+    /// Mapping file, relative to the root; defaults to
+    /// `anonymize::DEFAULT_FILE`.
+    pub mapping: Option<PathBuf>,
+}
+
+/// What `--dep-docs` does with a dependency outside the allow list.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Violation {
+    /// Fail when it was named, skip it when picked automatically.
+    #[default]
+    Refuse,
+    /// Copy it anyway, with a warning.
+    Warn,
+}
+
+/// One `[[plugin]]` table.
+#[derive(Debug, Deserialize)]
+#[cfg_attr(not(feature = "wasm"), allow(dead_code))]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PluginConfig {
+    /// Defaults to the path.
+    pub name: Option<String>,
+    /// `.wasm` module, relative to the root.
+    pub path: String,
+    /// Only files matching these globs are filtered/transformed (all when
+    /// empty).
+    #[serde(default)]
+    pub include: Vec<String>,
+}
+
+impl Config {
+    /// Reads `<root>/.cargo-qp.toml`, else `<root>/repomix.config.json`;
+    /// with neither, the empty config.
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = root.join(FILE_NAME);
+        if !path.exists() {
+            return Ok(repomix::load(root)?.unwrap_or_default());
+        }
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("invalid {}", path.display()))
+    }
+
+    pub fn exclude_set(&self) -> Result<GlobSet> {
+        glob_set(&self.exclude, "exclude")
+    }
+
+    pub fn deny_set(&self) -> Result<GlobSet> {
+        glob_set(&self.deny, "deny")
+    }
+
+    /// `SENSITIVE` plus the configured `sensitive` globs.
+    pub fn sensitive_patterns(&self) -> Vec<String> {
+        SENSITIVE
+            .iter()
+            .map(|p| p.to_string())
+            .chain(self.sensitive.iter().cloned())
+            .collect()
+    }
+}
+
+pub fn glob_set(patterns: &[String], key: &str) -> Result<GlobSet> {
+    let mut set = GlobSetBuilder::new();
+    for pat in patterns {
+        set.add(Glob::new(pat).with_context(|| format!("invalid {key} glob `{pat}`"))?);
+    }
+    Ok(set.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_counts_accept_suffixes() {
+        let config: Config = toml::from_str(
+            "max-tokens = \"30k\"\nper-crate-cap = 8000\n[crate-caps]\nbig = \"2k\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.max_tokens, Some(30_000));
+        assert_eq!(config.per_crate_cap, Some(8000));
+        assert_eq!(config.crate_caps["big"], 2000);
+        assert!(toml::from_str::<Config>("max-tokens = \"lots\"").is_err());
+    }
+}
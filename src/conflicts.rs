@@ -0,0 +1,45 @@
+//! `--conflicts` — context for resolving a conflicted merge or rebase.
+//! * Only unmerged paths are emitted, working-tree content with markers intact.
+//! * Each is followed by the base/ours/theirs versions from index stages 1-3.
+//! * The usual filters (sensitive paths, globs, members) and transforms apply
+//!   to every version.
+
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::{git, Ctx};
+
+const STAGES: [(&str, &str); 3] = [(":1", "base"), (":2", "ours"), (":3", "theirs")];
+
+pub fn compose(ctx: &mut Ctx, out: &mut dyn Write) -> Result<()> {
+    let names = git::run(
+        &ctx.root,
+        &["diff", "--name-only", "--diff-filter=U", "--relative", "-z"],
+    )?;
+    let mut unmerged = git::nul_separated(&names);
+    unmerged.sort();
+    unmerged.dedup();
+    if unmerged.is_empty() {
+        anyhow::bail!("no conflicted files");
+    }
+    unmerged.retain(|rel| ctx.wants(&ctx.root.join(rel)));
+
+    for rel in &unmerged {
+        let path = ctx.root.join(rel);
+        let body = match std::fs::read(&path) {
+            Ok(bytes) => ctx.decode(&path, bytes)?,
+            Err(_) => "(deleted in working tree)\n".into(),
+        };
+        ctx.push_file(out, &path, Some("conflicted"), &body)?;
+
+        for (stage, name) in STAGES {
+            let body = match git::show_bytes(&ctx.root, stage, rel) {
+                Ok(bytes) => ctx.decode(&path, bytes)?,
+                Err(_) => "(absent at this stage)\n".into(),
+            };
+            ctx.push_file(out, &path, Some(name), &body)?;
+        }
+    }
+    Ok(())
+}
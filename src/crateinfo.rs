@@ -0,0 +1,56 @@
+//! Crate sections for `--order crate|topo`: ahead of each crate's files, its
+//! description, license, edition and `rust-version` from the manifest, so
+//! suggestions can respect the MSRV and edition from the start.
+//! * Fields inherited with `{ workspace = true }` are resolved from the
+//!   root manifest's `[workspace.package]`.
+//! * A missing `edition` is shown as cargo reads it, `2015`; missing
+//!   license, description and `rust-version` are left out.
+//! * Read from the working tree, or from `--rev`.
+
+use std::path::Path;
+
+use crate::Ctx;
+
+/// `[package]` keys shown, in order, with their labels.
+const FIELDS: &[(&str, &str)] = &[
+    ("description", "description"),
+    ("license", "license"),
+    ("edition", "edition"),
+    ("rust-version", "rust-version (MSRV)"),
+];
+
+/// Title and text of the section for the crate in `dir`.
+pub fn section(ctx: &Ctx, dir: &Path) -> Option<(String, String)> {
+    let (name, version) = ctx.crates.get(dir)?;
+    let package = manifest(ctx, &dir.join("Cargo.toml"))?
+        .remove("package")?
+        .as_table()?
+        .clone();
+    let workspace = manifest(ctx, &ctx.root.join("Cargo.toml"))
+        .and_then(|m| m.get("workspace")?.get("package")?.as_table().cloned())
+        .unwrap_or_default();
+
+    let mut text = String::new();
+    for (key, label) in FIELDS {
+        let value = match package.get(*key) {
+            Some(toml::Value::Table(t))
+                if t.get("workspace") == Some(&toml::Value::Boolean(true)) =>
+            {
+                workspace.get(*key)
+            }
+            other => other,
+        };
+        let value = match value.and_then(toml::Value::as_str) {
+            Some(value) => value.split_whitespace().collect::<Vec<_>>().join(" "),
+            None if *key == "edition" => "2015".to_string(),
+            None => continue,
+        };
+        text.push_str(&format!("{label}: {value}\n"));
+    }
+    Some((format!("crate :: {name} v{version}"), text))
+}
+
+fn manifest(ctx: &Ctx, path: &Path) -> Option<toml::Table> {
+    let bytes = ctx.source_bytes(path).ok()?;
+    String::from_utf8(bytes).ok()?.parse().ok()
+}
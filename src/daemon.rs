@@ -0,0 +1,223 @@
+//! `cargo qp daemon` — the `rpc` methods on a Unix socket, with the state a
+//! cold start rebuilds kept warm between requests.
+//! * Listens on `target/qp-daemon.sock` (or `--socket`); each connection
+//!   speaks the same newline-delimited JSON-RPC as `cargo qp rpc`, and
+//!   requests from all connections are handled one at a time.
+//! * Kept warm: `cargo metadata` (rerun once a `Cargo.toml` or `Cargo.lock`
+//!   it read changes), the content-hash cache with raw file bytes (decoded
+//!   and transformed again for every request's own flags), and the last
+//!   commit per file (dropped when `HEAD` moves).
+//! * A crate added under a `members` glob is picked up once the root
+//!   manifest is touched, or after a restart.
+//! * `shutdown` stops the daemon and removes the socket.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Result;
+use cargo_metadata::{Metadata, MetadataCommand};
+
+use crate::{cache::Cache, git, Ctx};
+
+/// Last commit per path for one selection, as gathered by `Ctx`.
+pub type Commits = (HashSet<String>, HashMap<String, git::LastCommit>);
+
+/// Manifests read by `cargo metadata`, with their modification times.
+type Stamps = Vec<(PathBuf, Option<SystemTime>)>;
+
+/// State carried from one daemon request to the next.
+pub struct Warm {
+    metadata: Option<(Stamps, Option<Metadata>)>,
+    cache: Option<Cache>,
+    /// `HEAD` and `--rev` the commits were gathered at
+    commits: Option<(String, Option<String>, Commits)>,
+}
+
+impl Warm {
+    pub fn new() -> Self {
+        Warm {
+            metadata: None,
+            cache: None,
+            commits: None,
+        }
+    }
+
+    /// `cargo metadata` for `root`, rerun only when a manifest changed.
+    pub fn metadata(&mut self, root: &Path) -> Option<Metadata> {
+        if let Some((stamps, metadata)) = &self.metadata {
+            if stamps.iter().all(|(p, t)| modified(p) == *t) {
+                return metadata.clone();
+            }
+        }
+        let metadata = MetadataCommand::new()
+            .manifest_path(root.join("Cargo.toml"))
+            .exec()
+            .ok();
+        let mut manifests = vec![root.join("Cargo.toml"), root.join("Cargo.lock")];
+        if let Some(md) = &metadata {
+            manifests.extend(md.packages.iter().map(|p| p.manifest_path.clone().into()));
+        }
+        let stamps = manifests
+            .into_iter()
+            .map(|p| {
+                let t = modified(&p);
+                (p, t)
+            })
+            .collect();
+        self.metadata = Some((stamps, metadata.clone()));
+        metadata
+    }
+
+    /// The warm cache, or the one on disk for the first request.
+    pub fn cache(&mut self, root: &Path) -> Cache {
+        self.cache.take().unwrap_or_else(|| {
+            let mut cache = Cache::load(root);
+            cache.keep_bodies();
+            cache
+        })
+    }
+
+    /// Commits gathered by an earlier request at the same `HEAD` and `rev`.
+    pub fn commits(&mut self, root: &Path, rev: Option<&str>) -> Option<Commits> {
+        let head = git::run(root, &["rev-parse", "HEAD"]).ok()?;
+        match self.commits.take() {
+            Some((h, r, commits)) if h == head && r.as_deref() == rev => Some(commits),
+            _ => None,
+        }
+    }
+
+    /// Takes the cache and commits back from a finished request.
+    pub fn keep(&mut self, ctx: Ctx) -> Result<()> {
+        let root = ctx.root.clone();
+        let rev = ctx.rev.clone();
+        let (mut cache, commits) = ctx.into_warm();
+        cache.save()?;
+        self.cache = Some(cache);
+        if let Some(commits) = commits {
+            if let Ok(head) = git::run(&root, &["rev-parse", "HEAD"]) {
+                self.commits = Some((head, rev, commits));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(unix)]
+pub fn run(root: &Path, socket: Option<&Path>) -> Result<()> {
+    use std::{
+        io::BufReader,
+        os::unix::net::{UnixListener, UnixStream},
+        sync::{Arc, Mutex},
+    };
+
+    use anyhow::Context;
+
+    let socket = match socket {
+        Some(path) => root.join(path),
+        None => root.join("target").join("qp-daemon.sock"),
+    };
+    if UnixStream::connect(&socket).is_ok() {
+        anyhow::bail!("a daemon is already listening on {}", socket.display());
+    }
+    if let Some(dir) = socket.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let _ = std::fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket)
+        .with_context(|| format!("failed to listen on {}", socket.display()))?;
+    eprintln!("cargo-qp: daemon listening on {}", socket.display());
+
+    let warm = Arc::new(Mutex::new(Warm::new()));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let (root, warm, socket) = (root.to_path_buf(), warm.clone(), socket.clone());
+        std::thread::spawn(move || {
+            let Ok(input) = stream.try_clone() else {
+                return;
+            };
+            match crate::rpc::serve(&root, BufReader::new(input), stream, Some(&warm)) {
+                Ok(true) => {
+                    let _ = std::fs::remove_file(&socket);
+                    std::process::exit(0);
+                }
+                Ok(false) => {}
+                Err(e) => eprintln!("cargo-qp: daemon connection: {e:#}"),
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn run(_root: &Path, _socket: Option<&Path>) -> Result<()> {
+    anyhow::bail!("`cargo qp daemon` needs Unix sockets; use `cargo qp rpc` instead")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{process::Command, sync::Mutex};
+
+    use serde_json::Value;
+
+    use super::*;
+
+    /// A one-crate workspace in a fresh git repository.
+    fn workspace(name: &str) -> std::path::PathBuf {
+        let root = std::env::temp_dir().join(format!("qp-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(
+            root.join("Cargo.toml"),
+            "[package]\nname = \"w\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("src/lib.rs"),
+            "// mail jane@example.com\r\npub fn f() {}\r\n",
+        )
+        .unwrap();
+        Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(&root)
+            .status()
+            .unwrap();
+        root
+    }
+
+    fn request(args: &[&str]) -> String {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0", "id": 1, "method": "snapshot", "params": {"args": args},
+        });
+        format!("{request}\n")
+    }
+
+    #[test]
+    fn warm_cache_reapplies_each_requests_transforms() {
+        let root = workspace("daemon");
+        let warm = Mutex::new(Warm::new());
+        let mut input = request(&[]);
+        input.push_str(&request(&["--scrub-pii", "--normalize-eol"]));
+        input.push_str(&request(&[]));
+        let mut out = Vec::new();
+        crate::rpc::serve(&root, input.as_bytes(), &mut out, Some(&warm)).unwrap();
+        let texts: Vec<String> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| {
+                let response: Value = serde_json::from_str(l).unwrap();
+                response["result"]["text"].as_str().unwrap().to_string()
+            })
+            .collect();
+        assert!(texts[0].contains("jane@example.com\r\n"));
+        assert!(texts[1].contains("mail <email>\npub fn f"));
+        assert_eq!(texts[2], texts[0]);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,284 @@
+//! `--dedupe` — emit repeated content once.
+//! * `exact` (default): a byte-identical later file gets an
+//!   `[identical to <first>]` header and no body.
+//! * `near`: additionally, a file that mostly matches an earlier file of the
+//!   same name, or any earlier file whose MinHash sketch is close (modules
+//!   copy-pasted across crates), is emitted as a `[diff vs <first>]` patch
+//!   when that is much shorter than the body.
+//! * Sketches hash 3-line shingles of trimmed lines; candidate pairs come
+//!   from LSH banding, so files are not compared all against all.
+//! * Small files are always emitted in full; a reference wouldn't save much.
+//! * Budgets and caps may leave out the original or cut it to signatures;
+//!   `rebase` then gives its first surviving copy the full body, and later
+//!   identical copies refer to that one instead.
+//! * `similar_pairs` also feeds `cargo qp stats`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+};
+
+use clap::ValueEnum;
+
+use crate::{textdiff, Ctx};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Dedupe {
+    /// Emit every body
+    Off,
+    /// Emit byte-identical bodies once, then reference the first
+    Exact,
+    /// Also emit a look-alike as a diff against an earlier file when that
+    /// is much shorter
+    Near,
+}
+
+/// Bodies shorter than this are never replaced by a reference.
+const MIN_BYTES: usize = 256;
+/// Hash functions per sketch, split into `BANDS` bands for LSH.
+const HASHES: usize = 64;
+const BANDS: usize = 16;
+/// Lines per shingle.
+const SHINGLE: usize = 3;
+/// Estimated similarity for a diff to be worth trying.
+const NEAR: f64 = 0.5;
+
+/// MinHash signature of a body.
+struct Sketch([u64; HASHES]);
+
+/// For each of `files`, the `(tag, body)` to emit instead of the full body,
+/// if it repeats an earlier file.
+pub fn references(
+    ctx: &Ctx,
+    files: &[(PathBuf, String)],
+    mode: Dedupe,
+) -> Vec<Option<(String, String)>> {
+    let mut first_by_body: HashMap<&str, usize> = HashMap::new();
+    let mut by_name: HashMap<&std::ffi::OsStr, Vec<usize>> = HashMap::new();
+    let mut out = Vec::with_capacity(files.len());
+    let mut similar: HashMap<usize, Vec<usize>> = HashMap::new();
+    if mode == Dedupe::Near {
+        let bodies: Vec<&str> = files.iter().map(|(_, b)| b.as_str()).collect();
+        for (a, b, _) in similar_pairs(&bodies, NEAR) {
+            similar.entry(b).or_default().push(a);
+        }
+    }
+
+    for (i, (path, body)) in files.iter().enumerate() {
+        if mode == Dedupe::Off || body.len() < MIN_BYTES {
+            out.push(None);
+            continue;
+        }
+        if let Some(&first) = first_by_body.get(body.as_str()) {
+            let tag = format!("identical to {}", ctx.rel(&files[first].0).display());
+            out.push(Some((tag, String::new())));
+            continue;
+        }
+        first_by_body.insert(body, i);
+
+        let name = path.file_name().unwrap_or_default();
+        let near = (mode == Dedupe::Near)
+            .then(|| {
+                let mut candidates = by_name.get(name).cloned().unwrap_or_default();
+                candidates.extend(similar.get(&i).into_iter().flatten());
+                near_duplicate(ctx, files, &candidates, i)
+            })
+            .flatten();
+        out.push(near);
+        by_name.entry(name).or_default().push(i);
+    }
+    out
+}
+
+/// The path a `references` tag points at.
+fn target(tag: &str) -> Option<&str> {
+    tag.strip_prefix("identical to ")
+        .or_else(|| tag.strip_prefix("diff vs "))
+}
+
+/// Repoints the references among the `kept` files whose original is no
+/// longer emitted in full: the first identical copy takes the original's
+/// place, later ones refer to it, and diffs fall back to their full body.
+/// Returns the files that lost their reference, with the reference.
+pub fn rebase(
+    ctx: &Ctx,
+    files: &[(PathBuf, String)],
+    refs: &mut [Option<(String, String)>],
+    kept: &[bool],
+) -> Vec<(usize, (String, String))> {
+    let index: HashMap<String, usize> = files
+        .iter()
+        .enumerate()
+        .map(|(i, (p, _))| (ctx.rel(p).display().to_string(), i))
+        .collect();
+    let full = |refs: &[Option<(String, String)>], j: usize| kept[j] && refs[j].is_none();
+    // original → the copy standing in for it
+    let mut stand_in: HashMap<usize, usize> = HashMap::new();
+    let mut promoted = Vec::new();
+    for i in 0..files.len() {
+        let Some((tag, _)) = refs[i].as_ref().filter(|_| kept[i]) else {
+            continue;
+        };
+        let Some(&j) = target(tag).and_then(|rel| index.get(rel)) else {
+            continue;
+        };
+        if full(refs, j) {
+            continue;
+        }
+        let identical = tag.starts_with("identical to ");
+        match stand_in.get(&j) {
+            Some(&s) if identical && full(refs, s) => {
+                let tag = format!("identical to {}", ctx.rel(&files[s].0).display());
+                refs[i] = Some((tag, String::new()));
+            }
+            _ => {
+                if identical {
+                    stand_in.insert(j, i);
+                }
+                promoted.push((i, refs[i].take().unwrap()));
+            }
+        }
+    }
+    promoted
+}
+
+/// A patch against the earlier candidate it resembles most, if that patch
+/// is under half the size of the body.
+fn near_duplicate(
+    ctx: &Ctx,
+    files: &[(PathBuf, String)],
+    candidates: &[usize],
+    i: usize,
+) -> Option<(String, String)> {
+    let (path, body) = &files[i];
+    candidates
+        .iter()
+        .filter(|&&j| {
+            let len = files[j].1.len();
+            len * 5 >= body.len() * 4 && body.len() * 5 >= len * 4
+        })
+        .map(|&j| {
+            let (base, base_body) = &files[j];
+            let (old, new) = (ctx.rel(base).display(), ctx.rel(path).display());
+            let patch =
+                textdiff::unified(&format!("a/{old}"), &format!("b/{new}"), base_body, body);
+            (format!("diff vs {old}"), patch)
+        })
+        .filter(|(_, patch)| patch.len() * 2 < body.len())
+        .min_by_key(|(_, patch)| patch.len())
+}
+
+/// Pairs `(earlier, later, similarity)` of non-identical bodies of at least
+/// `MIN_BYTES` whose estimated similarity is `threshold` or more, most
+/// similar first.
+pub fn similar_pairs(bodies: &[&str], threshold: f64) -> Vec<(usize, usize, f64)> {
+    let sketches: Vec<Option<Sketch>> = bodies
+        .iter()
+        .map(|b| (b.len() >= MIN_BYTES).then(|| sketch(b)).flatten())
+        .collect();
+    let rows = HASHES / BANDS;
+    let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+    for (i, sketch) in sketches.iter().enumerate() {
+        let Some(sketch) = sketch else {
+            continue;
+        };
+        for (band, chunk) in sketch.0.chunks(rows).enumerate() {
+            buckets.entry((band, hash(&chunk))).or_default().push(i);
+        }
+    }
+    let mut seen = HashSet::new();
+    let mut pairs = Vec::new();
+    for members in buckets.values() {
+        for (n, &a) in members.iter().enumerate() {
+            for &b in &members[n + 1..] {
+                if !seen.insert((a, b)) || bodies[a] == bodies[b] {
+                    continue;
+                }
+                let (Some(sa), Some(sb)) = (&sketches[a], &sketches[b]) else {
+                    continue;
+                };
+                let same = sa.0.iter().zip(&sb.0).filter(|(x, y)| x == y).count();
+                let similarity = same as f64 / HASHES as f64;
+                if similarity >= threshold {
+                    pairs.push((a, b, similarity));
+                }
+            }
+        }
+    }
+    pairs.sort_by(|x, y| y.2.total_cmp(&x.2).then((x.0, x.1).cmp(&(y.0, y.1))));
+    pairs
+}
+
+/// `None` for bodies with fewer than `SHINGLE` non-blank lines.
+fn sketch(body: &str) -> Option<Sketch> {
+    let lines: Vec<&str> = body
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    if lines.len() < SHINGLE {
+        return None;
+    }
+    let mut mins = [u64::MAX; HASHES];
+    for shingle in lines.windows(SHINGLE) {
+        let base = hash(&shingle);
+        for (seed, min) in mins.iter_mut().enumerate() {
+            *min = (*min).min(mix(base ^ (seed as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)));
+        }
+    }
+    Some(Sketch(mins))
+}
+
+fn hash(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// splitmix64 finaliser: one cheap hash function per seed.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(seed: usize, lines: usize) -> String {
+        (0..lines)
+            .map(|n| format!("    let value_{n} = compute({n}, {});\n", n * seed))
+            .collect()
+    }
+
+    #[test]
+    fn near_copies_pair_up() {
+        let original = module(1, 40);
+        let mut edited = original.replace("value_7 ", "renamed_7 ");
+        edited.push_str("    finish();\n");
+        let unrelated = module(3, 40);
+        let pairs = similar_pairs(&[&original, &unrelated, &edited, &original], NEAR);
+        assert_eq!(pairs.len(), 2, "{pairs:?}");
+        assert!(pairs
+            .iter()
+            .all(|&(a, b, s)| { [(0, 2), (2, 3)].contains(&(a, b)) && (NEAR..1.0).contains(&s) }));
+    }
+
+    #[test]
+    fn small_and_identical_bodies_are_not_pairs() {
+        let body = module(1, 40);
+        let small = "a\nb\nc\n";
+        assert!(similar_pairs(&[&body, &body], 0.0).is_empty());
+        assert!(similar_pairs(&[small, "a\nb\nc\nd\n"], 0.0).is_empty());
+        assert!(sketch("one\n\n  \ntwo\n").is_none());
+    }
+
+    #[test]
+    fn targets() {
+        assert_eq!(target("identical to a/src/lib.rs"), Some("a/src/lib.rs"));
+        assert_eq!(target("diff vs b/src/lib.rs"), Some("b/src/lib.rs"));
+        assert_eq!(target("signatures only"), None);
+    }
+}
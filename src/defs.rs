@@ -0,0 +1,154 @@
+//! `--pull-defs` — definitions of the types and traits the snapshot uses but
+//! doesn't contain, appended after the files.
+//! * `--resolver syn` (default): names are the capitalised path segments of
+//!   the emitted `.rs` files, matched by name against every definition; a
+//!   name defined in the snapshot itself is not looked up.
+//! * `--resolver rust-analyzer`: each first use is resolved to its definition
+//!   (see `analyzer`), which follows re-exports, renames and macros.
+//! * Definitions are searched in every workspace crate, selected or not, and
+//!   emitted per file as just the defining items; `exclude` globs still apply.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use clap::ValueEnum;
+
+use crate::{
+    analyzer::Analyzer,
+    git,
+    syntax::{self, Definition},
+    Ctx,
+};
+
+/// `--resolver`
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Resolver {
+    /// Match names textually with syn
+    Syn,
+    /// Ask a rust-analyzer process
+    RustAnalyzer,
+}
+
+/// Definitions found per file, keyed by first line.
+type Found = BTreeMap<PathBuf, BTreeMap<usize, Definition>>;
+
+pub fn append(ctx: &mut Ctx, out: &mut dyn Write, resolver: Resolver) -> Result<()> {
+    let mut emitted: Vec<PathBuf> = ctx
+        .emitted
+        .iter()
+        .filter(|e| e.path.extension().is_some_and(|x| x == "rs"))
+        .map(|e| e.path.clone())
+        .collect();
+    emitted.sort();
+    emitted.dedup();
+    let found = match resolver {
+        Resolver::Syn => by_name(ctx, &emitted)?,
+        Resolver::RustAnalyzer => by_analyzer(ctx, &emitted)?,
+    };
+    for (path, defs) in found {
+        let names: Vec<&str> = defs.values().map(|d| d.name.as_str()).collect();
+        let tag = format!("definitions: {}", names.join(", "));
+        let texts: Vec<&str> = defs.values().map(|d| d.text.as_str()).collect();
+        ctx.push_file(out, &path, Some(&tag), &format!("{}\n", texts.join("\n\n")))?;
+    }
+    Ok(())
+}
+
+/// Whether definitions in `path` may be pulled in.
+fn searchable(ctx: &Ctx, path: &Path) -> bool {
+    path.starts_with(&ctx.root)
+        && !ctx.excludes.is_match(ctx.rel(path))
+        && ctx.crate_dir(path).is_some()
+}
+
+fn by_name(ctx: &Ctx, emitted: &[PathBuf]) -> Result<Found> {
+    let mut wanted = BTreeSet::new();
+    let mut defined = HashSet::new();
+    for path in emitted {
+        let src = ctx.read(path)?;
+        wanted.extend(
+            syntax::referenced_types(&src)
+                .unwrap_or_default()
+                .into_keys(),
+        );
+        for def in syntax::definitions(&src).unwrap_or_default() {
+            defined.insert(def.name);
+        }
+    }
+    wanted.retain(|name| !defined.contains(name));
+    let mut found = Found::new();
+    if wanted.is_empty() {
+        return Ok(found);
+    }
+
+    let files = match &ctx.rev {
+        Some(rev) => git::ls_tree(&ctx.root, rev)?,
+        None => git::ls_files(&ctx.root)?,
+    };
+    let candidates = files
+        .into_iter()
+        .filter(|rel| rel.ends_with(".rs"))
+        .map(|rel| ctx.root.join(rel))
+        .filter(|p| !emitted.contains(p) && searchable(ctx, p));
+    for path in candidates {
+        let Ok(src) = ctx.read(&path) else {
+            continue;
+        };
+        if !wanted.iter().any(|name| src.contains(name.as_str())) {
+            continue;
+        }
+        for def in syntax::definitions(&src).unwrap_or_default() {
+            if wanted.contains(&def.name) {
+                found
+                    .entry(path.clone())
+                    .or_default()
+                    .insert(def.lines.0, def);
+            }
+        }
+    }
+    Ok(found)
+}
+
+fn by_analyzer(ctx: &Ctx, emitted: &[PathBuf]) -> Result<Found> {
+    if ctx.rev.is_some() {
+        anyhow::bail!("--resolver rust-analyzer works on the working tree; drop --rev");
+    }
+    let mut ra = Analyzer::start(&ctx.root)?;
+    let mut parsed: HashMap<PathBuf, Vec<Definition>> = HashMap::new();
+    let mut found = Found::new();
+    for path in emitted {
+        let src = std::fs::read_to_string(path)?;
+        for at in syntax::referenced_types(&src)
+            .unwrap_or_default()
+            .into_values()
+        {
+            for loc in ra.definition(path, &src, at)? {
+                let target = loc.path;
+                if emitted.contains(&target) || !searchable(ctx, &target) {
+                    continue;
+                }
+                let defs = parsed.entry(target.clone()).or_insert_with(|| {
+                    let src = std::fs::read_to_string(&target).unwrap_or_default();
+                    syntax::definitions(&src).unwrap_or_default()
+                });
+                // innermost item containing the definition site
+                let Some(def) = defs
+                    .iter()
+                    .filter(|d| (d.lines.0..=d.lines.1).contains(&loc.line))
+                    .min_by_key(|d| d.lines.1 - d.lines.0)
+                else {
+                    continue;
+                };
+                found
+                    .entry(target)
+                    .or_default()
+                    .insert(def.lines.0, def.clone());
+            }
+        }
+    }
+    Ok(found)
+}
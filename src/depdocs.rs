@@ -0,0 +1,144 @@
+//! `--dep-docs[=NAME,…]` — the public API of dependencies, appended as one
+//! section per dependency: `pub` items with the first line of their docs and
+//! no function bodies (see `syntax::api`).
+//! * Sources come from cargo's registry/git checkouts via `cargo metadata`,
+//!   so no docs build (and no nightly rustdoc JSON) is needed.
+//! * Without names, the `AUTO_DEPS` direct dependencies named in the most
+//!   emitted `.rs` files are documented.
+//! * Each dependency's license must pass the `[licenses]` policy first (see
+//!   `licenses`).
+
+use std::{collections::HashMap, io::Write, path::Path};
+
+use anyhow::{Context, Result};
+use cargo_metadata::{Metadata, MetadataCommand, Package};
+
+use crate::{licenses, syntax, Ctx};
+
+/// Dependencies picked when none are named.
+const AUTO_DEPS: usize = 3;
+
+pub fn append(ctx: &mut Ctx, out: &mut dyn Write, names: &[String]) -> Result<()> {
+    let md = MetadataCommand::new()
+        .manifest_path(ctx.root.join("Cargo.toml"))
+        .exec()
+        .context("cargo metadata failed (needed for --dep-docs)")?;
+    let named = !names.is_empty();
+    let names = if named {
+        names.to_vec()
+    } else {
+        most_used(ctx, &md)
+    };
+    for name in &names {
+        let Some(pkg) = md
+            .packages
+            .iter()
+            .filter(|p| p.name == *name && !md.workspace_members.contains(&p.id))
+            .max_by(|a, b| a.version.cmp(&b.version))
+        else {
+            eprintln!("warning: --dep-docs: `{name}` is not a dependency");
+            continue;
+        };
+        if !licenses::check(&ctx.licenses, pkg, named)? {
+            continue;
+        }
+        let text = api(pkg)?;
+        ctx.push_section(
+            out,
+            &format!("dependency API :: {} v{}", pkg.name, pkg.version),
+            &text,
+        )?;
+    }
+    Ok(())
+}
+
+/// Direct dependencies of workspace members, by how many emitted files name
+/// them.
+fn most_used(ctx: &Ctx, md: &Metadata) -> Vec<String> {
+    let mut deps: Vec<String> = md
+        .workspace_packages()
+        .iter()
+        .flat_map(|p| &p.dependencies)
+        .map(|d| d.name.clone())
+        .filter(|name| md.workspace_packages().iter().all(|p| p.name != *name))
+        .collect();
+    deps.sort();
+    deps.dedup();
+
+    let sources: Vec<String> = ctx
+        .emitted
+        .iter()
+        .filter(|e| e.path.extension().is_some_and(|x| x == "rs"))
+        .filter_map(|e| ctx.read(&e.path).ok())
+        .collect();
+    let mut uses: HashMap<&str, usize> = HashMap::new();
+    for dep in &deps {
+        let ident = dep.replace('-', "_");
+        let n = sources
+            .iter()
+            .filter(|src| syntax::mentions(src, &ident))
+            .count();
+        if n > 0 {
+            uses.insert(dep, n);
+        }
+    }
+    let mut ranked: Vec<(&str, usize)> = uses.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    ranked
+        .into_iter()
+        .take(AUTO_DEPS)
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// The API view of every `.rs` file under the library target's directory.
+fn api(pkg: &Package) -> Result<String> {
+    let lib = pkg
+        .targets
+        .iter()
+        .find(|t| {
+            t.kind
+                .iter()
+                .any(|k| k.ends_with("lib") || k == "proc-macro")
+        })
+        .with_context(|| format!("{} has no library target", pkg.name))?;
+    let src_dir = lib.src_path.parent().context("library path")?.as_std_path();
+    let pkg_dir = pkg
+        .manifest_path
+        .parent()
+        .context("manifest path")?
+        .as_std_path();
+
+    let mut files = Vec::new();
+    collect_rs(src_dir, &mut files);
+    files.sort();
+    let mut text = String::new();
+    for file in files {
+        let Some(view) = std::fs::read_to_string(&file)
+            .ok()
+            .and_then(|src| syntax::api(&src))
+        else {
+            continue;
+        };
+        if view.trim().is_empty() {
+            continue;
+        }
+        let rel = file.strip_prefix(pkg_dir).unwrap_or(&file);
+        text.push_str(&format!("// {}\n{view}\n", rel.display()));
+    }
+    Ok(text)
+}
+
+fn collect_rs(dir: &Path, files: &mut Vec<std::path::PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_rs(&path, files);
+        } else if path.extension().is_some_and(|x| x == "rs") {
+            files.push(path);
+        }
+    }
+}
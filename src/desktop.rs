@@ -0,0 +1,38 @@
+//! `--notify` — a desktop notification when the snapshot is ready, for watch
+//! mode and long runs started before switching windows.
+//! * `cargo qp watch` notifies after every regeneration; other runs only
+//!   when they took longer than `SLOW`.
+//! * Needs the `desktop-notify` feature (on by default); an unreachable
+//!   notification service only prints a warning.
+
+use std::time::Duration;
+
+use crate::tokens;
+
+/// One-shot runs faster than this finish before anyone switches away.
+pub const SLOW: Duration = Duration::from_secs(5);
+
+/// "snapshot updated (82k tokens) and copied"
+pub fn snapshot_ready(tokens: usize, copied: bool) {
+    let mut body = format!("snapshot updated ({} tokens)", tokens::human(tokens));
+    if copied {
+        body.push_str(" and copied");
+    }
+    show(&body);
+}
+
+#[cfg(feature = "desktop-notify")]
+fn show(body: &str) {
+    let shown = notify_rust::Notification::new()
+        .summary("cargo qp")
+        .body(body)
+        .show();
+    if let Err(e) = shown {
+        eprintln!("warning: desktop notification failed: {e}");
+    }
+}
+
+#[cfg(not(feature = "desktop-notify"))]
+fn show(_: &str) {
+    eprintln!("warning: --notify needs cargo-qp built with the `desktop-notify` feature");
+}
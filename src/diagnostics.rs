@@ -0,0 +1,159 @@
+//! `cargo qp check-context` and `--from-clippy` — "here's my error and the
+//! relevant code".
+//! * Runs `cargo check` (or `cargo clippy`) `--message-format=json` with the
+//!   member selection,
+//!   collects every file a diagnostic points at (primary spans, related spans
+//!   and spans of attached notes), and emits exactly those files.
+//! * The rendered diagnostics follow as the last section.
+//! * `--from-clippy=LINT` keeps only that lint's diagnostics (`LINT` with or
+//!   without the `clippy::` prefix).
+//! * Files outside the root (dependencies, the standard library) are skipped;
+//!   `exclude` globs still apply, the extension filter doesn't.
+
+use std::{
+    collections::BTreeSet,
+    io::Write,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{Ctx, Opts};
+
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<Diagnostic>,
+}
+
+/// The cargo command whose diagnostics to collect.
+pub enum Tool<'a> {
+    Check,
+    Clippy { lint: Option<&'a str> },
+}
+
+#[derive(Deserialize)]
+struct Diagnostic {
+    level: String,
+    code: Option<Code>,
+    rendered: Option<String>,
+    #[serde(default)]
+    spans: Vec<Span>,
+    #[serde(default)]
+    children: Vec<Diagnostic>,
+}
+
+#[derive(Deserialize)]
+struct Code {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct Span {
+    file_name: String,
+}
+
+impl Diagnostic {
+    fn files(&self, into: &mut BTreeSet<String>) {
+        into.extend(self.spans.iter().map(|s| s.file_name.clone()));
+        for child in &self.children {
+            child.files(into);
+        }
+    }
+
+    fn is_lint(&self, lint: &str) -> bool {
+        let lint = lint.strip_prefix("clippy::").unwrap_or(lint);
+        self.code
+            .as_ref()
+            .is_some_and(|c| c.code == lint || c.code.strip_prefix("clippy::") == Some(lint))
+    }
+}
+
+pub fn compose(
+    ctx: &mut Ctx,
+    out: &mut dyn Write,
+    opts: &Opts,
+    tool: Tool,
+    args: &[String],
+) -> Result<()> {
+    let (command, lint) = match tool {
+        Tool::Check => ("check", None),
+        Tool::Clippy { lint } => ("clippy", lint),
+    };
+    if ctx.rev.is_some() {
+        anyhow::bail!("cargo {command} works on the working tree; drop --rev");
+    }
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut cmd = Command::new(cargo);
+    cmd.args([command, "--message-format=json"])
+        .current_dir(&ctx.root)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if opts.workspace {
+        cmd.arg("--workspace");
+    }
+    for name in &opts.exclude {
+        cmd.args(["--exclude", name]);
+    }
+    let output = cmd
+        .args(args)
+        .output()
+        .with_context(|| format!("failed to run cargo {command}"))?;
+
+    let mut rendered = Vec::new();
+    let mut files = BTreeSet::new();
+    let (mut errors, mut warnings) = (0, 0);
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Ok(msg) = serde_json::from_str::<CargoMessage>(line) else {
+            continue;
+        };
+        let Some(diag) = msg.message.filter(|_| msg.reason == "compiler-message") else {
+            continue;
+        };
+        let Some(text) = &diag.rendered else {
+            continue;
+        };
+        if lint.is_some_and(|lint| !diag.is_lint(lint)) {
+            continue;
+        }
+        // cargo replays cached warnings for every target that shares a file
+        if rendered.contains(text) {
+            continue;
+        }
+        match diag.level.as_str() {
+            "error" | "error: internal compiler error" => errors += 1,
+            "warning" => warnings += 1,
+            _ => {}
+        }
+        rendered.push(text.clone());
+        diag.files(&mut files);
+    }
+    if rendered.is_empty() && !output.status.success() {
+        anyhow::bail!(
+            "cargo {command} failed without diagnostics: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let paths: Vec<PathBuf> = files
+        .iter()
+        .filter(|f| !Path::new(f).components().any(|c| c == Component::ParentDir))
+        .map(|f| ctx.root.join(f))
+        .filter(|p| p.starts_with(&ctx.root) && p.is_file() && ctx.allowed(p))
+        .collect();
+    let bodies = ctx.read_all(&paths)?;
+    for (path, body) in paths.iter().zip(&bodies) {
+        ctx.push_file(out, path, None, body)?;
+    }
+
+    let title = format!("cargo {command} :: {errors} error(s), {warnings} warning(s)");
+    let text = if rendered.is_empty() {
+        "no diagnostics\n".to_string()
+    } else {
+        rendered.concat()
+    };
+    ctx.push_section(out, &title, &text)?;
+    Ok(())
+}
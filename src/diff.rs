@@ -0,0 +1,105 @@
+//! `cargo qp diff <BASE> [HEAD]` — unified diffs instead of file bodies.
+//! * Without HEAD the diff is against the working tree.
+//! * Each file's patch gets the usual `=== crate vX :: path ===` header so the
+//!   reader still knows which crate a hunk belongs to.
+//! * Patches are built from both sides' decoded bodies, so transforms
+//!   (`--scrub-pii`, `[[transform]]` hooks, plugins) see the changed lines
+//!   as they see whole files.
+//! * `--context` follows the patches with the full current content of every
+//!   changed file and a signatures-only view of the unchanged ones.
+//! * `Cargo.lock` is summarised (added/removed/upgraded packages) after the
+//!   patches rather than diffed.
+
+use std::{collections::HashSet, io::Write};
+
+use anyhow::Result;
+
+use crate::{git, lockdiff, textdiff, Ctx};
+
+pub fn compose(
+    ctx: &mut Ctx,
+    out: &mut dyn Write,
+    base: &str,
+    head: Option<&str>,
+    context: bool,
+) -> Result<()> {
+    let changed: Vec<String> = git::changed_files(&ctx.root, base, head)?
+        .into_iter()
+        .filter(|rel| !lockdiff::is_lockfile(rel) && ctx.wants(&ctx.root.join(rel)))
+        .collect();
+
+    for rel in &changed {
+        let old = current_content(ctx, Some(base), rel)?;
+        let new = current_content(ctx, head, rel)?;
+        let name = |side: &Option<String>, prefix| match side {
+            Some(_) => format!("{prefix}/{rel}"),
+            None => "/dev/null".to_string(),
+        };
+        let patch = textdiff::unified(
+            &name(&old, "a"),
+            &name(&new, "b"),
+            old.as_deref().unwrap_or_default(),
+            new.as_deref().unwrap_or_default(),
+        );
+        if patch.is_empty() {
+            continue;
+        }
+        let tag = context.then_some("diff");
+        ctx.push_file(out, &ctx.root.join(rel), tag, &patch)?;
+    }
+    lockdiff::append(ctx, out, base, head)?;
+    if !context {
+        return Ok(());
+    }
+
+    // changed files in full, as of HEAD (or the working tree)
+    for rel in &changed {
+        let Some(body) = current_content(ctx, head, rel)? else {
+            continue; // deleted
+        };
+        ctx.push_file(out, &ctx.root.join(rel), None, &body)?;
+    }
+
+    // everything else the filters select, signatures-only
+    let changed: HashSet<&str> = changed.iter().map(String::as_str).collect();
+    let mut rest: Vec<String> = match head {
+        Some(rev) => git::ls_tree(&ctx.root, rev)?,
+        None => git::ls_files(&ctx.root)?,
+    };
+    rest.retain(|rel| !changed.contains(rel.as_str()) && ctx.wants(&ctx.root.join(rel)));
+    rest.sort();
+    for rel in &rest {
+        let Some(body) = current_content(ctx, head, rel)? else {
+            continue;
+        };
+        let path = ctx.root.join(rel);
+        match rel
+            .ends_with(".rs")
+            .then(|| ctx.signatures(&body))
+            .flatten()
+        {
+            Some(sigs) => ctx.push_file(out, &path, Some("signatures"), &sigs)?,
+            None => ctx.push_file(out, &path, None, &body)?,
+        }
+    }
+    Ok(())
+}
+
+/// Decoded file content at `head`, or in the working tree; `None` if it
+/// doesn't exist.
+fn current_content(ctx: &Ctx, head: Option<&str>, rel: &str) -> Result<Option<String>> {
+    match head {
+        Some(rev) => match git::show_bytes(&ctx.root, rev, rel) {
+            Ok(bytes) => Ok(Some(ctx.decode(&ctx.root.join(rel), bytes)?)),
+            Err(_) => Ok(None),
+        },
+        None => {
+            let path = ctx.root.join(rel);
+            if path.is_file() {
+                Ok(Some(ctx.decode(&path, std::fs::read(&path)?)?))
+            } else {
+                Ok(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! `cargo qp doctor` — environment diagnostics with actionable fixes.
+
+use std::{path::Path, process::Command};
+
+use arboard::Clipboard;
+use cargo_metadata::MetadataCommand;
+
+use crate::{config, git, repomix, tokens};
+
+enum Level {
+    Ok,
+    Warn,
+    Fail,
+}
+
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn line(&mut self, level: Level, what: &str, detail: &str) {
+        let tag = match level {
+            Level::Ok => "ok",
+            Level::Warn => "warn",
+            Level::Fail => {
+                self.failures += 1;
+                "FAIL"
+            }
+        };
+        println!("[{tag:>4}] {what}: {detail}");
+    }
+}
+
+pub fn run(root: &Path) -> anyhow::Result<()> {
+    let mut r = Report { failures: 0 };
+
+    // git
+    match tool_version("git") {
+        Some(v) => r.line(Level::Ok, "git", &v),
+        None => r.line(Level::Fail, "git", "not found on PATH — install git"),
+    }
+    match git::toplevel(root) {
+        Ok(top) => r.line(Level::Ok, "worktree", &top.display().to_string()),
+        Err(_) => r.line(
+            Level::Fail,
+            "worktree",
+            "not inside a git repository — run from a checkout or pass --dir",
+        ),
+    }
+
+    // cargo
+    match MetadataCommand::new()
+        .manifest_path(root.join("Cargo.toml"))
+        .no_deps()
+        .exec()
+    {
+        Ok(md) => r.line(
+            Level::Ok,
+            "cargo metadata",
+            &format!("{} workspace member(s)", md.workspace_members.len()),
+        ),
+        Err(e) => r.line(
+            Level::Warn,
+            "cargo metadata",
+            &format!("{e} — crate headers fall back to manifest parsing"),
+        ),
+    }
+
+    // clipboard
+    clipboard(&mut r);
+
+    // --check-published
+    match tool_version("curl") {
+        Some(v) => r.line(Level::Ok, "curl", &v),
+        None => r.line(
+            Level::Warn,
+            "curl",
+            "not found — --check-published will only use the local index cache",
+        ),
+    }
+
+    // tokens
+    r.line(
+        Level::Ok,
+        "tokenizer",
+        &format!(
+            "built-in estimate (~4 bytes/token, e.g. \"fn main() {{}}\" ≈ {}), no data files needed",
+            tokens::estimate("fn main() {}")
+        ),
+    );
+
+    // config
+    let path = root.join(config::FILE_NAME);
+    if !path.exists() && root.join(repomix::FILE_NAME).exists() {
+        match config::Config::load(root).and_then(|c| c.exclude_set().map(|_| c)) {
+            Ok(_) => r.line(
+                Level::Warn,
+                "config",
+                &format!(
+                    "using {} (run `cargo qp init` for a native {})",
+                    repomix::FILE_NAME,
+                    config::FILE_NAME
+                ),
+            ),
+            Err(e) => r.line(Level::Fail, "config", &format!("{e:#}")),
+        }
+    } else if !path.exists() {
+        r.line(
+            Level::Ok,
+            "config",
+            &format!(
+                "no {} (run `cargo qp init` to create one)",
+                config::FILE_NAME
+            ),
+        );
+    } else {
+        match config::Config::load(root).and_then(|c| c.exclude_set().map(|_| c)) {
+            Ok(_) => r.line(Level::Ok, "config", &path.display().to_string()),
+            Err(e) => r.line(Level::Fail, "config", &format!("{e:#}")),
+        }
+    }
+
+    if r.failures > 0 {
+        anyhow::bail!("{} check(s) failed", r.failures);
+    }
+    Ok(())
+}
+
+fn tool_version(bin: &str) -> Option<String> {
+    let out = Command::new(bin).arg("--version").output().ok()?;
+    let text = String::from_utf8_lossy(&out.stdout);
+    out.status
+        .success()
+        .then(|| text.lines().next().unwrap_or_default().trim().to_string())
+}
+
+fn clipboard(r: &mut Report) {
+    let env = |k: &str| std::env::var_os(k).is_some_and(|v| !v.is_empty());
+    let ssh = env("SSH_CONNECTION") || env("SSH_TTY");
+    if cfg!(target_os = "linux") {
+        match (env("DISPLAY"), env("WAYLAND_DISPLAY")) {
+            (false, true) => r.line(
+                Level::Warn,
+                "display",
+                "Wayland without XWayland (DISPLAY unset) — the X11 clipboard backend cannot connect",
+            ),
+            (false, false) => r.line(
+                Level::Warn,
+                "display",
+                if ssh {
+                    "SSH session without X forwarding — use `ssh -X`, --no-clipboard or -o FILE"
+                } else {
+                    "no DISPLAY — use --no-clipboard or -o FILE"
+                },
+            ),
+            (true, wayland) => r.line(
+                Level::Ok,
+                "display",
+                if wayland {
+                    "X11 via XWayland"
+                } else {
+                    "X11"
+                },
+            ),
+        }
+    } else if ssh {
+        r.line(
+            Level::Warn,
+            "display",
+            "SSH session — the clipboard belongs to the remote machine; use --no-clipboard",
+        );
+    }
+
+    match Clipboard::new() {
+        Ok(mut c) => match c.get_text() {
+            Ok(_) | Err(arboard::Error::ContentNotAvailable) => {
+                r.line(Level::Ok, "clipboard", "backend reachable")
+            }
+            Err(e) => r.line(
+                Level::Warn,
+                "clipboard",
+                &format!("opened but unreadable ({e})"),
+            ),
+        },
+        Err(e) => r.line(
+            Level::Fail,
+            "clipboard",
+            &format!("{e} — output will fall back to stdout; pass --no-clipboard to silence"),
+        ),
+    }
+}
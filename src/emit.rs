@@ -0,0 +1,283 @@
+//! Output sinks: the snapshot is written out as it is composed.
+//! * `--output` files and stdout stream through a `BufWriter`, so nothing
+//!   holds the whole snapshot in memory.
+//! * Only the clipboard buffers, since it takes the text in one piece; the
+//!   buffer is handed over as is rather than copied.
+//! * `--clipboard auto` (default) only uses the clipboard when stdout is a
+//!   terminal and `CI` is unset, so pipes and CI logs get the text directly.
+//! * Setting the clipboard is retried with backoff and verified by reading it
+//!   back: on Windows, clipboard history and other owners can briefly hold
+//!   the clipboard and drop large payloads.
+//! * Copying more than `confirm-size` bytes (default `CONFIRM_BYTES`) from an
+//!   interactive terminal asks first; `l` lists the largest files, `--yes`
+//!   skips the question.
+//! * `--open` buffers the snapshot into a temp file and opens it in
+//!   `$VISUAL`/`$EDITOR`; what is left when the editor exits goes on to the
+//!   usual destination. Without an editor the system viewer opens the file
+//!   and nothing is copied.
+//! * A clipboard failure prints the snapshot first and the notice after it,
+//!   on stderr, so the two never interleave.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, IsTerminal, Write},
+    path::Path,
+    process::Command,
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use arboard::Clipboard;
+use clap::ValueEnum;
+
+use crate::{review, tokens, Ctx, Format, Opts};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ClipboardPolicy {
+    Never,
+    Auto,
+    Always,
+}
+
+impl ClipboardPolicy {
+    fn wanted(self) -> bool {
+        match self {
+            ClipboardPolicy::Never => false,
+            ClipboardPolicy::Always => true,
+            ClipboardPolicy::Auto => io::stdout().is_terminal() && std::env::var_os("CI").is_none(),
+        }
+    }
+}
+
+/// Tries at setting the clipboard before giving up.
+const ATTEMPTS: u32 = 5;
+/// Delay before the first retry; doubles after each failed attempt.
+const BACKOFF: Duration = Duration::from_millis(50);
+/// Payloads above this many bytes are known to fail or stall in some
+/// clipboards and paste targets.
+const LARGE_BYTES: usize = 16 << 20;
+/// Clipboard payloads above this many bytes need confirming.
+pub const CONFIRM_BYTES: usize = 400_000;
+/// Files listed by the `l` answer.
+const LARGEST: usize = 10;
+
+pub enum Sink {
+    Stream(BufWriter<Box<dyn Write>>),
+    Clipboard(Vec<u8>),
+    /// `--open`: buffered for the editor, then written to `then`
+    Open {
+        buf: Vec<u8>,
+        then: Box<Sink>,
+        ext: &'static str,
+    },
+}
+
+impl Sink {
+    /// `--output` file, else the clipboard when the policy wants it, else
+    /// stdout; behind the editor with `--open`.
+    pub fn open(opts: &Opts) -> Result<Self> {
+        let sink = Self::destination(opts)?;
+        if !opts.open {
+            return Ok(sink);
+        }
+        let ext = match opts.format {
+            Format::Text => "txt",
+            Format::Markdown => "md",
+            Format::Json => "json",
+            Format::Ndjson => "ndjson",
+            Format::Aider => "aider",
+            Format::Xml => "xml",
+        };
+        Ok(Sink::Open {
+            buf: Vec::new(),
+            then: Box::new(sink),
+            ext,
+        })
+    }
+
+    fn destination(opts: &Opts) -> Result<Self> {
+        let stream: Box<dyn Write> = if let Some(path) = &opts.output {
+            Box::new(
+                File::create(path)
+                    .with_context(|| format!("failed to write {}", path.display()))?,
+            )
+        } else if !opts.no_clipboard && opts.clipboard.wanted() {
+            return Ok(Sink::Clipboard(Vec::new()));
+        } else {
+            Box::new(io::stdout())
+        };
+        Ok(Sink::Stream(BufWriter::new(stream)))
+    }
+
+    /// For a clipboard payload over `limit` bytes, asks on the terminal
+    /// whether to copy it anyway. `false` if the answer was no.
+    pub fn confirm(&self, ctx: &Ctx, limit: usize) -> Result<bool> {
+        let Sink::Clipboard(buf) = self else {
+            return Ok(true);
+        };
+        if limit == 0
+            || buf.len() <= limit
+            || !io::stdin().is_terminal()
+            || !io::stderr().is_terminal()
+        {
+            return Ok(true);
+        }
+        let question = format!(
+            "snapshot is {} / ~{} tokens — copy anyway? [y/N/l(ist)] ",
+            human_bytes(buf.len()),
+            tokens::human(tokens::estimate_bytes(buf.len()))
+        );
+        loop {
+            eprint!("{question}");
+            io::stderr().flush()?;
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            match answer.trim().to_lowercase().as_str() {
+                "y" | "yes" => return Ok(true),
+                "l" | "list" => {
+                    let mut files: Vec<_> = ctx.emitted.iter().collect();
+                    files.sort_by(|a, b| b.tokens.cmp(&a.tokens).then(a.path.cmp(&b.path)));
+                    for file in files.into_iter().take(LARGEST) {
+                        eprintln!(
+                            "{:>8}  {}",
+                            tokens::human(file.tokens),
+                            ctx.rel(&file.path).display()
+                        );
+                    }
+                }
+                _ => return Ok(false),
+            }
+        }
+    }
+
+    /// Whether the output ends up on the clipboard.
+    pub fn is_clipboard(&self) -> bool {
+        match self {
+            Sink::Stream(_) => false,
+            Sink::Clipboard(_) => true,
+            Sink::Open { then, .. } => then.is_clipboard(),
+        }
+    }
+
+    /// Flushes the stream, or copies the buffer to the clipboard (falling
+    /// back to stdout when there is none). `true` if the clipboard failed.
+    pub fn finish(self) -> Result<bool> {
+        match self {
+            Sink::Stream(mut w) => w.flush()?,
+            Sink::Open { buf, then, ext } => return edit(&buf, *then, ext),
+            Sink::Clipboard(buf) => {
+                let text = String::from_utf8(buf)
+                    .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+                if text.len() > LARGE_BYTES {
+                    eprintln!(
+                        "warning: the snapshot is {} MiB; large clipboard payloads may be \
+                         truncated or rejected (consider `--output <file>` or fewer files)",
+                        text.len() >> 20
+                    );
+                }
+                if let Err(e) = copy(&text) {
+                    let mut stdout = io::stdout().lock();
+                    stdout.write_all(text.as_bytes())?;
+                    stdout.flush()?;
+                    eprintln!("clipboard error ({e}); printed to stdout instead");
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// `--open`: hands `buf` to the editor, then what is left to `then`.
+fn edit(buf: &[u8], mut then: Sink, ext: &str) -> Result<bool> {
+    let path = std::env::temp_dir().join(format!("qp-snapshot-{}.{ext}", std::process::id()));
+    std::fs::write(&path, buf).with_context(|| format!("failed to write {}", path.display()))?;
+    let Some(editor) = review::editor() else {
+        open_viewer(&path)?;
+        eprintln!(
+            "opened {} (set $VISUAL or $EDITOR to edit it before it is copied)",
+            path.display()
+        );
+        return Ok(false);
+    };
+    let ran = review::run_editor(&editor, &path);
+    let edited = std::fs::read(&path);
+    let _ = std::fs::remove_file(&path);
+    ran?;
+    let edited = edited?;
+    if edited.iter().all(u8::is_ascii_whitespace) {
+        anyhow::bail!("snapshot emptied in the editor; nothing copied");
+    }
+    then.write_all(&edited)?;
+    then.finish()
+}
+
+/// The platform's default application for `path`; doesn't wait for it.
+fn open_viewer(path: &Path) -> Result<()> {
+    let mut cmd = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", "start", ""]);
+        cmd
+    } else {
+        Command::new("xdg-open")
+    };
+    cmd.arg(path)
+        .spawn()
+        .context("no $VISUAL or $EDITOR, and the system opener failed to start")?;
+    Ok(())
+}
+
+/// `1.8 MB`, `412 KB`
+fn human_bytes(n: usize) -> String {
+    if n >= 1_000_000 {
+        format!("{:.1} MB", n as f64 / 1e6)
+    } else {
+        format!("{} KB", n.div_ceil(1000))
+    }
+}
+
+/// Sets the clipboard to `text` and reads it back, retrying with backoff
+/// until the read-back matches. No clipboard at all fails straight away.
+pub fn copy(text: &str) -> Result<()> {
+    let mut clipboard = Clipboard::new()?;
+    let mut delay = BACKOFF;
+    for attempt in 1..=ATTEMPTS {
+        match set_verified(&mut clipboard, text) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt == ATTEMPTS => return Err(e),
+            Err(_) => {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+    }
+    unreachable!("the last attempt returns")
+}
+
+fn set_verified(clipboard: &mut Clipboard, text: &str) -> Result<()> {
+    clipboard.set_text(text)?;
+    let back = clipboard.get_text()?;
+    // Windows may hand the text back with CRLF line endings
+    let same = back == text || back.replace("\r\n", "\n") == text.replace("\r\n", "\n");
+    anyhow::ensure!(same, "clipboard content did not match after copying");
+    Ok(())
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Stream(w) => w.write(buf),
+            Sink::Clipboard(b) | Sink::Open { buf: b, .. } => b.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Stream(w) => w.flush(),
+            Sink::Clipboard(_) | Sink::Open { .. } => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! `--env-info` — an `environment` section ahead of the files: the rustc
+//! version and release channel, host triple, LLVM and cargo versions, and
+//! the active rustup toolchain.
+//! * Commands run in the snapshot root, so a `rust-toolchain.toml` override
+//!   there is what gets reported.
+//! * A missing tool (no rustup, say) is reported as such instead of failing
+//!   the snapshot.
+
+use std::{path::Path, process::Command};
+
+/// Title and text of the section.
+pub fn section(root: &Path) -> (String, String) {
+    let mut text = String::new();
+    match output(root, "rustc", &["-vV"]) {
+        Some(verbose) => {
+            let field = |key: &str| {
+                verbose
+                    .lines()
+                    .find_map(|l| l.strip_prefix(key)?.strip_prefix(": "))
+                    .map(str::trim)
+            };
+            let version = verbose.lines().next().unwrap_or_default();
+            let channel = match field("release") {
+                Some(r) if r.contains("nightly") => "nightly",
+                Some(r) if r.contains("beta") => "beta",
+                Some(r) if r.contains("dev") => "dev",
+                Some(_) => "stable",
+                None => "unknown",
+            };
+            text.push_str(&format!("{version}, channel {channel}"));
+            if let Some(llvm) = field("LLVM version") {
+                text.push_str(&format!(", LLVM {llvm}"));
+            }
+            text.push('\n');
+            if let Some(host) = field("host") {
+                text.push_str(&format!("host: {host}\n"));
+            }
+        }
+        None => text.push_str("rustc: not found\n"),
+    }
+    let cargo = output(root, "cargo", &["--version"]);
+    text.push_str(&format!(
+        "{}\n",
+        cargo.as_deref().unwrap_or("cargo: not found").trim()
+    ));
+    if let Some(toolchain) = output(root, "rustup", &["show", "active-toolchain"]) {
+        text.push_str(&format!("toolchain: {}\n", toolchain.trim()));
+    }
+    ("environment".to_string(), text)
+}
+
+/// Stdout of a successful `program args` run in `root`.
+fn output(root: &Path, program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(program)
+        .args(args)
+        .current_dir(root)
+        .output()
+        .ok()?;
+    out.status
+        .success()
+        .then(|| String::from_utf8_lossy(&out.stdout).into_owned())
+}
@@ -0,0 +1,61 @@
+//! Exit codes, so scripts and CI wrappers can tell outcomes apart.
+//! * 0 success, 1 any error that aborts the run.
+//! * 2 nothing was emitted and `--fail-if-empty` was given.
+//! * 3 the output exceeded `--max-tokens`.
+//! * 4 some files could not be read and were replaced by placeholders.
+//! * 5 the clipboard failed and the output went to stdout instead.
+//! * 6 `--check` found denied paths or likely secrets.
+//!
+//! When several apply, the lowest code wins.
+
+use std::process::ExitCode;
+
+use crate::{tokens, Cmd, Ctx, Opts};
+
+pub const EMPTY: u8 = 2;
+pub const OVER_BUDGET: u8 = 3;
+pub const PARTIAL: u8 = 4;
+pub const CLIPBOARD_FALLBACK: u8 = 5;
+pub const VIOLATIONS: u8 = 6;
+
+/// Outcome of a snapshot-emitting run, with a stderr note for each problem.
+pub fn code(ctx: &Ctx, opts: &Opts, read_errors: bool, clipboard_failed: bool) -> ExitCode {
+    let total: usize = ctx.emitted.iter().map(|e| e.tokens).sum();
+    let mut codes = Vec::new();
+    // `api-diff` emits report sections, never files; sessions replay stored
+    // turns and say so themselves when nothing changed
+    if ctx.emitted.is_empty()
+        && !matches!(opts.cmd, Some(Cmd::ApiDiff { .. } | Cmd::Session { .. }))
+    {
+        eprintln!("warning: no files matched");
+        // members outside `default-members`, as `--exclude`d ones were asked for
+        let outside = ctx.skipped.len().saturating_sub(opts.exclude.len());
+        if outside > 0 && !opts.workspace {
+            eprintln!(
+                "note: {outside} workspace member(s) outside `default-members` were left out; \
+                 --workspace includes them"
+            );
+        }
+        if opts.fail_if_empty {
+            codes.push(EMPTY);
+        }
+    }
+    if let Some(max) = ctx.max_tokens.filter(|&max| total > max) {
+        eprintln!(
+            "warning: output is ~{} tokens, over the --max-tokens budget of {}",
+            tokens::human(total),
+            tokens::human(max)
+        );
+        codes.push(OVER_BUDGET);
+    }
+    if read_errors {
+        codes.push(PARTIAL);
+    }
+    if clipboard_failed {
+        codes.push(CLIPBOARD_FALLBACK);
+    }
+    codes
+        .into_iter()
+        .min()
+        .map_or(ExitCode::SUCCESS, ExitCode::from)
+}
@@ -0,0 +1,319 @@
+//! `-v/--explain` — why each candidate path is in the snapshot or not,
+//! printed to stderr after it.
+//! * Candidates are the files git lists (the tree of `--rev`), plus the
+//!   gitignored paths of the working tree, collapsed per directory.
+//! * Exclusions name the first rule that applies, in the order the selection
+//!   checks them: exclude glob, workspace member, package include/exclude,
+//!   size cap, extension, `--against`/`--users-of`, then `Filter`s.
+//! * `cargo qp why <PATH>` traces one file through every step instead, with
+//!   its crate, applied transforms and token count.
+//! * Included files note when they came in as crate anchors, were emitted as
+//!   a reference to a duplicate (`--dedupe`), or were not emitted by the mode.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{git, groups, tokens, Config, Ctx, Opts};
+
+/// First selection rule a path fails (see `Ctx::rejection`).
+pub enum Rejected {
+    Sensitive,
+    Glob,
+    Member,
+    Package,
+    TooLarge(u64),
+    Extension,
+    Include,
+}
+
+pub fn report(ctx: &Ctx, config: &Config, opts: &Opts) -> Result<String> {
+    let selected: HashSet<PathBuf> = ctx.selected()?.into_iter().collect();
+    let emitted: HashMap<&Path, Option<&str>> = ctx
+        .emitted
+        .iter()
+        .map(|e| (e.path.as_path(), e.tag.as_deref()))
+        .collect();
+    let globs = [config.exclude.clone(), groups::exclude_patterns(opts)].concat();
+    let mut rows: Vec<(String, bool, String)> = Vec::new();
+
+    let files = match &ctx.rev {
+        Some(rev) => git::ls_tree(&ctx.root, rev)?,
+        None => git::ls_files(&ctx.root)?,
+    };
+    for rel in files {
+        let path = ctx.root.join(&rel);
+        if ctx.rev.is_none() && !path.is_file() {
+            continue;
+        }
+        if selected.contains(&path) {
+            let mut why = Vec::new();
+            if ctx.extra.contains(&path) {
+                why.push("pinned with --extra".to_string());
+            } else if ctx.rejection(&path).is_some() {
+                why.push("crate anchor".to_string());
+            }
+            match emitted.get(path.as_path()) {
+                None => why.push("not emitted by this mode".into()),
+                Some(Some(tag)) if is_reference(tag) => why.push(format!("deduplicated: {tag}")),
+                Some(_) => {}
+            }
+            rows.push((rel, true, why.join("; ")));
+            continue;
+        }
+        let why = match ctx.rejection(&path) {
+            Some(Rejected::Sensitive) => match sensitive_glob(ctx, config, &path) {
+                Some(glob) => format!("sensitive path `{glob}` (--allow-sensitive)"),
+                None => "sensitive path (--allow-sensitive)".into(),
+            },
+            Some(Rejected::Glob) => {
+                let hits = ctx.excludes.matches(ctx.rel(&path));
+                match hits.first().and_then(|&i| globs.get(i)) {
+                    Some(glob) => format!("exclude glob `{glob}`"),
+                    None => "exclude glob".into(),
+                }
+            }
+            Some(Rejected::Member) => {
+                "workspace member not selected (--workspace, --exclude)".into()
+            }
+            Some(Rejected::Package) => format!(
+                "left out by the crate's `{}`",
+                ctx.package_field(&path).unwrap_or("package.exclude")
+            ),
+            Some(Rejected::TooLarge(len)) => format!(
+                "{len} bytes, over max-file-size ({})",
+                ctx.max_file_size.unwrap_or_default()
+            ),
+            Some(Rejected::Extension) => match path.extension() {
+                Some(ext) => format!("extension `.{}` not selected", ext.to_string_lossy()),
+                None => "no extension".into(),
+            },
+            Some(Rejected::Include) => "no `include` glob matches".into(),
+            None if ctx.only.as_ref().is_some_and(|only| !only.contains(&path)) => {
+                "outside the --against/--users-of selection".into()
+            }
+            None => "dropped by a filter".into(),
+        };
+        rows.push((rel, false, why));
+    }
+    if ctx.rev.is_none() {
+        for rel in git::ls_ignored(&ctx.root).unwrap_or_default() {
+            rows.push((rel, false, "ignored by git".into()));
+        }
+    }
+    rows.sort();
+
+    let width = rows.iter().map(|(rel, ..)| rel.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for (rel, included, why) in &rows {
+        let mark = if *included { '+' } else { '-' };
+        let verdict = if *included { "included" } else { "excluded" };
+        let why = if why.is_empty() {
+            String::new()
+        } else {
+            format!(": {why}")
+        };
+        out.push_str(&format!("{mark} {rel:<width$}  {verdict}{why}\n"));
+    }
+    let total: usize = ctx.emitted.iter().map(|e| e.tokens).sum();
+    let included = rows.iter().filter(|(_, inc, _)| *inc).count();
+    out.push_str(&format!(
+        "{included} of {} candidates included, ~{} tokens",
+        rows.len(),
+        tokens::human(total)
+    ));
+    if let Some(max) = ctx.max_tokens {
+        let over = if total > max { ", over" } else { ", within" };
+        out.push_str(&format!("{over} the {} token budget", tokens::human(max)));
+    }
+    out.push('\n');
+    Ok(out)
+}
+
+/// Tags `dedupe` gives bodies replaced by a reference.
+fn is_reference(tag: &str) -> bool {
+    tag.starts_with("identical to ") || tag.starts_with("diff vs ")
+}
+
+/// `cargo qp why <PATH>`: every step of the selection for one file, then
+/// its crate, transforms and tokens when it is included.
+pub fn why(ctx: &Ctx, config: &Config, opts: &Opts, path: &Path) -> Result<String> {
+    let path = path.canonicalize().unwrap_or_else(|_| ctx.root.join(path));
+    anyhow::ensure!(
+        path.starts_with(&ctx.root),
+        "{} is outside {}",
+        path.display(),
+        ctx.root.display()
+    );
+    let rel = ctx.rel(&path).to_string_lossy().into_owned();
+    let mut steps: Vec<(&str, String)> = Vec::new();
+    let mut ok = true;
+
+    let listed = match &ctx.rev {
+        Some(rev) => git::ls_tree(&ctx.root, rev)?.contains(&rel),
+        None => git::ls_files(&ctx.root)?.contains(&rel) && path.is_file(),
+    };
+    let source = match &ctx.rev {
+        Some(rev) if listed => format!("in the tree of {rev}"),
+        Some(rev) => format!("not in the tree of {rev}"),
+        None if listed => "listed by git (tracked, or untracked and not ignored)".into(),
+        None if !path.exists() => "no such file".into(),
+        None if is_ignored(ctx, &rel) => "ignored by git".into(),
+        None => "not a file git lists".into(),
+    };
+    ok &= listed;
+    steps.push(("source", mark(listed, source)));
+
+    steps.push((
+        "sensitive",
+        match sensitive_glob(ctx, config, &path) {
+            Some(glob) => mark(false, format!("matches `{glob}` (--allow-sensitive)")),
+            None => mark(true, "not a sensitive path".into()),
+        },
+    ));
+    let globs = [config.exclude.clone(), groups::exclude_patterns(opts)].concat();
+    let glob = ctx
+        .excludes
+        .matches(&rel)
+        .first()
+        .and_then(|&i| globs.get(i).cloned());
+    let rejection = ctx.rejection(&path);
+    steps.push((
+        "exclude",
+        match glob {
+            Some(glob) => mark(false, format!("matches `{glob}`")),
+            None => mark(true, "no exclude glob matches".into()),
+        },
+    ));
+    let member = ctx.crate_dir(&path).map(|dir| ctx.owner(dir).0);
+    let member_ok = ctx.selected_member(&path);
+    steps.push((
+        "member",
+        match (member_ok, member) {
+            (true, Some(name)) => mark(true, format!("`{name}` is selected")),
+            (true, None) => mark(true, "not in a workspace member".into()),
+            (false, _) => mark(false, "member not selected (--workspace, --exclude)".into()),
+        },
+    ));
+    if ctx.package_filters.is_some() {
+        let package = match ctx.package_field(&path) {
+            Some(field) => mark(false, format!("left out by the crate's `{field}`")),
+            None => mark(true, "kept by the crate's include/exclude".into()),
+        };
+        steps.push(("package", package));
+    }
+    let size = match &rejection {
+        Some(Rejected::TooLarge(len)) => mark(
+            false,
+            format!(
+                "{len} bytes, over max-file-size ({})",
+                ctx.max_file_size.unwrap_or_default()
+            ),
+        ),
+        _ => match (ctx.max_file_size, path.metadata()) {
+            (Some(max), Ok(meta)) if ctx.rev.is_none() => {
+                mark(true, format!("{} bytes, within {max}", meta.len()))
+            }
+            _ => mark(true, "no max-file-size".into()),
+        },
+    };
+    steps.push(("size", size));
+    let extension = if path.file_name() == Some("Cargo.toml".as_ref()) {
+        mark(true, "Cargo.toml is always wanted".into())
+    } else if ctx.groups.is_match(&rel) {
+        mark(true, "matches a --docs/--schemas/… group".into())
+    } else if let Some(includes) = &ctx.includes {
+        if includes.is_match(&rel) {
+            mark(true, "matches an `include` glob".into())
+        } else {
+            mark(false, "no `include` glob matches".into())
+        }
+    } else if let Some(ext) = path
+        .extension()
+        .map(|e| e.to_string_lossy())
+        .filter(|ext| ctx.exts.iter().any(|x| x == ext))
+    {
+        mark(true, format!("`.{ext}` is selected"))
+    } else if ctx.shebangs && path.extension().is_none() && ctx.has_shebang(&path) {
+        mark(true, "script with a shebang line".into())
+    } else {
+        mark(false, format!("not one of: {}", ctx.exts.join(", ")))
+    };
+    steps.push(("extension", extension));
+    let wanted = rejection.is_none();
+    ok &= wanted;
+
+    let in_only = ctx.only.as_ref().is_none_or(|only| only.contains(&path));
+    steps.push((
+        "selection",
+        match &ctx.only {
+            None => mark(true, "no --against/--users-of".into()),
+            Some(_) if in_only => mark(true, "in the --against/--users-of selection".into()),
+            Some(_) => mark(false, "outside the --against/--users-of selection".into()),
+        },
+    ));
+    ok &= in_only;
+
+    let selected = ctx.selected()?.contains(&path);
+    if ctx.extra.contains(&path) {
+        steps.push(("extra", mark(true, "pinned with --extra".into())));
+    } else if !ok && selected {
+        steps.push(("anchor", mark(true, "crate anchor, kept anyway".into())));
+    }
+    for filter in &ctx.filters {
+        let kept = filter.keep(Path::new(&rel))?;
+        steps.push(("filter", mark(kept, format!("`{}`", filter.name()))));
+    }
+    steps.push((
+        "verdict",
+        if selected { "included" } else { "excluded" }.into(),
+    ));
+
+    let (name, version) = ctx.owner(&path);
+    steps.push(("crate", format!("{name} v{version}")));
+    if selected {
+        let body = ctx.read(&path)?;
+        let applied = ctx.applied_transforms(&path);
+        let applied = if applied.is_empty() {
+            "none changed the body".into()
+        } else {
+            applied.join(", ")
+        };
+        steps.push(("transforms", applied));
+        steps.push((
+            "tokens",
+            format!(
+                "~{} ({} bytes)",
+                tokens::human(tokens::estimate(&body)),
+                body.len()
+            ),
+        ));
+    }
+
+    let mut out = format!("{rel}\n");
+    for (step, text) in steps {
+        out.push_str(&format!("  {step:<11}{text}\n"));
+    }
+    Ok(out)
+}
+
+fn mark(pass: bool, text: String) -> String {
+    format!("{} {text}", if pass { "ok  " } else { "FAIL" })
+}
+
+fn is_ignored(ctx: &Ctx, rel: &str) -> bool {
+    git::ls_ignored(&ctx.root)
+        .unwrap_or_default()
+        .iter()
+        .any(|ignored| rel == ignored || (ignored.ends_with('/') && rel.starts_with(ignored)))
+}
+
+/// The first sensitive-path glob `path` matches, unless `--allow-sensitive`.
+fn sensitive_glob(ctx: &Ctx, config: &Config, path: &Path) -> Option<String> {
+    let hits = ctx.sensitive.matches(ctx.rel(path));
+    hits.first()
+        .and_then(|&i| config.sensitive_patterns().get(i).cloned())
+}
@@ -0,0 +1,292 @@
+//! `--from-test[=FILTER]` — a snapshot focused on failing tests.
+//! * Runs `cargo test --no-fail-fast` (or, with `--test-runner nextest`,
+//!   `cargo nextest run` with its libtest-JSON output) and collects each
+//!   failing test's name and captured output.
+//! * Emits the files defining the failing test functions, the files their
+//!   panics point at, and for integration tests the library modules they
+//!   `use`; the failure output follows, one section per test.
+
+use std::{
+    collections::BTreeSet,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use syn::visit::{self, Visit};
+
+use crate::{git, Ctx, Opts};
+
+/// `--test-runner`
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Runner {
+    /// `cargo test`, human output
+    Cargo,
+    /// `cargo nextest run`, libtest-JSON output
+    Nextest,
+}
+
+struct Failure {
+    /// Path within its crate, e.g. `parser::tests::empty_input`.
+    name: String,
+    output: String,
+}
+
+pub fn compose(
+    ctx: &mut Ctx,
+    out: &mut dyn Write,
+    opts: &Opts,
+    filter: Option<&str>,
+) -> Result<()> {
+    if ctx.rev.is_some() {
+        anyhow::bail!("--from-test runs the working tree's tests; drop --rev");
+    }
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut cmd = Command::new(cargo);
+    match opts.test_runner {
+        Runner::Cargo => cmd.args(["test", "--no-fail-fast"]),
+        Runner::Nextest => cmd
+            .args(["nextest", "run", "--no-fail-fast"])
+            .args(["--message-format", "libtest-json"])
+            .env("NEXTEST_EXPERIMENTAL_LIBTEST_JSON", "1"),
+    };
+    if opts.workspace {
+        cmd.arg("--workspace");
+    }
+    for name in &opts.exclude {
+        cmd.args(["--exclude", name]);
+    }
+    cmd.args(filter).current_dir(&ctx.root);
+    let output = cmd.output().context("failed to run the tests")?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let failures = match opts.test_runner {
+        Runner::Cargo => parse_libtest(&stdout),
+        Runner::Nextest => parse_json(&stdout),
+    };
+    if failures.is_empty() && !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let tail: Vec<&str> = stderr.lines().rev().take(20).collect();
+        anyhow::bail!(
+            "the tests failed without reporting a failing test \
+             (a build error? try `cargo qp check-context`):\n{}",
+            tail.into_iter().rev().collect::<Vec<_>>().join("\n")
+        );
+    }
+
+    let paths = locate(ctx, &failures)?;
+    let bodies = ctx.read_all(&paths)?;
+    for (path, body) in paths.iter().zip(&bodies) {
+        ctx.push_file(out, path, None, body)?;
+    }
+    if failures.is_empty() {
+        ctx.push_section(out, "cargo test :: no failures", "")?;
+    }
+    for failure in &failures {
+        let title = format!("test failed :: {}", failure.name);
+        ctx.push_section(out, &title, &failure.output)?;
+    }
+    Ok(())
+}
+
+//──────────────────────── runner output ──────────────────────────────────────
+
+/// libtest's human output: `test NAME ... FAILED` lines, then a
+/// `---- NAME stdout ----` block per failure.
+fn parse_libtest(stdout: &str) -> Vec<Failure> {
+    let mut failures: Vec<Failure> = stdout
+        .lines()
+        .filter_map(|l| l.strip_prefix("test ")?.strip_suffix(" ... FAILED"))
+        .map(|name| Failure {
+            name: name.to_string(),
+            output: String::new(),
+        })
+        .collect();
+    let mut current: Option<usize> = None;
+    for line in stdout.lines() {
+        if let Some(name) = line
+            .strip_prefix("---- ")
+            .and_then(|l| l.strip_suffix(" stdout ----"))
+        {
+            current = failures.iter().position(|f| f.name == name);
+        } else if line == "failures:" || line.starts_with("test result: ") {
+            current = None;
+        } else if let Some(i) = current {
+            failures[i].output.push_str(line);
+            failures[i].output.push('\n');
+        }
+    }
+    for failure in &mut failures {
+        failure.output = format!("{}\n", failure.output.trim());
+    }
+    failures
+}
+
+#[derive(Deserialize)]
+struct Event {
+    #[serde(rename = "type")]
+    kind: String,
+    event: String,
+    name: Option<String>,
+    stdout: Option<String>,
+}
+
+/// libtest-JSON events; nextest prefixes names with `<binary-id>$`.
+fn parse_json(stdout: &str) -> Vec<Failure> {
+    stdout
+        .lines()
+        .filter_map(|l| serde_json::from_str::<Event>(l).ok())
+        .filter(|e| e.kind == "test" && e.event == "failed")
+        .filter_map(|e| {
+            let name = e.name?;
+            let name = name.rsplit_once('$').map_or(&*name, |(_, n)| n);
+            Some(Failure {
+                name: name.to_string(),
+                output: format!("{}\n", e.stdout.unwrap_or_default().trim()),
+            })
+        })
+        .collect()
+}
+
+//──────────────────────── locating code ──────────────────────────────────────
+
+/// Files defining or exercised by the failing tests, sorted.
+fn locate(ctx: &Ctx, failures: &[Failure]) -> Result<Vec<PathBuf>> {
+    let mut found = BTreeSet::new();
+    for failure in failures {
+        for line in failure.output.lines() {
+            if let Some(file) = panic_file(line) {
+                let path = ctx.root.join(file);
+                if path.starts_with(&ctx.root) && path.is_file() && ctx.allowed(&path) {
+                    found.insert(path);
+                }
+            }
+        }
+    }
+    if failures.is_empty() {
+        return Ok(found.into_iter().collect());
+    }
+
+    let names: Vec<&str> = failures.iter().map(|f| f.name.as_str()).collect();
+    for rel in git::ls_files(&ctx.root)? {
+        let path = ctx.root.join(&rel);
+        if !rel.ends_with(".rs") || !ctx.allowed(&path) {
+            continue;
+        }
+        let Ok(src) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        let mentioned = names.iter().any(|n| {
+            let last = n.rsplit("::").next().unwrap_or(n);
+            src.contains(&format!("fn {last}"))
+        });
+        let Some(file) = mentioned.then(|| syn::parse_file(&src).ok()).flatten() else {
+            continue;
+        };
+        let mut tests = TestFns::default();
+        tests.visit_file(&file);
+        if !tests
+            .paths
+            .iter()
+            .any(|p| names.iter().any(|n| ends_with(n, p)))
+        {
+            continue;
+        }
+        if is_integration_test(ctx, &path) {
+            found.extend(used_modules(ctx, &path, &file));
+        }
+        found.insert(path);
+    }
+    Ok(found.into_iter().collect())
+}
+
+/// The file of a `panicked at FILE:LINE:COL` line (either message layout).
+fn panic_file(line: &str) -> Option<&str> {
+    let rest = line.split_once("panicked at ")?.1;
+    let loc = rest.rsplit_once("', ").map_or(rest, |(_, loc)| loc);
+    let file = loc.split(':').next()?;
+    file.ends_with(".rs").then_some(file)
+}
+
+/// Whether `name` (`a::b::c`) ends with the segments of `path`.
+fn ends_with(name: &str, path: &str) -> bool {
+    name == path || name.ends_with(&format!("::{path}"))
+}
+
+fn is_integration_test(ctx: &Ctx, path: &Path) -> bool {
+    ctx.crate_dir(path)
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .is_some_and(|rel| rel.starts_with("tests"))
+}
+
+/// `src/<module>.rs` (or `mod.rs`) for each `use <this_crate>::<module>`.
+fn used_modules(ctx: &Ctx, path: &Path, file: &syn::File) -> Vec<PathBuf> {
+    let Some(dir) = ctx.crate_dir(path) else {
+        return Vec::new();
+    };
+    let krate = ctx.owner(path).0.replace('-', "_");
+    let mut modules = Vec::new();
+    for item in &file.items {
+        let syn::Item::Use(u) = item else { continue };
+        let syn::UseTree::Path(root) = &u.tree else {
+            continue;
+        };
+        if root.ident != krate {
+            continue;
+        }
+        let mut seconds = Vec::new();
+        match &*root.tree {
+            syn::UseTree::Path(p) => seconds.push(p.ident.to_string()),
+            syn::UseTree::Name(n) => seconds.push(n.ident.to_string()),
+            syn::UseTree::Group(g) => seconds.extend(g.items.iter().filter_map(|t| match t {
+                syn::UseTree::Path(p) => Some(p.ident.to_string()),
+                syn::UseTree::Name(n) => Some(n.ident.to_string()),
+                _ => None,
+            })),
+            _ => {}
+        }
+        for module in seconds {
+            let candidates = [
+                dir.join("src").join(format!("{module}.rs")),
+                dir.join("src").join(&module).join("mod.rs"),
+            ];
+            modules.extend(
+                candidates
+                    .into_iter()
+                    .filter(|p| p.is_file() && ctx.allowed(p)),
+            );
+        }
+    }
+    modules
+}
+
+/// `mod::path::name` of every function carrying a `…test` attribute.
+#[derive(Default)]
+struct TestFns {
+    mods: Vec<String>,
+    paths: Vec<String>,
+}
+
+impl<'ast> Visit<'ast> for TestFns {
+    fn visit_item_mod(&mut self, m: &'ast syn::ItemMod) {
+        self.mods.push(m.ident.to_string());
+        visit::visit_item_mod(self, m);
+        self.mods.pop();
+    }
+
+    fn visit_item_fn(&mut self, f: &'ast syn::ItemFn) {
+        let is_test = f.attrs.iter().any(|a| {
+            a.path()
+                .segments
+                .last()
+                .is_some_and(|s| s.ident.to_string().ends_with("test"))
+        });
+        if is_test {
+            let mut path = self.mods.clone();
+            path.push(f.sig.ident.to_string());
+            self.paths.push(path.join("::"));
+        }
+    }
+}
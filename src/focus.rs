@@ -0,0 +1,91 @@
+//! `--focus PATH` — a zoom lens on one part of the workspace.
+//! * Selected files under PATH are emitted in full.
+//! * The rest of PATH's crate is emitted signatures-only; its manifest stays
+//!   in full and its other non-Rust files are left out.
+//! * Every other selected crate contributes only its manifest and a file
+//!   tree, as one section per crate.
+//! * Files outside every crate are kept only when they are manifests (the
+//!   workspace `Cargo.toml`).
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{order, Ctx};
+
+pub fn compose(ctx: &mut Ctx, out: &mut dyn Write, focus: &Path) -> Result<()> {
+    let focus = focus
+        .canonicalize()
+        .unwrap_or_else(|_| ctx.root.join(focus));
+    anyhow::ensure!(
+        focus.starts_with(&ctx.root) && focus.exists(),
+        "--focus {} is not a path under {}",
+        focus.display(),
+        ctx.root.display()
+    );
+    let home = ctx.crate_dir(&focus).map(Path::to_path_buf);
+
+    let mut emit = Vec::new();
+    let mut trees: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for path in ctx.selected()? {
+        let manifest = path.file_name() == Some("Cargo.toml".as_ref());
+        let owner = ctx.crate_dir(&path).map(Path::to_path_buf);
+        if path.starts_with(&focus) || manifest {
+            emit.push(path);
+        } else if owner.is_some() && owner == home {
+            if path.extension().is_some_and(|x| x == "rs") {
+                emit.push(path);
+            }
+        } else if let Some(dir) = owner {
+            let rel = path.strip_prefix(&dir).unwrap_or(&path).to_path_buf();
+            trees.entry(dir).or_default().push(rel);
+        }
+    }
+
+    let bodies = ctx.read_all(&emit)?;
+    let mut files: Vec<(PathBuf, String)> = emit.into_iter().zip(bodies).collect();
+    order::apply(ctx, ctx.order, &mut files);
+    for (path, body) in &files {
+        let outline = !path.starts_with(&focus)
+            && path.extension().is_some_and(|x| x == "rs")
+            && ctx.crate_dir(path).map(Path::to_path_buf) == home;
+        match outline.then(|| ctx.signatures(body)).flatten() {
+            Some(sigs) => ctx.push_file(out, path, Some("signatures"), &sigs)?,
+            None => ctx.push_file(out, path, None, body)?,
+        }
+    }
+    for (dir, rels) in trees {
+        let (name, version) = ctx.owner(&dir);
+        let rel_dir = ctx.rel(&dir).display().to_string();
+        let title = format!("tree :: {name} v{version} ({rel_dir}/)");
+        ctx.push_section(out, &title, &tree(&rels))?;
+    }
+    Ok(())
+}
+
+/// Indented listing of relative paths, each directory once as `name/`.
+pub fn tree(rels: &[PathBuf]) -> String {
+    let mut rels = rels.to_vec();
+    rels.sort();
+    let mut out = String::new();
+    let mut open: Vec<String> = Vec::new();
+    for rel in &rels {
+        let parts: Vec<String> = rel
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let (name, dirs) = parts.split_last().expect("non-empty path");
+        let shared = open.iter().zip(dirs).take_while(|(a, b)| a == b).count();
+        open.truncate(shared);
+        for dir in &dirs[shared..] {
+            out.push_str(&format!("{}{dir}/\n", "  ".repeat(open.len())));
+            open.push(dir.clone());
+        }
+        out.push_str(&format!("{}{name}\n", "  ".repeat(open.len())));
+    }
+    out
+}
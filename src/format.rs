@@ -0,0 +1,440 @@
+//! `--format` — how emitted files and sections are rendered.
+//! * `text` (default): `=== crate vX :: path ===` headers followed by bodies;
+//!   other sections are `=== # title ===`, so no title reads as a file
+//!   header when an answer is pasted back to `cargo qp apply`.
+//! * `ndjson`: one `schema::Record` per line, streamed as files are read.
+//! * `json`: one `schema::Document`, written when the snapshot is complete.
+//! * `markdown`: a heading per file and its body in a fenced block tagged
+//!   with the file's language. The fence outgrows the longest backtick run
+//!   in the body (four backticks around a doc comment's ```` ``` ````), so
+//!   embedded code blocks and markdown files can't end it early.
+//! * `aider`: a command file for `aider --load`: an aider-style repo map of
+//!   the signatures-only files, then `/add` for every file emitted in full
+//!   and `/read-only` for the rest. Everything but the commands is a `#`
+//!   comment, which aider skips.
+//! * `xml`: `<documents>` holding one `<document index="N">` per file, with
+//!   `<source>` (the path), `<document_metadata>` (the header label) and
+//!   `<document_content>`; sections are `<section title="…">`. A closing tag
+//!   inside a body is escaped (`&lt;/document_content>`) so it can't end the
+//!   element early.
+
+use std::io::{self, Write};
+
+use clap::ValueEnum;
+
+use crate::{anonymize, lang, published, schema, secrets, style::Style, tokens, Ctx, Emitted};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// `=== crate :: path ===` headers over raw bodies
+    Text,
+    /// Fenced bodies tagged with their detected language
+    Markdown,
+    /// One document with every file and section
+    Json,
+    /// A header record, then one record per file or section
+    Ndjson,
+    /// A repo map and `/add` commands for `aider --load`
+    Aider,
+    /// A `<document>` per file
+    Xml,
+}
+
+/// Item keywords a repo-map line starts with, after visibility.
+const MAP_ITEMS: [&str; 12] = [
+    "fn ",
+    "async fn ",
+    "unsafe fn ",
+    "const fn ",
+    "struct ",
+    "enum ",
+    "union ",
+    "trait ",
+    "impl",
+    "type ",
+    "mod ",
+    "macro_rules!",
+];
+
+impl Ctx {
+    /// Starts the output: the ndjson header, or an empty json document
+    /// (which `aider` output collects into as well); then, under
+    /// `--anonymize`, the section `cargo qp apply` looks for.
+    pub(crate) fn begin_output(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        self.begin_document(out)?;
+        if self.anonymizer.is_some() {
+            self.push_section(out, anonymize::MARKER, anonymize::MARKER_NOTE)?;
+        }
+        Ok(())
+    }
+
+    fn begin_document(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        match self.format {
+            Format::Text | Format::Markdown => Ok(()),
+            Format::Xml => writeln!(out, "<documents>"),
+            Format::Ndjson => write_record(
+                out,
+                &schema::Record::Header {
+                    schema_version: schema::SCHEMA_VERSION,
+                    header: self.schema_header(),
+                },
+            ),
+            Format::Json | Format::Aider => {
+                self.document = Some(schema::Document {
+                    schema_version: schema::SCHEMA_VERSION,
+                    header: self.schema_header(),
+                    sections: Vec::new(),
+                    files: Vec::new(),
+                    total_tokens: 0,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Ends the output: the ndjson footer, or the whole json document or
+    /// aider command file.
+    pub(crate) fn finish_output(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let total_tokens = self.emitted.iter().map(|e| e.tokens).sum();
+        match self.format {
+            Format::Text | Format::Markdown => Ok(()),
+            Format::Xml => writeln!(out, "</documents>"),
+            Format::Ndjson => write_record(
+                out,
+                &schema::Record::Footer {
+                    files: self.emitted.len(),
+                    total_tokens,
+                },
+            ),
+            Format::Json => {
+                let Some(mut doc) = self.document.take() else {
+                    return Ok(());
+                };
+                doc.total_tokens = total_tokens;
+                serde_json::to_writer_pretty(&mut *out, &doc)?;
+                writeln!(out)
+            }
+            Format::Aider => match self.document.take() {
+                Some(doc) => out.write_all(aider(&doc).as_bytes()),
+                None => Ok(()),
+            },
+        }
+    }
+
+    /// A block of non-file text under a `=== # title ===` header.
+    pub(crate) fn push_section(
+        &mut self,
+        out: &mut dyn Write,
+        title: &str,
+        text: &str,
+    ) -> io::Result<()> {
+        let (title, text) = (&*self.anonymized(title), &*self.anonymized(text));
+        let section = schema::Section {
+            title: title.to_string(),
+            text: text.to_string(),
+        };
+        match self.format {
+            Format::Text => writeln!(out, "{}{text}", text_section_header(title)),
+            Format::Markdown if text.is_empty() => writeln!(out, "### {title}\n"),
+            Format::Markdown => {
+                let fence = self.fence(text);
+                writeln!(
+                    out,
+                    "### {title}\n\n{fence}text\n{}{fence}\n",
+                    with_newline(text)
+                )
+            }
+            Format::Xml => writeln!(
+                out,
+                "<section title=\"{}\">\n{}</section>",
+                xml_escape(title),
+                with_newline(&text.replace("</section>", "&lt;/section>"))
+            ),
+            Format::Ndjson => write_record(out, &schema::Record::Section(section)),
+            Format::Json | Format::Aider => {
+                if let Some(doc) = &mut self.document {
+                    doc.sections.push(section);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Renders one file and records it in `emitted`.
+    pub(crate) fn emit_file(
+        &mut self,
+        out: &mut dyn Write,
+        path: &std::path::Path,
+        status: Option<&str>,
+        tag: Option<&str>,
+        body: &str,
+    ) -> io::Result<()> {
+        let mut file = self.describe(path, status, tag);
+        let body = &*self.anonymized(body);
+        if let Some(found) = &mut self.secrets {
+            let hits = secrets::scan(body).into_iter();
+            found.extend(hits.map(|(line, kind)| (path.to_path_buf(), line, kind)));
+        }
+        file.tokens = tokens::estimate(body);
+        file.lines = body.lines().count();
+        file.language = Some(lang::detect(&file.path, body).to_string());
+        match self.format {
+            Format::Text => {
+                writeln!(out, "{}{body}", text_header(&file, self.header_stats))?
+            }
+            Format::Markdown => {
+                let fence = self.fence(body);
+                writeln!(
+                    out,
+                    "{}\n{fence}{}\n{}{fence}\n",
+                    markdown_header(&file, self.header_stats),
+                    file.language.as_deref().unwrap_or("text"),
+                    with_newline(body)
+                )?
+            }
+            Format::Xml => writeln!(
+                out,
+                "<document index=\"{}\">\n<source>{}</source>\n<document_metadata>{}</document_metadata>\n<document_content>\n{}</document_content>\n</document>",
+                self.emitted.len() + 1,
+                xml_escape(&file.path),
+                xml_escape(&label_with_tag(&file, self.header_stats)),
+                with_newline(&body.replace("</document_content>", "&lt;/document_content>"))
+            )?,
+            Format::Ndjson | Format::Json | Format::Aider => {
+                file.body = body.to_string();
+                if let Some(doc) = &mut self.document {
+                    doc.files.push(file.clone());
+                } else {
+                    write_record(out, &schema::Record::File(Box::new(file.clone())))?;
+                }
+            }
+        }
+        self.emitted.push(Emitted {
+            path: path.to_path_buf(),
+            tag: status.or(tag).map(String::from),
+            tokens: file.tokens,
+            transforms: file.transforms,
+        });
+        Ok(())
+    }
+
+    /// Markdown fence for `body`: backticks, or tildes when the style asks
+    /// for them and the body holds a backtick fence; one longer than the
+    /// longest run of that character in the body (at least three), so
+    /// nothing inside can close the block.
+    fn fence(&self, body: &str) -> String {
+        let ch = if self.style.is_some_and(Style::tildes) && body.contains("```") {
+            '~'
+        } else {
+            '`'
+        };
+        let longest = body.split(|c| c != ch).map(str::len).max().unwrap_or(0);
+        ch.to_string().repeat(longest.max(2) + 1)
+    }
+
+    /// Everything known about `path` except its body.
+    pub(crate) fn describe(
+        &mut self,
+        path: &std::path::Path,
+        status: Option<&str>,
+        tag: Option<&str>,
+    ) -> schema::File {
+        let (name, ver) = self.owner(path);
+        let rel = self.rel(path).to_string_lossy().replace('\\', "/");
+        let crates_io = (self.check_published && name != "unknown_crate").then(|| {
+            let offline = self.offline;
+            self.statuses
+                .entry(name.clone())
+                .or_insert_with(|| published::check(&name, &ver, offline))
+                .to_string()
+        });
+        let note = self.notes.lock().unwrap().get(path).cloned();
+        let last_commit = self
+            .git_info
+            .then(|| self.last_commits().get(&rel))
+            .flatten()
+            .map(|c| schema::LastCommit {
+                hash: c.hash.clone(),
+                date: c.date.clone(),
+                author: c.author.clone(),
+                subject: self.anonymized(&c.subject).into_owned(),
+            });
+        schema::File {
+            path: self.anonymized(&rel).into_owned(),
+            crate_name: self.anonymized(&name).into_owned(),
+            version: ver,
+            status: status.map(String::from),
+            tag: tag.map(String::from),
+            crates_io,
+            note,
+            last_commit,
+            language: None,
+            lines: 0,
+            tokens: 0,
+            transforms: self.applied_transforms(path),
+            body: String::new(),
+        }
+    }
+
+    /// `text` under `--anonymize`.
+    fn anonymized<'a>(&self, text: &'a str) -> std::borrow::Cow<'a, str> {
+        match &self.anonymizer {
+            Some(anonymizer) => std::borrow::Cow::Owned(anonymizer.apply(text)),
+            None => std::borrow::Cow::Borrowed(text),
+        }
+    }
+
+    fn schema_header(&self) -> schema::Header {
+        let rev = crate::git::run(
+            &self.root,
+            &["rev-parse", self.rev.as_deref().unwrap_or("HEAD")],
+        )
+        .ok()
+        .map(|s| s.trim().to_string());
+        schema::Header {
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            rev,
+        }
+    }
+}
+
+/// `=== [STATUS ]crate vX.Y.Z [annotations] :: rel/path [tag] ===`
+pub fn text_header(file: &schema::File, stats: bool) -> String {
+    let label = label(file, stats);
+    match &file.tag {
+        Some(tag) => format!("=== {label} :: {} [{tag}] ===\n", file.path),
+        None => format!("=== {label} :: {} ===\n", file.path),
+    }
+}
+
+/// `=== # title ===`: the `#` sets sections apart from file headers.
+pub fn text_section_header(title: &str) -> String {
+    format!("=== # {title} ===\n")
+}
+
+/// ``### `rel/path` — [STATUS ]crate vX.Y.Z [annotations] [tag]``
+fn markdown_header(file: &schema::File, stats: bool) -> String {
+    let label = label(file, stats);
+    match &file.tag {
+        Some(tag) => format!("### `{}` — {label} [{tag}]\n", file.path),
+        None => format!("### `{}` — {label}\n", file.path),
+    }
+}
+
+/// `[STATUS ]crate vX.Y.Z [annotations] [tag]`
+fn label_with_tag(file: &schema::File, stats: bool) -> String {
+    let label = label(file, stats);
+    match &file.tag {
+        Some(tag) => format!("{label} [{tag}]"),
+        None => label,
+    }
+}
+
+/// `text` escaped for XML content or a quoted attribute.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+/// `[STATUS ]crate vX.Y.Z [annotations]`, then `(N lines, ~T tokens)` with
+/// `stats`.
+fn label(file: &schema::File, stats: bool) -> String {
+    let mut label = match &file.status {
+        Some(status) => format!("{status} {} v{}", file.crate_name, file.version),
+        None => format!("{} v{}", file.crate_name, file.version),
+    };
+    if let Some(status) = &file.crates_io {
+        label.push_str(&format!(" [crates.io: {status}]"));
+    }
+    if let Some(note) = &file.note {
+        label.push_str(&format!(" [{note}]"));
+    }
+    if let Some(c) = &file.last_commit {
+        label.push_str(&format!(" [last: {} {} {}]", c.hash, c.date, c.author));
+    }
+    if stats {
+        let lines = if file.lines == 1 { "line" } else { "lines" };
+        label.push_str(&format!(
+            " ({} {lines}, ~{} tokens)",
+            file.lines,
+            tokens::human(file.tokens)
+        ));
+    }
+    label
+}
+
+/// `--format aider`: sections and the repo map as comments, then one
+/// `/add` or `/read-only` command per file.
+fn aider(doc: &schema::Document) -> String {
+    let mut out = String::from("# cargo-qp selection: `aider --load <this file>`\n");
+    for section in &doc.sections {
+        out.push_str(&format!("#\n# === {} ===\n", section.title));
+        for line in section.text.lines() {
+            if line.is_empty() {
+                out.push_str("#\n");
+            } else {
+                out.push_str(&format!("# {line}\n"));
+            }
+        }
+    }
+    let mut commands = String::new();
+    let mut map = String::new();
+    for file in &doc.files {
+        match (file.status.as_deref(), file.tag.as_deref()) {
+            (Some("DELETED"), _) => {}
+            (_, Some("signatures" | "over crate cap")) => {
+                map.push_str(&format!("# {}:\n", file.path));
+                let mut gap = true;
+                for line in file.body.lines() {
+                    if is_map_item(line) {
+                        if gap {
+                            map.push_str("# ⋮...\n");
+                        }
+                        map.push_str(&format!("# │{}\n", line.trim_end()));
+                        gap = false;
+                    } else {
+                        gap = true;
+                    }
+                }
+                map.push_str("# ⋮...\n");
+            }
+            (None, Some(_)) => commands.push_str(&format!("/read-only {}\n", file.path)),
+            _ => commands.push_str(&format!("/add {}\n", file.path)),
+        }
+    }
+    if !map.is_empty() {
+        out.push_str("#\n# === repo map ===\n");
+        out.push_str(&map);
+    }
+    out.push_str(&commands);
+    out
+}
+
+/// Whether `line` starts an item (`pub fn …`, `impl …`, …).
+fn is_map_item(line: &str) -> bool {
+    let mut rest = line.trim_start();
+    if let Some(after) = rest.strip_prefix("pub") {
+        rest = match after.strip_prefix('(') {
+            Some(scoped) => scoped.split_once(')').map_or(after, |(_, r)| r),
+            None => after,
+        }
+        .trim_start();
+    }
+    MAP_ITEMS.iter().any(|item| rest.starts_with(item))
+}
+
+/// `text`, ending in a newline so a closing fence starts its own line.
+fn with_newline(text: &str) -> std::borrow::Cow<'_, str> {
+    if text.is_empty() || text.ends_with('\n') {
+        text.into()
+    } else {
+        format!("{text}\n").into()
+    }
+}
+
+fn write_record(out: &mut dyn Write, record: &schema::Record) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, record)?;
+    writeln!(out)
+}
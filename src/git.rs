@@ -0,0 +1,480 @@
+//! Git access: enumeration goes through `gix` (no git binary needed, no
+//! stdout parsing); everything else wraps the `git` binary.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::atomic::AtomicBool,
+};
+
+use anyhow::{Context, Result};
+
+/// Runs `git <args>` in `dir` and returns stdout, failing on non-zero exit.
+pub fn run(dir: &Path, args: &[&str]) -> Result<String> {
+    Ok(String::from_utf8_lossy(&run_bytes(dir, args)?).into_owned())
+}
+
+/// `run`, without decoding stdout.
+pub fn run_bytes(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .with_context(|| format!("failed to run git {}", args[0]))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "`git {}` failed (exit {:?}): {}",
+            args[0],
+            output.status.code(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// `rev`, refused when git would take it for an option (`--output=…`
+/// writes a file): revisions reach git from the command line and from
+/// rpc requests alike.
+pub fn checked_rev(rev: &str) -> Result<&str> {
+    anyhow::ensure!(!rev.starts_with('-'), "`{rev}` is not a revision");
+    Ok(rev)
+}
+
+/// Canonical root of the worktree containing `dir` (honours linked worktrees
+/// and `GIT_DIR`/`GIT_WORK_TREE`).
+pub fn toplevel(dir: &Path) -> Result<PathBuf> {
+    let top = run(dir, &["rev-parse", "--show-toplevel"])?;
+    Ok(PathBuf::from(top.trim()).canonicalize()?)
+}
+
+/// Every path under `dir` that is *not* ignored, whether tracked or
+/// un-tracked, relative to `dir`. Falls back to `git ls-files` when gix
+/// can't open the repository.
+pub fn ls_files(dir: &Path) -> Result<Vec<String>> {
+    if let Ok(files) = gix_ls_files(dir) {
+        return Ok(files);
+    }
+    let out = run(dir, &["ls-files", "-co", "--exclude-standard", "-z"])?;
+    Ok(nul_separated(&out))
+}
+
+/// Index entries plus the untracked, non-ignored files of a dirwalk — the
+/// equivalent of `git ls-files -co --exclude-standard`.
+fn gix_ls_files(dir: &Path) -> Result<Vec<String>> {
+    use gix::dir::{entry, walk};
+
+    let repo = gix::discover_with_environment_overrides(dir)?;
+    let workdir = repo.workdir().context("bare repository")?.canonicalize()?;
+    let prefix = dir.canonicalize()?.strip_prefix(&workdir)?.to_path_buf();
+
+    let index = repo.index_or_empty()?;
+    let mut files: BTreeSet<String> = index
+        .entries()
+        .iter()
+        .map(|e| e.path(&index).to_string())
+        .collect();
+
+    let options = repo
+        .dirwalk_options()?
+        .emit_untracked(walk::EmissionMode::Matching)
+        .emit_tracked(false)
+        .emit_ignored(None)
+        .empty_patterns_match_prefix(false);
+    let mut collect = walk::delegate::Collect::default();
+    repo.dirwalk(
+        &index,
+        None::<&str>,
+        &AtomicBool::new(false),
+        options,
+        &mut collect,
+    )?;
+    files.extend(
+        collect
+            .unorded_entries
+            .into_iter()
+            .filter(|(e, _)| {
+                e.status == entry::Status::Untracked
+                    && matches!(e.disk_kind, Some(entry::Kind::File | entry::Kind::Symlink))
+            })
+            .map(|(e, _)| e.rela_path.to_string()),
+    );
+
+    Ok(files
+        .into_iter()
+        .filter_map(|rel| {
+            let rel = Path::new(&rel).strip_prefix(&prefix).ok()?;
+            Some(rel.to_string_lossy().into_owned())
+        })
+        .collect())
+}
+
+/// Gitignored paths under `dir`, a whole ignored directory as one `dir/`.
+pub fn ls_ignored(dir: &Path) -> Result<Vec<String>> {
+    let out = run(
+        dir,
+        &["ls-files", "-oi", "--exclude-standard", "--directory", "-z"],
+    )?;
+    Ok(nul_separated(&out))
+}
+
+/// Every path in the tree of `rev`, relative to `dir`.
+pub fn ls_tree(dir: &Path, rev: &str) -> Result<Vec<String>> {
+    let out = run(
+        dir,
+        &["ls-tree", "-r", "--name-only", "-z", checked_rev(rev)?],
+    )?;
+    Ok(nul_separated(&out))
+}
+
+/// Content of `rel` (relative to `dir`) as of `rev`.
+pub fn show(dir: &Path, rev: &str, rel: &str) -> Result<String> {
+    run(dir, &["show", &format!("{}:./{rel}", checked_rev(rev)?)])
+}
+
+/// `show`, as raw bytes.
+pub fn show_bytes(dir: &Path, rev: &str, rel: &str) -> Result<Vec<u8>> {
+    run_bytes(dir, &["show", &format!("{}:./{rel}", checked_rev(rev)?)])
+}
+
+/// Best common ancestor of `a` and `b`.
+pub fn merge_base(dir: &Path, a: &str, b: &str) -> Result<String> {
+    let (a, b) = (checked_rev(a)?, checked_rev(b)?);
+    Ok(run(dir, &["merge-base", a, b])?.trim().to_string())
+}
+
+/// Paths (relative to `dir`) that differ between `base` and `head`, or the
+/// working tree when `head` is `None`.
+pub fn changed_files(dir: &Path, base: &str, head: Option<&str>) -> Result<Vec<String>> {
+    let mut args = vec![
+        "diff",
+        "--name-only",
+        "--no-renames",
+        "--relative",
+        "-z",
+        checked_rev(base)?,
+    ];
+    args.extend(head.map(checked_rev).transpose()?);
+    Ok(nul_separated(&run(dir, &args)?))
+}
+
+pub struct Commit {
+    pub hash: String,
+    pub date: String,
+    pub author: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// The last `n` commits reachable from `rev` (default HEAD) touching any of
+/// `paths` (relative to `dir`). Streams `git log` and stops reading as soon
+/// as enough commits matched.
+pub fn log_touching(
+    dir: &Path,
+    rev: Option<&str>,
+    paths: &HashSet<String>,
+    n: usize,
+) -> Result<Vec<Commit>> {
+    let mut child = Command::new("git")
+        .args([
+            "-c",
+            "core.quotepath=off",
+            "log",
+            "--relative",
+            "--name-only",
+            "--date=short",
+            "--format=%x1e%h%x1f%ad%x1f%an%x1f%s%x1f%b%x1f",
+        ])
+        .args(rev.map(checked_rev).transpose()?)
+        .current_dir(dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("failed to run git log")?;
+    let stdout = child.stdout.take().context("git log has no stdout")?;
+
+    let mut commits = Vec::new();
+    for record in BufReader::new(stdout).split(0x1e) {
+        if commits.len() == n {
+            break;
+        }
+        let record = String::from_utf8_lossy(&record?).into_owned();
+        let fields: Vec<&str> = record.splitn(6, '\x1f').collect();
+        let [hash, date, author, subject, body, names] = fields[..] else {
+            continue;
+        };
+        if names.lines().any(|l| paths.contains(&unquote(l.trim()))) {
+            commits.push(Commit {
+                hash: hash.into(),
+                date: date.into(),
+                author: author.into(),
+                subject: subject.into(),
+                body: body.trim().into(),
+            });
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+    Ok(commits)
+}
+
+/// The commit that last touched a file.
+pub struct LastCommit {
+    pub hash: String,
+    /// Unix seconds, for ordering by recency.
+    pub time: i64,
+    pub date: String,
+    pub author: String,
+    pub subject: String,
+}
+
+/// Last commit (reachable from `rev`, default HEAD) for each of `paths`, in a
+/// single `git log --name-status` pass that stops once every path is found.
+/// Paths never committed are absent from the result.
+pub fn last_commits(
+    dir: &Path,
+    rev: Option<&str>,
+    paths: &HashSet<String>,
+) -> Result<HashMap<String, LastCommit>> {
+    let mut child = Command::new("git")
+        .args([
+            "-c",
+            "core.quotepath=off",
+            "log",
+            "--relative",
+            "--name-status",
+            "--no-renames",
+            "--format=%x1e%h%x1f%at%x1f%as%x1f%an%x1f%s%x1f",
+        ])
+        .args(rev.map(checked_rev).transpose()?)
+        .current_dir(dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("failed to run git log")?;
+    let stdout = child.stdout.take().context("git log has no stdout")?;
+
+    let mut found = HashMap::new();
+    for record in BufReader::new(stdout).split(0x1e) {
+        if found.len() == paths.len() {
+            break;
+        }
+        let record = String::from_utf8_lossy(&record?).into_owned();
+        let fields: Vec<&str> = record.splitn(6, '\x1f').collect();
+        let [hash, time, date, author, subject, names] = fields[..] else {
+            continue;
+        };
+        for line in names.lines() {
+            // `M\tpath`; deletions don't describe a file that still exists
+            let Some((status, rel)) = line.split_once('\t') else {
+                continue;
+            };
+            let rel = unquote(rel);
+            if status == "D" || !paths.contains(&rel) || found.contains_key(&rel) {
+                continue;
+            }
+            found.insert(
+                rel,
+                LastCommit {
+                    hash: hash.into(),
+                    time: time.parse().unwrap_or_default(),
+                    date: date.into(),
+                    author: author.into(),
+                    subject: subject.into(),
+                },
+            );
+        }
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+    Ok(found)
+}
+
+/// Blob ids of `rels` (relative to `dir`), also writing the blobs into the
+/// object database so they can be read back later.
+pub fn hash_objects(dir: &Path, rels: &[String]) -> Result<Vec<String>> {
+    let mut child = Command::new("git")
+        .args(["hash-object", "-w", "--stdin-paths"])
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("failed to run git hash-object")?;
+    let mut stdin = child.stdin.take().context("git hash-object has no stdin")?;
+    let input = rels.join("\n");
+    // feed stdin from a thread so a full stdout pipe can't deadlock us
+    let feeder = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    feeder.join().expect("stdin feeder panicked")?;
+    if !output.status.success() {
+        anyhow::bail!("`git hash-object` failed (exit {:?})", output.status.code());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(String::from)
+        .collect())
+}
+
+/// `rel → blob id` for every file in the tree of `rev`.
+pub fn tree_blobs(dir: &Path, rev: &str) -> Result<HashMap<String, String>> {
+    let out = run(dir, &["ls-tree", "-r", "-z", checked_rev(rev)?])?;
+    Ok(out
+        .split('\0')
+        .filter_map(|l| {
+            let (meta, path) = l.split_once('\t')?;
+            let id = meta.split_whitespace().nth(2)?;
+            Some((path.to_string(), id.to_string()))
+        })
+        .collect())
+}
+
+/// A long-running `git cat-file --batch`: many objects read without a
+/// process each.
+pub struct CatFile {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    /// `rev-parse --show-prefix` of `dir`, as object names are relative to
+    /// the repository root
+    prefix: String,
+}
+
+impl CatFile {
+    pub fn spawn(dir: &Path) -> Result<Self> {
+        let prefix = run(dir, &["rev-parse", "--show-prefix"])?
+            .trim_end()
+            .to_string();
+        let mut child = Command::new("git")
+            .args(["cat-file", "--batch"])
+            .current_dir(dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("failed to run git cat-file")?;
+        let stdin = child.stdin.take().context("git cat-file has no stdin")?;
+        let stdout = child.stdout.take().context("git cat-file has no stdout")?;
+        Ok(Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            prefix,
+        })
+    }
+
+    /// Content of `rel` (relative to the spawning directory) as of `rev`.
+    pub fn show(&mut self, rev: &str, rel: &str) -> Result<Vec<u8>> {
+        let object = format!("{}:{}{rel}", checked_rev(rev)?, self.prefix);
+        anyhow::ensure!(!object.contains('\n'), "`{object}` can't be batched");
+        writeln!(self.stdin, "{object}")?;
+        self.stdin.flush()?;
+        let mut header = String::new();
+        self.stdout.read_line(&mut header)?;
+        let header = header.trim_end();
+        if header.ends_with(" missing") || header.ends_with(" ambiguous") {
+            anyhow::bail!("{rel} is not in {rev}");
+        }
+        let size: usize = header
+            .rsplit(' ')
+            .next()
+            .and_then(|s| s.parse().ok())
+            .with_context(|| format!("unexpected `git cat-file` reply `{header}`"))?;
+        // the content, then a newline
+        let mut body = vec![0; size + 1];
+        self.stdout.read_exact(&mut body)?;
+        body.pop();
+        Ok(body)
+    }
+}
+
+impl Drop for CatFile {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Tree of the index (`git write-tree`): the staged state, as a tree-ish
+/// the `rev` functions accept. Fails while conflicts are unresolved.
+pub fn index_tree(dir: &Path) -> Result<String> {
+    Ok(run(dir, &["write-tree"])?.trim().to_string())
+}
+
+/// Content of the blob `id`.
+pub fn cat_blob(dir: &Path, id: &str) -> Result<String> {
+    run(dir, &["cat-file", "blob", id])
+}
+
+/// `cat_blob`, undecoded.
+pub fn cat_blob_bytes(dir: &Path, id: &str) -> Result<Vec<u8>> {
+    run_bytes(dir, &["cat-file", "blob", id])
+}
+
+/// Entries of `-z` output.
+pub fn nul_separated(out: &str) -> Vec<String> {
+    out.split('\0')
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Undoes git's C-style quoting of unusual paths (`"a\tb"`, `"caf\303\251"`)
+/// in output that has no `-z` mode; other paths are returned as is.
+pub fn unquote(path: &str) -> String {
+    let Some(inner) = path.strip_prefix('"').and_then(|p| p.strip_suffix('"')) else {
+        return path.to_string();
+    };
+    let mut bytes = Vec::with_capacity(inner.len());
+    let mut chars = inner.bytes().peekable();
+    while let Some(b) = chars.next() {
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        let Some(esc) = chars.next() else { break };
+        bytes.push(match esc {
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b't' => b'\t',
+            b'n' => b'\n',
+            b'v' => 0x0b,
+            b'f' => 0x0c,
+            b'r' => b'\r',
+            b'0'..=b'7' => {
+                let mut n = u32::from(esc - b'0');
+                for _ in 0..2 {
+                    match chars.peek() {
+                        Some(d @ b'0'..=b'7') => {
+                            n = n * 8 + u32::from(d - b'0');
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                n as u8
+            }
+            other => other,
+        });
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn option_like_revs_are_refused() {
+        assert!(checked_rev("--output=x").is_err());
+        assert_eq!(checked_rev("HEAD~1").unwrap(), "HEAD~1");
+    }
+
+    #[test]
+    fn unquote_octal_escapes() {
+        assert_eq!(unquote(r#""caf\303\251.rs""#), "café.rs");
+        assert_eq!(unquote(r#""a\tb\\c\"d""#), "a\tb\\c\"d");
+        assert_eq!(unquote(r#""x\1y""#), "x\u{1}y");
+        assert_eq!(unquote("plain/path.rs"), "plain/path.rs");
+    }
+}
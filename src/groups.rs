@@ -0,0 +1,96 @@
+//! Curated file groups that extensions alone can't express, included on top
+//! of the extension filter (`--docs`, `--schemas`, `--fixtures`,
+//! `--include-scripts`).
+//! * Patterns are globs relative to the snapshot root; member selection and
+//!   `exclude` globs still apply.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use globset::GlobSet;
+
+use crate::{config, Opts};
+
+/// `--docs`: READMEs (root and per crate), contributor docs and `docs/`.
+const DOCS: &[&str] = &[
+    "README.md",
+    "**/README.md",
+    "CONTRIBUTING.md",
+    "ARCHITECTURE.md",
+    "docs/**/*.md",
+];
+
+/// `--schemas`: interface definitions the Rust code mirrors.
+const SCHEMAS: &[&str] = &[
+    "**/*.proto",
+    "**/*.graphql",
+    "**/*.graphqls",
+    "**/*.gql",
+    "**/*.sql",
+    "**/*.schema.json",
+    "**/schemas/**/*.json",
+];
+
+/// `--include-scripts`: well-known build/task runner files. Extensionless
+/// shebang scripts are detected by content instead.
+const SCRIPTS: &[&str] = &[
+    "**/justfile",
+    "**/Justfile",
+    "**/.justfile",
+    "**/*.just",
+    "**/Makefile",
+    "**/makefile",
+    "**/GNUmakefile",
+    "**/*.mk",
+];
+
+/// `--fixtures`: insta snapshots and trybuild expected output.
+const FIXTURES: &[&str] = &[
+    "**/*.snap",
+    "**/*.snap.new",
+    "**/*.pending-snap",
+    "**/tests/ui/**/*.stderr",
+    "**/tests/ui/**/*.stdout",
+];
+
+/// `--fixtures`
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Fixtures {
+    /// Include fixtures in full
+    Include,
+    /// Include only the first `--fixture-lines` lines of each
+    Truncate,
+    /// Leave fixtures out, even when their extension is selected
+    Exclude,
+}
+
+/// Globs of every group enabled in `opts`.
+pub fn include_patterns(opts: &Opts) -> Vec<String> {
+    let mut patterns: Vec<&str> = Vec::new();
+    if opts.docs {
+        patterns.extend(DOCS);
+    }
+    if opts.schemas {
+        patterns.extend(SCHEMAS);
+    }
+    if opts.include_scripts {
+        patterns.extend(SCRIPTS);
+    }
+    if matches!(opts.fixtures, Some(Fixtures::Include | Fixtures::Truncate)) {
+        patterns.extend(FIXTURES);
+    }
+    patterns.into_iter().map(String::from).collect()
+}
+
+/// Globs `opts` rules out on top of the config's `exclude`.
+pub fn exclude_patterns(opts: &Opts) -> Vec<String> {
+    match opts.fixtures {
+        Some(Fixtures::Exclude) => FIXTURES.iter().map(|p| p.to_string()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Matches any fixture, whatever the policy.
+pub fn fixture_set() -> Result<GlobSet> {
+    let patterns: Vec<String> = FIXTURES.iter().map(|p| p.to_string()).collect();
+    config::glob_set(&patterns, "fixture")
+}
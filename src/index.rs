@@ -0,0 +1,710 @@
+//! `cargo qp index [--index-format scip]` — a SCIP code index of the
+//! selected `.rs` files (definitions and references), so code-intelligence
+//! tools (Sourcegraph, agents) can navigate the snapshot instead of
+//! grepping it.
+//! * Built from syn by default: items, methods, fields and variants are
+//!   definitions; an identifier naming one is a reference to it when the
+//!   name is unambiguous in its file, else in its crate, else in the
+//!   workspace. A bare name must be defined in its module or brought in by
+//!   `use`; private items count only in their module and its children;
+//!   members (fields, methods, variants) only after `.` within their file,
+//!   or after `Type::`; after `module::` only that module's items do.
+//! * `--resolver rust-analyzer` runs `rust-analyzer scip` instead and keeps
+//!   the documents of the selected files.
+//! * Written to `--output`, else `index.scip`. Symbols take rust-analyzer's
+//!   form, `rust-analyzer cargo <crate> <version> <module>/<Item>#…`.
+//! * Positions count lines from 0 and characters in code points, like every
+//!   SCIP consumer expects for `UTF32CodeUnitOffsetFromLineStart`.
+//! * The protobuf encoding is written by hand (`Pb`); SCIP needs only a few
+//!   message types. `--index-format` has its own values, apart from the
+//!   snapshot `--format`; SCIP is the only (and default) one so far.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use proc_macro2::{Spacing, Span, TokenStream, TokenTree};
+use syn::{spanned::Spanned, visit::Visit};
+
+use crate::{defs::Resolver, Ctx, Opts};
+
+/// Where the index goes without `--output`.
+pub const DEFAULT_FILE: &str = "index.scip";
+
+/// `SymbolRole.Definition`
+const DEFINITION: u64 = 1;
+/// `TextEncoding.UTF8`
+const UTF8: u64 = 1;
+/// `PositionEncoding.UTF32CodeUnitOffsetFromLineStart`
+const UTF32: u64 = 3;
+
+/// `cargo qp index --index-format`
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum IndexFormat {
+    /// SCIP protobuf, as Sourcegraph and `scip` tooling read it
+    Scip,
+}
+
+pub fn run(ctx: &Ctx, opts: &Opts, format: IndexFormat) -> Result<()> {
+    let files: Vec<PathBuf> = ctx
+        .selected()?
+        .into_iter()
+        .filter(|p| p.extension().is_some_and(|x| x == "rs") && ctx.crate_dir(p).is_some())
+        .collect();
+    let (index, documents) = match (format, opts.resolver) {
+        (IndexFormat::Scip, Resolver::Syn) => build(ctx, &files)?,
+        (IndexFormat::Scip, Resolver::RustAnalyzer) => from_rust_analyzer(ctx, &files)?,
+    };
+    let path = opts
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_FILE));
+    std::fs::write(&path, index).with_context(|| format!("failed to write {}", path.display()))?;
+    eprintln!("wrote {} ({documents} documents)", path.display());
+    Ok(())
+}
+
+//──────────────────────── syn ───────────────────────────────────────────────
+
+/// A definition: its symbol, where its name is, and the whole item.
+pub struct Def {
+    file: usize,
+    krate: String,
+    pub name: String,
+    symbol: String,
+    pub name_span: Span,
+    item_span: Span,
+    docs: String,
+    /// Module path of the defining file, `a/b/`.
+    module: String,
+    /// Has some `pub`; members count as public.
+    public: bool,
+    /// ctags kind name: `function`, `struct`, `field`, `method`, …
+    pub kind: &'static str,
+    /// The type or trait of a field, variant, method or associated item.
+    pub parent: Option<String>,
+    /// ctags kind of `parent`: `struct`, `enum`, `interface`, `implementation`.
+    pub scope: Option<&'static str>,
+}
+
+/// What comes right before an identifier.
+#[derive(Clone, PartialEq)]
+enum Before {
+    Dot,
+    /// `::`, with the identifier before it, if any.
+    Path(Option<String>),
+    /// Not a reference: a lifetime, label, or `name:` field or binding.
+    Skip,
+    Other,
+}
+
+fn build(ctx: &Ctx, files: &[PathBuf]) -> Result<(Vec<u8>, usize)> {
+    let mut sources = Vec::new();
+    let mut imports = Vec::new();
+    let mut defs = Vec::new();
+    for (i, path) in files.iter().enumerate() {
+        let src = String::from_utf8_lossy(&ctx.source_bytes(path)?).into_owned();
+        let (krate, version) = ctx.owner(path);
+        let module = module_path(ctx, path);
+        let mut used = Imports::default();
+        if let Ok(file) = syn::parse_file(&src) {
+            used.visit_file(&file);
+            let prefix = format!("rust-analyzer cargo {krate} {version} {module}");
+            defs.extend(definitions(&file, prefix).into_iter().map(|d| Def {
+                file: i,
+                krate: krate.clone(),
+                module: module.clone(),
+                ..d
+            }));
+        }
+        sources.push(src);
+        imports.push(used);
+    }
+
+    let mut by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, d) in defs.iter().enumerate() {
+        by_name.entry(d.name.as_str()).or_default().push(i);
+    }
+    let mut index = Pb::default();
+    index.message(1, metadata(ctx));
+    for (i, (path, src)) in files.iter().zip(&sources).enumerate() {
+        let krate = ctx.owner(path).0;
+        let module = module_path(ctx, path);
+        let own: Vec<&Def> = defs.iter().filter(|d| d.file == i).collect();
+        let at_def: HashSet<(usize, usize)> = own.iter().map(|d| start(d.name_span)).collect();
+
+        let mut doc = Pb::default();
+        doc.string(1, &ctx.rel(path).to_string_lossy().replace('\\', "/"));
+        for d in &own {
+            let mut occ = Pb::default();
+            occ.packed(1, &range(d.name_span));
+            occ.string(2, &d.symbol);
+            occ.uint(3, DEFINITION);
+            occ.packed(7, &range(d.item_span));
+            doc.message(2, occ);
+        }
+        let mut idents = Vec::new();
+        if let Ok(tokens) = src.parse::<TokenStream>() {
+            collect_idents(tokens, &mut idents);
+        }
+        for (name, span, before) in idents {
+            if at_def.contains(&start(span)) {
+                continue;
+            }
+            let visible = |d: &Def| d.public || (d.krate == krate && module.starts_with(&d.module));
+            let fits = |d: &Def| match &before {
+                Before::Dot => d.parent.is_some() && d.file == i,
+                Before::Path(None) => visible(d),
+                Before::Path(Some(q)) if d.parent.is_some() => {
+                    d.parent.as_ref() == Some(q) || (q == "Self" && d.file == i)
+                }
+                Before::Path(Some(q)) => {
+                    visible(d)
+                        && (last_module(d) == Some(q)
+                            || ["crate", "self", "super"].contains(&q.as_str())
+                            || d.krate.replace('-', "_") == *q)
+                }
+                Before::Skip => false,
+                Before::Other => {
+                    let used = &imports[i];
+                    let in_scope = d.module == module
+                        || used.names.contains(&d.name)
+                        || last_module(d).is_some_and(|m| used.globs.contains(m));
+                    d.parent.is_none() && visible(d) && in_scope
+                }
+            };
+            let Some(target) = by_name.get(name.as_str()).and_then(|candidates| {
+                let pick = |keep: &dyn Fn(&Def) -> bool| {
+                    let hits: Vec<usize> = candidates
+                        .iter()
+                        .copied()
+                        .filter(|&c| fits(&defs[c]) && keep(&defs[c]))
+                        .collect();
+                    (!hits.is_empty()).then_some(hits)
+                };
+                let hits = pick(&|d| d.file == i)
+                    .or_else(|| pick(&|d| d.krate == krate))
+                    .or_else(|| pick(&|_| true))?;
+                (hits.len() == 1).then(|| hits[0])
+            }) else {
+                continue;
+            };
+            let mut occ = Pb::default();
+            occ.packed(1, &range(span));
+            occ.string(2, &defs[target].symbol);
+            doc.message(2, occ);
+        }
+        for d in &own {
+            let mut info = Pb::default();
+            info.string(1, &d.symbol);
+            info.string(3, &d.docs);
+            info.string(6, &d.name);
+            doc.message(3, info);
+        }
+        doc.string(4, "rust");
+        doc.uint(6, UTF32);
+        index.message(2, doc);
+    }
+    Ok((index.0, files.len()))
+}
+
+fn metadata(ctx: &Ctx) -> Pb {
+    let mut tool = Pb::default();
+    tool.string(1, env!("CARGO_PKG_NAME"));
+    tool.string(2, env!("CARGO_PKG_VERSION"));
+    let mut meta = Pb::default();
+    meta.message(2, tool);
+    meta.string(3, &format!("file://{}/", ctx.root.display()));
+    meta.uint(4, UTF8);
+    meta
+}
+
+/// `a/b/` for `src/a/b.rs` or `src/a/b/mod.rs`; empty for crate roots and
+/// files outside `src/` (bins, tests, examples).
+fn module_path(ctx: &Ctx, path: &Path) -> String {
+    let Some(rel) = ctx
+        .crate_dir(path)
+        .and_then(|dir| path.strip_prefix(dir.join("src")).ok())
+    else {
+        return String::new();
+    };
+    if rel.starts_with("bin") {
+        return String::new();
+    }
+    let mut parts: Vec<String> = rel
+        .with_extension("")
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if parts.last().is_some_and(|p| p == "mod") {
+        parts.pop();
+    }
+    if parts.len() == 1 && (parts[0] == "lib" || parts[0] == "main") {
+        parts.clear();
+    }
+    parts.iter().map(|p| format!("{p}/")).collect()
+}
+
+/// Last segment of the module a definition is in.
+fn last_module(d: &Def) -> Option<&str> {
+    d.module.trim_end_matches('/').rsplit('/').next()
+}
+
+/// Names a file brings into scope with `use`, and the modules it imports
+/// with `*`.
+#[derive(Default)]
+struct Imports {
+    names: HashSet<String>,
+    globs: HashSet<String>,
+}
+
+impl<'ast> Visit<'ast> for Imports {
+    fn visit_use_path(&mut self, p: &'ast syn::UsePath) {
+        if matches!(*p.tree, syn::UseTree::Glob(_)) {
+            self.globs.insert(ident_name(&p.ident));
+        }
+        syn::visit::visit_use_path(self, p);
+    }
+
+    fn visit_use_name(&mut self, n: &'ast syn::UseName) {
+        self.names.insert(ident_name(&n.ident));
+    }
+
+    fn visit_use_rename(&mut self, r: &'ast syn::UseRename) {
+        self.names.insert(ident_name(&r.ident));
+    }
+}
+
+/// Every item, field, variant and associated item of `file`, in source
+/// order; symbols start with `prefix`.
+pub fn definitions(file: &syn::File, prefix: String) -> Vec<Def> {
+    let mut found = Definitions {
+        prefix: vec![prefix],
+        scopes: Vec::new(),
+        out: Vec::new(),
+    };
+    found.visit_file(file);
+    found.out
+}
+
+/// Every definition in a file, with descriptors nested under its module,
+/// type or trait.
+struct Definitions {
+    /// Symbol so far: scheme, package, modules, then the enclosing item.
+    prefix: Vec<String>,
+    /// ctags kinds of the enclosing items.
+    scopes: Vec<&'static str>,
+    out: Vec<Def>,
+}
+
+impl Definitions {
+    /// Records an item; `vis` is `None` for members.
+    fn add(
+        &mut self,
+        ident: &syn::Ident,
+        (suffix, kind): (&str, &'static str),
+        item: Span,
+        attrs: &[syn::Attribute],
+        vis: Option<&syn::Visibility>,
+    ) {
+        let name = ident_name(ident);
+        let parent = self
+            .prefix
+            .last()
+            .and_then(|p| p.strip_suffix('#'))
+            .filter(|_| self.prefix.len() > 1);
+        self.out.push(Def {
+            file: 0,
+            krate: String::new(),
+            symbol: format!("{}{name}{suffix}", self.prefix.concat()),
+            name,
+            name_span: ident.span(),
+            item_span: item,
+            docs: docs(attrs),
+            module: String::new(),
+            public: vis.is_none_or(|v| !matches!(v, syn::Visibility::Inherited)),
+            kind,
+            parent: parent.map(str::to_string),
+            scope: parent.and(self.scopes.last().copied()),
+        });
+    }
+
+    fn nested(
+        &mut self,
+        ident: &syn::Ident,
+        (suffix, kind): (&str, &'static str),
+        f: impl FnOnce(&mut Self),
+    ) {
+        self.prefix.push(format!("{}{suffix}", ident_name(ident)));
+        self.scopes.push(kind);
+        f(self);
+        self.scopes.pop();
+        self.prefix.pop();
+    }
+}
+
+impl<'ast> Visit<'ast> for Definitions {
+    fn visit_item_fn(&mut self, i: &'ast syn::ItemFn) {
+        self.add(
+            &i.sig.ident,
+            ("().", "function"),
+            i.span(),
+            &i.attrs,
+            Some(&i.vis),
+        );
+    }
+
+    fn visit_item_struct(&mut self, i: &'ast syn::ItemStruct) {
+        self.add(&i.ident, ("#", "struct"), i.span(), &i.attrs, Some(&i.vis));
+        self.nested(&i.ident, ("#", "struct"), |this| {
+            for field in &i.fields {
+                if let Some(ident) = &field.ident {
+                    this.add(ident, (".", "field"), field.span(), &field.attrs, None);
+                }
+            }
+        });
+    }
+
+    fn visit_item_enum(&mut self, i: &'ast syn::ItemEnum) {
+        self.add(&i.ident, ("#", "enum"), i.span(), &i.attrs, Some(&i.vis));
+        self.nested(&i.ident, ("#", "enum"), |this| {
+            for v in &i.variants {
+                this.add(&v.ident, (".", "enumerator"), v.span(), &v.attrs, None);
+            }
+        });
+    }
+
+    fn visit_item_union(&mut self, i: &'ast syn::ItemUnion) {
+        self.add(&i.ident, ("#", "struct"), i.span(), &i.attrs, Some(&i.vis));
+    }
+
+    fn visit_item_type(&mut self, i: &'ast syn::ItemType) {
+        self.add(&i.ident, ("#", "typedef"), i.span(), &i.attrs, Some(&i.vis));
+    }
+
+    fn visit_item_const(&mut self, i: &'ast syn::ItemConst) {
+        self.add(
+            &i.ident,
+            (".", "constant"),
+            i.span(),
+            &i.attrs,
+            Some(&i.vis),
+        );
+    }
+
+    fn visit_item_static(&mut self, i: &'ast syn::ItemStatic) {
+        self.add(
+            &i.ident,
+            (".", "variable"),
+            i.span(),
+            &i.attrs,
+            Some(&i.vis),
+        );
+    }
+
+    fn visit_item_macro(&mut self, i: &'ast syn::ItemMacro) {
+        if let Some(ident) = &i.ident {
+            self.add(ident, ("!", "macro"), i.span(), &i.attrs, None);
+        }
+    }
+
+    fn visit_item_mod(&mut self, i: &'ast syn::ItemMod) {
+        self.add(&i.ident, ("/", "module"), i.span(), &i.attrs, Some(&i.vis));
+        if let Some((_, items)) = &i.content {
+            self.nested(&i.ident, ("/", "module"), |this| {
+                for item in items {
+                    this.visit_item(item);
+                }
+            });
+        }
+    }
+
+    fn visit_item_trait(&mut self, i: &'ast syn::ItemTrait) {
+        self.add(
+            &i.ident,
+            ("#", "interface"),
+            i.span(),
+            &i.attrs,
+            Some(&i.vis),
+        );
+        self.nested(&i.ident, ("#", "interface"), |this| {
+            for item in &i.items {
+                match item {
+                    syn::TraitItem::Fn(f) => {
+                        this.add(&f.sig.ident, ("().", "method"), f.span(), &f.attrs, None)
+                    }
+                    syn::TraitItem::Const(c) => {
+                        this.add(&c.ident, (".", "constant"), c.span(), &c.attrs, None)
+                    }
+                    syn::TraitItem::Type(t) => {
+                        this.add(&t.ident, ("#", "typedef"), t.span(), &t.attrs, None)
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    fn visit_item_impl(&mut self, i: &'ast syn::ItemImpl) {
+        let syn::Type::Path(ty) = &*i.self_ty else {
+            return;
+        };
+        let Some(last) = ty.path.segments.last() else {
+            return;
+        };
+        self.nested(&last.ident, ("#", "implementation"), |this| {
+            for item in &i.items {
+                match item {
+                    syn::ImplItem::Fn(f) => {
+                        this.add(&f.sig.ident, ("().", "method"), f.span(), &f.attrs, None)
+                    }
+                    syn::ImplItem::Const(c) => {
+                        this.add(&c.ident, (".", "constant"), c.span(), &c.attrs, None)
+                    }
+                    syn::ImplItem::Type(t) => {
+                        this.add(&t.ident, ("#", "typedef"), t.span(), &t.attrs, None)
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+}
+
+fn ident_name(ident: &syn::Ident) -> String {
+    let name = ident.to_string();
+    name.strip_prefix("r#").map(str::to_string).unwrap_or(name)
+}
+
+/// `///` docs, one line each.
+fn docs(attrs: &[syn::Attribute]) -> String {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|a| a.path().is_ident("doc"))
+        .filter_map(|a| match &a.meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                value:
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(s),
+                        ..
+                    }),
+                ..
+            }) => Some(s.value().trim().to_string()),
+            _ => None,
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Every identifier, with what comes right before it.
+fn collect_idents(tokens: TokenStream, out: &mut Vec<(String, Span, Before)>) {
+    // the last identifier and the `:`s since; whether the last token was `.`
+    let (mut last, mut colons, mut dot) = (None::<String>, 0, false);
+    let mut before = Before::Other;
+    for tree in tokens {
+        match &tree {
+            TokenTree::Ident(ident) => {
+                let name = ident_name(ident);
+                out.push((name.clone(), ident.span(), before.clone()));
+                (last, colons, dot) = (Some(name), 0, false);
+                before = Before::Other;
+                continue;
+            }
+            TokenTree::Punct(p) if p.as_char() == ':' => {
+                colons += 1;
+                // `name: …` names a field, binding or label, not an item
+                let alone = p.spacing() == Spacing::Alone;
+                if let (1, true, Some(entry)) = (colons, alone, out.last_mut()) {
+                    if last.as_ref() == Some(&entry.0) {
+                        entry.2 = Before::Skip;
+                    }
+                }
+                before = if colons == 2 {
+                    Before::Path(last.clone())
+                } else {
+                    Before::Other
+                };
+                dot = false;
+                continue;
+            }
+            TokenTree::Punct(p) => {
+                // `..` and `..=` are ranges, not field access
+                let single = !dot && p.spacing() == Spacing::Alone;
+                before = match p.as_char() {
+                    '.' if single => Before::Dot,
+                    '\'' => Before::Skip,
+                    _ => Before::Other,
+                };
+                dot = p.as_char() == '.';
+                (last, colons) = (None, 0);
+                continue;
+            }
+            TokenTree::Group(g) => {
+                collect_idents(g.stream(), out);
+                before = Before::Other;
+            }
+            TokenTree::Literal(_) => before = Before::Other,
+        }
+        (last, colons, dot) = (None, 0, false);
+    }
+}
+
+fn start(span: Span) -> (usize, usize) {
+    (span.start().line, span.start().column)
+}
+
+/// `[line, char, char]` on one line, else `[line, char, line, char]`.
+fn range(span: Span) -> Vec<u64> {
+    let (a, b) = (span.start(), span.end());
+    let (la, lb) = (a.line as u64 - 1, b.line as u64 - 1);
+    if la == lb {
+        vec![la, a.column as u64, b.column as u64]
+    } else {
+        vec![la, a.column as u64, lb, b.column as u64]
+    }
+}
+
+//──────────────────────── rust-analyzer ─────────────────────────────────────
+
+/// `rust-analyzer scip`, reduced to the documents of `files`.
+fn from_rust_analyzer(ctx: &Ctx, files: &[PathBuf]) -> Result<(Vec<u8>, usize)> {
+    if ctx.rev.is_some() {
+        anyhow::bail!("--resolver rust-analyzer works on the working tree; drop --rev");
+    }
+    let bin = std::env::var_os("RUST_ANALYZER").unwrap_or_else(|| "rust-analyzer".into());
+    let tmp = std::env::temp_dir().join(format!("qp-index-{}.scip", std::process::id()));
+    let status = Command::new(&bin)
+        .arg("scip")
+        .arg(&ctx.root)
+        .arg("--output")
+        .arg(&tmp)
+        .current_dir(&ctx.root)
+        .status()
+        .context("failed to run rust-analyzer (rustup component add rust-analyzer)")?;
+    anyhow::ensure!(status.success(), "rust-analyzer scip failed ({status})");
+    let full = std::fs::read(&tmp).context("rust-analyzer wrote no index")?;
+    let _ = std::fs::remove_file(&tmp);
+
+    let keep: HashSet<String> = files
+        .iter()
+        .map(|p| ctx.rel(p).to_string_lossy().replace('\\', "/"))
+        .collect();
+    let mut index = Vec::new();
+    let mut documents = 0;
+    for field in fields(&full)? {
+        if field.number == 2 {
+            let path = fields(field.payload)?
+                .into_iter()
+                .find(|f| f.number == 1)
+                .map(|f| String::from_utf8_lossy(f.payload).into_owned());
+            if !path.is_some_and(|p| keep.contains(&p)) {
+                continue;
+            }
+            documents += 1;
+        }
+        index.extend_from_slice(field.raw);
+    }
+    Ok((index, documents))
+}
+
+/// A top-level protobuf field: its number, the bytes of the whole field and
+/// the payload of a length-delimited one.
+struct Field<'a> {
+    number: u64,
+    raw: &'a [u8],
+    payload: &'a [u8],
+}
+
+fn fields(mut buf: &[u8]) -> Result<Vec<Field<'_>>> {
+    fn varint(buf: &mut &[u8]) -> Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = buf.split_first().context("truncated protobuf")?;
+            *buf = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        anyhow::bail!("invalid protobuf varint")
+    }
+    let mut out = Vec::new();
+    while !buf.is_empty() {
+        let whole = buf;
+        let key = varint(&mut buf)?;
+        let payload_len = match key & 7 {
+            0 => {
+                varint(&mut buf)?;
+                0
+            }
+            1 => 8,
+            2 => varint(&mut buf)? as usize,
+            5 => 4,
+            wire => anyhow::bail!("unsupported protobuf wire type {wire}"),
+        };
+        anyhow::ensure!(buf.len() >= payload_len, "truncated protobuf");
+        let payload = &buf[..payload_len];
+        buf = &buf[payload_len..];
+        out.push(Field {
+            number: key >> 3,
+            raw: &whole[..whole.len() - buf.len()],
+            payload,
+        });
+    }
+    Ok(out)
+}
+
+//──────────────────────── protobuf ──────────────────────────────────────────
+
+/// A protobuf message being encoded; fields at their default are omitted.
+#[derive(Default)]
+struct Pb(Vec<u8>);
+
+impl Pb {
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.0.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.0.push(v as u8);
+    }
+
+    fn key(&mut self, field: u64, wire: u64) {
+        self.varint(field << 3 | wire);
+    }
+
+    fn uint(&mut self, field: u64, v: u64) {
+        if v != 0 {
+            self.key(field, 0);
+            self.varint(v);
+        }
+    }
+
+    fn bytes(&mut self, field: u64, bytes: &[u8]) {
+        self.key(field, 2);
+        self.varint(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn string(&mut self, field: u64, s: &str) {
+        if !s.is_empty() {
+            self.bytes(field, s.as_bytes());
+        }
+    }
+
+    fn message(&mut self, field: u64, m: Pb) {
+        self.bytes(field, &m.0);
+    }
+
+    fn packed(&mut self, field: u64, values: &[u64]) {
+        let mut p = Pb::default();
+        for &v in values {
+            p.varint(v);
+        }
+        self.bytes(field, &p.0);
+    }
+}
@@ -0,0 +1,171 @@
+//! `cargo qp init` — scaffold a commented `.cargo-qp.toml`.
+//! * Inspects the current selection for obvious exclude candidates:
+//!   generated code (`@generated` / "DO NOT EDIT" markers, `generated/` dirs),
+//!   vendored trees, and files far larger than the rest.
+
+use std::{collections::BTreeSet, fmt::Write, path::Path};
+
+use anyhow::Result;
+
+use crate::{config, tokens, Ctx};
+
+/// Files estimated above this many tokens are suggested as excludes.
+pub const HUGE_TOKENS: usize = 20_000;
+
+const GENERATED_DIRS: &[&str] = &["generated", "gen", "codegen", "autogen"];
+const VENDORED_DIRS: &[&str] = &["vendor", "third_party", "third-party"];
+
+pub fn run(ctx: &Ctx, force: bool) -> Result<()> {
+    let path = ctx.root.join(config::FILE_NAME);
+    if path.exists() && !force {
+        anyhow::bail!(
+            "{} already exists (use --force to overwrite)",
+            path.display()
+        );
+    }
+
+    let mut dirs = BTreeSet::new();
+    let mut generated = BTreeSet::new();
+    let mut huge = Vec::new();
+    for file in ctx.selected()? {
+        let rel = ctx.rel(&file).to_path_buf();
+        if let Some(dir) = marked_dir(&rel) {
+            dirs.insert(dir);
+            continue;
+        }
+        let text = ctx.read(&file)?;
+        if has_generated_marker(&text) {
+            generated.insert(rel.display().to_string());
+        } else if tokens::estimate(&text) > HUGE_TOKENS {
+            huge.push((rel.display().to_string(), tokens::estimate(&text)));
+        }
+    }
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "# cargo-qp configuration (generated by `cargo qp init`)."
+    )?;
+    writeln!(out, "# Command-line flags override these values.\n")?;
+    writeln!(
+        out,
+        "# Extensions to include when none are given on the command line."
+    )?;
+    writeln!(out, "# exts = [\"rs\", \"toml\"]\n")?;
+    writeln!(out, "# Skip files larger than this many bytes.")?;
+    writeln!(out, "# max-file-size = 262144\n")?;
+    writeln!(out, "# Token budget for snapshots and `--check`.")?;
+    writeln!(out, "# max-tokens = 200000\n")?;
+    writeln!(
+        out,
+        "# Tokens of full bodies per crate; the rest goes signatures-only."
+    )?;
+    writeln!(out, "# per-crate-cap = 30000")?;
+    writeln!(out, "# [crate-caps] is a table of per-crate overrides.\n")?;
+    writeln!(
+        out,
+        "# Ask before copying larger snapshots (bytes; 0 never asks)."
+    )?;
+    writeln!(out, "# confirm-size = 400000\n")?;
+    writeln!(
+        out,
+        "# Globs that must never be included; `--check` fails on them."
+    )?;
+    writeln!(out, "# deny = [\".env*\", \"**/*.pem\"]\n")?;
+    writeln!(
+        out,
+        "# More paths never to include, on top of .env*, keys and secrets/ (--allow-sensitive to override)."
+    )?;
+    writeln!(out, "# sensitive = [\"**/credentials.json\"]\n")?;
+    writeln!(
+        out,
+        "# Globs that replace the extension filter: only matching files (and manifests)."
+    )?;
+    writeln!(out, "# include = [\"src/**\", \"docs/**/*.md\"]\n")?;
+    writeln!(
+        out,
+        "# Files always sent whole, whatever the filters, budgets or gitignore say."
+    )?;
+    writeln!(out, "# extra = [\"NOTES.md\"]\n")?;
+    writeln!(out, "# Default `--output` for snapshots.")?;
+    writeln!(out, "# output = \"snapshot.txt\"\n")?;
+    writeln!(
+        out,
+        "# Globs (relative to this directory) that are never included."
+    )?;
+    writeln!(out, "exclude = [")?;
+    for dir in &dirs {
+        writeln!(out, "    \"{dir}/**\", # generated or vendored directory")?;
+    }
+    for file in &generated {
+        writeln!(out, "    \"{file}\", # has a generated-code marker")?;
+    }
+    for (file, toks) in &huge {
+        writeln!(out, "    \"{file}\", # ~{} tokens", tokens::human(*toks))?;
+    }
+    writeln!(out, "]")?;
+    writeln!(
+        out,
+        "\n# External transforms: read {{\"path\", \"body\"}} JSON on stdin, print {{\"body\"}}."
+    )?;
+    writeln!(out, "# [[transform]]")?;
+    writeln!(out, "# command = \"scripts/scrub.py\"")?;
+    writeln!(out, "# include = [\"**/*.rs\"]")?;
+    writeln!(
+        out,
+        "\n# Sandboxed WebAssembly filters/transforms (see the `plugin` module docs)."
+    )?;
+    writeln!(out, "# [[plugin]]")?;
+    writeln!(out, "# path = \"plugins/scrub.wasm\"")?;
+    writeln!(
+        out,
+        "\n# Local model for `--preamble auto` crate summaries: prompt on stdin, summary on stdout."
+    )?;
+    writeln!(out, "# [preamble]")?;
+    writeln!(out, "# command = \"ollama\"")?;
+    writeln!(out, "# args = [\"run\", \"llama3.2\"]")?;
+    writeln!(
+        out,
+        "\n# Dependency licenses `--dep-docs` may copy (default: permissive ones); \"warn\" copies the rest too."
+    )?;
+    writeln!(out, "# [licenses]")?;
+    writeln!(
+        out,
+        "# allow = [\"MIT\", \"Apache-2.0\", \"BSD-3-Clause\", \"MPL-2.0\"]"
+    )?;
+    writeln!(out, "# on-violation = \"refuse\"")?;
+    writeln!(
+        out,
+        "\n# Extra names `--anonymize` replaces (crates and modules always are)."
+    )?;
+    writeln!(out, "# [anonymize]")?;
+    writeln!(out, "# identifiers = [\"Acme\", \"acme.internal\"]")?;
+
+    std::fs::write(&path, out)?;
+    eprintln!(
+        "wrote {} ({} exclude suggestion(s))",
+        path.display(),
+        dirs.len() + generated.len() + huge.len()
+    );
+    Ok(())
+}
+
+/// `@generated` or "DO NOT EDIT" in the first lines.
+pub fn has_generated_marker(text: &str) -> bool {
+    text.lines()
+        .take(5)
+        .any(|l| l.contains("@generated") || l.contains("DO NOT EDIT"))
+}
+
+/// Closest ancestor directory named like generated or vendored code.
+pub fn marked_dir(rel: &Path) -> Option<String> {
+    let mut acc = Vec::new();
+    for comp in rel.parent()?.components() {
+        let name = comp.as_os_str().to_string_lossy();
+        acc.push(name.to_string());
+        if GENERATED_DIRS.contains(&name.as_ref()) || VENDORED_DIRS.contains(&name.as_ref()) {
+            return Some(acc.join("/"));
+        }
+    }
+    None
+}
@@ -0,0 +1,132 @@
+//! Per-file language, for markdown fences and the `language` field of JSON
+//! output.
+//! * Bodies that are patches (`cargo qp diff`, `diff vs` references) are
+//!   `diff`, whatever the file.
+//! * Then well-known file names (`Dockerfile`, `Makefile`, `justfile`,
+//!   `CMakeLists.txt`), the extension, and for extensionless files the
+//!   shebang interpreter (`#!/usr/bin/env python3` → `python`) or an XML/HTML
+//!   prolog. Anything else is `text`.
+//! * Names follow the fence tags GitHub and most markdown renderers know.
+
+/// The language of `body`, shown as the file at `path`.
+pub fn detect(path: &str, body: &str) -> &'static str {
+    if is_patch(body) {
+        return "diff";
+    }
+    let name = path.rsplit('/').next().unwrap_or(path);
+    if let Some(lang) = by_name(name) {
+        return lang;
+    }
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => by_extension(&ext.to_ascii_lowercase()),
+        _ => by_content(body),
+    }
+}
+
+fn is_patch(body: &str) -> bool {
+    body.starts_with("diff --git ")
+        || (body.starts_with("--- ") && body.lines().nth(1).is_some_and(|l| l.starts_with("+++ ")))
+}
+
+fn by_name(name: &str) -> Option<&'static str> {
+    let lang = match name {
+        "Dockerfile" | "Containerfile" => "dockerfile",
+        "Makefile" | "makefile" | "GNUmakefile" => "makefile",
+        "justfile" | "Justfile" | ".justfile" => "just",
+        "CMakeLists.txt" => "cmake",
+        "rust-toolchain" => "toml",
+        ".gitignore" | ".gitattributes" | ".dockerignore" => "gitignore",
+        ".editorconfig" => "ini",
+        _ if name.starts_with("Dockerfile.") || name.ends_with(".dockerfile") => "dockerfile",
+        _ => return None,
+    };
+    Some(lang)
+}
+
+fn by_extension(ext: &str) -> &'static str {
+    match ext {
+        "rs" => "rust",
+        "toml" | "lock" => "toml",
+        "md" | "markdown" => "markdown",
+        "json" | "jsonc" | "json5" => "json",
+        "proto" => "protobuf",
+        "graphql" | "graphqls" | "gql" => "graphql",
+        "sql" => "sql",
+        "yml" | "yaml" => "yaml",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "tsx",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "py" | "pyi" => "python",
+        "sh" | "bash" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "ps1" => "powershell",
+        "bat" | "cmd" => "batch",
+        "rb" => "ruby",
+        "pl" | "pm" => "perl",
+        "lua" => "lua",
+        "go" => "go",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "cs" => "csharp",
+        "php" => "php",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "xml" | "svg" | "xsd" => "xml",
+        "ini" | "cfg" => "ini",
+        "nix" => "nix",
+        "cmake" => "cmake",
+        "mk" => "makefile",
+        "just" => "just",
+        "wgsl" => "wgsl",
+        "glsl" | "vert" | "frag" => "glsl",
+        "hlsl" => "hlsl",
+        "csv" => "csv",
+        "diff" | "patch" => "diff",
+        _ => "text",
+    }
+}
+
+/// Shebang interpreter or markup prolog of an extensionless file.
+fn by_content(body: &str) -> &'static str {
+    let first = body
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .next()
+        .unwrap_or("");
+    if let Some(command) = first.strip_prefix("#!") {
+        let mut words = command.split_whitespace();
+        let mut program = words.next().unwrap_or("").rsplit('/').next().unwrap_or("");
+        if program == "env" {
+            program = words.find(|w| !w.starts_with('-')).unwrap_or("");
+        }
+        let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        return match program {
+            "python" => "python",
+            "sh" | "bash" | "dash" | "ksh" => "bash",
+            "zsh" => "zsh",
+            "fish" => "fish",
+            "node" | "deno" | "bun" => "javascript",
+            "ruby" => "ruby",
+            "perl" => "perl",
+            "lua" => "lua",
+            "pwsh" => "powershell",
+            "rust-script" | "cargo" => "rust",
+            _ => "text",
+        };
+    }
+    let first = first.trim_start();
+    if first.starts_with("<?xml") {
+        "xml"
+    } else if first.to_ascii_lowercase().starts_with("<!doctype html") || first.starts_with("<html")
+    {
+        "html"
+    } else {
+        "text"
+    }
+}
@@ -0,0 +1,1832 @@
+//! cargo-qp — turns a Rust workspace, or the part of it a question is
+//! about, into one prompt-ready snapshot on the clipboard.
+//! * Selection: every file git does not ignore, tracked or not, under the
+//!   workspace root cargo would find; `.rs` files and manifests by default,
+//!   cargo's package selection (`default-members`, `--workspace`,
+//!   `--exclude`), plus each selected crate's `ANCHORS`. Flags and
+//!   `.cargo-qp.toml` (see `config`) widen or narrow it; sensitive paths
+//!   never reach a snapshot.
+//! * Content comes from the working tree, the index, `HEAD` or a `--rev`
+//!   (see `source`) and passes through the `Transform` pipeline, cached
+//!   under `target/qp-cache/`.
+//! * Budgets (`--max-tokens`, `--per-crate-cap`, `--rank-by`) decide which
+//!   files stay whole, go signatures-only or drop out; repeated bodies are
+//!   emitted once (see `dedupe`).
+//! * Output goes to the clipboard, stdout or `--output` in one of the
+//!   `Format`s; exit codes tell the outcomes apart (see `exit`).
+//! * Subcommands cover the rest of the loop, from `diff` and `auto` to
+//!   `apply`, which writes a model's answer back, and `rpc`/`daemon` for
+//!   editor plugins.
+//! * As a library: `SnapshotBuilder` composes the same snapshots in-process
+//!   (for xtasks and editor plugins); `chunk_file` splits a file to a token
+//!   budget at item boundaries; `run` is the whole CLI.
+//!
+//! `cargo qp --help` describes every flag.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    ffi::OsString,
+    io::Write,
+    path::{Component, Path, PathBuf},
+    process::ExitCode,
+    sync::{Arc, Mutex, OnceLock},
+    time::Instant,
+};
+
+use anyhow::{Context, Result};
+use cache::Cache;
+use caps::Caps;
+use cargo_metadata::{Metadata, MetadataCommand};
+use cargo_toml::{Inheritable, Manifest};
+use clap::{Parser, Subcommand, ValueEnum, ValueHint};
+use config::Config;
+use defs::Resolver;
+use diagnostics::Tool;
+use emit::Sink;
+use explain::Rejected;
+use failures::Runner;
+use globset::GlobSet;
+use preamble::Preamble;
+use progress::Progress;
+use rank::RankBy;
+use rayon::prelude::*;
+use style::Style;
+
+mod analyzer;
+mod anonymize;
+mod apidiff;
+mod apply;
+mod auto;
+mod benches;
+mod cache;
+mod caps;
+mod cfg;
+mod check;
+mod chunk;
+mod config;
+mod conflicts;
+mod crateinfo;
+mod daemon;
+mod dedupe;
+mod defs;
+mod depdocs;
+mod desktop;
+mod diagnostics;
+mod diff;
+mod doctor;
+mod emit;
+mod envinfo;
+mod exit;
+mod explain;
+mod failures;
+mod focus;
+mod format;
+mod git;
+mod groups;
+mod index;
+mod init;
+mod lang;
+mod licenses;
+mod list;
+mod lockdiff;
+mod manifest;
+mod order;
+mod overview;
+mod patch;
+mod pii;
+mod pkgfilter;
+#[cfg(feature = "wasm")]
+mod plugin;
+mod pr;
+mod preamble;
+mod progress;
+mod published;
+mod rank;
+mod refs;
+mod repomix;
+mod review;
+mod rpc;
+pub mod schema;
+mod secrets;
+mod session;
+mod snapshot;
+mod source;
+mod stats;
+mod style;
+mod suggest;
+mod syntax;
+mod tags;
+mod testsfor;
+mod textdiff;
+mod tokens;
+mod transform;
+mod users;
+mod watch;
+
+pub use chunk::{chunk_file, Chunk};
+pub use dedupe::Dedupe;
+pub use emit::ClipboardPolicy;
+pub use format::Format;
+pub use groups::Fixtures;
+pub use order::Order;
+pub use snapshot::{Snapshot, SnapshotBuilder};
+pub use source::Source;
+pub use transform::{FileEntry, Filter, Transform};
+
+type CrateMap = HashMap<PathBuf, (String, String)>;
+
+/// Files (relative to a crate's directory) included for every crate with a
+/// selected file, so its module structure stays visible. Not added to an
+/// exact selection (`--against`, `--users-of`, …), which must stay as given.
+const ANCHORS: &[&str] = &["Cargo.toml", "build.rs", "src/lib.rs", "src/main.rs"];
+
+/// `cargo qp [OPTIONS] [ext ...] [COMMAND]`
+#[derive(Parser)]
+#[command(name = "cargo-qp", version, about)]
+struct Opts {
+    #[command(subcommand)]
+    cmd: Option<Cmd>,
+
+    /// Directory to operate in (defaults to cwd); cargo-qp walks up from it
+    /// to the workspace root, within the git worktree
+    #[arg(short, long, global = true, value_hint = ValueHint::DirPath, default_value = ".")]
+    dir: PathBuf,
+
+    /// Use `--dir` as the root instead of walking up to the workspace root
+    /// (or, without one, the nearest `Cargo.toml`)
+    #[arg(long, global = true)]
+    no_discover: bool,
+
+    /// Extra extensions to include (default: rs toml)
+    exts: Vec<String>,
+
+    /// Print to stdout only (same as `--clipboard never`)
+    #[arg(long, global = true, conflicts_with = "clipboard")]
+    no_clipboard: bool,
+
+    /// When to copy to the clipboard; `auto` skips it when stdout is not a
+    /// terminal or `CI` is set
+    #[arg(long, global = true, value_enum, default_value_t = ClipboardPolicy::Auto)]
+    clipboard: ClipboardPolicy,
+
+    /// Write the snapshot to this file instead of the clipboard
+    #[arg(short, long, global = true, value_hint = ValueHint::FilePath)]
+    output: Option<PathBuf>,
+
+    /// Also include READMEs, CONTRIBUTING.md and docs/**/*.md
+    #[arg(long)]
+    docs: bool,
+
+    /// Also include .proto, GraphQL, SQL and JSON schema files
+    #[arg(long)]
+    schemas: bool,
+
+    /// Also include justfiles, Makefiles and extensionless scripts with a
+    /// shebang line
+    #[arg(long)]
+    include_scripts: bool,
+
+    /// Also apply each crate's `package.include` / `package.exclude`
+    #[arg(long)]
+    respect_package_filters: bool,
+
+    /// Always include this file, whatever filters, budgets or gitignore say
+    /// (repeatable)
+    #[arg(long, value_name = "PATH")]
+    extra: Vec<PathBuf>,
+
+    /// Include `.env*`, keys, `secrets/` and other sensitive paths, which are
+    /// otherwise always left out
+    #[arg(long)]
+    allow_sensitive: bool,
+
+    /// Emit the files `cargo clippy` flags plus the lint messages; `=LINT`
+    /// keeps one lint
+    #[arg(long, value_name = "LINT", num_args = 0..=1, require_equals = true)]
+    from_clippy: Option<Option<String>>,
+
+    /// Run the tests and emit the failing tests' code and output; `=FILTER`
+    /// is passed to the test runner
+    #[arg(
+        long,
+        value_name = "FILTER",
+        num_args = 0..=1,
+        require_equals = true,
+        conflicts_with = "from_clippy"
+    )]
+    from_test: Option<Option<String>>,
+
+    /// Emit the workspace files a pasted backtrace points at; reads the
+    /// clipboard, or `=FILE` (`-` for stdin)
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        conflicts_with_all = ["from_clippy", "from_test"]
+    )]
+    from_backtrace: Option<Option<PathBuf>>,
+
+    /// Emit the workspace files referenced as `path:line[:col]` in a tool's
+    /// output (`-` for stdin)
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["from_clippy", "from_test", "from_backtrace"]
+    )]
+    from_log: Option<PathBuf>,
+
+    /// Test runner for `--from-test`
+    #[arg(long, value_enum, default_value_t = Runner::Cargo)]
+    test_runner: Runner,
+
+    /// Append the definitions of types and traits the selected files use
+    /// from unselected workspace files
+    #[arg(long)]
+    pull_defs: bool,
+
+    /// Only files using this file (a module) or item, and the target itself
+    #[arg(long, value_name = "PATH|SYMBOL")]
+    users_of: Option<String>,
+
+    /// Only this file or directory and the tests exercising it (repeatable)
+    #[arg(long, value_name = "PATH")]
+    with_tests_for: Vec<PathBuf>,
+
+    /// Only this file or item and the criterion benches measuring it, with
+    /// their latest results
+    #[arg(long, value_name = "PATH|SYMBOL")]
+    benches_for: Option<String>,
+
+    /// Append the public API (signatures and doc summaries) of these
+    /// dependencies, or of the most used ones; only those the `[licenses]`
+    /// policy allows
+    #[arg(
+        long,
+        value_name = "NAME,…",
+        num_args = 0..,
+        value_delimiter = ',',
+        require_equals = true
+    )]
+    dep_docs: Option<Vec<String>>,
+
+    /// Append the public API changes since BASE (default: the latest tag),
+    /// as `cargo qp api-diff` reports them
+    #[arg(long, value_name = "BASE", num_args = 0..=1, require_equals = true)]
+    api_diff: Option<Option<String>>,
+
+    /// Name resolution for `--pull-defs`, `--users-of` and `index`
+    #[arg(long, global = true, value_enum, default_value_t = Resolver::Syn)]
+    resolver: Resolver,
+
+    /// Snapshot-test fixtures (`.snap`, `tests/ui/*.stderr`): pull them in,
+    /// in full or truncated, or keep them out
+    #[arg(long, value_enum, value_name = "POLICY")]
+    fixtures: Option<Fixtures>,
+
+    /// Lines kept per fixture with `--fixtures truncate`
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    fixture_lines: usize,
+
+    /// Full source under PATH, signatures for the rest of its crate, and
+    /// only manifests and file trees for other crates
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["conflicts", "since_manifest", "follow_up"])]
+    focus: Option<PathBuf>,
+
+    /// Workspace overview: every member's minified manifest, the file tree
+    /// and the dependency graph, no source
+    #[arg(long, conflicts_with_all = ["conflicts", "since_manifest", "follow_up", "focus"])]
+    manifests_only: bool,
+
+    /// Tokens of full bodies per crate; later files of a crate over its cap
+    /// are emitted signatures-only
+    #[arg(long, value_name = "N", value_parser = tokens::parse)]
+    per_crate_cap: Option<usize>,
+
+    /// Include every workspace member, not just `default-members`
+    #[arg(long, global = true)]
+    workspace: bool,
+
+    /// Leave out a workspace member (repeatable)
+    #[arg(long, global = true, value_name = "MEMBER")]
+    exclude: Vec<String>,
+
+    /// Snapshot the repository as of this revision instead of the working tree
+    #[arg(long, global = true, value_name = "REF")]
+    rev: Option<String>,
+
+    /// Read files from the index (staged), `HEAD` or the working tree
+    #[arg(long, global = true, value_enum, conflicts_with = "rev")]
+    from: Option<Source>,
+
+    /// Only files that differ from the merge-base with this branch, ending
+    /// with what changed in `Cargo.lock` instead of the raw lockfile
+    #[arg(long, value_name = "BRANCH")]
+    against: Option<String>,
+
+    /// Only conflicted files, plus their base/ours/theirs index versions
+    #[arg(long)]
+    conflicts: bool,
+
+    /// Record what the snapshot contained, paths, blob ids and tokens
+    /// (default file: qp-manifest.json)
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = manifest::DEFAULT_FILE)]
+    manifest: Option<PathBuf>,
+
+    /// Output format; `json` and `ndjson` follow the versioned schema, which
+    /// also describes the manifest file
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Preset for a chat UI: format, fences and file tree placement
+    #[arg(long, global = true, value_enum, conflicts_with = "format")]
+    style: Option<Style>,
+
+    /// Sequence of files in the snapshot
+    #[arg(long, value_enum, default_value_t = Order::Path)]
+    order: Order,
+
+    /// Which files win under a budget; with --max-tokens, the snapshot is
+    /// trimmed to fit, best first
+    #[arg(long, global = true, value_enum, value_name = "STRATEGY")]
+    rank_by: Option<RankBy>,
+
+    /// Emit repeated file content once (`near` also diffs look-alikes)
+    #[arg(long, value_enum, default_value_t = Dedupe::Exact)]
+    dedupe: Dedupe,
+
+    /// Emit only what changed since the snapshot recorded in this manifest
+    #[arg(long, value_name = "FILE")]
+    since_manifest: Option<PathBuf>,
+
+    /// Only files changed since the last follow-up (labelled UPDATED/NEW/DELETED);
+    /// updates the manifest afterwards
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = manifest::DEFAULT_FILE)]
+    follow_up: Option<PathBuf>,
+
+    /// No progress bar while reading large selections
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Edit the snapshot in `$VISUAL`/`$EDITOR` before it is copied
+    #[arg(long)]
+    open: bool,
+
+    /// Desktop notification when the snapshot is ready (watch mode, and runs
+    /// taking more than a few seconds)
+    #[arg(long, global = true)]
+    notify: bool,
+
+    /// After the snapshot, list every candidate path on stderr with why it
+    /// was included or excluded
+    #[arg(short = 'v', long, global = true)]
+    explain: bool,
+
+    /// Copy large snapshots without asking
+    #[arg(short, long, global = true)]
+    yes: bool,
+
+    /// Exit with status 2 when no files were emitted
+    #[arg(long, global = true)]
+    fail_if_empty: bool,
+
+    /// Token budget for the output (`30k`, `1.5m`); exceeding it exits with
+    /// status 3, or trims the snapshot with `--rank-by`
+    #[arg(long, global = true, value_name = "N", value_parser = tokens::parse)]
+    max_tokens: Option<usize>,
+
+    /// CI gate: emit nothing, fail on budget overruns, denied paths or secrets
+    #[arg(long)]
+    check: bool,
+
+    /// Prepend the rustc, cargo and toolchain versions and the host triple
+    #[arg(long, global = true)]
+    env_info: bool,
+
+    /// Prepend the last N commits touching the selected paths
+    #[arg(long, global = true, value_name = "N")]
+    log: Option<usize>,
+
+    /// Open with a summary of the workspace: crates, descriptions, edition
+    /// and key dependencies
+    #[arg(long, global = true, value_enum)]
+    preamble: Option<Preamble>,
+
+    /// Annotate file headers with the commit that last touched the file (one
+    /// `git log` pass)
+    #[arg(long, global = true)]
+    git_info: bool,
+
+    /// Abort on the first unreadable file instead of emitting a placeholder
+    #[arg(long, global = true)]
+    strict: bool,
+
+    /// Fail when a file gets no owning crate (a missing or broken manifest)
+    /// instead of warning
+    #[arg(long, global = true)]
+    strict_crates: bool,
+
+    /// Convert CRLF line endings to LF and strip byte-order marks
+    #[arg(long, global = true)]
+    normalize_eol: bool,
+
+    /// Add `(N lines, ~T tokens)` to every file header
+    #[arg(long, global = true)]
+    header_stats: bool,
+
+    /// Reduce Cargo.toml files to package, dependencies, features and
+    /// targets, without comments
+    #[arg(long, global = true)]
+    minify_manifests: bool,
+
+    /// Mask email addresses, IP addresses and phone numbers in comments and
+    /// string literals (in the whole body of non-Rust files)
+    #[arg(long, global = true)]
+    scrub_pii: bool,
+
+    /// Drop code and files behind `#[cfg]` predicates that are false for
+    /// this target triple
+    #[arg(long, global = true, value_name = "TRIPLE")]
+    target: Option<String>,
+
+    /// Like `--target`, for the host
+    #[arg(long, global = true, conflicts_with = "target")]
+    cfg_prune: bool,
+
+    /// Replace workspace crate and module names and `[anonymize]
+    /// identifiers` with neutral tokens, saving the mapping in
+    /// qp-anonymize.json; `cargo qp apply` reverses them
+    #[arg(long, global = true)]
+    anonymize: bool,
+
+    /// What to do with files that are not valid UTF-8
+    #[arg(long, global = true, value_enum, default_value_t = InvalidUtf8::Lossy)]
+    on_invalid_utf8: InvalidUtf8,
+
+    /// Annotate crate headers with crates.io status (unreleased/yanked/behind)
+    #[arg(long)]
+    check_published: bool,
+
+    /// Only use the local sparse-index cache for `--check-published`
+    #[arg(long)]
+    offline: bool,
+}
+
+/// `--on-invalid-utf8`
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InvalidUtf8 {
+    /// Replace invalid sequences with U+FFFD and flag the header
+    Lossy,
+    /// Emit a placeholder instead of the body
+    Skip,
+    /// Abort the run
+    Error,
+}
+
+#[derive(Subcommand)]
+enum Cmd {
+    /// Unified diffs between two refs (or BASE and the working tree)
+    Diff {
+        /// Base revision
+        base: String,
+        /// Head revision (defaults to the working tree)
+        head: Option<String>,
+        /// Also emit changed files in full and unchanged files signatures-only
+        #[arg(long)]
+        context: bool,
+    },
+    /// Dry run: list the files that would be included, with sizes and owners
+    List,
+    /// Repository report: per-crate totals, largest files, tests vs source
+    Stats,
+    /// Regenerate the snapshot whenever a selected file changes
+    Watch {
+        /// Quiet period before regenerating, in milliseconds
+        #[arg(long, default_value_t = 300)]
+        debounce: u64,
+    },
+    /// Public API changes per library crate: added, removed and changed
+    /// items
+    ApiDiff {
+        /// Base revision (defaults to the latest tag)
+        base: Option<String>,
+        /// Head revision (defaults to the working tree)
+        head: Option<String>,
+    },
+    /// The files most relevant to a question, fitted to the token budget
+    Auto {
+        /// The question, in plain words
+        question: String,
+        /// Budget for this model's context window unless --max-tokens is set
+        #[arg(long, value_name = "NAME")]
+        model: Option<String>,
+        /// Append the question as the last section
+        #[arg(long)]
+        prompt: bool,
+    },
+    /// Named conversations: a full snapshot, then deltas per turn
+    Session {
+        #[command(subcommand)]
+        action: session::Action,
+    },
+    /// Write a commented `.cargo-qp.toml` with detected exclude candidates
+    Init {
+        /// Overwrite an existing config
+        #[arg(long)]
+        force: bool,
+    },
+    /// Suggest `.cargo-qp.toml` exclude rules with the tokens each saves
+    SuggestExcludes {
+        /// Add the suggestions to the config (after asking)
+        #[arg(long)]
+        write: bool,
+        /// Don't ask before writing
+        #[arg(long, requires = "write")]
+        yes: bool,
+    },
+    /// Check git, cargo, clipboard and config health
+    Doctor,
+    /// Serve JSON-RPC over stdio (one message per line) for editor plugins
+    Rpc,
+    /// Serve the `rpc` methods on a Unix socket, keeping caches warm
+    Daemon {
+        /// Socket path (defaults to `target/qp-daemon.sock`)
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+    },
+    /// Write files from a pasted answer (qp sections or path-labelled fences)
+    Apply {
+        /// Read from this file (`-` for stdin) instead of the clipboard
+        input: Option<PathBuf>,
+        /// Show a diff per file instead of writing
+        #[arg(long)]
+        dry_run: bool,
+        /// Accept, reject or edit each hunk before it is written
+        #[arg(short = 'p', long, conflicts_with = "dry_run")]
+        interactive: bool,
+        /// Manifest of the snapshot the answer is based on, for merging
+        /// files changed since
+        #[arg(long, value_name = "FILE", default_value = manifest::DEFAULT_FILE)]
+        manifest: PathBuf,
+    },
+    /// GitHub pull request: description, comments, diff and touched files
+    Pr {
+        /// Pull request number
+        number: u64,
+    },
+    /// Trace why one file is (or isn't) in the snapshot
+    Why {
+        /// File to trace
+        path: PathBuf,
+    },
+    /// Code index (definitions, references) of the selected files
+    Index {
+        /// Index encoding (the global `--format` is for snapshots)
+        #[arg(long, value_enum, default_value_t = index::IndexFormat::Scip)]
+        index_format: index::IndexFormat,
+    },
+    /// ctags `tags` file of the selected files, for editor navigation
+    Tags,
+    /// The files `cargo check` diagnostics point at, then the diagnostics
+    CheckContext {
+        /// Extra `cargo check` arguments (after `--`)
+        #[arg(last = true)]
+        cargo_args: Vec<String>,
+    },
+}
+
+/// The `cargo-qp` command line; `args` includes the program name.
+pub fn run(args: impl IntoIterator<Item = OsString>) -> Result<ExitCode> {
+    let started = Instant::now();
+    let mut opts = Opts::parse_from(args);
+    style::apply(&mut opts);
+    let root = resolve_root(&opts.dir, !opts.no_discover)?;
+    if let Some(Cmd::Doctor) = opts.cmd {
+        doctor::run(&root)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(Cmd::Rpc) = opts.cmd {
+        let stdin = std::io::stdin().lock();
+        rpc::serve(&root, stdin, std::io::stdout().lock(), None)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(Cmd::Daemon { socket }) = &opts.cmd {
+        daemon::run(&root, socket.as_deref())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let config = match opts.cmd {
+        // a broken config must not stop `init --force` from replacing it
+        Some(Cmd::Init { .. }) => Config::default(),
+        _ => Config::load(&root)?,
+    };
+    if !matches!(opts.cmd, Some(Cmd::Index { .. } | Cmd::Tags)) {
+        opts.output = opts.output.take().or_else(|| config.output.clone());
+    }
+
+    let mut ctx = context(&opts, root, &config)?;
+
+    //--------------------------------------------------------
+    // 2. modes that print a report or act instead of emitting a snapshot
+    //--------------------------------------------------------
+    match &opts.cmd {
+        Some(Cmd::List) => {
+            print!("{}", list::render(&ctx)?);
+            return Ok(partial_exit(ctx.report_read_errors()));
+        }
+        Some(Cmd::Stats) => {
+            print!("{}", stats::render(&ctx)?);
+            return Ok(partial_exit(ctx.report_read_errors()));
+        }
+        Some(Cmd::Index { index_format }) => {
+            index::run(&ctx, &opts, *index_format)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Cmd::Tags) => {
+            tags::run(&ctx, &opts)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Cmd::Why { path }) => {
+            print!("{}", explain::why(&ctx, &config, &opts, path)?);
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Cmd::Doctor | Cmd::Rpc | Cmd::Daemon { .. }) => {
+            unreachable!("handled before context setup")
+        }
+        Some(Cmd::Apply {
+            input,
+            dry_run,
+            interactive,
+            manifest,
+        }) => {
+            apply::run(
+                &ctx,
+                anonymize::Anonymizer::saved(&ctx.root, &config)?.as_ref(),
+                input.as_deref(),
+                *dry_run,
+                *interactive,
+                &ctx.root.join(manifest),
+            )?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Cmd::Session {
+            action: action @ (session::Action::List | session::Action::Drop { .. }),
+        }) => {
+            session::manage(&ctx, action)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Cmd::Init { force }) => {
+            init::run(&ctx, *force)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Cmd::SuggestExcludes { write, yes }) => {
+            suggest::run(&ctx, *write, *yes)?;
+            return Ok(partial_exit(ctx.report_read_errors()));
+        }
+        Some(Cmd::Watch { debounce }) => {
+            watch::run(&mut ctx, &opts, *debounce)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        _ => {}
+    }
+
+    if opts.check {
+        return check::run(&mut ctx, &opts);
+    }
+
+    //--------------------------------------------------------
+    // 3. stream the output to a file, stdout or the clipboard
+    //--------------------------------------------------------
+    let mut out = Sink::open(&opts)?;
+    ctx.begin_output(&mut out)?;
+    // sessions record the intro and trailing tree inside each turn
+    let session = matches!(opts.cmd, Some(Cmd::Session { .. }));
+    if !session {
+        write_intro(&mut ctx, &opts, &mut out)?;
+    }
+    match &opts.cmd {
+        Some(Cmd::Diff {
+            base,
+            head,
+            context,
+        }) => diff::compose(&mut ctx, &mut out, base, head.as_deref(), *context)?,
+        Some(Cmd::ApiDiff { base, head }) => {
+            apidiff::compose(&mut ctx, &mut out, base.as_deref(), head.as_deref())?
+        }
+        Some(Cmd::Pr { number }) => pr::compose(&mut ctx, &mut out, *number)?,
+        Some(Cmd::Auto {
+            question,
+            model,
+            prompt,
+        }) => auto::compose(&mut ctx, &mut out, question, model.as_deref(), *prompt)?,
+        Some(Cmd::Session { action }) => session::compose(&mut ctx, &mut out, &opts, action)?,
+        Some(Cmd::CheckContext { cargo_args }) => {
+            diagnostics::compose(&mut ctx, &mut out, &opts, Tool::Check, cargo_args)?
+        }
+        _ if opts.from_backtrace.is_some() => {
+            let input = opts.from_backtrace.as_ref().and_then(|i| i.as_deref());
+            refs::backtrace(&mut ctx, &mut out, input)?
+        }
+        _ if opts.from_log.is_some() => {
+            let input = opts.from_log.as_deref().unwrap_or(Path::new("-"));
+            refs::log(&mut ctx, &mut out, input)?
+        }
+        _ if opts.from_test.is_some() => {
+            let filter = opts.from_test.as_ref().and_then(|f| f.as_deref());
+            failures::compose(&mut ctx, &mut out, &opts, filter)?
+        }
+        _ if opts.from_clippy.is_some() => {
+            let lint = opts.from_clippy.as_ref().and_then(|l| l.as_deref());
+            diagnostics::compose(&mut ctx, &mut out, &opts, Tool::Clippy { lint }, &[])?
+        }
+        _ => default_mode(&mut ctx, &opts, &mut out)?,
+    }
+    if !session {
+        style::tree(&mut ctx, &mut out, style::Tree::Bottom)?;
+    }
+    ctx.finish_output(&mut out)?;
+    ctx.report_unknown_crates()?;
+    if opts.explain {
+        eprint!("{}", explain::report(&ctx, &config, &opts)?);
+    }
+    let limit = config.confirm_size.unwrap_or(emit::CONFIRM_BYTES);
+    if !opts.yes && !out.confirm(&ctx, limit)? {
+        anyhow::bail!("snapshot not copied");
+    }
+    let copying = out.is_clipboard();
+    let clipboard_failed = out.finish()?;
+    if opts.notify && started.elapsed() >= desktop::SLOW {
+        let total = ctx.emitted.iter().map(|e| e.tokens).sum();
+        desktop::snapshot_ready(total, copying && !clipboard_failed);
+    }
+    let read_errors = ctx.report_read_errors();
+    Ok(exit::code(&ctx, &opts, read_errors, clipboard_failed))
+}
+
+/// Filters, crate lookup and per-run state for `opts` at `root`.
+fn context(opts: &Opts, root: PathBuf, config: &Config) -> Result<Ctx> {
+    context_with(opts, root, config, None)
+}
+
+/// `context`, reusing what a daemon kept from earlier requests.
+fn context_with(
+    opts: &Opts,
+    root: PathBuf,
+    config: &Config,
+    mut warm: Option<&mut daemon::Warm>,
+) -> Result<Ctx> {
+    // default extension set
+    let mut exts = if !opts.exts.is_empty() {
+        opts.exts.clone()
+    } else if let Some(exts) = &config.exts {
+        exts.clone()
+    } else {
+        vec!["rs".into(), "toml".into()]
+    };
+    if !exts.contains(&"toml".to_string()) {
+        exts.push("toml".into()); // ensure toml present so we keep workspace Cargo.toml
+    }
+
+    //--------------------------------------------------------
+    // 1. build crate map (workspace + loose crates)
+    //--------------------------------------------------------
+    let metadata = match warm.as_deref_mut() {
+        Some(warm) => warm.metadata(&root),
+        None => MetadataCommand::new()
+            .manifest_path(root.join("Cargo.toml"))
+            .exec()
+            .ok(),
+    };
+    let (rev, history) = source::resolve(&root, opts)?;
+    let crates = match &rev {
+        Some(rev) => rev_crate_map(&root, rev)?,
+        None => build_crate_map(&root, metadata.as_ref())?,
+    };
+    let crate_deps = metadata
+        .as_ref()
+        .map(order::internal_deps)
+        .unwrap_or_default();
+    let bin_crates = metadata.as_ref().map(order::binaries).unwrap_or_default();
+    let (members, skipped) = metadata
+        .as_ref()
+        .map(|md| member_selection(md, &root, opts))
+        .unwrap_or_default();
+
+    let only = match &opts.against {
+        Some(branch) => {
+            let head = history.as_deref().unwrap_or("HEAD");
+            let base = git::merge_base(&root, branch, head)?;
+            let changed = git::changed_files(&root, &base, rev.as_deref())?;
+            Some(
+                changed
+                    .into_iter()
+                    .filter(|rel| !lockdiff::is_lockfile(rel))
+                    .map(|rel| root.join(rel))
+                    .collect(),
+            )
+        }
+        None => None,
+    };
+
+    let stages = transform::pipeline(&root, config, opts)?;
+    anyhow::ensure!(
+        opts.rank_by != Some(RankBy::Relevance) || matches!(opts.cmd, Some(Cmd::Auto { .. })),
+        "--rank-by relevance needs a question; use `cargo qp auto \"<question>\"`"
+    );
+
+    let (mut cache, prior_commits) = match warm {
+        Some(warm) => (warm.cache(&root), warm.commits(&root, rev.as_deref())),
+        None => (Cache::load(&root), None),
+    };
+    let utf8 = opts.on_invalid_utf8.to_possible_value();
+    let utf8 = utf8.as_ref().map_or("", |v| v.get_name());
+    cache.set_pipeline(format!("{utf8} {}", stages.identity()));
+    let mut ctx = Ctx {
+        cache: Mutex::new(cache),
+        root,
+        exts,
+        crates,
+        members,
+        skipped,
+        rev,
+        history,
+        cat_file: Mutex::new(None),
+        only,
+        excludes: config::glob_set(
+            &[config.exclude.clone(), groups::exclude_patterns(opts)].concat(),
+            "exclude",
+        )?,
+        groups: config::glob_set(&groups::include_patterns(opts), "group")?,
+        includes: if config.include.is_empty() {
+            None
+        } else {
+            Some(config::glob_set(&config.include, "include")?)
+        },
+        shebangs: opts.include_scripts,
+        package_filters: None,
+        extra: Vec::new(),
+        deny: config.deny_set()?,
+        sensitive: if opts.allow_sensitive {
+            GlobSet::empty()
+        } else {
+            config::glob_set(&config.sensitive_patterns(), "sensitive")?
+        },
+        max_tokens: opts.max_tokens.or(config.max_tokens),
+        max_file_size: config.max_file_size,
+        check_published: opts.check_published,
+        offline: opts.offline,
+        git_info: opts.git_info,
+        on_invalid_utf8: opts.on_invalid_utf8,
+        strict: opts.strict,
+        quiet: opts.quiet,
+        strict_crates: opts.strict_crates,
+        unknown_crates: Mutex::new(BTreeSet::new()),
+        order: opts.order,
+        rank_by: opts.rank_by,
+        dedupe: opts.dedupe,
+        caps: Caps::new(config, opts),
+        preamble_hook: config.preamble.clone(),
+        licenses: config.licenses.clone().unwrap_or_default(),
+        crate_deps,
+        bin_crates,
+        filters: stages.filters,
+        pipeline: stages.transforms,
+        transforms: Mutex::new(HashMap::new()),
+        read_errors: Mutex::new(Vec::new()),
+        notes: Mutex::new(HashMap::new()),
+        last_commits: OnceLock::new(),
+        prior_commits: Mutex::new(prior_commits),
+        statuses: HashMap::new(),
+        format: opts.format,
+        style: opts.style,
+        header_stats: opts.header_stats,
+        document: None,
+        emitted: Vec::new(),
+        secrets: None,
+        anonymizer: None,
+    };
+    for rel in config.extra.iter().chain(&opts.extra) {
+        let path = ctx.pinned_path(rel)?;
+        if !ctx.extra.contains(&path) {
+            ctx.extra.push(path);
+        }
+    }
+    if opts.respect_package_filters {
+        ctx.package_filters = Some(pkgfilter::load(&ctx)?);
+    }
+    if opts.anonymize {
+        ctx.anonymizer = Some(anonymize::Anonymizer::build(&ctx, config)?);
+    }
+    if let Some(target) = &opts.users_of {
+        let users = users::select(&ctx, target, opts.resolver)?;
+        ctx.only = Some(match ctx.only.take() {
+            Some(only) => only.intersection(&users).cloned().collect(),
+            None => users,
+        });
+    }
+    if !opts.with_tests_for.is_empty() {
+        let selected = testsfor::select(&ctx, &opts.with_tests_for)?;
+        ctx.only = Some(match ctx.only.take() {
+            Some(only) => only.intersection(&selected).cloned().collect(),
+            None => selected,
+        });
+    }
+    if let Some(target) = &opts.benches_for {
+        let selected = benches::select(&ctx, target)?;
+        ctx.only = Some(match ctx.only.take() {
+            Some(only) => only.intersection(&selected).cloned().collect(),
+            None => selected,
+        });
+    }
+    Ok(ctx)
+}
+
+fn partial_exit(read_errors: bool) -> ExitCode {
+    if read_errors {
+        ExitCode::from(exit::PARTIAL)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Output of the subcommand-less invocation.
+fn default_mode(ctx: &mut Ctx, opts: &Opts, out: &mut dyn Write) -> Result<()> {
+    if opts.conflicts {
+        conflicts::compose(ctx, out)?;
+    } else if let Some(path) = &opts.follow_up {
+        return manifest::follow_up(ctx, out, &ctx.root.join(path));
+    } else if let Some(path) = &opts.since_manifest {
+        manifest::since(ctx, out, &ctx.root.join(path))?;
+    } else if opts.manifests_only {
+        overview::compose(ctx, out)?;
+    } else if let Some(path) = &opts.focus {
+        focus::compose(ctx, out, path)?;
+    } else {
+        snapshot(ctx, out)?;
+    }
+    if opts.benches_for.is_some() {
+        benches::append(ctx, out)?;
+    }
+    if let Some(branch) = &opts.against {
+        let head = ctx.rev.clone();
+        let base = git::merge_base(&ctx.root, branch, ctx.history().unwrap_or("HEAD"))?;
+        lockdiff::append(ctx, out, &base, head.as_deref())?;
+    }
+    if opts.pull_defs {
+        defs::append(ctx, out, opts.resolver)?;
+    }
+    if let Some(names) = &opts.dep_docs {
+        depdocs::append(ctx, out, names)?;
+    }
+    if let Some(base) = &opts.api_diff {
+        let head = ctx.rev.clone();
+        apidiff::compose(ctx, out, base.as_deref(), head.as_deref())?;
+    }
+    if let Some(path) = &opts.manifest {
+        manifest::build(ctx)?.write(&ctx.root.join(path))?;
+    }
+    Ok(())
+}
+
+/// Full-file snapshot of every non-ignored, selected path.
+fn snapshot(ctx: &mut Ctx, out: &mut dyn Write) -> Result<()> {
+    let paths = ctx.selected()?;
+    let bodies = ctx.read_all(&paths)?;
+    let mut files: Vec<(PathBuf, String)> = paths.into_iter().zip(bodies).collect();
+    order::apply(ctx, ctx.order, &mut files);
+    let refs = dedupe::references(ctx, &files, ctx.dedupe);
+    let mut refs = caps::limit(ctx, &files, refs);
+    let kept = match (ctx.rank_by, ctx.max_tokens) {
+        (Some(rank_by), Some(budget)) => {
+            let order = rank::priority(ctx, rank_by, &files, None);
+            let (kept, _) = rank::fit(ctx, &files, &order, &mut refs, 0, budget);
+            let dropped = kept.iter().filter(|k| !**k).count();
+            if dropped > 0 && !ctx.quiet {
+                eprintln!("{dropped} file(s) left out to fit --max-tokens {budget}");
+            }
+            kept
+        }
+        _ => vec![true; files.len()],
+    };
+    let grouped = matches!(ctx.order, Order::Crate | Order::Topo);
+    let mut last_crate = None;
+    for (((path, body), reference), _) in files.iter().zip(refs).zip(kept).filter(|(_, k)| *k) {
+        let dir = ctx.crate_dir(path).map(Path::to_path_buf);
+        if grouped && dir.is_some() && dir != last_crate {
+            if let Some((title, text)) = dir.as_deref().and_then(|d| crateinfo::section(ctx, d)) {
+                ctx.push_section(out, &title, &text)?;
+            }
+            last_crate = dir;
+        }
+        match reference {
+            Some((tag, body)) => ctx.push_file(out, path, Some(&tag), &body)?,
+            None => ctx.push_file(out, path, None, body)?,
+        }
+    }
+    Ok(())
+}
+
+/// `--env-info`, `--preamble`, `--log` and `--style` tree sections, ahead
+/// of the files.
+fn write_intro(ctx: &mut Ctx, opts: &Opts, out: &mut dyn Write) -> Result<()> {
+    if opts.env_info {
+        let (title, text) = envinfo::section(&ctx.root);
+        ctx.push_section(out, &title, &text)?;
+    }
+    if opts.preamble.is_some() {
+        preamble::write(ctx, opts, out)?;
+    }
+    if let Some(n) = opts.log {
+        let (title, text) = log_section(ctx, n)?;
+        ctx.push_section(out, &title, &text)?;
+    }
+    style::tree(ctx, out, style::Tree::Top)
+}
+
+/// `--log N`: recent commits touching the selected paths, as a section
+/// title and text.
+fn log_section(ctx: &Ctx, n: usize) -> Result<(String, String)> {
+    let paths: HashSet<String> = ctx
+        .selected()?
+        .iter()
+        .filter_map(|p| p.strip_prefix(&ctx.root).ok())
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect();
+    let commits = git::log_touching(&ctx.root, ctx.history(), &paths, n)?;
+
+    let title = format!("git log :: last {} commits", commits.len());
+    let mut out = String::new();
+    for c in &commits {
+        out.push_str(&format!(
+            "{} {} {} — {}\n",
+            c.hash, c.date, c.author, c.subject
+        ));
+        for line in c.body.lines() {
+            out.push_str(&format!("    {line}\n"));
+        }
+    }
+    Ok((title, out))
+}
+
+/// `--dir`, unless it lies outside the git worktree (e.g. `GIT_WORK_TREE`
+/// points elsewhere), in which case the worktree root. With `discover`, the
+/// enclosing workspace root instead (see `discover_root`).
+fn resolve_root(dir: &Path, discover: bool) -> Result<PathBuf> {
+    let dir = dir.canonicalize()?;
+    match git::toplevel(&dir) {
+        Ok(top) if !dir.starts_with(&top) => Ok(top),
+        Ok(top) if discover => Ok(discover_root(&dir, Some(&top))),
+        Err(_) if discover => Ok(discover_root(&dir, None)),
+        _ => Ok(dir),
+    }
+}
+
+/// Nearest ancestor of `dir` (up to `stop`) whose `Cargo.toml` has a
+/// `[workspace]` table, else the nearest one with any `Cargo.toml`, else
+/// `dir`.
+fn discover_root(dir: &Path, stop: Option<&Path>) -> PathBuf {
+    let mut package = None;
+    for cur in dir.ancestors() {
+        let manifest = cur.join("Cargo.toml");
+        if let Ok(text) = std::fs::read_to_string(&manifest) {
+            let is_workspace = text
+                .parse::<toml::Table>()
+                .is_ok_and(|t| t.contains_key("workspace"));
+            if is_workspace {
+                return cur.to_path_buf();
+            }
+            package.get_or_insert(cur);
+        }
+        if Some(cur) == stop {
+            break;
+        }
+    }
+    package.unwrap_or(dir).to_path_buf()
+}
+
+/// State shared by every output mode: root, filters and crate lookup.
+struct Ctx {
+    root: PathBuf,
+    exts: Vec<String>,
+    crates: CrateMap,
+    /// every workspace member dir, and the ones deselected by `member_selection`
+    members: Vec<PathBuf>,
+    skipped: Vec<PathBuf>,
+    /// read blobs from this revision (or tree, for `--from index`) instead
+    /// of the working tree
+    rev: Option<String>,
+    /// the commit history is taken from when `rev` is a tree
+    history: Option<String>,
+    /// `git cat-file --batch` reading `rev`'s blobs, started on first use
+    cat_file: Mutex<Option<git::CatFile>>,
+    /// restrict the selection to these paths (`--against`, `--users-of`,
+    /// `--with-tests-for`, `--benches-for`)
+    only: Option<HashSet<PathBuf>>,
+    /// `.cargo-qp.toml` filters
+    excludes: GlobSet,
+    /// files wanted regardless of extension (`--docs`, `--schemas`, …)
+    groups: GlobSet,
+    /// config `include` globs, replacing the extension filter when set
+    includes: Option<GlobSet>,
+    /// `--include-scripts`: also extensionless files starting with `#!`
+    shebangs: bool,
+    /// `--respect-package-filters`: the crates' own include/exclude lists
+    package_filters: Option<pkgfilter::PackageFilters>,
+    /// `--extra` and config `extra`: files always selected and sent whole,
+    /// read from the working tree even with `--rev`
+    extra: Vec<PathBuf>,
+    /// `deny` globs, enforced by `--check`
+    deny: GlobSet,
+    /// built-in and config `sensitive` globs; empty with `--allow-sensitive`
+    sensitive: GlobSet,
+    max_tokens: Option<usize>,
+    max_file_size: Option<u64>,
+    check_published: bool,
+    offline: bool,
+    statuses: HashMap<String, published::Status>,
+    git_info: bool,
+    on_invalid_utf8: InvalidUtf8,
+    /// applied in order by `selected`
+    filters: Vec<Arc<dyn Filter>>,
+    /// applied in order by `decode`
+    pipeline: Vec<Arc<dyn Transform>>,
+    order: Order,
+    /// `--rank-by`: priority under a budget
+    rank_by: Option<RankBy>,
+    dedupe: Dedupe,
+    /// `--per-crate-cap` and `[crate-caps]`
+    caps: Option<Caps>,
+    /// `[preamble]`: local model for `--preamble` summaries
+    preamble_hook: Option<config::PreambleHook>,
+    /// `[licenses]`, for `--dep-docs`
+    licenses: config::LicensePolicy,
+    /// `--anonymize`: applied to every emitted path, label, body and section
+    anonymizer: Option<anonymize::Anonymizer>,
+    /// workspace member → members it depends on, for `--order topo`
+    crate_deps: HashMap<String, Vec<String>>,
+    /// members without a library target, ranked last by `--order topo`
+    bin_crates: HashSet<String>,
+    /// transforms `decode` applied per file, copied into `Emitted`
+    transforms: Mutex<HashMap<PathBuf, Vec<String>>>,
+    /// fail on unreadable files instead of collecting them in `read_errors`
+    strict: bool,
+    /// `--strict-crates`: `report_unknown_crates` fails the run
+    strict_crates: bool,
+    /// files `owner` found no crate for
+    unknown_crates: Mutex<BTreeSet<PathBuf>>,
+    /// `--quiet`: no progress bar in `read_all`
+    quiet: bool,
+    read_errors: Mutex<Vec<(PathBuf, String)>>,
+    /// per-file header annotations found while reading (`lossy utf-8`, …)
+    notes: Mutex<HashMap<PathBuf, String>>,
+    /// the selection and its `rel → last commit`, gathered on first use
+    last_commits: OnceLock<daemon::Commits>,
+    /// commits a daemon kept from an earlier request, reused for the same
+    /// selection
+    prior_commits: Mutex<Option<daemon::Commits>>,
+    format: Format,
+    /// `--style`: tildes for colliding fences, file tree placement
+    style: Option<Style>,
+    /// `--header-stats`: lines and tokens in every file header
+    header_stats: bool,
+    /// `--format json` output collected until `finish_output`
+    document: Option<schema::Document>,
+    /// every file written by `push_file`, in output order
+    emitted: Vec<Emitted>,
+    /// `--check`: `(path, line, kind)` of likely secrets in emitted bodies
+    secrets: Option<Vec<(PathBuf, usize, &'static str)>>,
+    cache: Mutex<Cache>,
+}
+
+/// Bookkeeping for one emitted file body.
+struct Emitted {
+    path: PathBuf,
+    tag: Option<String>,
+    tokens: usize,
+    /// names of transforms applied to the body, in order
+    transforms: Vec<String>,
+}
+
+impl Ctx {
+    /// Every non-ignored path (via git) that passes the filters, sorted.
+    fn selected(&self) -> Result<Vec<PathBuf>> {
+        let files = match &self.rev {
+            Some(rev) => git::ls_tree(&self.root, rev)?,
+            None => git::ls_files(&self.root)?,
+        };
+        let listed: Vec<PathBuf> = files
+            .into_iter()
+            .map(|rel| self.root.join(rel))
+            .filter(|p| self.rev.is_some() || p.is_file())
+            .collect();
+        let mut wanted: Vec<PathBuf> = listed
+            .iter()
+            .filter(|p| self.wants(p))
+            .filter(|p| self.only.as_ref().is_none_or(|only| only.contains(*p)))
+            .cloned()
+            .collect();
+        // crate roots of every crate with a selected file, whatever the filters
+        let owners: HashSet<&Path> = wanted.iter().filter_map(|p| self.crate_dir(p)).collect();
+        let chosen: HashSet<PathBuf> = wanted.iter().cloned().collect();
+        wanted.extend(listed.into_iter().filter(|p| {
+            self.only.is_none()
+                && !chosen.contains(p)
+                && self.crate_dir(p).is_some_and(|dir| {
+                    owners.contains(dir)
+                        && ANCHORS
+                            .iter()
+                            .any(|a| p.strip_prefix(dir) == Ok(Path::new(a)))
+                })
+                && self.allowed(p)
+        }));
+        for filter in &self.filters {
+            let mut kept = Vec::with_capacity(wanted.len());
+            for p in wanted {
+                if filter
+                    .keep(self.rel(&p))
+                    .with_context(|| format!("filter `{}` failed", filter.name()))?
+                {
+                    kept.push(p);
+                }
+            }
+            wanted = kept;
+        }
+        for p in &self.extra {
+            if !wanted.contains(p) {
+                wanted.push(p.clone());
+            }
+        }
+        wanted.sort();
+        Ok(wanted)
+    }
+
+    /// `--extra` path `rel`, checked: an existing, non-sensitive file inside
+    /// the root.
+    fn pinned_path(&self, rel: &Path) -> Result<PathBuf> {
+        let inside = rel
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        let path: PathBuf = self.root.join(
+            rel.components()
+                .filter(|c| matches!(c, Component::Normal(_)))
+                .collect::<PathBuf>(),
+        );
+        anyhow::ensure!(
+            inside && path.is_file(),
+            "--extra {} is not a file under {}",
+            rel.display(),
+            self.root.display()
+        );
+        anyhow::ensure!(
+            !self.sensitive.is_match(self.rel(&path)),
+            "--extra {} is a sensitive path (--allow-sensitive to include it)",
+            rel.display()
+        );
+        Ok(path)
+    }
+
+    /// File content from the working tree, or from `--rev`.
+    /// An unreadable file (deleted mid-run, permissions, …) yields a
+    /// placeholder body and is reported by `report_read_errors`, unless
+    /// `--strict`.
+    fn read(&self, path: &Path) -> Result<String> {
+        match self.source_bytes(path) {
+            Ok(bytes) => self.decode(path, bytes),
+            Err(e) if self.strict => {
+                Err(e.context(format!("failed to read {}", self.rel(path).display())))
+            }
+            Err(e) => {
+                let msg = format!("{e:#}");
+                self.notes
+                    .lock()
+                    .unwrap()
+                    .insert(path.to_path_buf(), "unreadable".into());
+                let body = format!("(could not be read: {msg})\n");
+                self.read_errors
+                    .lock()
+                    .unwrap()
+                    .push((path.to_path_buf(), msg));
+                Ok(body)
+            }
+        }
+    }
+
+    /// Undecoded, untransformed content from the working tree, or from
+    /// `--rev`.
+    fn source_bytes(&self, path: &Path) -> Result<Vec<u8>> {
+        match &self.rev {
+            Some(rev) if !self.extra.iter().any(|p| p == path) => {
+                let rel = path.strip_prefix(&self.root).unwrap_or(path);
+                let rel = rel.to_string_lossy().replace('\\', "/");
+                if rel.contains('\n') {
+                    return git::show_bytes(&self.root, rev, &rel);
+                }
+                let mut cat_file = self.cat_file.lock().unwrap();
+                if cat_file.is_none() {
+                    *cat_file = Some(git::CatFile::spawn(&self.root)?);
+                }
+                cat_file.as_mut().unwrap().show(rev, &rel)
+            }
+            _ => std::fs::read(path).map_err(Into::into),
+        }
+    }
+
+    /// The commit whose history `--log`, `--git-info` and `--against`
+    /// follow: `--rev`, `HEAD` for `--from index`, else none (`HEAD`).
+    fn history(&self) -> Option<&str> {
+        self.history.as_deref()
+    }
+
+    /// Prints (and clears) the files `read` replaced with placeholders;
+    /// `true` if there were any.
+    fn report_read_errors(&self) -> bool {
+        let errors = std::mem::take(&mut *self.read_errors.lock().unwrap());
+        if errors.is_empty() {
+            return false;
+        }
+        eprintln!(
+            "warning: {} file(s) could not be read and were replaced by placeholders (--strict to fail):",
+            errors.len()
+        );
+        for (path, msg) in &errors {
+            eprintln!("  {}: {msg}", self.rel(path).display());
+        }
+        true
+    }
+
+    /// Warns about files labelled `unknown_crate` for a missing or broken
+    /// manifest; files of a virtual workspace root are expected to have no
+    /// crate. An error instead under `--strict-crates`.
+    fn report_unknown_crates(&self) -> Result<()> {
+        let paths = std::mem::take(&mut *self.unknown_crates.lock().unwrap());
+        let problems: Vec<(PathBuf, String)> = paths
+            .into_iter()
+            .filter_map(|p| {
+                let hint = unknown_crate_hint(&self.root, &p)?;
+                Some((p, hint))
+            })
+            .collect();
+        if problems.is_empty() {
+            return Ok(());
+        }
+        let mut msg = format!(
+            "{} file(s) have no owning crate and are labelled `unknown_crate v?`:",
+            problems.len()
+        );
+        for (path, hint) in &problems {
+            msg.push_str(&format!("\n  {}: {hint}", self.rel(path).display()));
+        }
+        if self.strict_crates {
+            anyhow::bail!(msg);
+        }
+        eprintln!("warning: {msg}\n(--strict-crates to fail)");
+        Ok(())
+    }
+
+    /// UTF-8 text of `path`'s content, following `--on-invalid-utf8`.
+    /// Then runs the transform pipeline (`--normalize-eol`, `[[transform]]`).
+    fn decode(&self, path: &Path, bytes: Vec<u8>) -> Result<String> {
+        let mut file = FileEntry {
+            path: self.rel(path).to_path_buf(),
+            body: self.decode_utf8(path, bytes)?,
+        };
+        let mut applied = Vec::new();
+        for transform in &self.pipeline {
+            let before = file.body.clone();
+            transform.apply(&mut file)?;
+            if file.body != before {
+                applied.push(transform.name().to_string());
+            }
+        }
+        let mut transforms = self.transforms.lock().unwrap();
+        if applied.is_empty() {
+            transforms.remove(path);
+        } else {
+            transforms.insert(path.to_path_buf(), applied);
+        }
+        Ok(file.body)
+    }
+
+    fn decode_utf8(&self, path: &Path, bytes: Vec<u8>) -> Result<String> {
+        let mut notes = self.notes.lock().unwrap();
+        let err = match String::from_utf8(bytes) {
+            Ok(text) => {
+                notes.remove(path);
+                return Ok(text);
+            }
+            Err(e) => e,
+        };
+        match self.on_invalid_utf8 {
+            InvalidUtf8::Lossy => {
+                notes.insert(path.to_path_buf(), "lossy utf-8".into());
+                Ok(String::from_utf8_lossy(err.as_bytes()).into_owned())
+            }
+            InvalidUtf8::Skip => {
+                notes.insert(path.to_path_buf(), "skipped: invalid utf-8".into());
+                Ok(format!(
+                    "({} bytes, not valid UTF-8)\n",
+                    err.as_bytes().len()
+                ))
+            }
+            InvalidUtf8::Error => anyhow::bail!(
+                "{} is not valid UTF-8 ({}); use --on-invalid-utf8 lossy|skip",
+                self.rel(path).display(),
+                err.utf8_error()
+            ),
+        }
+    }
+
+    /// `read` for every path at once, in parallel; results keep `paths` order.
+    /// Working-tree files the cache has seen unchanged are not read again,
+    /// but still decoded and transformed.
+    fn read_all(&self, paths: &[PathBuf]) -> Result<Vec<String>> {
+        let progress = Progress::new(paths.len(), self.quiet);
+        let mut cache = self.cache.lock().unwrap();
+        let seen = &*cache;
+        type Read = (String, Option<(std::fs::Metadata, Option<Vec<u8>>)>);
+        let read: Vec<Read> = paths
+            .par_iter()
+            .map(|p| {
+                let meta = self.rev.is_none().then(|| p.metadata().ok()).flatten();
+                let rel = self.rel(p).to_string_lossy();
+                let cached = meta.as_ref().and_then(|m| seen.bytes(&rel, m));
+                let read = match (cached, meta) {
+                    (Some(bytes), _) => (self.decode(p, bytes)?, None),
+                    (None, Some(m)) if seen.keeps_bodies() => match self.source_bytes(p) {
+                        Ok(bytes) => (self.decode(p, bytes.clone())?, Some((m, Some(bytes)))),
+                        Err(_) => (self.read(p)?, Some((m, None))),
+                    },
+                    (None, meta) => (self.read(p)?, meta.map(|m| (m, None))),
+                };
+                progress.read(&self.owner(p).0, read.0.len());
+                Ok(read)
+            })
+            .collect::<Result<_>>()?;
+        let mut bodies = Vec::with_capacity(read.len());
+        for (p, (body, fresh)) in paths.iter().zip(read) {
+            if let Some((meta, bytes)) = fresh {
+                cache.insert(&self.rel(p).to_string_lossy(), &meta, &body, bytes);
+            }
+            bodies.push(body);
+        }
+        Ok(bodies)
+    }
+
+    /// Last commit touching each selected file, from one `git log` pass
+    /// (empty outside a repository or with no history).
+    fn last_commits(&self) -> &HashMap<String, git::LastCommit> {
+        &self
+            .last_commits
+            .get_or_init(|| {
+                let paths: HashSet<String> = self
+                    .selected()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|p| self.rel(p).to_string_lossy().into_owned())
+                    .collect();
+                match self.prior_commits.lock().unwrap().take() {
+                    Some(prior) if prior.0 == paths => prior,
+                    _ => {
+                        let commits = git::last_commits(&self.root, self.history(), &paths)
+                            .unwrap_or_default();
+                        (paths, commits)
+                    }
+                }
+            })
+            .1
+    }
+
+    /// The cache and gathered commits, for a daemon to keep.
+    fn into_warm(self) -> (Cache, Option<daemon::Commits>) {
+        (
+            self.cache.into_inner().unwrap(),
+            self.last_commits.into_inner(),
+        )
+    }
+
+    /// `syntax::signatures`, cached by content hash.
+    fn signatures(&self, body: &str) -> Option<String> {
+        self.cache
+            .lock()
+            .unwrap()
+            .transformed(body, "signatures", syntax::signatures)
+    }
+
+    /// Extension filter, config excludes and workspace-member selection.
+    fn wants(&self, p: &Path) -> bool {
+        self.rejection(p).is_none()
+    }
+
+    /// The first of `wants`' rules that `p` fails.
+    fn rejection(&self, p: &Path) -> Option<Rejected> {
+        if self.sensitive.is_match(self.rel(p)) {
+            return Some(Rejected::Sensitive);
+        }
+        if self.excludes.is_match(self.rel(p)) {
+            return Some(Rejected::Glob);
+        }
+        if !self.selected_member(p) {
+            return Some(Rejected::Member);
+        }
+        if self.package_field(p).is_some() {
+            return Some(Rejected::Package);
+        }
+        if let (Some(max), None) = (self.max_file_size, &self.rev) {
+            if let Some(len) = p.metadata().ok().map(|m| m.len()).filter(|&len| len > max) {
+                return Some(Rejected::TooLarge(len));
+            }
+        }
+        let wanted = p.file_name() == Some("Cargo.toml".as_ref())
+            || self.groups.is_match(self.rel(p))
+            || match &self.includes {
+                Some(includes) => includes.is_match(self.rel(p)),
+                None => {
+                    p.extension()
+                        .and_then(|e| e.to_str())
+                        .is_some_and(|ext| self.exts.iter().any(|x| x == ext))
+                        || (self.shebangs && p.extension().is_none() && self.has_shebang(p))
+                }
+            };
+        match (wanted, &self.includes) {
+            (true, _) => None,
+            (false, Some(_)) => Some(Rejected::Include),
+            (false, None) => Some(Rejected::Extension),
+        }
+    }
+
+    /// Not sensitive, and not excluded by a glob or by member selection.
+    fn allowed(&self, p: &Path) -> bool {
+        let rel = self.rel(p);
+        !self.sensitive.is_match(rel)
+            && !self.excludes.is_match(rel)
+            && self.selected_member(p)
+            && self.package_field(p).is_none()
+    }
+
+    /// The `package.include`/`package.exclude` field leaving `p` out, with
+    /// `--respect-package-filters`.
+    fn package_field(&self, p: &Path) -> Option<&'static str> {
+        self.package_filters.as_ref()?.rejecting_field(self, p)
+    }
+
+    /// Outside every workspace member, or in one `member_selection` kept.
+    fn selected_member(&self, p: &Path) -> bool {
+        self.members
+            .iter()
+            .filter(|dir| p.starts_with(dir))
+            .max_by_key(|dir| dir.components().count())
+            .is_none_or(|dir| !self.skipped.contains(dir))
+    }
+
+    /// Directory of the crate owning `path`.
+    fn crate_dir(&self, path: &Path) -> Option<&Path> {
+        self.crates
+            .keys()
+            .filter(|dir| path.starts_with(dir))
+            .max_by_key(|dir| dir.components().count())
+            .map(PathBuf::as_path)
+    }
+
+    /// Whether the file (in the working tree, or at `--rev`) starts with `#!`.
+    fn has_shebang(&self, p: &Path) -> bool {
+        match &self.rev {
+            Some(_) => self
+                .source_bytes(p)
+                .is_ok_and(|bytes| bytes.starts_with(b"#!")),
+            None => {
+                let mut head = [0; 2];
+                std::fs::File::open(p)
+                    .and_then(|mut f| std::io::Read::read_exact(&mut f, &mut head))
+                    .is_ok_and(|()| &head == b"#!")
+            }
+        }
+    }
+
+    /// Owning crate's (name, version), or `unknown_crate v?` (remembered for
+    /// `report_unknown_crates`).
+    fn owner(&self, path: &Path) -> (String, String) {
+        crate_for_path(path, &self.crates).unwrap_or_else(|| {
+            self.unknown_crates
+                .lock()
+                .unwrap()
+                .insert(path.to_path_buf());
+            ("unknown_crate".into(), "?".into())
+        })
+    }
+
+    fn rel<'p>(&self, path: &'p Path) -> &'p Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    /// Writes one file in the `--format` and records it in `emitted`.
+    fn push_file(
+        &mut self,
+        out: &mut dyn Write,
+        path: &Path,
+        tag: Option<&str>,
+        body: &str,
+    ) -> std::io::Result<()> {
+        self.emit_file(out, path, None, tag, body)
+    }
+
+    fn applied_transforms(&self, path: &Path) -> Vec<String> {
+        self.transforms
+            .lock()
+            .unwrap()
+            .get(path)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Like `push_file`, with a status word (`UPDATED`, `NEW`, …) leading the
+    /// header label.
+    fn push_status(
+        &mut self,
+        out: &mut dyn Write,
+        path: &Path,
+        status: &str,
+        body: &str,
+    ) -> std::io::Result<()> {
+        self.emit_file(out, path, Some(status), None, body)
+    }
+}
+
+//──────────────────────── helpers ────────────────────────────────────────────
+
+fn build_crate_map(root: &Path, md: Option<&Metadata>) -> Result<CrateMap> {
+    let mut map = CrateMap::new();
+
+    // workspace crates
+    for pkg in md.map(|m| m.packages.as_slice()).unwrap_or_default() {
+        map.insert(
+            package_dir(pkg),
+            (pkg.name.clone(), pkg.version.to_string()),
+        );
+    }
+
+    // root crate (if not already covered)
+    let root_manifest = root.join("Cargo.toml");
+    if !map.contains_key(root) && root_manifest.exists() {
+        if let Ok(m) = Manifest::from_path(&root_manifest) {
+            if let Some(pkg) = m.package {
+                map.insert(root.to_path_buf(), (pkg.name, fmt_ver(&pkg.version)));
+            }
+        }
+    }
+    Ok(map)
+}
+
+/// Why no crate owns `path`: the nearest manifest up to `root` is missing,
+/// unparsable or has no `[package]`. `None` when it is a workspace manifest,
+/// whose directory legitimately holds crate-less files.
+fn unknown_crate_hint(root: &Path, path: &Path) -> Option<String> {
+    let start = if path.is_dir() {
+        Some(path)
+    } else {
+        path.parent()
+    };
+    let Some(manifest) = start
+        .into_iter()
+        .flat_map(Path::ancestors)
+        .take_while(|dir| dir.starts_with(root))
+        .map(|dir| dir.join("Cargo.toml"))
+        .find(|m| m.is_file())
+    else {
+        return Some(format!("no Cargo.toml between it and {}", root.display()));
+    };
+    let rel = manifest.strip_prefix(root).unwrap_or(&manifest).display();
+    let text = match std::fs::read_to_string(&manifest) {
+        Ok(text) => text,
+        Err(e) => return Some(format!("{rel} could not be read: {e}")),
+    };
+    let table = match text.parse::<toml::Table>() {
+        Ok(table) => table,
+        Err(e) => {
+            let first = e.to_string();
+            let first = first.lines().next().unwrap_or_default().to_string();
+            return Some(format!("{rel} does not parse: {first}"));
+        }
+    };
+    match (
+        table.contains_key("package"),
+        table.contains_key("workspace"),
+    ) {
+        (false, true) => None,
+        (false, false) => Some(format!("{rel} has no [package] table")),
+        (true, _) => match Manifest::from_slice(text.as_bytes()) {
+            Err(e) => Some(format!("{rel} is not a valid manifest: {e}")),
+            Ok(_) => Some(format!(
+                "{rel} could not be resolved (is it a workspace member?)"
+            )),
+        },
+    }
+}
+
+/// Crate map built from the manifests committed at `rev`.
+fn rev_crate_map(root: &Path, rev: &str) -> Result<CrateMap> {
+    let manifests: Vec<String> = git::ls_tree(root, rev)?
+        .into_iter()
+        .filter(|rel| Path::new(rel).file_name() == Some("Cargo.toml".as_ref()))
+        .collect();
+
+    let parse = |rel: &str| {
+        git::show(root, rev, rel)
+            .ok()
+            .and_then(|text| Manifest::from_slice(text.as_bytes()).ok())
+    };
+    let ws_version = parse("Cargo.toml")
+        .and_then(|m| m.workspace)
+        .and_then(|ws| ws.package)
+        .and_then(|p| p.version);
+
+    let mut map = CrateMap::new();
+    for rel in &manifests {
+        let Some(pkg) = parse(rel).and_then(|m| m.package) else {
+            continue;
+        };
+        let ver = match (&pkg.version, &ws_version) {
+            (Inheritable::Inherited { .. }, Some(v)) => v.clone(),
+            (v, _) => fmt_ver(v),
+        };
+        let dir = root.join(Path::new(rel).parent().unwrap_or(Path::new("")));
+        map.insert(dir, (pkg.name, ver));
+    }
+    Ok(map)
+}
+
+fn package_dir(pkg: &cargo_metadata::Package) -> PathBuf {
+    pkg.manifest_path
+        .parent()
+        .unwrap()
+        .as_std_path()
+        .to_path_buf()
+}
+
+/// Returns (all member dirs, deselected member dirs) following cargo's rules:
+/// `default-members` from the workspace root unless `--workspace`, minus any
+/// `--exclude`d member.
+fn member_selection(md: &Metadata, root: &Path, opts: &Opts) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let members = md.workspace_packages();
+    for name in &opts.exclude {
+        if !members.iter().any(|p| &p.name == name) {
+            eprintln!("warning: excluded package `{name}` not found in workspace");
+        }
+    }
+
+    let at_ws_root = md.workspace_root.as_std_path() == root;
+    let use_defaults = !opts.workspace
+        && !opts.manifests_only
+        && at_ws_root
+        && !cargo_metadata::workspace_default_members_is_missing(&md.workspace_default_members);
+
+    let skipped = members
+        .iter()
+        .filter(|p| {
+            opts.exclude.contains(&p.name)
+                || (use_defaults && !md.workspace_default_members.contains(&p.id))
+        })
+        .map(|p| package_dir(p))
+        .collect();
+    (members.iter().map(|p| package_dir(p)).collect(), skipped)
+}
+
+fn fmt_ver(v: &Inheritable<String>) -> String {
+    match v {
+        Inheritable::Set(s) => s.clone(),
+        _ => "<workspace>".into(),
+    }
+}
+
+fn crate_for_path(p: &Path, crates: &CrateMap) -> Option<(String, String)> {
+    crates
+        .iter()
+        .filter(|(root, _)| p.starts_with(root))
+        .max_by_key(|(root, _)| root.components().count())
+        .map(|(_, v)| v.clone())
+        .or_else(|| {
+            let mut cur = p.parent();
+            while let Some(dir) = cur {
+                let mani = dir.join("Cargo.toml");
+                if mani.exists() {
+                    if let Ok(m) = Manifest::from_path(&mani) {
+                        if let Some(pkg) = m.package {
+                            return Some((pkg.name, fmt_ver(&pkg.version)));
+                        }
+                    }
+                }
+                cur = dir.parent();
+            }
+            None
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn cli_is_consistent() {
+        Opts::command().debug_assert();
+    }
+}
@@ -0,0 +1,122 @@
+//! License policy for dependency source (`--dep-docs`): a dependency's
+//! declared `license` must be satisfiable with the `[licenses] allow` list
+//! (permissive licenses by default) before its code is copied into a prompt.
+//! * SPDX expressions are evaluated: `MIT OR GPL-3.0` passes on `MIT`,
+//!   `MIT AND GPL-3.0` needs both; `WITH` exceptions and a trailing `+` are
+//!   ignored, and the legacy `MIT/Apache-2.0` is read as `OR`.
+//! * No declared license, or only a `license-file`, is a violation.
+//! * `on-violation = "refuse"` (default) fails for a dependency named on the
+//!   command line and skips one picked automatically; `"warn"` copies it
+//!   anyway with a warning.
+
+use anyhow::Result;
+use cargo_metadata::Package;
+
+use crate::config::{LicensePolicy, Violation};
+
+/// `allow` when `[licenses]` doesn't set one.
+pub const PERMISSIVE: &[&str] = &[
+    "MIT",
+    "MIT-0",
+    "Apache-2.0",
+    "BSD-2-Clause",
+    "BSD-3-Clause",
+    "0BSD",
+    "ISC",
+    "Zlib",
+    "BSL-1.0",
+    "Unlicense",
+    "CC0-1.0",
+    "Unicode-DFS-2016",
+    "Unicode-3.0",
+];
+
+/// Whether `pkg`'s source may be copied; `named` when asked for explicitly.
+pub fn check(policy: &LicensePolicy, pkg: &Package, named: bool) -> Result<bool> {
+    let declared = pkg
+        .license
+        .as_deref()
+        .map(str::trim)
+        .filter(|l| !l.is_empty());
+    let fits = declared.is_some_and(|l| satisfied(l, policy));
+    if fits {
+        return Ok(true);
+    }
+    let what = match (declared, &pkg.license_file) {
+        (Some(license), _) => {
+            format!("is licensed `{license}`, which the allow list doesn't cover")
+        }
+        (None, Some(_)) => "has only a license file".to_string(),
+        (None, None) => "declares no license".to_string(),
+    };
+    let who = format!("`{}` v{}", pkg.name, pkg.version);
+    match policy.on_violation {
+        Violation::Warn => {
+            eprintln!("warning: {who} {what}; copying its source anyway");
+            Ok(true)
+        }
+        Violation::Refuse if named => anyhow::bail!(
+            "{who} {what}; refusing to copy its source (extend `[licenses] allow` \
+             or set `on-violation = \"warn\"` in .cargo-qp.toml)"
+        ),
+        Violation::Refuse => {
+            eprintln!("note: skipping {who}: it {what}");
+            Ok(false)
+        }
+    }
+}
+
+/// Whether the SPDX expression `license` holds with only allowed licenses.
+fn satisfied(license: &str, policy: &LicensePolicy) -> bool {
+    let allowed = |id: &str| {
+        let id = id.trim_end_matches('+');
+        match &policy.allow {
+            Some(allow) => allow.iter().any(|a| a.eq_ignore_ascii_case(id)),
+            None => PERMISSIVE.iter().any(|a| a.eq_ignore_ascii_case(id)),
+        }
+    };
+    let spaced = license
+        .replace('/', " OR ")
+        .replace('(', " ( ")
+        .replace(')', " ) ");
+    let mut tokens = spaced.split_whitespace().peekable();
+    let holds = any(&mut tokens, &allowed);
+    holds && tokens.next().is_none()
+}
+
+type Tokens<'a> = std::iter::Peekable<std::str::SplitWhitespace<'a>>;
+
+/// `all ("OR" all)*`
+fn any(tokens: &mut Tokens, allowed: &dyn Fn(&str) -> bool) -> bool {
+    let mut holds = all(tokens, allowed);
+    while tokens.next_if(|t| t.eq_ignore_ascii_case("OR")).is_some() {
+        holds |= all(tokens, allowed);
+    }
+    holds
+}
+
+/// `atom ("AND" atom)*`
+fn all(tokens: &mut Tokens, allowed: &dyn Fn(&str) -> bool) -> bool {
+    let mut holds = atom(tokens, allowed);
+    while tokens.next_if(|t| t.eq_ignore_ascii_case("AND")).is_some() {
+        holds &= atom(tokens, allowed);
+    }
+    holds
+}
+
+/// `"(" any ")" | id ["WITH" exception]`
+fn atom(tokens: &mut Tokens, allowed: &dyn Fn(&str) -> bool) -> bool {
+    match tokens.next() {
+        Some("(") => {
+            let holds = any(tokens, allowed);
+            tokens.next() == Some(")") && holds
+        }
+        Some(id) => {
+            if tokens.next_if(|t| t.eq_ignore_ascii_case("WITH")).is_some() {
+                tokens.next();
+            }
+            allowed(id)
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,40 @@
+//! `cargo qp list` — what a snapshot *would* contain, without building it.
+
+use std::fmt::Write;
+
+use anyhow::Result;
+
+use crate::{tokens, Ctx};
+
+pub fn render(ctx: &Ctx) -> Result<String> {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "{:>9} {:>7} {:>8}  {:<24} path",
+        "bytes", "lines", "tokens", "crate"
+    )?;
+
+    let (mut bytes, mut lines, mut toks, mut files) = (0, 0, 0, 0);
+    let paths = ctx.selected()?;
+    for (path, text) in paths.iter().zip(ctx.read_all(&paths)?) {
+        let (name, ver) = ctx.owner(path);
+        let (b, l, t) = (text.len(), text.lines().count(), tokens::estimate(&text));
+        writeln!(
+            out,
+            "{b:>9} {l:>7} {:>8}  {:<24} {}",
+            tokens::human(t),
+            format!("{name} v{ver}"),
+            ctx.rel(path).display()
+        )?;
+        bytes += b;
+        lines += l;
+        toks += t;
+        files += 1;
+    }
+    writeln!(
+        out,
+        "{bytes:>9} {lines:>7} {:>8}  {files} files",
+        tokens::human(toks)
+    )?;
+    Ok(out)
+}
@@ -0,0 +1,211 @@
+//! `Cargo.lock` delta for `--against` and `cargo qp diff`: which packages
+//! were added, removed, upgraded or downgraded between the two revisions,
+//! instead of the raw lockfile diff.
+//! * Compared by name: a package whose set of locked versions changed is
+//!   upgraded or downgraded when it had one version on each side, else
+//!   listed with both sets (`syn 1.0.109, 2.0.38 → 2.0.48`).
+//! * Git dependencies show the short commit they are pinned to, so a moved
+//!   rev counts as a change even with the same version.
+//! * No section when the lockfile is unchanged or there is none on either
+//!   side.
+//! * Only the root lockfile is summarised; nested ones (`fuzz/Cargo.lock`)
+//!   stay ordinary files and are diffed as such.
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+};
+
+use anyhow::Result;
+use cargo_metadata::semver::Version;
+use serde::Deserialize;
+
+use crate::{git, Ctx};
+
+const LOCKFILE: &str = "Cargo.lock";
+
+#[derive(Deserialize)]
+struct Lock {
+    #[serde(default)]
+    package: Vec<Package>,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+    version: String,
+    source: Option<String>,
+}
+
+/// Locked versions per package name.
+type Locked = BTreeMap<String, BTreeSet<String>>;
+
+/// The `Cargo.lock` section for `base` against `head` (the working tree
+/// when `None`).
+pub fn append(ctx: &mut Ctx, out: &mut dyn Write, base: &str, head: Option<&str>) -> Result<()> {
+    let old = git::show(&ctx.root, base, LOCKFILE).ok();
+    let new = match head {
+        Some(rev) => git::show(&ctx.root, rev, LOCKFILE).ok(),
+        None => std::fs::read_to_string(ctx.root.join(LOCKFILE)).ok(),
+    };
+    let (Some(old), Some(new)) = (old, new) else {
+        return Ok(());
+    };
+    if old == new {
+        return Ok(());
+    }
+    let (old, new) = (locked(&old)?, locked(&new)?);
+    let lines = delta(&old, &new);
+    if lines.is_empty() {
+        return Ok(());
+    }
+    let title = format!(
+        "{LOCKFILE} :: {}..{} :: {} package(s) changed",
+        short(base),
+        head.map_or("worktree", short),
+        lines.len()
+    );
+    ctx.push_section(out, &title, &format!("{}\n", lines.join("\n")))?;
+    Ok(())
+}
+
+/// A full commit hash shortened to 12 digits; anything else as given.
+fn short(rev: &str) -> &str {
+    let hash = rev.len() == 40 && rev.bytes().all(|b| b.is_ascii_hexdigit());
+    &rev[..if hash { 12 } else { rev.len() }]
+}
+
+/// Whether `rel` is the root lockfile, which `append` summarises instead.
+pub fn is_lockfile(rel: &str) -> bool {
+    rel == LOCKFILE
+}
+
+fn locked(text: &str) -> Result<Locked> {
+    let lock: Lock = toml::from_str(text)?;
+    let mut locked = Locked::new();
+    for p in lock.package {
+        let pin = p
+            .source
+            .as_deref()
+            .filter(|s| s.starts_with("git+"))
+            .and_then(|s| s.rsplit_once('#'))
+            .map(|(_, rev)| format!(" ({})", &rev[..rev.len().min(8)]))
+            .unwrap_or_default();
+        locked
+            .entry(p.name)
+            .or_default()
+            .insert(format!("{}{pin}", p.version));
+    }
+    Ok(locked)
+}
+
+/// One line per changed package, in name order.
+fn delta(old: &Locked, new: &Locked) -> Vec<String> {
+    let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let list =
+        |versions: &BTreeSet<String>| versions.iter().cloned().collect::<Vec<_>>().join(", ");
+    let mut lines = Vec::new();
+    for name in names {
+        let line = match (old.get(name), new.get(name)) {
+            (None, Some(v)) => format!("added       {name} {}", list(v)),
+            (Some(v), None) => format!("removed     {name} {}", list(v)),
+            (Some(a), Some(b)) if a != b => {
+                let kind = match (a.len(), b.len()) {
+                    (1, 1) => match compare(a.first().unwrap(), b.first().unwrap()) {
+                        Ordering::Less => "upgraded  ",
+                        Ordering::Greater => "downgraded",
+                        Ordering::Equal => "changed   ",
+                    },
+                    _ => "changed   ",
+                };
+                format!("{kind}  {name} {} → {}", list(a), list(b))
+            }
+            _ => continue,
+        };
+        lines.push(line);
+    }
+    lines
+}
+
+/// Semver order of two locked versions (git pins ignored).
+fn compare(a: &str, b: &str) -> Ordering {
+    let parse = |v: &str| Version::parse(v.split(' ').next().unwrap_or(v)).ok();
+    match (parse(a), parse(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        _ => Ordering::Equal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock(packages: &[(&str, &str, Option<&str>)]) -> Locked {
+        let mut text = String::from("version = 3\n");
+        for (name, version, source) in packages {
+            text.push_str(&format!(
+                "\n[[package]]\nname = \"{name}\"\nversion = \"{version}\"\n"
+            ));
+            if let Some(source) = source {
+                text.push_str(&format!("source = \"{source}\"\n"));
+            }
+        }
+        locked(&text).unwrap()
+    }
+
+    #[test]
+    fn only_the_root_lockfile_is_summarised() {
+        assert!(is_lockfile("Cargo.lock"));
+        assert!(!is_lockfile("fuzz/Cargo.lock"));
+        assert!(!is_lockfile("Cargo.toml"));
+    }
+
+    #[test]
+    fn delta_per_package() {
+        let old = lock(&[
+            ("anyhow", "1.0.0", None),
+            ("gone", "0.1.0", None),
+            ("serde", "1.0.100", None),
+            ("syn", "1.0.109", None),
+            ("syn", "2.0.38", None),
+            ("time", "0.3.20", None),
+        ]);
+        let new = lock(&[
+            ("anyhow", "1.0.0", None),
+            ("fresh", "0.2.0", None),
+            ("serde", "1.0.200", None),
+            ("syn", "2.0.48", None),
+            ("time", "0.3.9", None),
+        ]);
+        assert_eq!(
+            delta(&old, &new),
+            [
+                "added       fresh 0.2.0",
+                "removed     gone 0.1.0",
+                "upgraded    serde 1.0.100 → 1.0.200",
+                "changed     syn 1.0.109, 2.0.38 → 2.0.48",
+                "downgraded  time 0.3.20 → 0.3.9",
+            ]
+        );
+    }
+
+    #[test]
+    fn git_pins_count_as_changes() {
+        let pin = |rev| format!("git+https://example.com/x#{rev}");
+        let (a, b) = (pin("0123456789abcdef"), pin("fedcba9876543210"));
+        let old = lock(&[("x", "0.1.0", Some(&a))]);
+        let new = lock(&[("x", "0.1.0", Some(&b))]);
+        assert_eq!(
+            delta(&old, &new),
+            ["changed     x 0.1.0 (01234567) → 0.1.0 (fedcba98)"]
+        );
+    }
+
+    #[test]
+    fn short_hashes() {
+        let hash = "0123456789abcdef0123456789abcdef01234567";
+        assert_eq!(short(hash), "0123456789ab");
+        assert_eq!(short("main"), "main");
+    }
+}
@@ -0,0 +1,236 @@
+//! Snapshot manifests (`--manifest`, `--since-manifest`, `--follow-up`).
+//! * Records exactly what a snapshot contained: paths, git blob ids, applied
+//!   transforms, token counts and the git revision.
+//! * Blobs are written to the object database when hashed, so a later run
+//!   can diff against precisely what the model saw.
+//! * The file format is `schema::Manifest`.
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{git, schema, textdiff, tokens, Ctx};
+use anyhow::{Context, Result};
+
+pub const DEFAULT_FILE: &str = "qp-manifest.json";
+
+pub use crate::schema::{Manifest, ManifestEntry as Entry};
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read manifest {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("invalid manifest {}", path.display()))
+    }
+
+    /// A manifest of no files, holding only `summaries`.
+    pub fn empty() -> Self {
+        Manifest {
+            schema_version: schema::SCHEMA_VERSION,
+            rev: None,
+            created: now(),
+            total_tokens: 0,
+            files: Vec::new(),
+            summaries: BTreeMap::new(),
+        }
+    }
+
+    /// Summaries cached in the file being replaced survive when `self` has
+    /// none of its own.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut this = self.clone();
+        if this.summaries.is_empty() && path.exists() {
+            if let Ok(old) = Manifest::load(path) {
+                this.summaries = old.summaries;
+            }
+        }
+        let json = serde_json::to_string_pretty(&this)?;
+        std::fs::write(path, json + "\n")
+            .with_context(|| format!("failed to write manifest {}", path.display()))
+    }
+
+    pub fn entry(&self, rel: &str) -> Option<&Entry> {
+        self.files.iter().find(|e| e.path == rel)
+    }
+}
+
+/// Manifest for the full-body files emitted so far.
+pub fn build(ctx: &Ctx) -> Result<Manifest> {
+    let emitted: Vec<_> = ctx.emitted.iter().filter(|e| e.tag.is_none()).collect();
+    let rels: Vec<String> = emitted.iter().map(|e| rel_string(ctx, &e.path)).collect();
+    let blobs = blob_ids(ctx, &rels)?;
+
+    let files: Vec<Entry> = emitted
+        .iter()
+        .zip(rels)
+        .zip(blobs)
+        .map(|((e, path), blob)| Entry {
+            bytes: e.path.metadata().map(|m| m.len()).unwrap_or_default(),
+            path,
+            blob,
+            tokens: e.tokens,
+            transforms: e.transforms.clone(),
+        })
+        .collect();
+    let rev = git::run(
+        &ctx.root,
+        &["rev-parse", ctx.rev.as_deref().unwrap_or("HEAD")],
+    )
+    .ok()
+    .map(|s| s.trim().to_string());
+    Ok(Manifest {
+        schema_version: schema::SCHEMA_VERSION,
+        rev,
+        created: now(),
+        total_tokens: files.iter().map(|f| f.tokens).sum(),
+        files,
+        summaries: BTreeMap::new(),
+    })
+}
+
+/// Title of the section opening a follow-up; a `#` section, so `apply`
+/// never reads the counts as a file.
+pub fn follow_up_title(updated: usize, new: usize, deleted: usize) -> String {
+    format!("follow-up :: {updated} updated, {new} new, {deleted} deleted since the last snapshot")
+}
+
+/// Unix seconds.
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Blob ids for `rels`, from `--rev`'s tree or by hashing the working tree.
+pub fn blob_ids(ctx: &Ctx, rels: &[String]) -> Result<Vec<String>> {
+    match &ctx.rev {
+        Some(rev) => {
+            let tree = git::tree_blobs(&ctx.root, rev)?;
+            Ok(rels
+                .iter()
+                .map(|r| tree.get(r).cloned().unwrap_or_default())
+                .collect())
+        }
+        None if rels.is_empty() => Ok(Vec::new()),
+        None => git::hash_objects(&ctx.root, rels),
+    }
+}
+
+pub fn rel_string(ctx: &Ctx, path: &Path) -> String {
+    ctx.rel(path).to_string_lossy().replace('\\', "/")
+}
+
+/// `--since-manifest`: unified diffs of everything that changed since the
+/// manifest was written, plus new and deleted files.
+pub fn since(ctx: &mut Ctx, out: &mut dyn Write, manifest_path: &Path) -> Result<()> {
+    let old = Manifest::load(manifest_path)?;
+    let current: Vec<PathBuf> = ctx.selected()?;
+    let rels: Vec<String> = current.iter().map(|p| rel_string(ctx, p)).collect();
+    let blobs = blob_ids(ctx, &rels)?;
+
+    // the summary comes first but needs the counts, so bodies wait here
+    let mut body_buf = Vec::new();
+    let mut changed = 0;
+    for ((path, rel), blob) in current.iter().zip(&rels).zip(&blobs) {
+        match old.entry(rel) {
+            Some(e) if &e.blob == blob => {}
+            Some(e) => {
+                // decoded like `after`, so transforms don't show up as changes
+                let before = match git::cat_blob_bytes(&ctx.root, &e.blob) {
+                    Ok(bytes) => ctx.decode(path, bytes)?,
+                    Err(_) => String::new(),
+                };
+                let after = ctx.read(path)?;
+                let patch =
+                    textdiff::unified(&format!("a/{rel}"), &format!("b/{rel}"), &before, &after);
+                ctx.push_file(&mut body_buf, path, Some("diff"), &patch)?;
+                changed += 1;
+            }
+            None => {
+                let body = ctx.read(path)?;
+                ctx.push_file(&mut body_buf, path, Some("new"), &body)?;
+                changed += 1;
+            }
+        }
+    }
+    let deleted: Vec<&Entry> = old
+        .files
+        .iter()
+        .filter(|e| !rels.contains(&e.path))
+        .collect();
+    for e in &deleted {
+        ctx.push_section(&mut body_buf, &format!("deleted :: {}", e.path), "")?;
+    }
+    let summary = format!(
+        "changes since manifest :: {} changed/new, {} deleted (was ~{} tokens)",
+        changed,
+        deleted.len(),
+        tokens::human(old.total_tokens)
+    );
+    ctx.push_section(out, &summary, "")?;
+    out.write_all(&body_buf)?;
+    Ok(())
+}
+
+/// `--follow-up`: only files changed since the manifest — in full, labelled
+/// `UPDATED`/`NEW`/`DELETED` — then rewrites the manifest to the current
+/// state so the next follow-up continues from here. A missing manifest
+/// means everything is `NEW`.
+pub fn follow_up(ctx: &mut Ctx, out: &mut dyn Write, manifest_path: &Path) -> Result<()> {
+    let old = if manifest_path.exists() {
+        Some(Manifest::load(manifest_path)?)
+    } else {
+        None
+    };
+    let current: Vec<PathBuf> = ctx.selected()?;
+    let rels: Vec<String> = current.iter().map(|p| rel_string(ctx, p)).collect();
+    let blobs = blob_ids(ctx, &rels)?;
+
+    let mut body_buf = Vec::new();
+    let mut files = Vec::new();
+    let (mut updated, mut new) = (0, 0);
+    for ((path, rel), blob) in current.iter().zip(&rels).zip(blobs) {
+        let prev = old.as_ref().and_then(|m| m.entry(rel));
+        let (tokens, transforms) = match prev {
+            Some(e) if e.blob == blob => (e.tokens, e.transforms.clone()),
+            _ => {
+                let body = ctx.read(path)?;
+                let status = if prev.is_some() { "UPDATED" } else { "NEW" };
+                ctx.push_status(&mut body_buf, path, status, &body)?;
+                if prev.is_some() {
+                    updated += 1;
+                } else {
+                    new += 1;
+                }
+                (tokens::estimate(&body), ctx.applied_transforms(path))
+            }
+        };
+        files.push(Entry {
+            path: rel.clone(),
+            blob,
+            bytes: path.metadata().map(|m| m.len()).unwrap_or_default(),
+            tokens,
+            transforms,
+        });
+    }
+
+    let mut deleted = 0;
+    for e in old.iter().flat_map(|m| &m.files) {
+        if !rels.contains(&e.path) {
+            let path = ctx.root.join(&e.path);
+            ctx.push_status(&mut body_buf, &path, "DELETED", "")?;
+            deleted += 1;
+        }
+    }
+    ctx.push_section(out, &follow_up_title(updated, new, deleted), "")?;
+    out.write_all(&body_buf)?;
+
+    let mut manifest = build(ctx)?;
+    manifest.total_tokens = files.iter().map(|f| f.tokens).sum();
+    manifest.files = files;
+    manifest.write(manifest_path)?;
+    Ok(())
+}
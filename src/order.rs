@@ -0,0 +1,351 @@
+//! `--order` — the sequence files appear in a snapshot.
+//! * `path` (default): sorted by path.
+//! * `crate`: grouped by owning crate, in module-tree order.
+//! * `topo`: crates in internal dependency order, leaves first and
+//!   binary-only crates last, each in module-tree order. Edges come from the
+//!   resolve graph (normal and build dependencies; dev-dependencies would
+//!   only add cycles).
+//! * Module-tree order: the manifest, then each crate root (`lib.rs`,
+//!   `main.rs`, then bins, tests, examples, benches, `build.rs`) followed
+//!   depth-first by its modules in declaration order. Files no root reaches
+//!   come last, by path.
+//! * `recent`: most recently committed first (uncommitted files lead).
+//! * `size`: largest first.
+
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use cargo_metadata::{DependencyKind, Metadata};
+use clap::ValueEnum;
+
+use crate::{syntax, Ctx};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Order {
+    /// Sorted by path
+    Path,
+    /// Crate by crate, each opening with its description, license, edition
+    /// and MSRV and walking its module tree from the root
+    Crate,
+    /// Like `crate`, in workspace dependency order, leaves first
+    Topo,
+    /// Uncommitted files, then the most recently committed
+    Recent,
+    /// Largest first
+    Size,
+}
+
+/// Reorders `files` (path, body) in place; ties always fall back to path.
+pub fn apply(ctx: &Ctx, order: Order, files: &mut [(PathBuf, String)]) {
+    match order {
+        Order::Path => files.sort_by(|a, b| a.0.cmp(&b.0)),
+        Order::Crate => {
+            let tree = module_ranks(ctx, files);
+            files.sort_by_cached_key(|(p, _)| {
+                (ctx.owner(p).0, within_crate(ctx, &tree, p), p.clone())
+            });
+        }
+        Order::Topo => {
+            let rank = crate_ranks(&ctx.crate_deps, &ctx.bin_crates);
+            let tree = module_ranks(ctx, files);
+            files.sort_by_cached_key(|(p, _)| {
+                let name = crate_for(ctx, p);
+                // files outside any crate (workspace manifest, …) come first
+                let crate_rank = name
+                    .as_ref()
+                    .map(|n| rank.get(n).copied().unwrap_or(usize::MAX));
+                (crate_rank, name, within_crate(ctx, &tree, p), p.clone())
+            });
+        }
+        Order::Recent => {
+            let last = ctx.last_commits();
+            files.sort_by_cached_key(|(p, _)| {
+                let time = last.get(&*ctx.rel(p).to_string_lossy()).map(|c| c.time);
+                (time.is_some(), Reverse(time), p.clone())
+            });
+        }
+        Order::Size => files.sort_by_cached_key(|(p, body)| (Reverse(body.len()), p.clone())),
+    }
+}
+
+/// Workspace member → the members it depends on (normal and build), from
+/// the resolve graph, or the manifests when metadata has none.
+pub fn internal_deps(md: &Metadata) -> HashMap<String, Vec<String>> {
+    let members: HashMap<_, &str> = md
+        .workspace_packages()
+        .iter()
+        .map(|p| (&p.id, p.name.as_str()))
+        .collect();
+    let Some(resolve) = &md.resolve else {
+        return md
+            .workspace_packages()
+            .iter()
+            .map(|p| {
+                let deps = p
+                    .dependencies
+                    .iter()
+                    .filter(|d| d.kind != DependencyKind::Development && d.name != p.name)
+                    .filter(|d| members.values().any(|m| *m == d.name))
+                    .map(|d| d.name.clone())
+                    .collect();
+                (p.name.clone(), deps)
+            })
+            .collect();
+    };
+    resolve
+        .nodes
+        .iter()
+        .filter_map(|node| {
+            let name = members.get(&node.id)?;
+            let deps = node
+                .deps
+                .iter()
+                .filter(|d| {
+                    d.dep_kinds
+                        .iter()
+                        .any(|k| k.kind != DependencyKind::Development)
+                })
+                .filter_map(|d| members.get(&d.pkg))
+                .filter(|dep| *dep != name)
+                .map(|dep| dep.to_string())
+                .collect();
+            Some((name.to_string(), deps))
+        })
+        .collect()
+}
+
+/// Target kinds other crates can depend on.
+const LIB_KINDS: [&str; 6] = ["lib", "rlib", "dylib", "cdylib", "staticlib", "proc-macro"];
+
+/// Workspace members with binaries but no library target.
+pub fn binaries(md: &Metadata) -> HashSet<String> {
+    md.workspace_packages()
+        .iter()
+        .filter(|p| p.targets.iter().any(|t| t.is_bin()))
+        .filter(|p| {
+            !p.targets
+                .iter()
+                .any(|t| t.kind.iter().any(|k| LIB_KINDS.contains(&k.as_str())))
+        })
+        .map(|p| p.name.clone())
+        .collect()
+}
+
+/// Topological rank of each crate, leaves first and `bins` only once no
+/// library is left; ties (and cycles) are broken by name.
+fn crate_ranks(
+    deps: &HashMap<String, Vec<String>>,
+    bins: &HashSet<String>,
+) -> HashMap<String, usize> {
+    let mut pending: BTreeMap<&str, BTreeSet<&str>> = deps
+        .iter()
+        .map(|(name, d)| (name.as_str(), d.iter().map(String::as_str).collect()))
+        .collect();
+    let mut rank = HashMap::new();
+    while !pending.is_empty() {
+        let ready: Vec<&str> = pending
+            .iter()
+            .filter(|(_, d)| d.iter().all(|dep| !pending.contains_key(dep)))
+            .map(|(name, _)| *name)
+            .collect();
+        let libs: Vec<&str> = ready
+            .iter()
+            .copied()
+            .filter(|name| !bins.contains(*name))
+            .collect();
+        let ready = if libs.is_empty() { ready } else { libs };
+        // a cycle: release the first remaining crate
+        let ready = if ready.is_empty() {
+            vec![*pending.keys().next().unwrap()]
+        } else {
+            ready
+        };
+        for name in ready {
+            pending.remove(name);
+            rank.insert(name.to_string(), rank.len());
+        }
+    }
+    rank
+}
+
+/// Owning crate, or `None` for files that belong to no crate.
+fn crate_for(ctx: &Ctx, p: &Path) -> Option<String> {
+    let (name, _) = ctx.owner(p);
+    (name != "unknown_crate").then_some(name)
+}
+
+/// The manifest first, then files in module-tree order, then the rest.
+fn within_crate(ctx: &Ctx, tree: &HashMap<PathBuf, usize>, p: &Path) -> (u8, usize) {
+    if ctx.rel(p).file_name() == Some("Cargo.toml".as_ref()) {
+        return (0, 0);
+    }
+    match tree.get(p) {
+        Some(&rank) => (1, rank),
+        None => (2, 0),
+    }
+}
+
+/// Depth-first position of every file reachable from a crate root.
+fn module_ranks(ctx: &Ctx, files: &[(PathBuf, String)]) -> HashMap<PathBuf, usize> {
+    let bodies: HashMap<&Path, &str> = files
+        .iter()
+        .map(|(p, body)| (p.as_path(), body.as_str()))
+        .collect();
+    let mut by_crate: BTreeMap<&Path, Vec<&Path>> = BTreeMap::new();
+    for (path, _) in files {
+        if let Some(dir) = ctx.crate_dir(path) {
+            by_crate.entry(dir).or_default().push(path);
+        }
+    }
+    let mut rank = HashMap::new();
+    for (dir, mut paths) in by_crate {
+        paths.sort();
+        let src = dir.join("src");
+        let roots = [src.join("lib.rs"), src.join("main.rs")].into_iter().chain(
+            paths
+                .iter()
+                .filter(|p| is_root(p.strip_prefix(dir).unwrap_or(p)))
+                .map(|p| p.to_path_buf()),
+        );
+        for root in roots {
+            walk(ctx, &bodies, &root, true, &mut rank);
+        }
+    }
+    rank
+}
+
+/// Target roots Cargo finds by convention, besides `src/lib.rs`.
+fn is_root(rel: &Path) -> bool {
+    let parent = rel.parent().and_then(|d| d.file_name());
+    rel == Path::new("build.rs")
+        || rel.file_name() == Some("main.rs".as_ref())
+        || parent.is_some_and(|d| {
+            ["bin", "tests", "examples", "benches"].contains(&&*d.to_string_lossy())
+        }) && rel.extension().is_some_and(|x| x == "rs")
+}
+
+/// Ranks `file`, then the modules it declares; `mod_rs` files (crate roots,
+/// `mod.rs`) keep their modules next to them rather than in `<stem>/`.
+fn walk(
+    ctx: &Ctx,
+    bodies: &HashMap<&Path, &str>,
+    file: &Path,
+    mod_rs: bool,
+    rank: &mut HashMap<PathBuf, usize>,
+) {
+    if rank.contains_key(file) {
+        return;
+    }
+    let Some(body) = source(ctx, bodies, file) else {
+        return;
+    };
+    rank.insert(file.to_path_buf(), rank.len());
+    let Some(parent) = file.parent() else {
+        return;
+    };
+    let dir = match file.file_stem() {
+        Some(stem) if !mod_rs => parent.join(stem),
+        _ => parent.to_path_buf(),
+    };
+    for decl in syntax::modules(&body).unwrap_or_default() {
+        let base = decl.inline.iter().fold(dir.clone(), |d, m| d.join(m));
+        let (child, child_mod_rs) = match &decl.path {
+            Some(path) if decl.inline.is_empty() => (parent.join(path), true),
+            Some(path) => (base.join(path), true),
+            None => {
+                let flat = base.join(format!("{}.rs", decl.name));
+                if source(ctx, bodies, &flat).is_some() {
+                    (flat, false)
+                } else {
+                    (base.join(&decl.name).join("mod.rs"), true)
+                }
+            }
+        };
+        walk(ctx, bodies, &child, child_mod_rs, rank);
+    }
+}
+
+/// The selected body of `file`, or else its raw text (working tree or
+/// `--rev`) for roots and parents outside the selection.
+fn source<'a>(ctx: &Ctx, bodies: &HashMap<&Path, &'a str>, file: &Path) -> Option<Cow<'a, str>> {
+    if let Some(body) = bodies.get(file) {
+        return Some(Cow::Borrowed(body));
+    }
+    let bytes = ctx.source_bytes(file).ok()?;
+    String::from_utf8(bytes).ok().map(Cow::Owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(edges: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        edges
+            .iter()
+            .map(|(name, deps)| {
+                (
+                    name.to_string(),
+                    deps.iter().map(|d| d.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn sorted(rank: &HashMap<String, usize>) -> Vec<&str> {
+        let mut names: Vec<&str> = rank.keys().map(String::as_str).collect();
+        names.sort_by_key(|n| rank[*n]);
+        names
+    }
+
+    #[test]
+    fn leaves_first_binaries_last() {
+        let deps = graph(&[
+            ("cli", &["core", "net"]),
+            ("net", &["core"]),
+            ("core", &[]),
+            ("tool", &[]),
+            ("util", &[]),
+        ]);
+        let bins = HashSet::from(["cli".to_string(), "tool".to_string()]);
+        assert_eq!(
+            sorted(&crate_ranks(&deps, &bins)),
+            ["core", "util", "net", "cli", "tool"]
+        );
+    }
+
+    #[test]
+    fn cycles_are_broken_by_name() {
+        let deps = graph(&[("b", &["a"]), ("a", &["b"]), ("c", &["a"])]);
+        assert_eq!(
+            sorted(&crate_ranks(&deps, &HashSet::new())),
+            ["a", "b", "c"]
+        );
+    }
+
+    #[test]
+    fn conventional_roots() {
+        for rel in [
+            "build.rs",
+            "src/main.rs",
+            "src/bin/tool.rs",
+            "src/bin/tool/main.rs",
+            "tests/it.rs",
+            "examples/demo.rs",
+            "benches/b.rs",
+        ] {
+            assert!(is_root(Path::new(rel)), "{rel}");
+        }
+        for rel in [
+            "src/lib.rs",
+            "src/build.rs",
+            "tests/data/x.txt",
+            "src/util.rs",
+        ] {
+            assert!(!is_root(Path::new(rel)), "{rel}");
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! `--manifests-only` — a cheap workspace overview to start a conversation
+//! with: every manifest (minified), the file tree and the dependency graph
+//! between workspace members, but no source.
+//! * Covers every workspace member, not just `default-members`; `--exclude`
+//!   still leaves members out.
+
+use std::{collections::BTreeMap, io::Write, path::PathBuf};
+
+use anyhow::Result;
+
+use crate::{focus, Ctx};
+
+pub fn compose(ctx: &mut Ctx, out: &mut dyn Write) -> Result<()> {
+    let paths = ctx.selected()?;
+    let manifests: Vec<PathBuf> = paths
+        .iter()
+        .filter(|p| p.file_name() == Some("Cargo.toml".as_ref()))
+        .cloned()
+        .collect();
+    let bodies = ctx.read_all(&manifests)?;
+    for (path, body) in manifests.iter().zip(bodies) {
+        ctx.push_file(out, path, None, &body)?;
+    }
+
+    let rels: Vec<PathBuf> = paths.iter().map(|p| ctx.rel(p).to_path_buf()).collect();
+    ctx.push_section(out, "tree", &focus::tree(&rels))?;
+    ctx.push_section(out, "dependency graph", &graph(ctx))?;
+    Ok(())
+}
+
+/// `member -> dep, dep` per workspace member, by name.
+fn graph(ctx: &Ctx) -> String {
+    let deps: BTreeMap<&String, &Vec<String>> = ctx.crate_deps.iter().collect();
+    let mut out = String::new();
+    for (name, deps) in deps {
+        let mut deps: Vec<&str> = deps.iter().map(String::as_str).collect();
+        deps.sort();
+        deps.dedup();
+        if deps.is_empty() {
+            out.push_str(&format!("{name} (no workspace dependencies)\n"));
+        } else {
+            out.push_str(&format!("{name} -> {}\n", deps.join(", ")));
+        }
+    }
+    out
+}
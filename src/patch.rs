@@ -0,0 +1,328 @@
+//! Unified-diff application for `cargo qp apply`, tolerant of the quirks in
+//! model-written patches.
+//! * Hunk line numbers are only a hint; hunks are located by content, nearest
+//!   to the hinted position first.
+//! * Matching falls back to ignoring trailing, then all surrounding
+//!   whitespace, then drops up to two outer context lines (like `patch`'s
+//!   fuzz factor). Context lines keep the file's version, so a loose match
+//!   never rewrites their whitespace.
+//! * Blank lines without the leading space still count as context, and
+//!   `@@ @@` headers without numbers are accepted.
+//! * A file with CRLF line endings keeps them; patch lines are compared
+//!   without the `\r`.
+
+use std::path::PathBuf;
+
+/// All hunks for one file. `None` paths stand for `/dev/null`.
+pub struct FilePatch {
+    pub old: Option<PathBuf>,
+    pub new: Option<PathBuf>,
+    pub hunks: Vec<PatchHunk>,
+}
+
+impl FilePatch {
+    /// Path the result should be written to.
+    pub fn target(&self) -> Option<&PathBuf> {
+        self.new.as_ref().or(self.old.as_ref())
+    }
+}
+
+pub struct PatchHunk {
+    /// 1-based old start line from the `@@` header, if any.
+    pub old_start: Option<usize>,
+    pub lines: Vec<Line>,
+}
+
+#[derive(Clone)]
+pub enum Line {
+    Context(String),
+    Delete(String),
+    Insert(String),
+}
+
+impl PatchHunk {
+    fn old_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|l| match l {
+                Line::Context(s) | Line::Delete(s) => Some(s.as_str()),
+                Line::Insert(_) => None,
+            })
+            .collect()
+    }
+
+    /// The hunk as it would appear in a `.rej` file.
+    pub fn render(&self) -> String {
+        let mut out = match self.old_start {
+            Some(n) => format!("@@ -{n} @@\n"),
+            None => "@@ @@\n".to_string(),
+        };
+        for l in &self.lines {
+            let (c, s) = match l {
+                Line::Context(s) => (' ', s),
+                Line::Delete(s) => ('-', s),
+                Line::Insert(s) => ('+', s),
+            };
+            out.push(c);
+            out.push_str(s);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+//──────────────────────── parsing ────────────────────────────────────────────
+
+/// Every `--- a/x` / `+++ b/x` file section in `text`, wherever it appears
+/// (fenced blocks, prose in between, `diff --git` preambles…).
+pub fn parse(text: &str) -> Vec<FilePatch> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i + 1 < lines.len() {
+        let (Some(old), Some(new)) = (
+            lines[i].strip_prefix("--- "),
+            lines[i + 1].strip_prefix("+++ "),
+        ) else {
+            i += 1;
+            continue;
+        };
+        let mut patch = FilePatch {
+            old: diff_path(old),
+            new: diff_path(new),
+            hunks: Vec::new(),
+        };
+        i += 2;
+        while i < lines.len() && lines[i].starts_with("@@") {
+            let mut hunk = PatchHunk {
+                old_start: hunk_start(lines[i]),
+                lines: Vec::new(),
+            };
+            i += 1;
+            while i < lines.len() {
+                let l = lines[i];
+                let line = match l.chars().next() {
+                    Some(' ') => Line::Context(l[1..].to_string()),
+                    Some('-') if !l.starts_with("--- ") => Line::Delete(l[1..].to_string()),
+                    Some('+') if !l.starts_with("+++ ") => Line::Insert(l[1..].to_string()),
+                    Some('\\') => {
+                        i += 1; // "\ No newline at end of file"
+                        continue;
+                    }
+                    None => Line::Context(String::new()),
+                    _ => break,
+                };
+                hunk.lines.push(line);
+                i += 1;
+            }
+            // trailing blank lines are usually prose spacing, not context
+            while matches!(hunk.lines.last(), Some(Line::Context(s)) if s.is_empty()) {
+                hunk.lines.pop();
+            }
+            patch.hunks.push(hunk);
+        }
+        if !patch.hunks.is_empty() {
+            out.push(patch);
+        }
+    }
+    out
+}
+
+fn diff_path(raw: &str) -> Option<PathBuf> {
+    let raw = raw.split('\t').next().unwrap_or(raw).trim();
+    if raw == "/dev/null" {
+        return None;
+    }
+    let raw = raw
+        .strip_prefix("a/")
+        .or_else(|| raw.strip_prefix("b/"))
+        .unwrap_or(raw);
+    Some(PathBuf::from(raw))
+}
+
+/// `@@ -12,7 +12,8 @@` → `Some(12)`; `@@ @@` → `None`.
+fn hunk_start(header: &str) -> Option<usize> {
+    let old = header.split_whitespace().find(|t| t.starts_with('-'))?;
+    old[1..].split(',').next()?.parse().ok()
+}
+
+//──────────────────────── application ────────────────────────────────────────
+
+pub struct Outcome {
+    pub text: String,
+    pub applied: usize,
+    pub rejected: Vec<usize>,
+}
+
+/// Applies every hunk that can be located; the others are reported by index.
+pub fn apply(original: &str, patch: &FilePatch) -> Outcome {
+    let mut lines: Vec<String> = original.lines().map(String::from).collect();
+    let trailing_newline = original.is_empty() || original.ends_with('\n');
+    let eol = if original.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut applied = 0;
+    let mut rejected = Vec::new();
+    // shift between hinted and actual positions, carried to later hunks
+    let mut offset: isize = 0;
+
+    for (idx, hunk) in patch.hunks.iter().enumerate() {
+        let hint = hunk
+            .old_start
+            .map(|n| (n as isize - 1 + offset).max(0) as usize)
+            .unwrap_or(0);
+        match locate(&lines, hunk, hint) {
+            Some((at, trim_front, trim_back)) => {
+                let old_len = hunk.old_lines().len() - trim_front - trim_back;
+                // context keeps the file's own line, which a looser match may
+                // have let differ in whitespace
+                let mut local = lines[at..at + old_len].iter();
+                let mut replacement = Vec::new();
+                for line in &hunk.lines[trim_front..hunk.lines.len() - trim_back] {
+                    match line {
+                        Line::Context(_) => replacement.extend(local.next().cloned()),
+                        Line::Delete(_) => {
+                            local.next();
+                        }
+                        Line::Insert(s) => replacement.push(s.clone()),
+                    }
+                }
+                let new_len = replacement.len();
+                lines.splice(at..at + old_len, replacement);
+                offset += at as isize - trim_front as isize - hint as isize + new_len as isize
+                    - old_len as isize;
+                applied += 1;
+            }
+            None => rejected.push(idx),
+        }
+    }
+
+    let mut text = lines.join(eol);
+    if trailing_newline && !lines.is_empty() {
+        text.push_str(eol);
+    }
+    Outcome {
+        text,
+        applied,
+        rejected,
+    }
+}
+
+type LineEq = fn(&str, &str) -> bool;
+
+/// Line comparisons, strictest first.
+const LINE_EQ: [LineEq; 3] = [
+    |a, b| a == b,
+    |a, b| a.trim_end() == b.trim_end(),
+    |a, b| a.trim() == b.trim(),
+];
+
+/// Position of the hunk's old side, plus how many outer context lines had to
+/// be dropped (front, back) to find it.
+fn locate(lines: &[String], hunk: &PatchHunk, hint: usize) -> Option<(usize, usize, usize)> {
+    let old = hunk.old_lines();
+    let lead = hunk
+        .lines
+        .iter()
+        .take_while(|l| matches!(l, Line::Context(_)))
+        .count();
+    let tail = hunk
+        .lines
+        .iter()
+        .rev()
+        .take_while(|l| matches!(l, Line::Context(_)))
+        .count();
+
+    for fuzz in 0..=2 {
+        let (front, back) = (fuzz.min(lead), fuzz.min(tail));
+        if fuzz > 0 && front + back == 0 {
+            break;
+        }
+        let needle = &old[front..old.len() - back];
+        if needle.is_empty() && !old.is_empty() {
+            break;
+        }
+        for cmp in LINE_EQ {
+            if let Some(at) = search(lines, needle, hint + front, cmp) {
+                return Some((at, front, back));
+            }
+        }
+    }
+    None
+}
+
+/// Nearest match to `hint`, scanning outwards in both directions.
+fn search(lines: &[String], needle: &[&str], hint: usize, eq: LineEq) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(hint.min(lines.len()));
+    }
+    if needle.len() > lines.len() {
+        return None;
+    }
+    let last = lines.len() - needle.len();
+    let hint = hint.min(last);
+    let matches = |at: usize| needle.iter().zip(&lines[at..]).all(|(n, l)| eq(l, n));
+    (0..=last.max(hint)).find_map(|d| {
+        [
+            hint.checked_sub(d),
+            hint.checked_add(d).filter(|&p| p <= last),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|&p| matches(p))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch(hunks: &str) -> FilePatch {
+        let text = format!("--- a/src/lib.rs\n+++ b/src/lib.rs\n{hunks}");
+        parse(&text).pop().expect("one file patch")
+    }
+
+    #[test]
+    fn exact_hit() {
+        let p = patch("@@ -2,2 +2,2 @@\n b\n-c\n+C\n");
+        let out = apply("a\nb\nc\nd\n", &p);
+        assert_eq!((out.text.as_str(), out.applied), ("a\nb\nC\nd\n", 1));
+        assert!(out.rejected.is_empty());
+    }
+
+    #[test]
+    fn offset_hit() {
+        // the hint says line 2, the content is five lines further down
+        let p = patch("@@ -2,3 +2,3 @@\n x\n-y\n+Y\n z\n");
+        let out = apply("1\n2\n3\n4\n5\n6\nx\ny\nz\n", &p);
+        assert_eq!(out.text, "1\n2\n3\n4\n5\n6\nx\nY\nz\n");
+        assert_eq!(out.applied, 1);
+    }
+
+    #[test]
+    fn fuzz_hit() {
+        // outer context that no longer matches is dropped, whitespace ignored
+        let p = patch("@@ -1,4 +1,4 @@\n stale\n  keep\n-old\n+new\n");
+        let out = apply("fresh\nkeep\nold\n", &p);
+        assert_eq!(out.text, "fresh\nkeep\nnew\n");
+        assert!(out.rejected.is_empty());
+    }
+
+    #[test]
+    fn failed_hunk_is_reported() {
+        let p = patch("@@ -1,2 +1,2 @@\n a\n-b\n+B\n@@ -9,2 +9,2 @@\n nowhere\n-gone\n+here\n");
+        let out = apply("a\nb\nc\n", &p);
+        assert_eq!(out.text, "a\nB\nc\n");
+        assert_eq!((out.applied, out.rejected), (1, vec![1]));
+    }
+
+    #[test]
+    fn crlf_body_keeps_line_endings() {
+        let p = patch("@@ -1,2 +1,3 @@\n a\n b\n+c\n");
+        let out = apply("a\r\nb\r\n", &p);
+        assert_eq!(out.text, "a\r\nb\r\nc\r\n");
+        assert_eq!(out.applied, 1);
+    }
+}
@@ -0,0 +1,301 @@
+//! `--scrub-pii` — masks personal data before it leaves the machine: email
+//! addresses become `<email>`, IPv4/IPv6 addresses `<ip>` and phone-number
+//! shapes `<phone>`.
+//! * In `.rs` files only comments and string literals are scrubbed, so code
+//!   stays intact; other files (manifests, docs) are scrubbed throughout.
+//! * Heuristic, like `secrets`: a phone number needs 10–15 digits and a
+//!   leading `+` or at least two separators, and is not followed by `:` (a
+//!   date and time); version-like dotted numbers only match as IPv4 when
+//!   every part fits in a byte.
+
+use std::{ops::Range, path::Path};
+
+/// `body` with personal data masked; `None` when nothing matched.
+pub fn scrub(path: &Path, body: &str) -> Option<String> {
+    let regions = if path.extension().is_some_and(|x| x == "rs") {
+        rust_regions(body)
+    } else {
+        std::iter::once(0..body.len()).collect()
+    };
+    let mut out = String::with_capacity(body.len());
+    let mut last = 0;
+    for r in regions {
+        out.push_str(&body[last..r.start]);
+        out.push_str(&mask(&body[r.clone()]));
+        last = r.end;
+    }
+    out.push_str(&body[last..]);
+    (out != body).then_some(out)
+}
+
+/// Every mask applied in turn; a placeholder never matches a later one.
+fn mask(text: &str) -> String {
+    let text = replace(text, "<email>", email);
+    let text = replace(&text, "<ip>", ipv4);
+    let text = replace(&text, "<ip>", ipv6);
+    replace(&text, "<phone>", phone)
+}
+
+/// Replaces every match `find` reports (the end of a match starting at the
+/// given index) with `label`.
+fn replace(text: &str, label: &str, find: fn(&[u8], usize) -> Option<usize>) -> String {
+    let b = text.as_bytes();
+    let mut out = String::with_capacity(text.len());
+    let (mut i, mut last) = (0, 0);
+    while i < b.len() {
+        match find(b, i) {
+            Some(end) => {
+                out.push_str(&text[last..i]);
+                out.push_str(label);
+                (i, last) = (end, end);
+            }
+            None => i += 1,
+        }
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+fn word(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// `local@domain.tld`
+fn email(b: &[u8], i: usize) -> Option<usize> {
+    let local = |c: u8| word(c) || b".%+-".contains(&c);
+    if !local(b[i]) || b[i] == b'.' || (i > 0 && local(b[i - 1])) {
+        return None;
+    }
+    let at = i + b[i..].iter().take_while(|&&c| local(c)).count();
+    if b.get(at) != Some(&b'@') {
+        return None;
+    }
+    let mut end = at
+        + 1
+        + b[at + 1..]
+            .iter()
+            .take_while(|&&c| c.is_ascii_alphanumeric() || c == b'.' || c == b'-')
+            .count();
+    while end > at + 1 && matches!(b[end - 1], b'.' | b'-') {
+        end -= 1;
+    }
+    let labels: Vec<&[u8]> = b[at + 1..end].split(|&c| c == b'.').collect();
+    let tld = labels.last()?;
+    let ok = labels.len() >= 2
+        && labels.iter().all(|l| !l.is_empty())
+        && tld.len() >= 2
+        && tld.iter().all(u8::is_ascii_alphabetic);
+    ok.then_some(end)
+}
+
+/// `a.b.c.d`, each part at most 255.
+fn ipv4(b: &[u8], i: usize) -> Option<usize> {
+    if i > 0 && (word(b[i - 1]) || b[i - 1] == b'.') {
+        return None;
+    }
+    let mut at = i;
+    for part in 0..4 {
+        if part > 0 {
+            if b.get(at) != Some(&b'.') {
+                return None;
+            }
+            at += 1;
+        }
+        let digits = b[at..]
+            .iter()
+            .take(4)
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        let value: u32 = std::str::from_utf8(&b[at..at + digits])
+            .ok()?
+            .parse()
+            .ok()?;
+        if !(1..=3).contains(&digits) || value > 255 {
+            return None;
+        }
+        at += digits;
+    }
+    let dotted_on = b.get(at) == Some(&b'.') && b.get(at + 1).is_some_and(u8::is_ascii_digit);
+    (!b.get(at).is_some_and(|&c| word(c)) && !dotted_on).then_some(at)
+}
+
+/// Hex groups with `::`, or eight of them; at least one digit.
+fn ipv6(b: &[u8], i: usize) -> Option<usize> {
+    if i > 0 && (word(b[i - 1]) || b[i - 1] == b':') {
+        return None;
+    }
+    let len = b[i..]
+        .iter()
+        .take_while(|c| c.is_ascii_hexdigit() || **c == b':')
+        .count();
+    let run = &b[i..i + len];
+    if b.get(i + len).is_some_and(|&c| word(c)) {
+        return None;
+    }
+    let text = std::str::from_utf8(run).ok()?;
+    let colons = text.matches(':').count();
+    let groups: Vec<&str> = text.split(':').filter(|g| !g.is_empty()).collect();
+    let ok = (2..=7).contains(&colons)
+        && !text.contains(":::")
+        && (text.contains("::") || colons == 7)
+        && !groups.is_empty()
+        && groups.iter().all(|g| g.len() <= 4)
+        && text.bytes().any(|c| c.is_ascii_digit());
+    ok.then_some(i + len)
+}
+
+/// `+1 555 123 4567`, `(555) 123-4567`, `555.123.4567`, …
+fn phone(b: &[u8], i: usize) -> Option<usize> {
+    let start_ok = b[i] == b'+' || b[i] == b'(' || b[i].is_ascii_digit();
+    let prev_ok = i == 0 || !(word(b[i - 1]) || b"+.-(".contains(&b[i - 1]));
+    if !start_ok || !prev_ok {
+        return None;
+    }
+    let mut end = i + 1;
+    while let Some(&c) = b.get(end) {
+        let next = b.get(end + 1).copied().unwrap_or(b' ');
+        let fits = c.is_ascii_digit()
+            || b"-.()".contains(&c)
+            || (c == b' ' && (next.is_ascii_digit() || next == b'('));
+        if !fits {
+            break;
+        }
+        end += 1;
+    }
+    while end > i && !b[end - 1].is_ascii_digit() {
+        end -= 1;
+    }
+    let run = &b[i..end];
+    let digits = run.iter().filter(|c| c.is_ascii_digit()).count();
+    if digits == 0 {
+        return None;
+    }
+    let separators = run
+        .split(u8::is_ascii_digit)
+        .filter(|s| !s.is_empty())
+        .count()
+        .saturating_sub(usize::from(b[i] == b'+' || b[i] == b'('));
+    let after = b.get(end).copied();
+    let ok = (10..=15).contains(&digits)
+        && (b[i] == b'+' || separators >= 2)
+        && after.is_none_or(|c| !word(c) && c != b':');
+    ok.then_some(end)
+}
+
+/// Byte ranges of comments and string literal contents in Rust source.
+fn rust_regions(src: &str) -> Vec<Range<usize>> {
+    let b = src.as_bytes();
+    let mut regions = Vec::new();
+    let mut i = 0;
+    while i < b.len() {
+        match (b[i], b.get(i + 1).copied()) {
+            (b'/', Some(b'/')) => {
+                let end = src[i..].find('\n').map_or(b.len(), |n| i + n);
+                regions.push(i..end);
+                i = end;
+            }
+            (b'/', Some(b'*')) => {
+                let (mut depth, mut j) = (1, i + 2);
+                while j < b.len() && depth > 0 {
+                    match (b[j], b.get(j + 1).copied()) {
+                        (b'/', Some(b'*')) => (depth, j) = (depth + 1, j + 2),
+                        (b'*', Some(b'/')) => (depth, j) = (depth - 1, j + 2),
+                        _ => j += 1,
+                    }
+                }
+                regions.push(i..j);
+                i = j;
+            }
+            (b'"', _) => {
+                let mut j = i + 1;
+                while j < b.len() && b[j] != b'"' {
+                    j += if b[j] == b'\\' { 2 } else { 1 };
+                }
+                let end = j.min(b.len());
+                regions.push(i + 1..end);
+                i = end + 1;
+            }
+            // a char literal: `'"'`, `'\''`; otherwise a lifetime
+            (b'\'', Some(b'\\')) => {
+                i += 3;
+                while i < b.len() && b[i] != b'\'' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            (b'\'', _) if b.get(i + 2) == Some(&b'\'') => i += 3,
+            (c, _) if word(c) => {
+                let len = b[i..].iter().take_while(|&&c| word(c)).count();
+                let ident = &src[i..i + len];
+                i += len;
+                let hashes = b[i..].iter().take_while(|&&c| c == b'#').count();
+                let raw = matches!(ident, "r" | "br" | "cr") && b.get(i + hashes) == Some(&b'"');
+                if raw {
+                    let open = i + hashes + 1;
+                    let close = format!("\"{}", "#".repeat(hashes));
+                    let end = src[open..].find(&close).map_or(b.len(), |n| open + n);
+                    regions.push(open..end);
+                    i = (end + close.len()).min(b.len());
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parenthesised_prose_is_kept() {
+        for text in ["see (the docs)", "(", "+", "a + b", "f(x) + (y)", "(1)"] {
+            assert_eq!(mask(text), text);
+        }
+    }
+
+    #[test]
+    fn phone_numbers() {
+        assert_eq!(mask("call +1 555 123 4567 now"), "call <phone> now");
+        assert_eq!(mask("tel (555) 123-4567"), "tel <phone>");
+        assert_eq!(mask("555.123.4567"), "<phone>");
+        assert_eq!(mask("+44 (20) 7946 0958"), "<phone>");
+        assert_eq!(mask("id 5551234567"), "id 5551234567");
+    }
+
+    #[test]
+    fn dates_and_versions_are_kept() {
+        for text in [
+            "2024-01-15 12:30",
+            "2024-01-15T12:30:00",
+            "v1.2.3",
+            "1.2.3.4.5",
+            "1.2.300.4",
+        ] {
+            assert_eq!(mask(text), text);
+        }
+    }
+
+    #[test]
+    fn emails_and_ips() {
+        assert_eq!(mask("mail jane.doe+x@example.co.uk."), "mail <email>.");
+        assert_eq!(mask("user@localhost"), "user@localhost");
+        assert_eq!(mask("host 192.168.0.1:80"), "host <ip>:80");
+        assert_eq!(
+            mask("at fe80::1 and 2001:db8::8a2e:370:7334"),
+            "at <ip> and <ip>"
+        );
+        assert_eq!(mask("a::b"), "a::b");
+    }
+
+    #[test]
+    fn rust_code_outside_comments_and_strings_is_kept() {
+        let src = "let ip = \"10.0.0.1\"; // mail a@b.io\nlet v = 10.0;\n";
+        assert_eq!(
+            scrub(Path::new("x.rs"), src).unwrap(),
+            "let ip = \"<ip>\"; // mail <email>\nlet v = 10.0;\n"
+        );
+        assert_eq!(scrub(Path::new("x.rs"), "fn f() {}\n"), None);
+    }
+}
@@ -0,0 +1,168 @@
+//! `--respect-package-filters` — each crate's own `package.include` /
+//! `package.exclude` as an extra filter, so a snapshot holds what
+//! `cargo package` would ship.
+//! * Patterns are gitignore-style and relative to the crate directory; when
+//!   both lists are set, `include` wins, as in cargo.
+//! * `include.workspace = true` (or `exclude`) takes the list from the root
+//!   manifest's `[workspace.package]`.
+//! * `Cargo.toml` is always kept; crates with neither field are unaffected.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use cargo_toml::{Inheritable, Manifest};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::Ctx;
+
+/// Compiled filters per crate directory.
+pub struct PackageFilters(HashMap<PathBuf, Filter>);
+
+enum Filter {
+    Include(Gitignore),
+    Exclude(Gitignore),
+}
+
+pub fn load(ctx: &Ctx) -> Result<PackageFilters> {
+    let template = ctx
+        .read(&ctx.root.join("Cargo.toml"))
+        .ok()
+        .and_then(|text| Manifest::from_str(&text).ok())
+        .and_then(|m| m.workspace?.package);
+    let mut filters = HashMap::new();
+    for dir in ctx.crates.keys() {
+        let path = dir.join("Cargo.toml");
+        let Ok(text) = ctx.read(&path) else {
+            continue;
+        };
+        let manifest = Manifest::from_str(&text)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        let Some(package) = manifest.package else {
+            continue;
+        };
+        let resolve = |list: &Inheritable<Vec<String>>, inherited: Option<&Vec<String>>| match list
+        {
+            Inheritable::Set(list) => list.clone(),
+            Inheritable::Inherited { .. } => inherited.cloned().unwrap_or_default(),
+        };
+        let include = resolve(
+            &package.include,
+            template.as_ref().and_then(|t| t.include.as_ref()),
+        );
+        let exclude = resolve(
+            &package.exclude,
+            template.as_ref().and_then(|t| t.exclude.as_ref()),
+        );
+        let filter = match (include.is_empty(), exclude.is_empty()) {
+            (false, _) => Filter::Include(compile(dir, &include, &path)?),
+            (true, false) => Filter::Exclude(compile(dir, &exclude, &path)?),
+            (true, true) => continue,
+        };
+        filters.insert(dir.clone(), filter);
+    }
+    Ok(PackageFilters(filters))
+}
+
+fn compile(dir: &Path, patterns: &[String], manifest: &Path) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(dir);
+    for pattern in patterns {
+        builder
+            .add_line(None, pattern)
+            .with_context(|| format!("bad pattern `{pattern}` in {}", manifest.display()))?;
+    }
+    Ok(builder.build()?)
+}
+
+impl PackageFilters {
+    /// The manifest field leaving `p` out of its crate, if any.
+    pub fn rejecting_field(&self, ctx: &Ctx, p: &Path) -> Option<&'static str> {
+        if p.file_name() == Some("Cargo.toml".as_ref()) {
+            return None;
+        }
+        let dir = ctx.crate_dir(p)?;
+        match self.0.get(dir)? {
+            Filter::Include(globs) => (!globs.matched_path_or_any_parents(p, false).is_ignore())
+                .then_some("package.include"),
+            Filter::Exclude(globs) => globs
+                .matched_path_or_any_parents(p, false)
+                .is_ignore()
+                .then_some("package.exclude"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use clap::Parser;
+
+    use crate::{config::Config, Opts};
+
+    #[test]
+    fn fields_reject_per_crate() {
+        let root = std::env::temp_dir().join(format!("qp-pkgfilter-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let files = [
+            (
+                "Cargo.toml",
+                "[workspace]\nmembers = [\"inc\", \"exc\", \"inh\", \"all\"]\n\
+                 [workspace.package]\nexclude = [\"*.bin\"]\n",
+            ),
+            (
+                "inc/Cargo.toml",
+                "[package]\nname = \"inc\"\nversion = \"0.1.0\"\n\
+                 include = [\"src/**\"]\nexclude = [\"src/lib.rs\"]\n",
+            ),
+            (
+                "exc/Cargo.toml",
+                "[package]\nname = \"exc\"\nversion = \"0.1.0\"\nexclude = [\"tests/\"]\n",
+            ),
+            (
+                "inh/Cargo.toml",
+                "[package]\nname = \"inh\"\nversion = \"0.1.0\"\nexclude.workspace = true\n",
+            ),
+            (
+                "all/Cargo.toml",
+                "[package]\nname = \"all\"\nversion = \"0.1.0\"\n",
+            ),
+        ];
+        for (rel, text) in files {
+            let path = root.join(rel);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        for krate in ["inc", "exc", "inh", "all"] {
+            std::fs::create_dir_all(root.join(krate).join("src")).unwrap();
+            std::fs::write(root.join(krate).join("src/lib.rs"), "").unwrap();
+        }
+        Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(&root)
+            .status()
+            .unwrap();
+        let opts = Opts::try_parse_from(["cargo-qp", "--respect-package-filters"]).unwrap();
+        let ctx = crate::context(&opts, root.clone(), &Config::default()).unwrap();
+        let filters = ctx.package_filters.as_ref().unwrap();
+        for (rel, field) in [
+            ("inc/Cargo.toml", None),
+            ("inc/src/lib.rs", None),
+            ("inc/README.md", Some("package.include")),
+            ("exc/src/lib.rs", None),
+            ("exc/tests/it.rs", Some("package.exclude")),
+            ("inh/data/blob.bin", Some("package.exclude")),
+            ("inh/src/lib.rs", None),
+            ("all/tests/it.rs", None),
+        ] {
+            assert_eq!(
+                filters.rejecting_field(&ctx, &root.join(rel)),
+                field,
+                "{rel}"
+            );
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
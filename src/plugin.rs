@@ -0,0 +1,281 @@
+//! `[[plugin]]` — sandboxed WebAssembly filters and transforms, run by an
+//! interpreter (`wasmi`) so a plugin behaves the same on every machine.
+//! * A plugin exports `memory`, `qp_alloc(len) -> ptr`, and at least one of
+//!   - `qp_filter(ptr, len) -> i32`: input is the relative path; `0` drops
+//!     the file from the snapshot;
+//!   - `qp_transform(ptr, len) -> i64`: input is `{"path", "body"}` JSON, the
+//!     result is `(ptr << 32) | len` of `{"body"}` JSON.
+//! * WASI modules load, but every `wasi_snapshot_preview1` import is a stub
+//!   returning `ENOSYS` (`proc_exit` traps): no files, clock, env or network.
+//! * Each call gets a fresh instance, a fuel budget and a memory cap, so
+//!   plugins keep no state between files and can't hang the run or exhaust
+//!   memory; a returned buffer must lie inside the plugin's memory.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use globset::GlobSet;
+use wasmi::{
+    Config, Engine, Error, ExternType, Instance, Linker, Module, Store, StoreLimits,
+    StoreLimitsBuilder, Val,
+};
+
+use crate::{
+    config::{self, PluginConfig},
+    transform::{FileEntry, Filter, Transform},
+};
+
+/// Instructions (roughly) a single call may execute.
+const FUEL: u64 = 5_000_000_000;
+/// Bytes a plugin's linear memory may grow to.
+const MEMORY: usize = 256 << 20;
+/// `ENOSYS` in WASI's errno numbering.
+const ENOSYS: i32 = 52;
+
+pub struct WasmPlugin {
+    name: String,
+    /// Hash of the module, for `identity`.
+    hash: String,
+    engine: Engine,
+    module: Module,
+    include: Option<GlobSet>,
+    has_filter: bool,
+    has_transform: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct Output {
+    body: String,
+}
+
+impl WasmPlugin {
+    pub fn load(root: &Path, plugin: &PluginConfig) -> Result<Self> {
+        let path = root.join(&plugin.path);
+        let bytes =
+            std::fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &bytes[..])
+            .map_err(|e| anyhow::anyhow!("invalid plugin {}: {e}", plugin.path))?;
+        let exports = |name| module.exports().any(|e| e.name() == name);
+        let (has_filter, has_transform) = (exports("qp_filter"), exports("qp_transform"));
+        if !has_filter && !has_transform || !exports("qp_alloc") || !exports("memory") {
+            anyhow::bail!(
+                "plugin {} must export `memory`, `qp_alloc` and `qp_filter` and/or `qp_transform`",
+                plugin.path
+            );
+        }
+        let include = if plugin.include.is_empty() {
+            None
+        } else {
+            Some(config::glob_set(&plugin.include, "plugin include")?)
+        };
+        Ok(WasmPlugin {
+            name: plugin.name.clone().unwrap_or_else(|| plugin.path.clone()),
+            hash: blake3::hash(&bytes).to_hex().to_string(),
+            engine,
+            module,
+            include,
+            has_filter,
+            has_transform,
+        })
+    }
+
+    pub fn has_filter(&self) -> bool {
+        self.has_filter
+    }
+
+    pub fn has_transform(&self) -> bool {
+        self.has_transform
+    }
+
+    fn applies_to(&self, rel: &Path) -> bool {
+        self.include.as_ref().is_none_or(|g| g.is_match(rel))
+    }
+
+    /// A fresh sandboxed instance with WASI stubbed out.
+    fn instantiate(&self) -> Result<(Store<StoreLimits>, Instance), Error> {
+        let limits = StoreLimitsBuilder::new().memory_size(MEMORY).build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL)?;
+        let mut linker = Linker::<StoreLimits>::new(&self.engine);
+        for import in self.module.imports() {
+            let ExternType::Func(ty) = import.ty() else {
+                continue;
+            };
+            if import.module() != "wasi_snapshot_preview1" {
+                continue; // reported by instantiate as an unknown import
+            }
+            let errno =
+                (import.name() != "proc_exit" && !ty.results().is_empty()).then_some(ENOSYS);
+            linker.func_new(
+                import.module(),
+                import.name(),
+                ty.clone(),
+                move |_, _, results| match errno {
+                    Some(errno) => {
+                        results[0] = Val::I32(errno);
+                        Ok(())
+                    }
+                    None => Err(Error::new("plugin exited")),
+                },
+            )?;
+        }
+        let instance = linker
+            .instantiate(&mut store, &self.module)?
+            .start(&mut store)?;
+        Ok((store, instance))
+    }
+
+    /// Calls `export` with `input` copied into the plugin's memory.
+    fn call<R: wasmi::WasmResults>(
+        &self,
+        export: &str,
+        input: &[u8],
+    ) -> Result<(Store<StoreLimits>, Instance, R)> {
+        let run = || -> Result<_, Error> {
+            let (mut store, instance) = self.instantiate()?;
+            let memory = instance
+                .get_memory(&store, "memory")
+                .ok_or_else(|| Error::new("no `memory` export"))?;
+            let alloc = instance.get_typed_func::<i32, i32>(&store, "qp_alloc")?;
+            let ptr = alloc.call(&mut store, input.len() as i32)?;
+            memory.write(&mut store, ptr as u32 as usize, input)?;
+            let func = instance.get_typed_func::<(i32, i32), R>(&store, export)?;
+            let result = func.call(&mut store, (ptr, input.len() as i32))?;
+            Ok((store, instance, result))
+        };
+        run().map_err(|e| anyhow::anyhow!("plugin `{}` failed in {export}: {e}", self.name))
+    }
+}
+
+impl Filter for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn keep(&self, rel: &Path) -> Result<bool> {
+        if !self.has_filter || !self.applies_to(rel) {
+            return Ok(true);
+        }
+        let path = rel.to_string_lossy();
+        let (_, _, keep) = self.call::<i32>("qp_filter", path.as_bytes())?;
+        Ok(keep != 0)
+    }
+}
+
+impl Transform for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn identity(&self) -> String {
+        format!("{} {}", self.name, self.hash)
+    }
+
+    fn apply(&self, file: &mut FileEntry) -> Result<()> {
+        if !self.has_transform || !self.applies_to(&file.path) {
+            return Ok(());
+        }
+        let input = serde_json::to_vec(&*file)?;
+        let (store, instance, packed) = self.call::<i64>("qp_transform", &input)?;
+        let (ptr, len) = (
+            (packed as u64 >> 32) as usize,
+            (packed as u64 & 0xffff_ffff) as usize,
+        );
+        let memory = instance.get_memory(&store, "memory").context("no memory")?;
+        if ptr
+            .checked_add(len)
+            .is_none_or(|end| end > memory.data_size(&store))
+        {
+            anyhow::bail!(
+                "plugin `{}` returned a buffer outside its memory ({len} bytes at {ptr})",
+                self.name
+            );
+        }
+        let mut out = vec![0; len];
+        memory
+            .read(&store, ptr, &mut out)
+            .map_err(|e| anyhow::anyhow!("plugin `{}` returned a bad buffer: {e}", self.name))?;
+        let out: Output = serde_json::from_slice(&out).with_context(|| {
+            format!(
+                "plugin `{}` returned invalid JSON for {} (expected {{\"body\": \"…\"}})",
+                self.name,
+                file.path.display()
+            )
+        })?;
+        file.body = out.body;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module exporting `memory` (`pages` pages), `qp_alloc` returning 0
+    /// and a `qp_transform` returning `packed`.
+    fn module(pages: &[u8], packed: &[u8]) -> Vec<u8> {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        // types: (i32) -> i32, (i32, i32) -> i64
+        wasm.extend([
+            1, 12, 2, 0x60, 1, 0x7f, 1, 0x7f, 0x60, 2, 0x7f, 0x7f, 1, 0x7e,
+        ]);
+        wasm.extend([3, 3, 2, 0, 1]);
+        wasm.extend([5, 2 + pages.len() as u8, 1, 0]);
+        wasm.extend(pages);
+        wasm.extend([7, 36, 3, 6]);
+        wasm.extend(b"memory\x02\0\x08qp_alloc\0\0\x0cqp_transform\0\x01");
+        let transform = [&[0, 0x42][..], packed, &[0x0b]].concat();
+        let code = [
+            &[2, 4, 0, 0x41, 0, 0x0b, transform.len() as u8][..],
+            &transform,
+        ]
+        .concat();
+        wasm.extend([10, code.len() as u8]);
+        wasm.extend(code);
+        wasm
+    }
+
+    fn load(name: &str, wasm: &[u8]) -> Result<WasmPlugin> {
+        let dir = std::env::temp_dir();
+        let path = format!("qp-plugin-{name}-{}.wasm", std::process::id());
+        std::fs::write(dir.join(&path), wasm)?;
+        let plugin = WasmPlugin::load(
+            &dir,
+            &PluginConfig {
+                name: None,
+                path: path.clone(),
+                include: Vec::new(),
+            },
+        );
+        std::fs::remove_file(dir.join(&path))?;
+        plugin
+    }
+
+    fn transform(plugin: &WasmPlugin) -> Result<()> {
+        let mut file = FileEntry {
+            path: "a.rs".into(),
+            body: "fn a() {}\n".into(),
+        };
+        plugin.apply(&mut file)
+    }
+
+    #[test]
+    fn buffer_outside_memory_is_refused() {
+        // len 0xffff_ffff at 0
+        let plugin = load("bounds", &module(&[1], &[0xff, 0xff, 0xff, 0xff, 0x0f])).unwrap();
+        let err = transform(&plugin).unwrap_err().to_string();
+        assert!(err.contains("outside its memory"), "{err}");
+    }
+
+    #[test]
+    fn memory_is_capped() {
+        // 8192 pages, 512 MiB
+        let plugin = load("memory", &module(&[0x80, 0x40], &[0])).unwrap();
+        let err = transform(&plugin).unwrap_err().to_string();
+        assert!(err.contains("memory allocation"), "{err}");
+    }
+}
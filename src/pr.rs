@@ -0,0 +1,147 @@
+//! `cargo qp pr <NUMBER>` — review-ready snapshot of a GitHub pull request.
+//! * Description, conversation comments, inline review comments and the diff
+//!   come from the `gh` CLI (which handles auth and the current remote).
+//! * Touched files that pass the filters follow in full, from the working tree.
+
+use std::{io::Write, process::Command};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{git, Ctx};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Pr {
+    title: String,
+    #[serde(default)]
+    body: String,
+    url: String,
+    author: Login,
+    base_ref_name: String,
+    head_ref_name: String,
+    #[serde(default)]
+    files: Vec<PrFile>,
+    #[serde(default)]
+    comments: Vec<Comment>,
+    #[serde(default)]
+    reviews: Vec<Comment>,
+}
+
+#[derive(Deserialize)]
+struct Login {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct PrFile {
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct Comment {
+    author: Option<Login>,
+    #[serde(default)]
+    body: String,
+}
+
+#[derive(Deserialize)]
+struct ReviewComment {
+    user: Option<Login>,
+    path: String,
+    line: Option<u64>,
+    #[serde(default)]
+    body: String,
+}
+
+fn gh(dir: &std::path::Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("gh")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .context("failed to run gh (is the GitHub CLI installed?)")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "`gh {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+pub fn compose(ctx: &mut Ctx, out: &mut dyn Write, number: u64) -> Result<()> {
+    let n = number.to_string();
+    let pr: Pr = serde_json::from_str(&gh(
+        &ctx.root,
+        &[
+            "pr",
+            "view",
+            &n,
+            "--json",
+            "title,body,url,author,baseRefName,headRefName,files,comments,reviews",
+        ],
+    )?)
+    .context("unexpected `gh pr view` output")?;
+    let inline: Vec<ReviewComment> = serde_json::from_str(&gh(
+        &ctx.root,
+        &[
+            "api",
+            &format!("repos/{{owner}}/{{repo}}/pulls/{n}/comments"),
+        ],
+    )?)
+    .context("unexpected review comments payload")?;
+    let patch = gh(&ctx.root, &["pr", "diff", &n, "--color", "never"])?;
+
+    let description = format!(
+        "{}\nby @{} — {} ← {}\n\n{}\n",
+        pr.url,
+        pr.author.login,
+        pr.base_ref_name,
+        pr.head_ref_name,
+        pr.body.trim()
+    );
+    ctx.push_section(out, &format!("PR #{n} :: {}", pr.title), &description)?;
+
+    let discussion: Vec<&Comment> = pr
+        .comments
+        .iter()
+        .chain(&pr.reviews)
+        .filter(|c| !c.body.trim().is_empty())
+        .collect();
+    if !discussion.is_empty() || !inline.is_empty() {
+        let mut text = String::new();
+        for c in discussion {
+            text.push_str(&format!("@{}: {}\n\n", login(&c.author), c.body.trim()));
+        }
+        for c in &inline {
+            let at = c.line.map(|l| format!(":{l}")).unwrap_or_default();
+            text.push_str(&format!(
+                "@{} on {}{at}: {}\n\n",
+                login(&c.user),
+                c.path,
+                c.body.trim()
+            ));
+        }
+        text.pop(); // the section adds the closing newline
+        ctx.push_section(out, &format!("PR #{n} :: comments"), &text)?;
+    }
+
+    ctx.push_section(out, &format!("PR #{n} :: diff"), &patch)?;
+
+    // PR paths are relative to the repository root
+    let top = git::toplevel(&ctx.root)?;
+    for file in &pr.files {
+        let path = top.join(&file.path);
+        if !path.starts_with(&ctx.root) || !path.is_file() || !ctx.wants(&path) {
+            continue;
+        }
+        let body = ctx.read(&path)?;
+        ctx.push_file(out, &path, None, &body)?;
+    }
+    Ok(())
+}
+
+fn login(l: &Option<Login>) -> &str {
+    l.as_ref().map_or("ghost", |l| l.login.as_str())
+}
@@ -0,0 +1,248 @@
+//! `--preamble auto` — a short plain-language introduction ahead of the
+//! files: the workspace, each selected crate with its manifest
+//! `description`, the Rust edition and the most used dependencies.
+//! * A `[preamble]` hook in `.cargo-qp.toml` adds a richer summary per crate
+//!   from a local model: the command reads a prompt (the crate's manifest and
+//!   public API) on stdin and prints the summary. A failing hook only warns.
+//! * Summaries are cached in the manifest file (`--manifest`/`--follow-up`,
+//!   else `qp-manifest.json`) under a fingerprint of the prompt, so the model
+//!   only runs again for crates whose manifest or API changed.
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result};
+use cargo_metadata::{DependencyKind, MetadataCommand, Package};
+use clap::ValueEnum;
+
+use crate::{
+    config::PreambleHook,
+    manifest::{self, Manifest},
+    schema::CrateSummary,
+    syntax, Ctx, Opts,
+};
+
+/// External dependencies listed under "key dependencies".
+const KEY_DEPS: usize = 8;
+/// Prompt bytes handed to the hook per crate.
+const MAX_PROMPT: usize = 48_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preamble {
+    /// Synthesized from `cargo metadata`
+    Auto,
+}
+
+pub fn write(ctx: &mut Ctx, opts: &Opts, out: &mut dyn Write) -> Result<()> {
+    let md = MetadataCommand::new()
+        .manifest_path(ctx.root.join("Cargo.toml"))
+        .no_deps()
+        .exec()
+        .context("cargo metadata failed (needed for --preamble)")?;
+    let packages: Vec<&Package> = md
+        .workspace_packages()
+        .into_iter()
+        .filter(|p| !ctx.skipped.contains(&crate::package_dir(p)))
+        .collect();
+    let summaries = match &ctx.preamble_hook {
+        Some(hook) => summarize(ctx, opts, hook, &packages)?,
+        None => BTreeMap::new(),
+    };
+
+    let name = md
+        .root_package()
+        .map(|p| p.name.clone())
+        .or_else(|| {
+            let dir = ctx.root.file_name()?;
+            Some(dir.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default();
+    let editions: Vec<String> = packages.iter().map(|p| p.edition.to_string()).collect();
+    let common = editions
+        .first()
+        .filter(|first| editions.iter().all(|e| e == *first));
+    let mut text = format!(
+        "Workspace `{name}`: {} crate{}",
+        packages.len(),
+        if packages.len() == 1 { "" } else { "s" }
+    );
+    if let Some(edition) = common {
+        text.push_str(&format!(", Rust edition {edition}"));
+    }
+    text.push_str(".\n");
+    for p in &packages {
+        let mut line = format!("- `{}` v{}", p.name, p.version);
+        if common.is_none() {
+            line.push_str(&format!(" (edition {})", p.edition));
+        }
+        match p.description.as_deref().map(str::trim) {
+            Some(description) if !description.is_empty() => {
+                line.push_str(&format!(": {}", first_line(description)))
+            }
+            _ => line.push_str(": no description"),
+        }
+        text.push_str(&line);
+        text.push('\n');
+        if let Some(summary) = summaries.get(&p.name) {
+            for l in summary.lines() {
+                text.push_str(&format!("  {l}\n"));
+            }
+        }
+    }
+    let deps = key_deps(&packages);
+    if !deps.is_empty() {
+        text.push_str(&format!("Key dependencies: {}.\n", deps.join(", ")));
+    }
+    ctx.push_section(out, "preamble", &text)?;
+    Ok(())
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default().trim()
+}
+
+/// Normal dependencies outside the workspace, `name req`, most shared first.
+fn key_deps(packages: &[&Package]) -> Vec<String> {
+    let mut used: BTreeMap<&str, (usize, String)> = BTreeMap::new();
+    for p in packages {
+        for d in &p.dependencies {
+            if d.kind != DependencyKind::Normal || packages.iter().any(|m| m.name == d.name) {
+                continue;
+            }
+            let req = d.req.to_string();
+            let entry = used
+                .entry(d.name.as_str())
+                .or_insert((0, req.trim_start_matches('^').to_string()));
+            entry.0 += 1;
+        }
+    }
+    let mut deps: Vec<_> = used.into_iter().collect();
+    deps.sort_by_key(|(name, (count, _))| (std::cmp::Reverse(*count), *name));
+    deps.into_iter()
+        .take(KEY_DEPS)
+        .map(|(name, (count, req))| match count {
+            1 => format!("{name} {req}"),
+            n => format!("{name} {req} ({n} crates)"),
+        })
+        .collect()
+}
+
+/// Summaries per crate name, from the cache or the hook; updates the cache.
+fn summarize(
+    ctx: &Ctx,
+    opts: &Opts,
+    hook: &PreambleHook,
+    packages: &[&Package],
+) -> Result<BTreeMap<String, String>> {
+    let cache_path = ctx.root.join(
+        opts.manifest
+            .as_deref()
+            .or(opts.follow_up.as_deref())
+            .unwrap_or(Path::new(manifest::DEFAULT_FILE)),
+    );
+    let mut cache = if cache_path.exists() {
+        Manifest::load(&cache_path)?
+    } else {
+        Manifest::empty()
+    };
+    let selected = ctx.selected()?;
+    let mut summaries = BTreeMap::new();
+    let mut changed = false;
+    for p in packages {
+        let prompt = prompt(ctx, p, &selected);
+        let fingerprint = blake3::hash(prompt.as_bytes()).to_hex().to_string();
+        let cached = cache
+            .summaries
+            .get(&p.name)
+            .filter(|s| s.fingerprint == fingerprint);
+        if let Some(summary) = cached {
+            summaries.insert(p.name.clone(), summary.text.clone());
+            continue;
+        }
+        match run_hook(ctx, hook, &prompt) {
+            Ok(text) if !text.is_empty() => {
+                cache.summaries.insert(
+                    p.name.clone(),
+                    CrateSummary {
+                        fingerprint,
+                        text: text.clone(),
+                    },
+                );
+                summaries.insert(p.name.clone(), text);
+                changed = true;
+            }
+            Ok(_) => eprintln!("warning: preamble hook printed nothing for `{}`", p.name),
+            Err(e) => eprintln!("warning: preamble hook failed for `{}`: {e:#}", p.name),
+        }
+    }
+    if changed {
+        cache.write(&cache_path)?;
+    }
+    Ok(summaries)
+}
+
+/// The crate's manifest and the public API of its selected `.rs` files.
+fn prompt(ctx: &Ctx, p: &Package, selected: &[PathBuf]) -> String {
+    let dir = crate::package_dir(p);
+    let mut prompt = format!(
+        "Summarize the Rust crate `{}` in two or three sentences for a reader \
+         about to see its source: what it is for and its main entry points.\n",
+        p.name
+    );
+    let manifest = std::fs::read_to_string(dir.join("Cargo.toml")).unwrap_or_default();
+    prompt.push_str(&format!("\n--- Cargo.toml ---\n{manifest}"));
+    for path in selected {
+        if prompt.len() >= MAX_PROMPT {
+            break;
+        }
+        let owned = ctx.crate_dir(path) == Some(dir.as_path());
+        if !owned || path.extension().is_none_or(|x| x != "rs") {
+            continue;
+        }
+        let api = ctx.read(path).ok().and_then(|src| syntax::api(&src));
+        if let Some(api) = api.filter(|api| !api.trim().is_empty()) {
+            let rel = path.strip_prefix(&dir).unwrap_or(path);
+            prompt.push_str(&format!("\n--- {} ---\n{api}", rel.display()));
+        }
+    }
+    let mut end = prompt.len().min(MAX_PROMPT);
+    while !prompt.is_char_boundary(end) {
+        end -= 1;
+    }
+    prompt.truncate(end);
+    prompt
+}
+
+fn run_hook(ctx: &Ctx, hook: &PreambleHook, prompt: &str) -> Result<String> {
+    let program = if hook.command.contains(['/', '\\']) {
+        ctx.root.join(&hook.command)
+    } else {
+        PathBuf::from(&hook.command)
+    };
+    let mut child = Command::new(&program)
+        .args(&hook.args)
+        .current_dir(&ctx.root)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run `{}`", hook.command))?;
+    let mut stdin = child.stdin.take().context("preamble hook has no stdin")?;
+    let input = prompt.as_bytes().to_vec();
+    // feed stdin from a thread so a full stdout pipe can't deadlock us
+    let feeder = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output()?;
+    let _ = feeder.join();
+    if !output.status.success() {
+        anyhow::bail!(
+            "exit {:?}: {}",
+            output.status.code(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,54 @@
+//! Progress bar on stderr while a large selection is read, so big runs don't
+//! look hung.
+//! * Shown past `THRESHOLD` files, and only when stderr is a terminal;
+//!   `--quiet` (and the library, unless asked) turns it off.
+//! * Counts files and bytes read and names the crate being read.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use indicatif::{HumanBytes, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// Fewer files read fast enough to need no bar.
+const THRESHOLD: usize = 300;
+
+pub struct Progress {
+    bar: Option<ProgressBar>,
+    bytes: AtomicU64,
+}
+
+impl Progress {
+    /// A bar for reading `files` files; hidden when not worth showing.
+    pub fn new(files: usize, quiet: bool) -> Self {
+        let bar = (!quiet && files > THRESHOLD).then(|| {
+            let bar =
+                ProgressBar::with_draw_target(Some(files as u64), ProgressDrawTarget::stderr());
+            bar.set_style(
+                ProgressStyle::with_template("{bar:30} {pos}/{len} files, {msg}")
+                    .expect("valid template"),
+            );
+            bar
+        });
+        Progress {
+            bar,
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// One more file of `crate_name` read, `bytes` long.
+    pub fn read(&self, crate_name: &str, bytes: usize) {
+        let Some(bar) = &self.bar else {
+            return;
+        };
+        let total = self.bytes.fetch_add(bytes as u64, Ordering::Relaxed) + bytes as u64;
+        bar.set_message(format!("{} · {crate_name}", HumanBytes(total)));
+        bar.inc(1);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
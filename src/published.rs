@@ -0,0 +1,207 @@
+//! crates.io publication status for `--check-published`.
+//! * Asks the sparse index (`index.crates.io`) through `curl`.
+//! * Falls back to cargo's local sparse-index cache when offline or when the
+//!   network request fails; only a 404 means the crate was never published
+//!   (a 429 or 5xx is a failed request).
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use cargo_metadata::semver::Version;
+use serde::Deserialize;
+
+#[derive(Debug, Clone)]
+pub enum Status {
+    /// Local version (or the whole crate) is not in the index.
+    Unreleased,
+    /// Local version was published and later yanked.
+    Yanked,
+    /// A newer non-yanked release exists.
+    Behind(Version),
+    /// Local version is the newest release.
+    Latest,
+    /// No index data available (offline without a cache entry).
+    Unknown,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Unreleased => f.write_str("unreleased"),
+            Status::Yanked => f.write_str("yanked"),
+            Status::Behind(v) => write!(f, "behind {v}"),
+            Status::Latest => f.write_str("latest"),
+            Status::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct IndexEntry {
+    vers: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+/// Status of `name@version` relative to crates.io.
+pub fn check(name: &str, version: &str, offline: bool) -> Status {
+    let Ok(local) = Version::parse(version) else {
+        return Status::Unknown;
+    };
+    let entries = (!offline)
+        .then(|| fetch_remote(name))
+        .flatten()
+        .or_else(|| read_cache(name));
+    match entries {
+        Some(entries) => classify(&local, &entries),
+        None => Status::Unknown,
+    }
+}
+
+fn classify(local: &Version, entries: &[IndexEntry]) -> Status {
+    let versions: Vec<(Version, bool)> = entries
+        .iter()
+        .filter_map(|e| Version::parse(&e.vers).ok().map(|v| (v, e.yanked)))
+        .collect();
+
+    let Some((_, yanked)) = versions.iter().find(|(v, _)| v == local) else {
+        return Status::Unreleased;
+    };
+    if *yanked {
+        return Status::Yanked;
+    }
+    // pre-releases only count as "newer" when the local version is one too
+    let newest = versions
+        .iter()
+        .filter(|(v, yanked)| !yanked && (v.pre.is_empty() || !local.pre.is_empty()))
+        .map(|(v, _)| v)
+        .max();
+    match newest {
+        Some(v) if v > local => Status::Behind(v.clone()),
+        _ => Status::Latest,
+    }
+}
+
+//──────────────────────── index access ───────────────────────────────────────
+
+/// Relative index path, e.g. `se/rd/serde` or `3/a/arc`.
+fn index_path(name: &str) -> String {
+    let name = name.to_lowercase();
+    match name.len() {
+        1 => format!("1/{name}"),
+        2 => format!("2/{name}"),
+        3 => format!("3/{}/{name}", &name[..1]),
+        _ => format!("{}/{}/{name}", &name[..2], &name[2..4]),
+    }
+}
+
+fn fetch_remote(name: &str) -> Option<Vec<IndexEntry>> {
+    let url = format!("https://index.crates.io/{}", index_path(name));
+    let output = Command::new("curl")
+        .args(["-sL", "--max-time", "10", "-w", "\n%{http_code}", &url])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let split = output.stdout.iter().rposition(|&b| b == b'\n')?;
+    let (body, status) = output.stdout.split_at(split);
+    match &status[1..] {
+        b"200" => Some(parse_lines(body)),
+        // never published
+        b"404" => Some(Vec::new()),
+        _ => None,
+    }
+}
+
+fn parse_lines(body: &[u8]) -> Vec<IndexEntry> {
+    String::from_utf8_lossy(body)
+        .lines()
+        .filter_map(|l| serde_json::from_str(l).ok())
+        .collect()
+}
+
+/// Reads cargo's sparse cache file:
+/// `[version u8][index version u32][header\0]` then `version\0json\0` pairs.
+fn read_cache(name: &str) -> Option<Vec<IndexEntry>> {
+    let rel = index_path(name);
+    let bytes = cache_dirs()
+        .into_iter()
+        .find_map(|dir| std::fs::read(dir.join(".cache").join(&rel)).ok())?;
+    let body = bytes.get(5..)?;
+    let mut fields = body.split(|b| *b == 0).skip(1);
+    let mut entries = Vec::new();
+    while let (Some(_), Some(json)) = (fields.next(), fields.next()) {
+        if let Ok(e) = serde_json::from_slice(json) {
+            entries.push(e);
+        }
+    }
+    Some(entries)
+}
+
+fn cache_dirs() -> Vec<PathBuf> {
+    let Some(home) = cargo_home() else {
+        return Vec::new();
+    };
+    let Ok(rd) = std::fs::read_dir(home.join("registry").join("index")) else {
+        return Vec::new();
+    };
+    rd.filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| is_crates_io_index(p))
+        .collect()
+}
+
+fn is_crates_io_index(p: &Path) -> bool {
+    p.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with("index.crates.io-"))
+}
+
+fn cargo_home() -> Option<PathBuf> {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cargo")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(list: &[(&str, bool)]) -> Vec<IndexEntry> {
+        list.iter()
+            .map(|&(vers, yanked)| IndexEntry {
+                vers: vers.to_string(),
+                yanked,
+            })
+            .collect()
+    }
+
+    fn status(local: &str, list: &[(&str, bool)]) -> String {
+        classify(&Version::parse(local).unwrap(), &entries(list)).to_string()
+    }
+
+    #[test]
+    fn classify_against_the_index() {
+        assert_eq!(status("0.1.0", &[]), "unreleased");
+        assert_eq!(status("0.2.0", &[("0.1.0", false)]), "unreleased");
+        assert_eq!(status("0.1.0", &[("0.1.0", true)]), "yanked");
+        let list = [("0.1.0", false), ("0.2.0", false), ("0.3.0", true)];
+        assert_eq!(status("0.1.0", &list), "behind 0.2.0");
+        assert_eq!(status("0.2.0", &list), "latest");
+        let pre = [("1.0.0", false), ("1.1.0-rc.1", false)];
+        assert_eq!(status("1.0.0", &pre), "latest");
+        let pre = [("1.1.0-rc.1", false), ("1.1.0-rc.2", false)];
+        assert_eq!(status("1.1.0-rc.1", &pre), "behind 1.1.0-rc.2");
+    }
+
+    #[test]
+    fn index_paths() {
+        assert_eq!(index_path("a"), "1/a");
+        assert_eq!(index_path("cc"), "2/cc");
+        assert_eq!(index_path("Arc"), "3/a/arc");
+        assert_eq!(index_path("serde"), "se/rd/serde");
+    }
+}
@@ -0,0 +1,438 @@
+//! `--rank-by` — which files win when a budget can't hold them all.
+//! * `size`: smallest first, so the most files fit.
+//! * `recency`: most recently committed first (uncommitted files lead).
+//! * `relevance`: BM25 against `cargo qp auto`'s question (its default).
+//! * `centrality`: most imported first — the number of other selected files
+//!   whose paths (`use` trees included) or `mod` declarations reach the
+//!   file's module, across workspace crates too. Modules are found by file name
+//!   (`src/a/b.rs`, `src/a/b/mod.rs` → `a::b`); `#[path]` is not followed.
+//! * Manifests always come first: they are small and say what each crate
+//!   is.
+//! * Applies to `auto`, `--per-crate-cap`, and with `--max-tokens` to the
+//!   snapshot itself: files are then admitted best first, whole or as
+//!   signatures, and dropped once nothing more fits. Output order stays
+//!   `--order`'s.
+
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use syn::visit::{self, Visit};
+
+use crate::{dedupe, tokens, Ctx};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RankBy {
+    Size,
+    Recency,
+    Relevance,
+    Centrality,
+}
+
+/// BM25 parameters, the usual defaults.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+/// Weight of a term found in the path over one in the body.
+const PATH_WEIGHT: f64 = 3.0;
+
+/// Indices of `files` (path, body), manifests first, then best first; ties
+/// fall back to path. `relevance` ranks by `scores`, which `relevance`
+/// computes.
+pub fn priority(
+    ctx: &Ctx,
+    rank_by: RankBy,
+    files: &[(PathBuf, String)],
+    scores: Option<&[f64]>,
+) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    let by_path = |i: &usize| files[*i].0.clone();
+    match rank_by {
+        RankBy::Size => order.sort_by_cached_key(|i| (files[*i].1.len(), by_path(i))),
+        RankBy::Recency => {
+            let last = ctx.last_commits();
+            order.sort_by_cached_key(|i| {
+                let time = last
+                    .get(&*ctx.rel(&files[*i].0).to_string_lossy())
+                    .map(|c| c.time);
+                (time.is_some(), Reverse(time), by_path(i))
+            });
+        }
+        RankBy::Relevance => {
+            let scores = scores.unwrap_or_default();
+            let score = |i: usize| scores.get(i).copied().unwrap_or_default();
+            order.sort_by(|a, b| {
+                score(*b)
+                    .total_cmp(&score(*a))
+                    .then(files[*a].0.cmp(&files[*b].0))
+            });
+        }
+        RankBy::Centrality => {
+            let mut importers = vec![0; files.len()];
+            for j in imports(ctx, files).into_iter().flatten() {
+                importers[j] += 1;
+            }
+            order.sort_by_cached_key(|i| (Reverse(importers[*i]), by_path(i)));
+        }
+    }
+    order.sort_by_key(|i| files[*i].0.file_name() != Some("Cargo.toml".as_ref()));
+    order
+}
+
+/// Admits the files at `order` (indices into `files`) while they fit in
+/// `budget` tokens on top of `used`: each whole (or as its `refs` entry, see
+/// `caps::limit`), else as signatures, else not at all. Returns which files
+/// were kept and the tokens used. `--extra` files are kept whatever the
+/// budget. A dedupe reference whose original was left out is replaced by
+/// the full body (or signatures) if that fits, else dropped too.
+pub fn fit(
+    ctx: &Ctx,
+    files: &[(PathBuf, String)],
+    order: &[usize],
+    refs: &mut [Option<(String, String)>],
+    mut used: usize,
+    budget: usize,
+) -> (Vec<bool>, usize) {
+    let mut kept = vec![false; files.len()];
+    // `--extra` files go in whole, whatever the budget
+    let (pinned, order): (Vec<usize>, Vec<usize>) = order
+        .iter()
+        .partition(|&&i| ctx.extra.contains(&files[i].0));
+    for i in pinned {
+        let (path, body) = &files[i];
+        let text = refs[i].as_ref().map_or(body.as_str(), |(_, b)| b.as_str());
+        used += cost(ctx, path, text);
+        kept[i] = true;
+    }
+    for i in order {
+        let (path, body) = &files[i];
+        let text = refs[i].as_ref().map_or(body.as_str(), |(_, b)| b.as_str());
+        let whole = cost(ctx, path, text);
+        if used + whole <= budget {
+            used += whole;
+            kept[i] = true;
+            continue;
+        }
+        if let Some(sigs) = signatures_within(ctx, path, body, budget.saturating_sub(used)) {
+            used += cost(ctx, path, &sigs);
+            refs[i] = Some(("signatures".into(), sigs));
+            kept[i] = true;
+        }
+    }
+    loop {
+        let promoted = dedupe::rebase(ctx, files, refs, &kept);
+        if promoted.is_empty() {
+            break;
+        }
+        for (i, (_, reference)) in promoted {
+            let (path, body) = &files[i];
+            used -= cost(ctx, path, &reference);
+            let whole = cost(ctx, path, body);
+            if used + whole <= budget || ctx.extra.contains(path) {
+                used += whole;
+                continue;
+            }
+            match signatures_within(ctx, path, body, budget.saturating_sub(used)) {
+                Some(sigs) => {
+                    used += cost(ctx, path, &sigs);
+                    refs[i] = Some(("signatures".into(), sigs));
+                }
+                None => kept[i] = false,
+            }
+        }
+    }
+    (kept, used)
+}
+
+/// Signatures of a `.rs` body, if they cost at most `room` tokens.
+fn signatures_within(ctx: &Ctx, path: &Path, body: &str, room: usize) -> Option<String> {
+    path.extension()
+        .is_some_and(|x| x == "rs")
+        .then(|| ctx.signatures(body))
+        .flatten()
+        .filter(|sigs| cost(ctx, path, sigs) <= room)
+}
+
+/// Estimated tokens of `text` sent as `path`, header included.
+pub fn cost(ctx: &Ctx, path: &Path, text: &str) -> usize {
+    tokens::estimate(text) + tokens::estimate(&ctx.rel(path).to_string_lossy()) + 8
+}
+
+/// Lowercase words of `question` worth searching for.
+pub fn terms(question: &str) -> HashSet<String> {
+    const STOPWORDS: &[&str] = &[
+        "a", "an", "and", "are", "as", "at", "be", "by", "can", "do", "does", "for", "from", "how",
+        "i", "if", "in", "is", "it", "my", "of", "on", "or", "should", "so", "that", "the", "this",
+        "to", "we", "what", "when", "where", "which", "who", "why", "with", "you",
+    ];
+    // before stemming, which would turn "does" into "doe"
+    question
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !STOPWORDS.contains(&w.to_lowercase().as_str()))
+        .flat_map(words)
+        .collect()
+}
+
+/// BM25 score of each of `files` against `terms`; 0 for no match.
+pub fn relevance(ctx: &Ctx, terms: &HashSet<String>, files: &[(PathBuf, String)]) -> Vec<f64> {
+    let docs: Vec<HashMap<String, f64>> = files
+        .iter()
+        .map(|(path, body)| {
+            let mut tf: HashMap<String, f64> = HashMap::new();
+            for w in words(body).filter(|w| terms.contains(w)) {
+                *tf.entry(w).or_default() += 1.0;
+            }
+            for w in words(&ctx.rel(path).to_string_lossy()).filter(|w| terms.contains(w)) {
+                *tf.entry(w).or_default() += PATH_WEIGHT;
+            }
+            tf
+        })
+        .collect();
+    let lens: Vec<f64> = files
+        .iter()
+        .map(|(_, b)| tokens::estimate(b) as f64)
+        .collect();
+    let n = docs.len() as f64;
+    let avg = (lens.iter().sum::<f64>() / n.max(1.0)).max(1.0);
+    docs.iter()
+        .enumerate()
+        .map(|(i, tf)| {
+            tf.iter()
+                .map(|(term, &f)| {
+                    let df = docs.iter().filter(|d| d.contains_key(term)).count() as f64;
+                    let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+                    idf * f * (K1 + 1.0) / (f + K1 * (1.0 - B + B * lens[i] / avg))
+                })
+                .sum()
+        })
+        .collect()
+}
+
+/// Lowercase words of `text`, identifiers split at `_`, `-` and case
+/// changes; a trailing plural `s` is dropped.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .flat_map(|ident| {
+            let mut parts = Vec::new();
+            let mut start = 0;
+            let chars: Vec<(usize, char)> = ident.char_indices().collect();
+            for w in chars.windows(2) {
+                let ((_, a), (i, b)) = (w[0], w[1]);
+                if a.is_lowercase() && b.is_uppercase() {
+                    parts.push(&ident[start..i]);
+                    start = i;
+                }
+            }
+            parts.push(&ident[start..]);
+            parts
+        })
+        .filter(|w| w.len() > 1 && !w.bytes().all(|b| b.is_ascii_digit()))
+        .map(|w| {
+            let w = w.to_lowercase();
+            match w.strip_suffix('s') {
+                Some(stem) if stem.len() > 2 && !stem.ends_with('s') => stem.to_string(),
+                _ => w,
+            }
+        })
+}
+
+/// For each of `files`, the other files it imports (indices into `files`).
+pub fn imports(ctx: &Ctx, files: &[(PathBuf, String)]) -> Vec<HashSet<usize>> {
+    // (crate dir, module path) → file, and lib name → crate dir
+    let mut modules: HashMap<(&Path, Vec<String>), usize> = HashMap::new();
+    let mut module_of: Vec<Option<(&Path, Vec<String>)>> = Vec::new();
+    for (i, (path, _)) in files.iter().enumerate() {
+        let module = ctx
+            .crate_dir(path)
+            .and_then(|dir| Some((dir, module_path(dir, path)?)));
+        if let Some(key) = &module {
+            // a crate root is its library when it also has `src/main.rs`
+            let entry = modules.entry(key.clone());
+            if path.file_name() == Some("main.rs".as_ref()) {
+                entry.or_insert(i);
+            } else {
+                entry.insert_entry(i);
+            }
+        }
+        module_of.push(module);
+    }
+    let libs: HashMap<String, &Path> = ctx
+        .crates
+        .iter()
+        .map(|(dir, (name, _))| (name.replace('-', "_"), dir.as_path()))
+        .collect();
+
+    let mut graph = vec![HashSet::new(); files.len()];
+    for (i, (path, body)) in files.iter().enumerate() {
+        let Some(dir) = ctx.crate_dir(path) else {
+            continue;
+        };
+        let file = match path.extension() {
+            Some(x) if x == "rs" => syn::parse_file(body).ok(),
+            _ => None,
+        };
+        let Some(file) = file else {
+            continue;
+        };
+        let mut paths = Paths::default();
+        paths.visit_file(&file);
+        let here = module_of[i]
+            .as_ref()
+            .map(|(_, m)| m.clone())
+            .unwrap_or_default();
+        let imported = &mut graph[i];
+        for segments in paths.0 {
+            let (krate, mut rest) = match segments[0].as_str() {
+                "crate" => (dir, segments[1..].to_vec()),
+                "self" => (dir, [&here[..], &segments[1..]].concat()),
+                "super" => {
+                    let ups = segments.iter().take_while(|s| *s == "super").count();
+                    let base = &here[..here.len().saturating_sub(ups)];
+                    (dir, [base, &segments[ups..]].concat())
+                }
+                first => match libs.get(first) {
+                    Some(other) if *other != dir => (*other, segments[1..].to_vec()),
+                    _ => (dir, [&here[..], &segments[..]].concat()),
+                },
+            };
+            // the longest prefix naming a selected module file
+            while !rest.is_empty() && !modules.contains_key(&(krate, rest.clone())) {
+                rest.pop();
+            }
+            if let Some(&j) = modules.get(&(krate, rest)) {
+                if j != i {
+                    imported.insert(j);
+                }
+            }
+        }
+    }
+    graph
+}
+
+/// Module path of `path` in the crate at `dir`: `[]` for `src/lib.rs` and
+/// `src/main.rs`, `None` outside `src/` or under `src/bin/`.
+fn module_path(dir: &Path, path: &Path) -> Option<Vec<String>> {
+    let rel = path.strip_prefix(dir.join("src")).ok()?;
+    let mut parts: Vec<String> = rel
+        .iter()
+        .map(|c| c.to_string_lossy().into_owned())
+        .collect();
+    let file = parts.pop()?;
+    if parts.first().is_some_and(|p| p == "bin") {
+        return None;
+    }
+    match file.strip_suffix(".rs")? {
+        "lib" | "main" if parts.is_empty() => {}
+        "mod" => {}
+        stem => parts.push(stem.to_string()),
+    }
+    Some(parts)
+}
+
+/// Every path in a file, `use` trees expanded, as segments; `mod name;` as
+/// `self::name`.
+#[derive(Default)]
+struct Paths(Vec<Vec<String>>);
+
+impl<'ast> Visit<'ast> for Paths {
+    fn visit_path(&mut self, path: &'ast syn::Path) {
+        let segments: Vec<String> = path.segments.iter().map(|s| s.ident.to_string()).collect();
+        if segments.len() > 1 || segments.first().is_some_and(|s| s == "crate") {
+            self.0.push(segments);
+        }
+        visit::visit_path(self, path);
+    }
+
+    fn visit_item_mod(&mut self, item: &'ast syn::ItemMod) {
+        if item.content.is_none() {
+            self.0.push(vec!["self".into(), item.ident.to_string()]);
+        }
+        visit::visit_item_mod(self, item);
+    }
+
+    fn visit_item_use(&mut self, item: &'ast syn::ItemUse) {
+        let mut prefix = Vec::new();
+        use_paths(&item.tree, &mut prefix, &mut self.0);
+    }
+}
+
+fn use_paths(tree: &syn::UseTree, prefix: &mut Vec<String>, out: &mut Vec<Vec<String>>) {
+    match tree {
+        syn::UseTree::Path(p) => {
+            prefix.push(p.ident.to_string());
+            use_paths(&p.tree, prefix, out);
+            prefix.pop();
+        }
+        syn::UseTree::Name(n) => out.push([&prefix[..], &[n.ident.to_string()]].concat()),
+        syn::UseTree::Rename(r) => out.push([&prefix[..], &[r.ident.to_string()]].concat()),
+        syn::UseTree::Glob(_) => out.push(prefix.clone()),
+        syn::UseTree::Group(g) => {
+            for tree in &g.items {
+                use_paths(tree, prefix, out);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn question_terms() {
+        let mut got: Vec<String> = terms("How does the HttpClient retry failed requests_per-host?")
+            .into_iter()
+            .collect();
+        got.sort();
+        assert_eq!(
+            got,
+            ["client", "failed", "host", "http", "per", "request", "retry"]
+        );
+        assert!(terms("what is it in 2024?").is_empty());
+    }
+
+    #[test]
+    fn plurals_are_stemmed_conservatively() {
+        let got: Vec<String> = words("files class bus tests ids").collect();
+        assert_eq!(got, ["file", "class", "bus", "test", "ids"]);
+    }
+
+    #[test]
+    fn module_paths() {
+        let dir = Path::new("/w/a");
+        let module = |rel: &str| module_path(dir, &dir.join(rel));
+        assert_eq!(module("src/lib.rs"), Some(vec![]));
+        assert_eq!(module("src/main.rs"), Some(vec![]));
+        assert_eq!(module("src/net/mod.rs"), Some(vec!["net".to_string()]));
+        assert_eq!(
+            module("src/net/http.rs"),
+            Some(vec!["net".to_string(), "http".to_string()])
+        );
+        assert_eq!(module("src/bin/tool.rs"), None);
+        assert_eq!(module("tests/it.rs"), None);
+        assert_eq!(module("src/data.json"), None);
+    }
+
+    #[test]
+    fn paths_expand_use_trees_and_mods() {
+        let file = syn::parse_file(
+            "use crate::{net::{http, tcp as t}, util::*};\nmod cfg;\nfn f() { super::g(); h(); }\n",
+        )
+        .unwrap();
+        let mut paths = Paths::default();
+        paths.visit_file(&file);
+        let got: Vec<String> = paths.0.iter().map(|p| p.join("::")).collect();
+        assert_eq!(
+            got,
+            [
+                "crate::net::http",
+                "crate::net::tcp",
+                "crate::util",
+                "self::cfg",
+                "super::g"
+            ]
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! `--from-backtrace` and `--from-log` — select files by the `path:line`
+//! references in pasted text or tool output.
+//! * Backtraces come from the clipboard, a file, or stdin (`-`); logs from a
+//!   file or stdin.
+//! * A reference is `path:line[:col]` anywhere in a line (`at ./src/foo.rs:12:5`,
+//!   `panicked at src/foo.rs:12:5:`); paths outside the root (std, registry
+//!   crates, the toolchain) and files that don't exist are skipped.
+//! * Each file's referenced lines are listed in its header. Backtraces only
+//!   consider `.rs` frames, also mark the lines in the body, and follow as the
+//!   last section.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{apply, Ctx};
+
+/// Appended to each referenced line of a Rust file.
+const MARK: &str = "  // ← backtrace";
+
+/// Referenced lines (1-based) per file under the root, in path order.
+fn scan(ctx: &Ctx, text: &str, rust_only: bool) -> BTreeMap<PathBuf, BTreeSet<usize>> {
+    let mut refs: BTreeMap<PathBuf, BTreeSet<usize>> = BTreeMap::new();
+    let tokens = text.split(|c: char| c.is_whitespace() || "()[]<>\"'`,;".contains(c));
+    for (path, line) in tokens.filter_map(parse_ref) {
+        if rust_only && !path.ends_with(".rs") {
+            continue;
+        }
+        let path = Path::new(path.strip_prefix("./").unwrap_or(path));
+        let path = match path.strip_prefix(&ctx.root) {
+            Ok(rel) => ctx.root.join(rel),
+            Err(_) if path.is_absolute() => continue,
+            Err(_) => ctx.root.join(path),
+        };
+        if path.is_file() && !path.strip_prefix(&ctx.root).is_ok_and(escapes) && ctx.allowed(&path)
+        {
+            refs.entry(path).or_default().insert(line);
+        }
+    }
+    refs
+}
+
+/// `path:line` or `path:line:col`, with trailing punctuation ignored.
+fn parse_ref(token: &str) -> Option<(&str, usize)> {
+    let token = token.trim_end_matches([':', '.']);
+    let (head, last) = token.rsplit_once(':')?;
+    let last: usize = last.parse().ok()?;
+    let (path, line) = match head.rsplit_once(':') {
+        Some((path, line)) if line.parse::<usize>().is_ok() => (path, line.parse().ok()?),
+        _ => (head, last),
+    };
+    let name = path.rsplit(['/', '\\']).next()?;
+    (line > 0 && name.contains('.') && !name.starts_with('.')).then_some((path, line))
+}
+
+fn escapes(rel: &Path) -> bool {
+    rel.components()
+        .any(|c| c == std::path::Component::ParentDir)
+}
+
+/// `line 3`, `lines 3, 10-12`
+fn describe(lines: &BTreeSet<usize>) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &n in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == n => *end = n,
+            _ => ranges.push((n, n)),
+        }
+    }
+    let ranges: Vec<String> = ranges
+        .into_iter()
+        .map(|(a, b)| {
+            if a == b {
+                a.to_string()
+            } else {
+                format!("{a}-{b}")
+            }
+        })
+        .collect();
+    let noun = if lines.len() == 1 { "line" } else { "lines" };
+    format!("{noun} {}", ranges.join(", "))
+}
+
+pub fn backtrace(ctx: &mut Ctx, out: &mut dyn Write, input: Option<&Path>) -> Result<()> {
+    let text = apply::read_input(input)?;
+    let refs = scan(ctx, &text, true);
+    if refs.is_empty() {
+        eprintln!(
+            "warning: no frames under {} in the backtrace",
+            ctx.root.display()
+        );
+    }
+    let paths: Vec<PathBuf> = refs.keys().cloned().collect();
+    let bodies = ctx.read_all(&paths)?;
+    for ((path, lines), body) in refs.iter().zip(bodies) {
+        let marked: String = body
+            .split_inclusive('\n')
+            .enumerate()
+            .map(|(i, line)| {
+                if !lines.contains(&(i + 1)) {
+                    return line.to_string();
+                }
+                match line.strip_suffix('\n') {
+                    Some(code) => format!("{code}{MARK}\n"),
+                    None => format!("{line}{MARK}"),
+                }
+            })
+            .collect();
+        let tag = format!("backtrace: {}", describe(lines));
+        ctx.push_file(out, path, Some(&tag), &marked)?;
+    }
+    ctx.push_section(out, "backtrace", &text)?;
+    Ok(())
+}
+
+pub fn log(ctx: &mut Ctx, out: &mut dyn Write, input: &Path) -> Result<()> {
+    let text = apply::read_input(Some(input))?;
+    let refs = scan(ctx, &text, false);
+    if refs.is_empty() {
+        eprintln!(
+            "warning: no references to files under {} in the log",
+            ctx.root.display()
+        );
+    }
+    let paths: Vec<PathBuf> = refs.keys().cloned().collect();
+    let bodies = ctx.read_all(&paths)?;
+    for ((path, lines), body) in refs.iter().zip(bodies) {
+        let tag = format!("log: {}", describe(lines));
+        ctx.push_file(out, path, Some(&tag), &body)?;
+    }
+    Ok(())
+}
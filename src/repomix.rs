@@ -0,0 +1,130 @@
+//! `repomix.config.json` interop: a repository without `.cargo-qp.toml` but
+//! with a repomix config gets the same selection, with a warning suggesting
+//! a native config (`cargo qp init`).
+//! * `include` becomes `include`: only matching files (and manifests) are
+//!   selected, whatever their extension.
+//! * `ignore.customPatterns` become `exclude` globs; gitignore-style
+//!   patterns without a `/` match at any depth, and a directory excludes
+//!   everything under it.
+//! * `output.filePath` becomes the default `--output`.
+//! * Everything else (`output.style`, `ignore.useGitignore`, …) is left to
+//!   cargo-qp's own defaults and flags.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::config::{self, Config};
+
+pub const FILE_NAME: &str = "repomix.config.json";
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Repomix {
+    include: Vec<String>,
+    ignore: Ignore,
+    output: Output,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Ignore {
+    custom_patterns: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Output {
+    file_path: Option<PathBuf>,
+}
+
+/// The repomix config at `<root>` as a `Config`; `None` without one.
+pub fn load(root: &Path) -> Result<Option<Config>> {
+    let path = root.join(FILE_NAME);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let repomix: Repomix =
+        serde_json::from_str(&text).with_context(|| format!("invalid {}", path.display()))?;
+    eprintln!(
+        "warning: using {FILE_NAME} (include, ignore, output); run `cargo qp init` \
+         for a native {}",
+        config::FILE_NAME
+    );
+    Ok(Some(Config {
+        include: repomix
+            .include
+            .into_iter()
+            .filter(|p| p != "**/*")
+            .collect(),
+        exclude: repomix
+            .ignore
+            .custom_patterns
+            .iter()
+            .flat_map(|p| gitignore_globs(p))
+            .collect(),
+        output: repomix.output.file_path,
+        ..Config::default()
+    }))
+}
+
+/// A gitignore-style pattern as globs relative to the root: the paths it
+/// names and, for directories, everything under them.
+fn gitignore_globs(pattern: &str) -> Vec<String> {
+    let (dir_only, pattern) = match pattern.strip_suffix('/') {
+        Some(p) => (true, p),
+        None => (false, pattern),
+    };
+    let anchored = pattern.trim_start_matches('/');
+    let glob = if pattern.contains('/') {
+        anchored.to_string()
+    } else {
+        format!("**/{anchored}")
+    };
+    let under = format!("{glob}/**");
+    if dir_only {
+        vec![under]
+    } else {
+        vec![glob, under]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gitignore_patterns_as_globs() {
+        assert_eq!(gitignore_globs("*.log"), ["**/*.log", "**/*.log/**"]);
+        assert_eq!(gitignore_globs("target/"), ["**/target/**"]);
+        assert_eq!(gitignore_globs("/docs/gen"), ["docs/gen", "docs/gen/**"]);
+        assert_eq!(gitignore_globs("src/generated/"), ["src/generated/**"]);
+    }
+
+    #[test]
+    fn config_from_repomix() {
+        let root = std::env::temp_dir().join(format!("qp-repomix-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        assert!(load(&root).unwrap().is_none());
+        std::fs::write(
+            root.join(FILE_NAME),
+            r#"{
+                "include": ["**/*", "src/**"],
+                "ignore": {"customPatterns": ["fixtures/"], "useGitignore": true},
+                "output": {"filePath": "ctx.xml", "style": "xml"}
+            }"#,
+        )
+        .unwrap();
+        let config = load(&root).unwrap().unwrap();
+        assert_eq!(config.include, ["src/**"]);
+        assert_eq!(config.exclude, ["**/fixtures/**"]);
+        assert_eq!(config.output, Some(PathBuf::from("ctx.xml")));
+        std::fs::write(root.join(FILE_NAME), "{").unwrap();
+        assert!(load(&root).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
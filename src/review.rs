@@ -0,0 +1,244 @@
+//! `cargo qp apply --interactive` — review each incoming change hunk by hunk
+//! before it is written, like `git add -p`.
+//! * Per hunk: `y` accept, `n` reject, `e` edit in `$VISUAL`/`$EDITOR`,
+//!   `a`/`d` accept/reject the rest of the file, `q` stop reviewing (nothing
+//!   further is written), `?` help.
+//! * New and deleted files are confirmed as a whole.
+//! * Prompts read from the terminal, so the answer itself may come on stdin.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+    path::Path,
+    process::Command,
+};
+
+use anyhow::{Context, Result};
+
+use crate::textdiff::{self, Hunk};
+
+/// What to do with one file.
+pub enum Verdict {
+    /// Write this body (`None` deletes the file).
+    Write(Option<String>),
+    Skip,
+    Quit,
+}
+
+pub struct Reviewer {
+    input: Box<dyn BufRead>,
+    color: bool,
+}
+
+const HELP: &str = "y - apply this hunk
+n - skip this hunk
+e - edit this hunk
+a - apply this and all later hunks in the file
+d - skip this and all later hunks in the file
+q - quit; nothing further is written
+? - print help";
+
+impl Reviewer {
+    pub fn new() -> Result<Self> {
+        let input: Box<dyn BufRead> = match File::open("/dev/tty") {
+            Ok(tty) => Box::new(BufReader::new(tty)),
+            Err(_) if io::stdin().is_terminal() => Box::new(BufReader::new(io::stdin())),
+            Err(_) => anyhow::bail!("--interactive needs a terminal"),
+        };
+        Ok(Reviewer {
+            input,
+            color: io::stderr().is_terminal(),
+        })
+    }
+
+    /// Asks about the change from `old` to `new` (absent files are `None`).
+    pub fn file(&mut self, rel: &Path, old: Option<&str>, new: Option<&str>) -> Result<Verdict> {
+        let (old, new) = match (old, new) {
+            (None, Some(new)) => {
+                let question = format!("create {} ({} lines)?", rel.display(), new.lines().count());
+                return self.whole(&question, Some(new));
+            }
+            (Some(_), None) => {
+                return self.whole(&format!("delete {}?", rel.display()), None);
+            }
+            (Some(old), Some(new)) => (old, new),
+            (None, None) => return Ok(Verdict::Skip),
+        };
+        let a: Vec<&str> = old.lines().collect();
+        let b: Vec<&str> = new.lines().collect();
+        let hunks = textdiff::hunks(&a, &b, 3);
+        let name = rel.display().to_string();
+        self.paint(&format!("--- a/{name}\n+++ b/{name}"), "1");
+
+        // replacement lines per hunk, `None` keeps the old ones
+        let mut chosen: Vec<Option<Vec<String>>> = Vec::new();
+        // `a` or `d` answered for the remaining hunks
+        let mut rest: Option<bool> = None;
+        for (n, hunk) in hunks.iter().enumerate() {
+            if let Some(accept) = rest {
+                chosen.push(accept.then(|| new_side(hunk)));
+                continue;
+            }
+            self.show(hunk);
+            loop {
+                let answer = self.ask(&format!(
+                    "({}/{}) apply this hunk [y,n,e,a,d,q,?]?",
+                    n + 1,
+                    hunks.len()
+                ))?;
+                match answer.as_str() {
+                    "y" => chosen.push(Some(new_side(hunk))),
+                    "n" => chosen.push(None),
+                    "a" | "d" => {
+                        rest = Some(answer == "a");
+                        chosen.push((answer == "a").then(|| new_side(hunk)));
+                    }
+                    "e" => match edit(hunk)? {
+                        Some(lines) => chosen.push(Some(lines)),
+                        None => continue,
+                    },
+                    "q" => return Ok(Verdict::Quit),
+                    _ => {
+                        eprintln!("{HELP}");
+                        continue;
+                    }
+                }
+                break;
+            }
+        }
+        if chosen.iter().all(Option::is_none) {
+            return Ok(Verdict::Skip);
+        }
+
+        let mut out: Vec<String> = Vec::new();
+        let mut pos = 0;
+        for (hunk, lines) in hunks.iter().zip(chosen) {
+            let start = if hunk.old_len == 0 {
+                hunk.old_start
+            } else {
+                hunk.old_start - 1
+            };
+            out.extend(a[pos..start].iter().map(|l| l.to_string()));
+            match lines {
+                Some(lines) => out.extend(lines),
+                None => out.extend(a[start..start + hunk.old_len].iter().map(|l| l.to_string())),
+            }
+            pos = start + hunk.old_len;
+        }
+        out.extend(a[pos..].iter().map(|l| l.to_string()));
+        let mut body = out.join("\n");
+        if new.ends_with('\n') && !body.is_empty() {
+            body.push('\n');
+        }
+        Ok(Verdict::Write(Some(body)))
+    }
+
+    fn whole(&mut self, question: &str, body: Option<&str>) -> Result<Verdict> {
+        loop {
+            match self.ask(&format!("{question} [y,n,q]"))?.as_str() {
+                "y" => return Ok(Verdict::Write(body.map(String::from))),
+                "n" => return Ok(Verdict::Skip),
+                "q" => return Ok(Verdict::Quit),
+                _ => eprintln!("y - yes\nn - no\nq - quit; nothing further is written"),
+            }
+        }
+    }
+
+    fn show(&self, hunk: &Hunk) {
+        self.paint(&hunk.header(), "36");
+        for line in &hunk.lines {
+            let color = match line.as_bytes().first() {
+                Some(b'+') => "32",
+                Some(b'-') => "31",
+                _ => "",
+            };
+            self.paint(line, color);
+        }
+    }
+
+    fn paint(&self, text: &str, color: &str) {
+        if self.color && !color.is_empty() {
+            eprintln!("\x1b[{color}m{text}\x1b[0m");
+        } else {
+            eprintln!("{text}");
+        }
+    }
+
+    fn ask(&mut self, prompt: &str) -> Result<String> {
+        eprint!("{prompt} ");
+        io::stderr().flush()?;
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok("q".into());
+        }
+        Ok(line.trim().to_lowercase())
+    }
+}
+
+/// The context and added lines of `hunk`, without their prefix.
+fn new_side(hunk: &Hunk) -> Vec<String> {
+    hunk.lines
+        .iter()
+        .filter_map(|l| l.strip_prefix(' ').or_else(|| l.strip_prefix('+')))
+        .map(String::from)
+        .collect()
+}
+
+/// Opens the hunk in an editor; the kept ` ` and `+` lines replace the hunk's
+/// old lines. `None` when the edited hunk is left empty.
+fn edit(hunk: &Hunk) -> Result<Option<Vec<String>>> {
+    let path = std::env::temp_dir().join(format!("qp-hunk-{}.diff", std::process::id()));
+    let mut text = String::from(
+        "# Edit the hunk: remove `-` lines to keep them, add or change `+` lines.\n\
+         # Lines starting with `#` are ignored; emptying the file cancels the edit.\n",
+    );
+    text.push_str(&format!("{}\n", hunk.header()));
+    for line in &hunk.lines {
+        text.push_str(&format!("{line}\n"));
+    }
+    std::fs::write(&path, text)?;
+    let editor = editor().unwrap_or_else(|| "vi".into());
+    let ran = run_editor(&editor, &path);
+    let edited = std::fs::read_to_string(&path)?;
+    let _ = std::fs::remove_file(&path);
+    ran?;
+    let lines: Vec<&str> = edited
+        .lines()
+        .filter(|l| !l.starts_with('#') && !l.starts_with("@@"))
+        .collect();
+    if lines.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(
+        lines
+            .iter()
+            .filter_map(|l| match l.as_bytes().first() {
+                Some(b' ') | Some(b'+') => Some(l[1..].to_string()),
+                None => Some(String::new()),
+                _ => None,
+            })
+            .collect(),
+    ))
+}
+
+/// `$VISUAL`, else `$EDITOR`.
+pub fn editor() -> Option<String> {
+    std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+}
+
+/// Runs `editor` (a shell command such as `code --wait`) on `path` and waits
+/// for it to exit.
+pub fn run_editor(editor: &str, path: &Path) -> Result<()> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .with_context(|| format!("failed to run {editor}"))?;
+    anyhow::ensure!(status.success(), "{editor} exited with {status}");
+    Ok(())
+}
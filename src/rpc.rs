@@ -0,0 +1,296 @@
+//! `cargo qp rpc` — newline-delimited JSON-RPC 2.0 over stdio, so editor
+//! plugins can keep one cargo-qp process per workspace instead of spawning
+//! the CLI for every request.
+//! * One request per line on stdin, one response per line on stdout;
+//!   requests without an `id` are notifications and get no response.
+//!   Progress and warnings still go to stderr.
+//! * `.cargo-qp.toml` is re-read for every request, so edits apply without
+//!   a restart.
+//! * Methods (params are an object; all are optional unless noted):
+//!   - `snapshot {args}` — `args` are the CLI flags (`["--format",
+//!     "markdown", "--focus", "src/lib.rs"]`); result `{text, files,
+//!     tokens}`.
+//!   - `listFiles {args}` — the selection; result `[{path, crate, version,
+//!     bytes}]`.
+//!   - `fileAt {path, rev, args}` — `path` (required) at `rev`, else in the
+//!     working tree, decoded and transformed as a snapshot with `args` would
+//!     emit it; sensitive and excluded paths are refused. Result `{body}`.
+//!   - `applyPatch {text, dryRun}` — `cargo qp apply` on `text` (required);
+//!     result `{written, diff}`, where `diff` is the `--dry-run` output.
+//!   - `shutdown` — answers `null`, then exits (stops the whole daemon).
+//! * Errors use the JSON-RPC codes (`-32700` parse, `-32600` invalid
+//!   request, `-32601` unknown method, `-32602` invalid params) and `-32000`
+//!   for a failed operation, with the error chain as the message.
+
+use std::{
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+use clap::Parser;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    anonymize::Anonymizer, apply, context_with, daemon::Warm, default_mode, git, manifest, style,
+    write_intro, Config, Ctx, Opts,
+};
+
+/// A failed request: JSON-RPC error code and message.
+struct Failure(i64, String);
+
+impl From<anyhow::Error> for Failure {
+    fn from(e: anyhow::Error) -> Self {
+        Failure(-32000, format!("{e:#}"))
+    }
+}
+
+impl From<std::io::Error> for Failure {
+    fn from(e: std::io::Error) -> Self {
+        Failure(-32000, e.to_string())
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Args {
+    args: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct FileAt {
+    path: PathBuf,
+    rev: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApplyPatch {
+    text: String,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Answers the requests on `input` until it ends or `shutdown` is called;
+/// true for the latter. `warm` is the daemon's state, if any.
+pub fn serve(
+    root: &Path,
+    input: impl BufRead,
+    mut output: impl Write,
+    warm: Option<&Mutex<Warm>>,
+) -> Result<bool> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (id, outcome, shutdown) = match serde_json::from_str::<Value>(&line) {
+            Ok(request) => {
+                let id = request.get("id").cloned();
+                let method = request.get("method").and_then(Value::as_str);
+                let params = request.get("params").cloned().unwrap_or(json!({}));
+                let outcome = match method {
+                    Some(method) if request.get("jsonrpc") == Some(&json!("2.0")) => {
+                        let mut warm = warm.map(|w| w.lock().unwrap());
+                        call(root, method, params, warm.as_deref_mut())
+                    }
+                    _ => Err(Failure(-32600, "not a JSON-RPC 2.0 request".to_string())),
+                };
+                (id, outcome, method == Some("shutdown"))
+            }
+            Err(e) => (
+                Some(Value::Null),
+                Err(Failure(-32700, e.to_string())),
+                false,
+            ),
+        };
+        if let Some(id) = id {
+            let response = match outcome {
+                Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+                Err(Failure(code, message)) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {"code": code, "message": message},
+                }),
+            };
+            serde_json::to_writer(&mut output, &response)?;
+            writeln!(output)?;
+            output.flush()?;
+        }
+        if shutdown {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn call(
+    root: &Path,
+    method: &str,
+    params: Value,
+    warm: Option<&mut Warm>,
+) -> Result<Value, Failure> {
+    match method {
+        "snapshot" => snapshot(root, parse(params)?, warm),
+        "listFiles" => list_files(root, parse(params)?, warm),
+        "fileAt" => file_at(root, parse(params)?, warm),
+        "applyPatch" => apply_patch(root, parse(params)?, warm),
+        "shutdown" => Ok(Value::Null),
+        _ => Err(Failure(-32601, format!("unknown method `{method}`"))),
+    }
+}
+
+fn parse<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, Failure> {
+    serde_json::from_value(params).map_err(|e| Failure(-32602, e.to_string()))
+}
+
+/// A context as the CLI would build it from `args`.
+fn session(root: &Path, args: &[String], warm: Option<&mut Warm>) -> Result<(Ctx, Opts), Failure> {
+    let argv = std::iter::once("cargo-qp").chain(args.iter().map(String::as_str));
+    let mut opts = Opts::try_parse_from(argv).map_err(|e| Failure(-32602, e.to_string()))?;
+    if opts.cmd.is_some() {
+        return Err(Failure(
+            -32602,
+            "subcommands are not accepted in `args`".to_string(),
+        ));
+    }
+    opts.quiet = true;
+    style::apply(&mut opts);
+    let config = Config::load(root)?;
+    let ctx = context_with(&opts, root.to_path_buf(), &config, warm)?;
+    Ok((ctx, opts))
+}
+
+/// Hands `ctx` back to the daemon, if there is one.
+fn finish(ctx: Ctx, warm: Option<&mut Warm>) -> Result<(), Failure> {
+    match warm {
+        Some(warm) => Ok(warm.keep(ctx)?),
+        None => Ok(()),
+    }
+}
+
+fn snapshot(root: &Path, params: Args, mut warm: Option<&mut Warm>) -> Result<Value, Failure> {
+    let (mut ctx, opts) = session(root, &params.args, warm.as_deref_mut())?;
+    let mut out = Vec::new();
+    ctx.begin_output(&mut out)?;
+    write_intro(&mut ctx, &opts, &mut out)?;
+    default_mode(&mut ctx, &opts, &mut out)?;
+    ctx.finish_output(&mut out)?;
+    let files: Vec<String> = ctx
+        .emitted
+        .iter()
+        .map(|e| ctx.rel(&e.path).to_string_lossy().replace('\\', "/"))
+        .collect();
+    let tokens: usize = ctx.emitted.iter().map(|e| e.tokens).sum();
+    finish(ctx, warm)?;
+    Ok(json!({
+        "text": String::from_utf8_lossy(&out),
+        "files": files,
+        "tokens": tokens,
+    }))
+}
+
+fn list_files(root: &Path, params: Args, mut warm: Option<&mut Warm>) -> Result<Value, Failure> {
+    let (ctx, _) = session(root, &params.args, warm.as_deref_mut())?;
+    let files: Vec<Value> = ctx
+        .selected()?
+        .iter()
+        .map(|p| {
+            let (name, version) = ctx.owner(p);
+            json!({
+                "path": ctx.rel(p).to_string_lossy().replace('\\', "/"),
+                "crate": name,
+                "version": version,
+                "bytes": ctx.source_bytes(p).map_or(0, |b| b.len()),
+            })
+        })
+        .collect();
+    finish(ctx, warm)?;
+    Ok(Value::Array(files))
+}
+
+fn file_at(root: &Path, params: FileAt, mut warm: Option<&mut Warm>) -> Result<Value, Failure> {
+    let invalid = |e: anyhow::Error| Failure(-32602, e.to_string());
+    let rel = apply::checked_rel(&params.path).map_err(invalid)?;
+    let (ctx, _) = session(root, &params.args, warm.as_deref_mut())?;
+    let path = ctx.root.join(&rel);
+    if !ctx.allowed(&path) {
+        return Err(Failure(
+            -32602,
+            format!("{} is sensitive or excluded", rel.display()),
+        ));
+    }
+    let bytes = match &params.rev {
+        Some(rev) => {
+            let rev = git::checked_rev(rev).map_err(invalid)?;
+            git::show_bytes(root, rev, &rel.to_string_lossy().replace('\\', "/"))?
+        }
+        None => std::fs::read(&path)?,
+    };
+    let body = ctx.decode(&path, bytes)?;
+    finish(ctx, warm)?;
+    Ok(json!({ "body": body }))
+}
+
+fn apply_patch(
+    root: &Path,
+    params: ApplyPatch,
+    mut warm: Option<&mut Warm>,
+) -> Result<Value, Failure> {
+    let (ctx, _) = session(root, &[], warm.as_deref_mut())?;
+    let config = Config::load(root)?;
+    let anonymizer = Anonymizer::saved(root, &config)?;
+    let mut diff = Vec::new();
+    let written = apply::apply_text(
+        &ctx,
+        anonymizer.as_ref(),
+        params.text,
+        params.dry_run,
+        false,
+        &root.join(manifest::DEFAULT_FILE),
+        &mut diff,
+    )?;
+    finish(ctx, warm)?;
+    Ok(json!({
+        "written": written,
+        "diff": String::from_utf8_lossy(&diff),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+
+    #[test]
+    fn file_at_refuses_sensitive_paths_and_transforms() {
+        let root = std::env::temp_dir().join(format!("qp-rpc-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(
+            root.join("Cargo.toml"),
+            "[package]\nname = \"w\"\nversion = \"0.1.0\"\nedition = \"2021\"\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("src/lib.rs"), "// mail jane@example.com\n").unwrap();
+        std::fs::write(root.join(".env"), "TOKEN=1\n").unwrap();
+        Command::new("git")
+            .args(["init", "-q"])
+            .current_dir(&root)
+            .status()
+            .unwrap();
+        let at = |path: &str, args: &[&str]| {
+            let params = json!({"path": path, "args": args});
+            file_at(&root, parse(params).ok().unwrap(), None)
+        };
+        assert!(matches!(at(".env", &[]), Err(Failure(-32602, _))));
+        let scrubbed = at("src/lib.rs", &["--scrub-pii"]).ok().unwrap();
+        assert_eq!(scrubbed["body"], "// mail <email>\n");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
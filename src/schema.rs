@@ -0,0 +1,146 @@
+//! Machine-readable output: `--format json`, `--format ndjson` and the
+//! manifest file, versioned by `SCHEMA_VERSION`.
+//! * Within a schema version, changes are additive only: new optional fields
+//!   may appear, existing ones keep their name, type and meaning. Removing or
+//!   changing a field bumps the version.
+//! * Consumers should ignore fields they don't know.
+//! * Paths are relative to the snapshot root and `/`-separated.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Version of every structure in this module.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// `--format json`: the whole snapshot as one document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub header: Header,
+    /// Non-file text (git log, summaries, PR description, …), in order.
+    pub sections: Vec<Section>,
+    /// Emitted files, in output order.
+    pub files: Vec<File>,
+    /// Sum of the files' `tokens`.
+    pub total_tokens: usize,
+}
+
+/// Facts about the snapshot as a whole.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    /// Always `cargo-qp`.
+    pub tool: String,
+    pub tool_version: String,
+    /// Commit the snapshot was taken from (`--rev`, else HEAD), if any.
+    pub rev: Option<String>,
+}
+
+/// A block of text that isn't a file body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Section {
+    /// The text header's label, e.g. `git log :: last 5 commits`.
+    pub title: String,
+    pub text: String,
+}
+
+/// One emitted file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct File {
+    pub path: String,
+    /// Owning crate, or `unknown_crate`.
+    #[serde(rename = "crate")]
+    pub crate_name: String,
+    /// Owning crate's version, or `?`.
+    pub version: String,
+    /// `UPDATED`, `NEW` or `DELETED` in follow-up snapshots.
+    pub status: Option<String>,
+    /// How the body was rendered when not in full: `identical to <path>`,
+    /// `diff vs <path>`, `diff`, `new`, `signatures`, …
+    pub tag: Option<String>,
+    /// `--check-published` status of the crate.
+    pub crates_io: Option<String>,
+    /// Read or decoding issue (`lossy utf-8`, `unreadable`, …).
+    pub note: Option<String>,
+    /// `--git-info`: the commit that last touched the file.
+    pub last_commit: Option<LastCommit>,
+    /// Fence language of `body` (`rust`, `toml`, `python`, `diff`, …; see
+    /// `lang`).
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Lines of `body`.
+    #[serde(default)]
+    pub lines: usize,
+    /// Estimated tokens of `body`.
+    pub tokens: usize,
+    /// Transforms that changed the body, in order.
+    pub transforms: Vec<String>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastCommit {
+    pub hash: String,
+    /// `YYYY-MM-DD`.
+    pub date: String,
+    pub author: String,
+    pub subject: String,
+}
+
+/// `--format ndjson`: one record per line, `header` first and `footer` last.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Record {
+    Header {
+        schema_version: u32,
+        #[serde(flatten)]
+        header: Header,
+    },
+    Section(Section),
+    File(Box<File>),
+    Footer {
+        files: usize,
+        total_tokens: usize,
+    },
+}
+
+/// `--manifest`: what a snapshot contained, for later `--since-manifest` and
+/// `--follow-up` runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Manifests written before versioning are version 1.
+    #[serde(default = "first_version")]
+    pub schema_version: u32,
+    /// HEAD (or `--rev`) commit the snapshot was taken from.
+    pub rev: Option<String>,
+    /// Unix seconds.
+    pub created: u64,
+    pub total_tokens: usize,
+    pub files: Vec<ManifestEntry>,
+    /// `--preamble` crate summaries from the `[preamble]` hook, by crate.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub summaries: BTreeMap<String, CrateSummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    /// git blob id of the source content.
+    pub blob: String,
+    pub bytes: u64,
+    pub tokens: usize,
+    #[serde(default)]
+    pub transforms: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateSummary {
+    /// blake3 of the prompt the summary was generated from.
+    pub fingerprint: String,
+    pub text: String,
+}
+
+fn first_version() -> u32 {
+    1
+}
@@ -0,0 +1,195 @@
+//! `cargo qp session` — named conversations that remember what was sent.
+//! * `start NAME` sends a full snapshot; each `next NAME` sends only what
+//!   changed since the previous turn (`--follow-up` against the session's
+//!   own manifest) and records it as a new turn.
+//! * `replay NAME` re-sends every turn in order, for a fresh conversation;
+//!   `--delta` only the latest one.
+//! * Stored under `target/qp-sessions/NAME/`: `manifest.json` and one
+//!   `NNN.txt` per turn, exactly as sent, `--env-info`, `--log`,
+//!   `--preamble` and `--style` tree sections included. Text, markdown and
+//!   xml output only.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::Subcommand;
+
+use crate::{default_mode, manifest, style, tokens, write_intro, Ctx, Format, Opts};
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Action {
+    /// Begin a session with a full snapshot
+    Start {
+        name: String,
+        /// Replace an existing session of that name
+        #[arg(long)]
+        force: bool,
+    },
+    /// Send what changed since the session's last turn
+    Next { name: String },
+    /// Re-send every turn of a session
+    Replay {
+        name: String,
+        /// Only the latest turn
+        #[arg(long)]
+        delta: bool,
+    },
+    /// Sessions of this workspace, with turns and tokens sent
+    List,
+    /// Delete a session
+    Drop { name: String },
+}
+
+/// Output of `start`, `next` and `replay`.
+pub fn compose(ctx: &mut Ctx, out: &mut dyn Write, opts: &Opts, action: &Action) -> Result<()> {
+    anyhow::ensure!(
+        matches!(opts.format, Format::Text | Format::Markdown | Format::Xml),
+        "sessions record text, markdown or xml output only"
+    );
+    match action {
+        Action::Start { name, force } => {
+            let dir = dir(ctx, name)?;
+            if dir.exists() {
+                anyhow::ensure!(
+                    *force,
+                    "session `{name}` exists; pass --force to restart it"
+                );
+                std::fs::remove_dir_all(&dir)?;
+            }
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+            let turn = turn(ctx, opts, |ctx, turn| default_mode(ctx, opts, turn))?;
+            manifest::build(ctx)?.write(&dir.join("manifest.json"))?;
+            record(out, &dir, 1, &turn)
+        }
+        Action::Next { name } => {
+            let dir = existing(ctx, name)?;
+            let turns = turns(&dir)?;
+            let manifest = dir.join("manifest.json");
+            let turn = turn(ctx, opts, |ctx, turn| {
+                manifest::follow_up(ctx, turn, &manifest)
+            })?;
+            if ctx.emitted.is_empty() {
+                eprintln!(
+                    "session `{name}`: nothing changed since turn {}",
+                    turns.len()
+                );
+                return Ok(out.write_all(&turn)?);
+            }
+            record(out, &dir, turns.len() + 1, &turn)
+        }
+        Action::Replay { name, delta } => {
+            let turns = turns(&existing(ctx, name)?)?;
+            let skip = if *delta { turns.len() - 1 } else { 0 };
+            for path in &turns[skip..] {
+                out.write_all(&std::fs::read(path)?)?;
+            }
+            Ok(())
+        }
+        Action::List | Action::Drop { .. } => unreachable!("handled before any output"),
+    }
+}
+
+/// `list` and `drop`, which print a report instead of a snapshot.
+pub fn manage(ctx: &Ctx, action: &Action) -> Result<()> {
+    match action {
+        Action::Drop { name } => {
+            std::fs::remove_dir_all(existing(ctx, name)?)?;
+            eprintln!("dropped session `{name}`");
+        }
+        _ => {
+            let root = ctx.root.join("target").join("qp-sessions");
+            let mut names: Vec<String> = std::fs::read_dir(&root)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter(|e| e.path().join("manifest.json").is_file())
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            println!(
+                "{:<24} {:>5} {:>8} {:>8}",
+                "session", "turns", "files", "sent"
+            );
+            for name in names {
+                let dir = root.join(&name);
+                let manifest = manifest::Manifest::load(&dir.join("manifest.json"))?;
+                let turns = turns(&dir)?;
+                let sent: usize = turns
+                    .iter()
+                    .map(|p| tokens::estimate(&std::fs::read_to_string(p).unwrap_or_default()))
+                    .sum();
+                println!(
+                    "{name:<24} {:>5} {:>8} {:>8}",
+                    turns.len(),
+                    manifest.files.len(),
+                    tokens::human(sent)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What `body` writes, between the intro sections and the trailing tree.
+fn turn(
+    ctx: &mut Ctx,
+    opts: &Opts,
+    body: impl FnOnce(&mut Ctx, &mut Vec<u8>) -> Result<()>,
+) -> Result<Vec<u8>> {
+    let mut turn = Vec::new();
+    write_intro(ctx, opts, &mut turn)?;
+    body(ctx, &mut turn)?;
+    style::tree(ctx, &mut turn, style::Tree::Bottom)?;
+    Ok(turn)
+}
+
+/// Writes `turn` to the output and stores it as turn `n`.
+fn record(out: &mut dyn Write, dir: &Path, n: usize, turn: &[u8]) -> Result<()> {
+    std::fs::write(dir.join(format!("{n:03}.txt")), turn)?;
+    Ok(out.write_all(turn)?)
+}
+
+fn dir(ctx: &Ctx, name: &str) -> Result<PathBuf> {
+    anyhow::ensure!(
+        !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')),
+        "invalid session name `{name}` (letters, digits, `-`, `_` and `.`)"
+    );
+    Ok(ctx.root.join("target").join("qp-sessions").join(name))
+}
+
+fn existing(ctx: &Ctx, name: &str) -> Result<PathBuf> {
+    let dir = dir(ctx, name)?;
+    anyhow::ensure!(
+        dir.join("manifest.json").is_file(),
+        "no session `{name}`; begin one with `cargo qp session start {name}`"
+    );
+    Ok(dir)
+}
+
+/// Stored turns, oldest first.
+fn turns(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut turns: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.extension() == Some("txt".as_ref())
+                && p.file_stem()
+                    .is_some_and(|s| s.to_string_lossy().bytes().all(|b| b.is_ascii_digit()))
+        })
+        .collect();
+    turns.sort();
+    anyhow::ensure!(
+        !turns.is_empty(),
+        "session in {} has no turns",
+        dir.display()
+    );
+    Ok(turns)
+}
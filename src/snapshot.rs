@@ -0,0 +1,308 @@
+//! `SnapshotBuilder` — the library entry point: the CLI's selection and
+//! formatting without a process boundary.
+//! * Built on the same `Opts` the command line fills in, so every setter
+//!   behaves like its flag (`.workspace(true)` is `--workspace`, …).
+//! * `.cargo-qp.toml` in the root still applies; setters win over it.
+//! * Extra `Filter`s and `Transform`s run after the built-in and configured
+//!   ones.
+//! * Sinks: any `Write` (`write_to`), a `String` (`render`), or the clipboard.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use clap::Parser;
+
+use crate::{
+    context, default_mode, emit, resolve_root, write_intro, Config, Dedupe, Filter, Fixtures,
+    Format, InvalidUtf8, Opts, Order, Source, Transform,
+};
+
+/// What a composed snapshot contained.
+pub struct Snapshot {
+    /// Emitted files, relative to the root, in output order.
+    pub files: Vec<PathBuf>,
+    /// Estimated tokens of the emitted bodies.
+    pub tokens: usize,
+    /// Files replaced by placeholders because they could not be read.
+    pub unreadable: Vec<PathBuf>,
+}
+
+pub struct SnapshotBuilder {
+    opts: Opts,
+    /// run after the built-in and configured filters and transforms
+    filters: Vec<Arc<dyn Filter>>,
+    transforms: Vec<Arc<dyn Transform>>,
+}
+
+impl SnapshotBuilder {
+    /// A snapshot of the workspace containing `dir`, with the CLI defaults.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let mut opts = Opts::parse_from(["cargo-qp"]);
+        opts.dir = dir.into();
+        opts.quiet = true;
+        SnapshotBuilder {
+            opts,
+            filters: Vec::new(),
+            transforms: Vec::new(),
+        }
+    }
+
+    //──────────────────────── sources ────────────────────────────────────────
+
+    /// Read files as of this revision instead of the working tree.
+    pub fn rev(mut self, rev: impl Into<String>) -> Self {
+        self.opts.rev = Some(rev.into());
+        self
+    }
+
+    /// Read files from the index, `HEAD` or the working tree.
+    pub fn from(mut self, source: Source) -> Self {
+        self.opts.from = Some(source);
+        self
+    }
+
+    /// Only files that differ from the merge-base with `branch`.
+    pub fn against(mut self, branch: impl Into<String>) -> Self {
+        self.opts.against = Some(branch.into());
+        self
+    }
+
+    /// Use the given directory as the root instead of discovering the
+    /// workspace root above it.
+    pub fn no_discover(mut self) -> Self {
+        self.opts.no_discover = true;
+        self
+    }
+
+    //──────────────────────── filters ────────────────────────────────────────
+
+    /// Extensions to include (`toml` is always added).
+    pub fn exts<S: Into<String>>(mut self, exts: impl IntoIterator<Item = S>) -> Self {
+        self.opts.exts = exts.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Every workspace member instead of `default-members`.
+    pub fn workspace(mut self, all: bool) -> Self {
+        self.opts.workspace = all;
+        self
+    }
+
+    /// Also include READMEs, CONTRIBUTING.md and `docs/**/*.md`.
+    pub fn docs(mut self, on: bool) -> Self {
+        self.opts.docs = on;
+        self
+    }
+
+    /// Also include `.proto`, GraphQL, SQL and JSON schema files.
+    pub fn schemas(mut self, on: bool) -> Self {
+        self.opts.schemas = on;
+        self
+    }
+
+    /// Also include justfiles, Makefiles and extensionless shebang scripts.
+    pub fn include_scripts(mut self, on: bool) -> Self {
+        self.opts.include_scripts = on;
+        self
+    }
+
+    /// Include `.env*`, keys, `secrets/` and other paths that are otherwise
+    /// always left out (see `config::SENSITIVE`).
+    pub fn allow_sensitive(mut self, on: bool) -> Self {
+        self.opts.allow_sensitive = on;
+        self
+    }
+
+    /// How to treat snapshot-test fixtures; `None` leaves them to the
+    /// extension filter. `lines` applies to `Fixtures::Truncate`.
+    pub fn fixtures(mut self, policy: Option<Fixtures>, lines: usize) -> Self {
+        self.opts.fixtures = policy;
+        self.opts.fixture_lines = lines;
+        self
+    }
+
+    /// Leave out a workspace member.
+    pub fn exclude_member(mut self, name: impl Into<String>) -> Self {
+        self.opts.exclude.push(name.into());
+        self
+    }
+
+    /// Appends `filter` to the selection filters.
+    pub fn filter(mut self, filter: impl Filter + 'static) -> Self {
+        self.filters.push(Arc::new(filter));
+        self
+    }
+
+    //──────────────────────── transforms ─────────────────────────────────────
+
+    /// Strip Cargo.toml files down to package, dependencies, features and
+    /// targets (`--minify-manifests`).
+    pub fn minify_manifests(mut self, on: bool) -> Self {
+        self.opts.minify_manifests = on;
+        self
+    }
+
+    /// Add each body's line and token counts to its header.
+    pub fn header_stats(mut self, on: bool) -> Self {
+        self.opts.header_stats = on;
+        self
+    }
+
+    /// Convert CRLF to LF and strip byte-order marks.
+    pub fn normalize_eol(mut self, on: bool) -> Self {
+        self.opts.normalize_eol = on;
+        self
+    }
+
+    /// Mask email addresses, IP addresses and phone numbers in comments and
+    /// string literals (`--scrub-pii`).
+    pub fn scrub_pii(mut self, on: bool) -> Self {
+        self.opts.scrub_pii = on;
+        self
+    }
+
+    /// Drop code and files `#[cfg]`-gated off for `triple` (`--target`),
+    /// or for the host with `None` (`--cfg-prune`).
+    pub fn cfg_target(mut self, triple: Option<&str>) -> Self {
+        self.opts.target = triple.map(String::from);
+        self.opts.cfg_prune = triple.is_none();
+        self
+    }
+
+    /// Replace crate, module and configured names with neutral tokens
+    /// (`--anonymize`); the mapping is saved next to the root.
+    pub fn anonymize(mut self, on: bool) -> Self {
+        self.opts.anonymize = on;
+        self
+    }
+
+    pub fn on_invalid_utf8(mut self, policy: InvalidUtf8) -> Self {
+        self.opts.on_invalid_utf8 = policy;
+        self
+    }
+
+    /// Appends `transform` to the pipeline.
+    pub fn transform(mut self, transform: impl Transform + 'static) -> Self {
+        self.transforms.push(Arc::new(transform));
+        self
+    }
+
+    /// Fail on unreadable files instead of emitting placeholders.
+    pub fn strict(mut self, on: bool) -> Self {
+        self.opts.strict = on;
+        self
+    }
+
+    //──────────────────────── format ─────────────────────────────────────────
+
+    pub fn order(mut self, order: Order) -> Self {
+        self.opts.order = order;
+        self
+    }
+
+    /// `Format::Json`/`Ndjson` output follows `schema`.
+    pub fn format(mut self, format: Format) -> Self {
+        self.opts.format = format;
+        self
+    }
+
+    pub fn dedupe(mut self, dedupe: Dedupe) -> Self {
+        self.opts.dedupe = dedupe;
+        self
+    }
+
+    /// Emit a crate's files signatures-only once its full bodies reach
+    /// `tokens` (`--per-crate-cap`).
+    pub fn per_crate_cap(mut self, tokens: usize) -> Self {
+        self.opts.per_crate_cap = Some(tokens);
+        self
+    }
+
+    /// Annotate headers with the commit that last touched each file.
+    pub fn git_info(mut self, on: bool) -> Self {
+        self.opts.git_info = on;
+        self
+    }
+
+    /// Open with rustc/cargo versions, host triple and toolchain
+    /// (`--env-info`).
+    pub fn env_info(mut self, on: bool) -> Self {
+        self.opts.env_info = on;
+        self
+    }
+
+    /// Prepend the last `n` commits touching the selected paths.
+    pub fn log(mut self, n: usize) -> Self {
+        self.opts.log = Some(n);
+        self
+    }
+
+    /// Open with a summary of the workspace (see `--preamble auto`).
+    pub fn preamble(mut self, on: bool) -> Self {
+        self.opts.preamble = on.then_some(crate::Preamble::Auto);
+        self
+    }
+
+    /// Draw the CLI's progress bar on stderr for large selections (off by
+    /// default).
+    pub fn progress(mut self, on: bool) -> Self {
+        self.opts.quiet = !on;
+        self
+    }
+
+    //──────────────────────── sinks ──────────────────────────────────────────
+
+    /// Streams the snapshot into `out`.
+    pub fn write_to(&self, out: &mut dyn Write) -> Result<Snapshot> {
+        let root = resolve_root(&self.opts.dir, !self.opts.no_discover)?;
+        let config = Config::load(&root)?;
+        let mut ctx = context(&self.opts, root, &config)?;
+        ctx.filters.extend(self.filters.iter().cloned());
+        ctx.pipeline.extend(self.transforms.iter().cloned());
+        ctx.begin_output(out)?;
+        write_intro(&mut ctx, &self.opts, out)?;
+        default_mode(&mut ctx, &self.opts, out)?;
+        ctx.finish_output(out)?;
+        out.flush()?;
+
+        let unreadable = std::mem::take(&mut *ctx.read_errors.lock().unwrap());
+        Ok(Snapshot {
+            files: ctx
+                .emitted
+                .iter()
+                .map(|e| ctx.rel(&e.path).to_path_buf())
+                .collect(),
+            tokens: ctx.emitted.iter().map(|e| e.tokens).sum(),
+            unreadable: unreadable
+                .iter()
+                .map(|(p, _)| ctx.rel(p).to_path_buf())
+                .collect(),
+        })
+    }
+
+    /// The snapshot as a string.
+    pub fn render(&self) -> Result<(String, Snapshot)> {
+        let mut buf = Vec::new();
+        let snapshot = self.write_to(&mut buf)?;
+        let text = String::from_utf8(buf)
+            .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned());
+        Ok((text, snapshot))
+    }
+
+    /// Copies the snapshot to the clipboard (retried and verified).
+    pub fn copy_to_clipboard(&self) -> Result<Snapshot> {
+        let (text, snapshot) = self.render()?;
+        emit::copy(&text)?;
+        Ok(snapshot)
+    }
+
+    /// Writes the snapshot to `path`.
+    pub fn write_file(&self, path: &Path) -> Result<Snapshot> {
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_to(&mut out)
+    }
+}
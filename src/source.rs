@@ -0,0 +1,52 @@
+//! `--from index|head|worktree` — where file content comes from, for every
+//! mode: the staged state, the last commit, or the files on disk (the
+//! default).
+//! * `index` snapshots the tree `git write-tree` makes of the index, so
+//!   edits made after `git add` don't show; it fails while conflicts are
+//!   unresolved. This writes the tree object (not a commit or ref) into the
+//!   repository's object store, where `git gc` prunes it once unreachable.
+//! * `head` is `--rev HEAD`; the two can't be combined with `--rev`.
+//! * Blobs are read through one `git cat-file --batch` process rather than
+//!   a `git show` per file.
+//! * History (`--log`, `--git-info`, `--against`'s merge-base) follows
+//!   `HEAD` for `index`, as a tree has none of its own.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+
+use crate::{git, Opts};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Source {
+    /// The staged state, as `git add` left it
+    Index,
+    /// The last commit
+    Head,
+    /// The files on disk
+    Worktree,
+}
+
+/// The revision to read blobs from and the commit whose history applies,
+/// from `--from` or `--rev`; `None` for the working tree.
+pub fn resolve(root: &Path, opts: &Opts) -> Result<(Option<String>, Option<String>)> {
+    let head = || -> Result<String> {
+        Ok(git::run(root, &["rev-parse", "--verify", "HEAD"])
+            .context("--from needs a commit")?
+            .trim()
+            .to_string())
+    };
+    Ok(match opts.from {
+        Some(Source::Index) => {
+            let tree =
+                git::index_tree(root).context("--from index: can't write the index as a tree")?;
+            (Some(tree), Some(head()?))
+        }
+        Some(Source::Head) => {
+            let commit = head()?;
+            (Some(commit.clone()), Some(commit))
+        }
+        Some(Source::Worktree) | None => (opts.rev.clone(), opts.rev.clone()),
+    })
+}
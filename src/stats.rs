@@ -0,0 +1,202 @@
+//! `cargo qp stats` — a repository report for prompt planning.
+//! * files / lines / tokens per crate, the largest files, tests vs source,
+//!   the most recently changed files, near-duplicate files (see `dedupe`),
+//!   the most imported files per crate, and how many snapshot parts each
+//!   model would need.
+//! * Imports: fan-in is how many other selected files reach a file through
+//!   a path, `use` or `mod` declaration (see `rank`), fan-out how many it
+//!   reaches.
+//! * Parts are packed file by file; a file bigger than a whole window is cut
+//!   at item boundaries (see `chunk`).
+//! * Test lines: whole files under `tests/`, plus everything from the first
+//!   `#[cfg(test)]` to the end of a source file.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{chunk, dedupe, rank, tokens, Ctx};
+
+/// Estimated similarity from which files are reported as near duplicates.
+const SIMILAR: f64 = 0.8;
+/// Most imported files listed per crate.
+const MOST_IMPORTED: usize = 5;
+
+#[derive(Default)]
+struct Totals {
+    files: usize,
+    lines: usize,
+    tokens: usize,
+    test_lines: usize,
+}
+
+impl Totals {
+    fn add(&mut self, lines: usize, tokens: usize, test_lines: usize) {
+        self.files += 1;
+        self.lines += lines;
+        self.tokens += tokens;
+        self.test_lines += test_lines;
+    }
+}
+
+pub fn render(ctx: &Ctx) -> Result<String> {
+    let mut per_crate = BTreeMap::<String, Totals>::new();
+    let mut all = Totals::default();
+    let mut sizes = Vec::<(usize, PathBuf)>::new();
+
+    let paths = ctx.selected()?;
+    let texts = ctx.read_all(&paths)?;
+    for (path, text) in paths.iter().zip(&texts) {
+        let (name, ver) = ctx.owner(path);
+        let rel = ctx.rel(path).to_path_buf();
+        let (lines, toks) = (text.lines().count(), tokens::estimate(text));
+        let test_lines = if rel.components().any(|c| c.as_os_str() == "tests") {
+            lines
+        } else {
+            text.lines()
+                .skip_while(|l| l.trim() != "#[cfg(test)]")
+                .count()
+        };
+        per_crate
+            .entry(format!("{name} v{ver}"))
+            .or_default()
+            .add(lines, toks, test_lines);
+        all.add(lines, toks, test_lines);
+        sizes.push((toks, rel));
+    }
+
+    let mut out = String::new();
+    writeln!(out, "== per crate ==")?;
+    writeln!(
+        out,
+        "{:<32} {:>6} {:>8} {:>8} {:>6}",
+        "crate", "files", "lines", "tokens", "tests"
+    )?;
+    for (name, t) in &per_crate {
+        writeln!(
+            out,
+            "{name:<32} {:>6} {:>8} {:>8} {:>5}%",
+            t.files,
+            t.lines,
+            tokens::human(t.tokens),
+            percent(t.test_lines, t.lines)
+        )?;
+    }
+    writeln!(
+        out,
+        "{:<32} {:>6} {:>8} {:>8} {:>5}%",
+        "total",
+        all.files,
+        all.lines,
+        tokens::human(all.tokens),
+        percent(all.test_lines, all.lines)
+    )?;
+
+    writeln!(out, "\n== largest files ==")?;
+    sizes.sort_by_key(|(toks, _)| std::cmp::Reverse(*toks));
+    for (toks, rel) in sizes.iter().take(10) {
+        writeln!(out, "{:>8}  {}", tokens::human(*toks), rel.display())?;
+    }
+
+    let last = ctx.last_commits();
+    if !last.is_empty() {
+        writeln!(out, "\n== recently changed ==")?;
+        let mut recent: Vec<_> = last.iter().collect();
+        recent.sort_by_key(|(rel, c)| (std::cmp::Reverse(c.time), *rel));
+        for (rel, c) in recent.into_iter().take(10) {
+            writeln!(out, "{}  {}  {rel}  ({})", c.date, c.hash, c.subject)?;
+        }
+    }
+
+    let bodies: Vec<&str> = texts.iter().map(String::as_str).collect();
+    let similar = dedupe::similar_pairs(&bodies, SIMILAR);
+    if !similar.is_empty() {
+        writeln!(out, "\n== near duplicates ==")?;
+        for (a, b, similarity) in similar.into_iter().take(10) {
+            writeln!(
+                out,
+                "{:>4.0}%  {}  ~  {}",
+                similarity * 100.0,
+                ctx.rel(&paths[a]).display(),
+                ctx.rel(&paths[b]).display()
+            )?;
+        }
+    }
+
+    let files: Vec<(PathBuf, String)> = paths.iter().cloned().zip(texts.iter().cloned()).collect();
+    let graph = rank::imports(ctx, &files);
+    let mut fan_in = vec![0; files.len()];
+    for &j in graph.iter().flatten() {
+        fan_in[j] += 1;
+    }
+    let mut by_crate = BTreeMap::<String, Vec<usize>>::new();
+    for (i, path) in paths.iter().enumerate() {
+        if fan_in[i] > 0 || !graph[i].is_empty() {
+            let (name, ver) = ctx.owner(path);
+            by_crate
+                .entry(format!("{name} v{ver}"))
+                .or_default()
+                .push(i);
+        }
+    }
+    if !by_crate.is_empty() {
+        writeln!(out, "\n== most imported ==")?;
+        writeln!(out, "{:>6} {:>7}  file", "fan-in", "fan-out")?;
+        for (name, mut files) in by_crate {
+            files.sort_by_key(|&i| (std::cmp::Reverse(fan_in[i]), &paths[i]));
+            writeln!(out, "{name}")?;
+            for i in files.into_iter().take(MOST_IMPORTED) {
+                writeln!(
+                    out,
+                    "{:>6} {:>7}  {}",
+                    fan_in[i],
+                    graph[i].len(),
+                    ctx.rel(&paths[i]).display()
+                )?;
+            }
+        }
+    }
+
+    writeln!(out, "\n== snapshot parts per model ==")?;
+    for (model, window) in tokens::MODELS {
+        writeln!(
+            out,
+            "{model:<16} {:>6} window  {:>3} part(s)",
+            tokens::human(*window),
+            parts(&paths, &texts, *window)
+        )?;
+    }
+    Ok(out)
+}
+
+/// Snapshot parts of at most `window` tokens the files fill, in order.
+fn parts(paths: &[PathBuf], texts: &[String], window: usize) -> usize {
+    let (mut parts, mut used) = (1, 0);
+    for (path, text) in paths.iter().zip(texts) {
+        let size = tokens::estimate(text);
+        let pieces: Vec<usize> = if size <= window {
+            vec![size]
+        } else {
+            chunk::chunk_file(Path::new(path), text, window)
+                .iter()
+                .map(|c| c.tokens)
+                .collect()
+        };
+        for piece in pieces {
+            if used > 0 && used + piece > window {
+                parts += 1;
+                used = 0;
+            }
+            used += piece;
+        }
+    }
+    parts
+}
+
+fn percent(part: usize, whole: usize) -> usize {
+    (part * 100).checked_div(whole).unwrap_or(0)
+}
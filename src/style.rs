@@ -0,0 +1,87 @@
+//! `--style chatgpt|claude|gemini|plain` — presets for pasting into a chat
+//! UI: the output format, the fences and where a file tree goes.
+//! * `chatgpt`: markdown; a body containing ```` ``` ```` is fenced with
+//!   `~~~` instead, since the renderer ends a block at the first inner
+//!   backtick fence. The file tree comes first.
+//! * `claude`: `--format xml` — one `<document>` per file with `<source>`
+//!   and `<document_content>`, the shape Claude's long-context guidance
+//!   uses; the file tree comes first.
+//! * `gemini`: markdown with `~~~` fences like `chatgpt`; the file tree
+//!   comes last, after the files.
+//! * `plain`: the default `=== … ===` text, without a tree.
+//! * A style replaces `--format`; the two can't be combined.
+
+use std::{collections::BTreeSet, io::Write};
+
+use anyhow::Result;
+use clap::ValueEnum;
+
+use crate::{focus, Ctx, Format, Opts};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Style {
+    Chatgpt,
+    Claude,
+    Gemini,
+    Plain,
+}
+
+/// Where the file tree section goes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Tree {
+    Top,
+    Bottom,
+}
+
+impl Style {
+    pub fn format(self) -> Format {
+        match self {
+            Style::Chatgpt | Style::Gemini => Format::Markdown,
+            Style::Claude => Format::Xml,
+            Style::Plain => Format::Text,
+        }
+    }
+
+    /// Whether markdown bodies containing ```` ``` ```` get `~~~` fences.
+    pub fn tildes(self) -> bool {
+        matches!(self, Style::Chatgpt | Style::Gemini)
+    }
+
+    pub fn tree(self) -> Option<Tree> {
+        match self {
+            Style::Chatgpt | Style::Claude => Some(Tree::Top),
+            Style::Gemini => Some(Tree::Bottom),
+            Style::Plain => None,
+        }
+    }
+}
+
+/// Puts the style's format in `opts.format`.
+pub fn apply(opts: &mut Opts) {
+    if let Some(style) = opts.style {
+        opts.format = style.format();
+    }
+}
+
+/// The `tree` section, if the style puts one at `at`: of the selection
+/// ahead of the files, of what was emitted after them.
+pub fn tree(ctx: &mut Ctx, out: &mut dyn Write, at: Tree) -> Result<()> {
+    if ctx.style.and_then(Style::tree) != Some(at) {
+        return Ok(());
+    }
+    let paths = match at {
+        Tree::Top => ctx.selected()?,
+        Tree::Bottom => ctx.emitted.iter().map(|e| e.path.clone()).collect(),
+    };
+    if paths.is_empty() {
+        return Ok(());
+    }
+    let rels: Vec<_> = paths
+        .iter()
+        .map(|p| ctx.rel(p).to_path_buf())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    ctx.push_section(out, "tree", &focus::tree(&rels))?;
+    Ok(())
+}
@@ -0,0 +1,242 @@
+//! `cargo qp suggest-excludes` — proposes `exclude` globs for
+//! `.cargo-qp.toml`, each with the tokens it would save.
+//! * Candidates: generated and vendored directories and files (as found by
+//!   `cargo qp init`), fixture directories (`fixtures/`, `testdata/`,
+//!   `snapshots/`, …) above a few thousand tokens, files far larger than
+//!   the rest, and identical or near-identical copies of another file (the
+//!   later path of the pair is suggested).
+//! * Only the current selection is analysed, so paths already excluded are
+//!   not suggested again; a file counts towards the first rule covering it.
+//! * `--write` appends the rules to the config's `exclude` list (creating
+//!   the file if needed) after asking; `--yes` skips the question.
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::{config, dedupe, init, tokens, Ctx};
+
+/// Directories named like test data, suggested once this large.
+const FIXTURE_DIRS: &[&str] = &[
+    "fixtures",
+    "fixture",
+    "testdata",
+    "test-data",
+    "test_data",
+    "snapshots",
+    "golden",
+];
+const FIXTURE_TOKENS: usize = 5_000;
+/// Similarity for a file to count as a copy of another.
+const NEAR_DUPLICATE: f64 = 0.9;
+
+struct Rule {
+    glob: String,
+    reason: String,
+    /// indices into the selection
+    files: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Rules {
+    list: Vec<Rule>,
+    /// files some rule already excludes
+    covered: HashSet<usize>,
+}
+
+impl Rules {
+    /// Adds a rule for those of `files` no earlier rule covers.
+    fn add(&mut self, glob: String, reason: String, files: Vec<usize>) {
+        let files: Vec<usize> = files
+            .into_iter()
+            .filter(|&i| self.covered.insert(i))
+            .collect();
+        if !files.is_empty() {
+            self.list.push(Rule {
+                glob,
+                reason,
+                files,
+            });
+        }
+    }
+}
+
+pub fn run(ctx: &Ctx, write: bool, yes: bool) -> Result<()> {
+    let paths = ctx.selected()?;
+    let texts = ctx.read_all(&paths)?;
+    let rels: Vec<PathBuf> = paths.iter().map(|p| ctx.rel(p).to_path_buf()).collect();
+    let costs: Vec<usize> = texts.iter().map(|t| tokens::estimate(t)).collect();
+    let total: usize = costs.iter().sum();
+
+    let mut rules = Rules::default();
+
+    let mut dirs: HashMap<String, (&str, Vec<usize>)> = HashMap::new();
+    for (i, rel) in rels.iter().enumerate() {
+        if let Some(dir) = init::marked_dir(rel) {
+            let entry = dirs
+                .entry(dir)
+                .or_insert(("generated or vendored directory", Vec::new()));
+            entry.1.push(i);
+        } else if let Some(dir) = fixture_dir(rel) {
+            dirs.entry(dir)
+                .or_insert(("test fixtures", Vec::new()))
+                .1
+                .push(i);
+        }
+    }
+    let mut dirs: Vec<_> = dirs.into_iter().collect();
+    dirs.sort_by(|a, b| a.0.cmp(&b.0));
+    for (dir, (reason, files)) in dirs {
+        let size: usize = files.iter().map(|&i| costs[i]).sum();
+        if reason != "test fixtures" || size >= FIXTURE_TOKENS {
+            let glob = format!("{}/**", globset::escape(&dir));
+            rules.add(glob, reason.to_string(), files);
+        }
+    }
+    for (i, text) in texts.iter().enumerate() {
+        let reason = if init::has_generated_marker(text) {
+            "has a generated-code marker"
+        } else if costs[i] > init::HUGE_TOKENS {
+            "far larger than the rest"
+        } else {
+            continue;
+        };
+        rules.add(glob(&rels[i]), reason.to_string(), vec![i]);
+    }
+
+    let mut first: HashMap<&str, usize> = HashMap::new();
+    for (i, text) in texts.iter().enumerate() {
+        match first.get(text.as_str()) {
+            Some(&earlier) if !text.trim().is_empty() => {
+                let reason = format!("identical to {}", rels[earlier].display());
+                rules.add(glob(&rels[i]), reason, vec![i]);
+            }
+            Some(_) => {}
+            None => {
+                first.insert(text, i);
+            }
+        }
+    }
+    let bodies: Vec<&str> = texts.iter().map(String::as_str).collect();
+    for (a, b, similarity) in dedupe::similar_pairs(&bodies, NEAR_DUPLICATE) {
+        if rules.covered.contains(&a) {
+            continue;
+        }
+        let reason = format!("{:.0}% like {}", similarity * 100.0, rels[a].display());
+        rules.add(glob(&rels[b]), reason, vec![b]);
+    }
+
+    let mut rules = rules.list;
+    let saving = |rule: &Rule| rule.files.iter().map(|&i| costs[i]).sum::<usize>();
+    rules.sort_by(|a, b| saving(b).cmp(&saving(a)).then(a.glob.cmp(&b.glob)));
+    if rules.is_empty() {
+        eprintln!(
+            "no exclude suggestions for {} file(s), ~{} tokens",
+            paths.len(),
+            tokens::human(total)
+        );
+        return Ok(());
+    }
+    let width = rules
+        .iter()
+        .map(|r| r.glob.len() + 2)
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    println!("{:<width$} {:>5} {:>8}  reason", "rule", "files", "saves");
+    for rule in &rules {
+        println!(
+            "{:<width$} {:>5} {:>8}  {}",
+            format!("\"{}\"", rule.glob),
+            rule.files.len(),
+            tokens::human(saving(rule)),
+            rule.reason
+        );
+    }
+    let saved: usize = rules.iter().map(saving).sum();
+    println!(
+        "\n{} rule(s) save ~{} of ~{} tokens ({:.0}%)",
+        rules.len(),
+        tokens::human(saved),
+        tokens::human(total),
+        saved as f64 * 100.0 / total.max(1) as f64
+    );
+
+    if !write {
+        return Ok(());
+    }
+    let path = ctx.root.join(config::FILE_NAME);
+    if !yes {
+        anyhow::ensure!(
+            io::stdin().is_terminal() && io::stderr().is_terminal(),
+            "not asking without a terminal; pass --yes to write the rules"
+        );
+        eprint!("add {} rule(s) to {}? [y/N] ", rules.len(), path.display());
+        io::stderr().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            eprintln!("nothing written");
+            return Ok(());
+        }
+    }
+    append_excludes(&path, &rules)?;
+    eprintln!(
+        "added {} exclude rule(s) to {}",
+        rules.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// `rel` as a glob matching just that path.
+fn glob(rel: &Path) -> String {
+    globset::escape(&rel.to_string_lossy().replace('\\', "/"))
+}
+
+/// Closest ancestor directory named like test data.
+fn fixture_dir(rel: &Path) -> Option<String> {
+    let mut acc = Vec::new();
+    for comp in rel.parent()?.components() {
+        let name = comp.as_os_str().to_string_lossy();
+        acc.push(name.to_string());
+        if FIXTURE_DIRS.contains(&name.as_ref()) {
+            return Some(acc.join("/"));
+        }
+    }
+    None
+}
+
+/// Adds the rules to `exclude` in the config at `path`, keeping its
+/// formatting and comments.
+fn append_excludes(path: &Path, rules: &[Rule]) -> Result<()> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let mut doc: toml_edit::Document = text
+        .parse()
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+    let item = doc
+        .entry("exclude")
+        .or_insert_with(|| toml_edit::value(toml_edit::Array::new()));
+    let array = item
+        .as_array_mut()
+        .with_context(|| format!("`exclude` in {} is not a list", path.display()))?;
+    for rule in rules {
+        array.push(rule.glob.as_str());
+    }
+    // one glob per line, existing ones included
+    for value in array.iter_mut() {
+        value.decor_mut().set_prefix("\n    ");
+    }
+    array.set_trailing("\n");
+    array.set_trailing_comma(true);
+    std::fs::write(path, doc.to_string())
+        .with_context(|| format!("failed to write {}", path.display()))
+}
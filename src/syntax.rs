@@ -0,0 +1,380 @@
+//! syn-based views of Rust sources.
+//! * Spans are mapped back onto the original text, so formatting and comments
+//!   survive every transform.
+
+use std::collections::BTreeMap;
+
+use proc_macro2::LineColumn;
+use syn::{
+    spanned::Spanned,
+    visit::{self, Visit},
+};
+
+/// Signatures-only view: every function body becomes `{ ... }`.
+/// Returns `None` when the file does not parse.
+pub fn signatures(src: &str) -> Option<String> {
+    let file = syn::parse_file(src).ok()?;
+    let mut bodies = Bodies::default();
+    bodies.visit_file(&file);
+
+    let lines = LineIndex::new(src);
+    let mut out = String::with_capacity(src.len() / 2);
+    let mut last = 0;
+    for (start, end) in bodies.spans {
+        let (start, end) = (lines.offset(start), lines.offset(end));
+        if start < last {
+            continue; // nested in a body already elided
+        }
+        out.push_str(&src[last..start]);
+        out.push_str("{ ... }");
+        last = end;
+    }
+    out.push_str(&src[last..]);
+    Some(out)
+}
+
+/// Collects the brace spans of every function body, outermost only.
+#[derive(Default)]
+struct Bodies {
+    spans: Vec<(LineColumn, LineColumn)>,
+}
+
+impl Bodies {
+    fn push(&mut self, block: &syn::Block) {
+        let span = block.brace_token.span.join();
+        self.spans.push((span.start(), span.end()));
+    }
+}
+
+impl<'ast> Visit<'ast> for Bodies {
+    fn visit_item_fn(&mut self, f: &'ast syn::ItemFn) {
+        self.push(&f.block);
+    }
+
+    fn visit_impl_item_fn(&mut self, f: &'ast syn::ImplItemFn) {
+        self.push(&f.block);
+    }
+
+    fn visit_trait_item_fn(&mut self, f: &'ast syn::TraitItemFn) {
+        match &f.default {
+            Some(block) => self.push(block),
+            None => visit::visit_trait_item_fn(self, f),
+        }
+    }
+}
+
+/// Capitalised path segments in `src` (the types and traits it refers to),
+/// each with the position of its first use.
+/// Returns `None` when the file does not parse.
+pub fn referenced_types(src: &str) -> Option<BTreeMap<String, LineColumn>> {
+    let file = syn::parse_file(src).ok()?;
+    let mut names = TypeNames::default();
+    names.visit_file(&file);
+    Some(names.0)
+}
+
+#[derive(Default)]
+struct TypeNames(BTreeMap<String, LineColumn>);
+
+impl<'ast> Visit<'ast> for TypeNames {
+    fn visit_path_segment(&mut self, seg: &'ast syn::PathSegment) {
+        let name = seg.ident.to_string();
+        if name.starts_with(|c: char| c.is_ascii_uppercase()) {
+            self.0.entry(name).or_insert(seg.ident.span().start());
+        }
+        visit::visit_path_segment(self, seg);
+    }
+}
+
+/// Whether `name` appears as an identifier in `src` (outside comments and
+/// string literals). Falls back to a text search when `src` doesn't lex.
+pub fn mentions(src: &str, name: &str) -> bool {
+    fn walk(tokens: proc_macro2::TokenStream, name: &str) -> bool {
+        tokens.into_iter().any(|t| match t {
+            proc_macro2::TokenTree::Ident(ident) => ident == name,
+            proc_macro2::TokenTree::Group(g) => walk(g.stream(), name),
+            _ => false,
+        })
+    }
+    if !src.contains(name) {
+        return false;
+    }
+    match src.parse() {
+        Ok(tokens) => walk(tokens, name),
+        Err(_) => true,
+    }
+}
+
+/// A type-level item and its source, docs and attributes included.
+#[derive(Clone)]
+pub struct Definition {
+    pub name: String,
+    /// First and last line, 1-based.
+    pub lines: (usize, usize),
+    pub text: String,
+}
+
+/// Struct, enum, union, trait and type alias definitions in `src`, inline
+/// modules included. Returns `None` when the file does not parse.
+pub fn definitions(src: &str) -> Option<Vec<Definition>> {
+    let file = syn::parse_file(src).ok()?;
+    let mut defs = Definitions::default();
+    defs.visit_file(&file);
+
+    let lines = LineIndex::new(src);
+    let items = defs.0.into_iter().map(|(name, span)| {
+        let (start, end) = (lines.offset(span.start()), lines.offset(span.end()));
+        // keep the indentation of a nested item's first line
+        let line_start = lines.starts[span.start().line - 1];
+        let start = if src[line_start..start].trim().is_empty() {
+            line_start
+        } else {
+            start
+        };
+        Definition {
+            name,
+            lines: (span.start().line, span.end().line),
+            text: src[start..end].to_string(),
+        }
+    });
+    Some(items.collect())
+}
+
+#[derive(Default)]
+struct Definitions(Vec<(String, proc_macro2::Span)>);
+
+impl<'ast> Visit<'ast> for Definitions {
+    fn visit_item(&mut self, item: &'ast syn::Item) {
+        let name = match item {
+            syn::Item::Struct(i) => &i.ident,
+            syn::Item::Enum(i) => &i.ident,
+            syn::Item::Union(i) => &i.ident,
+            syn::Item::Trait(i) => &i.ident,
+            syn::Item::Type(i) => &i.ident,
+            syn::Item::Mod(_) => return visit::visit_item(self, item),
+            _ => return,
+        };
+        self.0.push((name.to_string(), item.span()));
+    }
+}
+
+/// First line (1-based, docs and attributes included) of every top-level
+/// item, each with the first lines of the items inside it (`impl`, `trait`
+/// and inline `mod` bodies). Returns `None` when the file does not parse.
+pub fn item_lines(src: &str) -> Option<Vec<(usize, Vec<usize>)>> {
+    let file = syn::parse_file(src).ok()?;
+    let first = |span: proc_macro2::Span| span.start().line;
+    let items = file.items.iter().map(|item| {
+        let inner: Vec<usize> = match item {
+            syn::Item::Impl(i) => i.items.iter().map(|x| first(x.span())).collect(),
+            syn::Item::Trait(t) => t.items.iter().map(|x| first(x.span())).collect(),
+            syn::Item::Mod(m) => m
+                .content
+                .iter()
+                .flat_map(|(_, items)| items)
+                .map(|x| first(x.span()))
+                .collect(),
+            _ => Vec::new(),
+        };
+        (first(item.span()), inner)
+    });
+    Some(items.collect())
+}
+
+/// An out-of-line `mod name;` declaration.
+pub struct ModDecl {
+    /// Enclosing inline modules, outermost first.
+    pub inline: Vec<String>,
+    pub name: String,
+    /// The value of a `#[path = "…"]` attribute.
+    pub path: Option<String>,
+}
+
+/// Out-of-line module declarations in `src`, inline modules included, in
+/// declaration order. Returns `None` when the file does not parse.
+pub fn modules(src: &str) -> Option<Vec<ModDecl>> {
+    let file = syn::parse_file(src).ok()?;
+    let mut out = Vec::new();
+    mod_decls(&file.items, &mut Vec::new(), &mut out);
+    Some(out)
+}
+
+fn mod_decls(items: &[syn::Item], inline: &mut Vec<String>, out: &mut Vec<ModDecl>) {
+    for item in items {
+        let syn::Item::Mod(m) = item else { continue };
+        match &m.content {
+            Some((_, items)) => {
+                inline.push(m.ident.to_string());
+                mod_decls(items, inline, out);
+                inline.pop();
+            }
+            None => {
+                let path = m.attrs.iter().find_map(|a| {
+                    let syn::Meta::NameValue(nv) = &a.meta else {
+                        return None;
+                    };
+                    let syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(s),
+                        ..
+                    }) = &nv.value
+                    else {
+                        return None;
+                    };
+                    nv.path.is_ident("path").then(|| s.value())
+                });
+                out.push(ModDecl {
+                    inline: inline.clone(),
+                    name: m.ident.to_string(),
+                    path,
+                });
+            }
+        }
+    }
+}
+
+/// Public-API view: `pub` items only, each with the first line of its docs,
+/// function bodies dropped, private fields hidden and inherent impls reduced
+/// to their `pub fn`s.
+/// Returns `None` when the file does not parse.
+pub fn api(src: &str) -> Option<String> {
+    let file = syn::parse_file(src).ok()?;
+    let lines = LineIndex::new(src);
+    let mut out = String::new();
+    api_items(&file.items, &lines, "", &mut out);
+    Some(out)
+}
+
+fn api_items(items: &[syn::Item], lines: &LineIndex, indent: &str, out: &mut String) {
+    let text = |from: LineColumn, to: LineColumn| &lines.src[lines.offset(from)..lines.offset(to)];
+    for item in items {
+        let public = |vis: &syn::Visibility| matches!(vis, syn::Visibility::Public(_));
+        let (attrs, rendered) = match item {
+            syn::Item::Fn(f) if public(&f.vis) => {
+                let sig = text(f.vis.span().start(), f.block.span().start());
+                (&f.attrs, format!("{};", sig.trim_end()))
+            }
+            syn::Item::Mod(m) if public(&m.vis) => {
+                let Some((_, items)) = &m.content else {
+                    continue;
+                };
+                let mut inner = String::new();
+                api_items(items, lines, &format!("{indent}    "), &mut inner);
+                (
+                    &m.attrs,
+                    format!("pub mod {} {{\n{inner}{indent}}}", m.ident),
+                )
+            }
+            syn::Item::Impl(i) if i.trait_.is_none() => {
+                let mut inner = String::new();
+                for member in &i.items {
+                    let syn::ImplItem::Fn(f) = member else {
+                        continue;
+                    };
+                    if !public(&f.vis) {
+                        continue;
+                    }
+                    let sig = text(f.vis.span().start(), f.block.span().start());
+                    push_doc(&f.attrs, &format!("{indent}    "), &mut inner);
+                    inner.push_str(&format!("{indent}    {};\n", sig.trim_end()));
+                }
+                if inner.is_empty() {
+                    continue;
+                }
+                let header = text(i.impl_token.span.start(), i.brace_token.span.open().start());
+                (
+                    &i.attrs,
+                    format!("{} {{\n{inner}{indent}}}", header.trim_end()),
+                )
+            }
+            syn::Item::Struct(
+                st @ syn::ItemStruct {
+                    fields: syn::Fields::Named(named),
+                    ..
+                },
+            ) if public(&st.vis) => {
+                let header = text(st.vis.span().start(), named.brace_token.span.open().start());
+                let mut inner = String::new();
+                for field in named.named.iter().filter(|f| public(&f.vis)) {
+                    push_doc(&field.attrs, &format!("{indent}    "), &mut inner);
+                    let decl = text(field.vis.span().start(), field.ty.span().end());
+                    inner.push_str(&format!("{indent}    {decl},\n"));
+                }
+                if named.named.iter().any(|f| !public(&f.vis)) {
+                    inner.push_str(&format!("{indent}    // private fields omitted\n"));
+                }
+                let rendered = format!("{} {{\n{inner}{indent}}}", header.trim_end());
+                (&st.attrs, rendered)
+            }
+            syn::Item::Struct(syn::ItemStruct { vis, attrs, .. })
+            | syn::Item::Enum(syn::ItemEnum { vis, attrs, .. })
+            | syn::Item::Union(syn::ItemUnion { vis, attrs, .. })
+            | syn::Item::Type(syn::ItemType { vis, attrs, .. })
+            | syn::Item::Const(syn::ItemConst { vis, attrs, .. })
+            | syn::Item::Static(syn::ItemStatic { vis, attrs, .. })
+            | syn::Item::Use(syn::ItemUse { vis, attrs, .. })
+                if public(vis) =>
+            {
+                (
+                    attrs,
+                    text(vis.span().start(), item.span().end()).to_string(),
+                )
+            }
+            syn::Item::Trait(t) if public(&t.vis) => {
+                let body = text(t.vis.span().start(), t.span().end());
+                (
+                    &t.attrs,
+                    signatures(body).unwrap_or_else(|| body.to_string()),
+                )
+            }
+            _ => continue,
+        };
+        push_doc(attrs, indent, out);
+        out.push_str(&format!("{indent}{rendered}\n"));
+    }
+}
+
+/// The first line of the item's `///` docs, if any.
+fn push_doc(attrs: &[syn::Attribute], indent: &str, out: &mut String) {
+    let first = attrs.iter().find_map(|a| match &a.meta {
+        syn::Meta::NameValue(nv) if nv.path.is_ident("doc") => match &nv.value {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(s),
+                ..
+            }) => Some(s.value()),
+            _ => None,
+        },
+        _ => None,
+    });
+    if let Some(doc) = first.as_deref().map(str::trim).filter(|d| !d.is_empty()) {
+        out.push_str(&format!("{indent}/// {doc}\n"));
+    }
+}
+
+/// Maps proc-macro2 line/column positions (1-based lines, char columns) to
+/// byte offsets.
+pub struct LineIndex<'a> {
+    src: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(src: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { src, starts }
+    }
+
+    /// The source between two positions.
+    pub fn slice(&self, from: LineColumn, to: LineColumn) -> &'a str {
+        &self.src[self.offset(from)..self.offset(to)]
+    }
+
+    pub fn offset(&self, at: LineColumn) -> usize {
+        let start = self.starts[at.line - 1];
+        self.src[start..]
+            .char_indices()
+            .nth(at.column)
+            .map_or(self.src.len(), |(i, _)| start + i)
+    }
+}
@@ -0,0 +1,104 @@
+//! `cargo qp tags` — a universal-ctags compatible `tags` file of the selected
+//! `.rs` files, so the editor navigates exactly the snapshot's scope.
+//! * Definitions come from the same syn walk as `cargo qp index`: items,
+//!   fields, variants, methods and associated items, with universal-ctags'
+//!   Rust kind letters and a `struct:`/`enum:`/`interface:`/`implementation:`
+//!   scope for members.
+//! * Written to `--output`, else `tags`; paths are relative to the directory
+//!   the file is written to, absolute when outside it. Lines are sorted so
+//!   editors can binary-search them (`!_TAG_FILE_SORTED 1`).
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::{index, Ctx, Opts};
+
+/// Where the tags go without `--output`.
+pub const DEFAULT_FILE: &str = "tags";
+
+/// universal-ctags' Rust kinds, by name.
+const KINDS: &[(&str, char)] = &[
+    ("module", 'n'),
+    ("struct", 's'),
+    ("interface", 'i'),
+    ("implementation", 'c'),
+    ("function", 'f'),
+    ("enum", 'g'),
+    ("typedef", 't'),
+    ("variable", 'v'),
+    ("macro", 'M'),
+    ("field", 'm'),
+    ("enumerator", 'e'),
+    ("method", 'P'),
+    ("constant", 'C'),
+];
+
+pub fn run(ctx: &Ctx, opts: &Opts) -> Result<()> {
+    let path = opts
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_FILE));
+    let base = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .canonicalize()
+        .unwrap_or_else(|_| ctx.root.clone());
+
+    let mut lines = Vec::new();
+    for file in ctx.selected()? {
+        if file.extension().is_none_or(|x| x != "rs") {
+            continue;
+        }
+        let src = String::from_utf8_lossy(&ctx.source_bytes(&file)?).into_owned();
+        let Ok(parsed) = syn::parse_file(&src) else {
+            continue;
+        };
+        let shown = file
+            .strip_prefix(&base)
+            .unwrap_or(&file)
+            .to_string_lossy()
+            .replace('\\', "/");
+        let source: Vec<&str> = src.lines().collect();
+        for d in index::definitions(&parsed, String::new()) {
+            let line = d.name_span.start().line;
+            let text = source.get(line - 1).copied().unwrap_or_default();
+            lines.push(tag(&d, &shown, line, text));
+        }
+    }
+    lines.sort();
+
+    let mut out = String::from(
+        "!_TAG_FILE_FORMAT\t2\t/extended format; --format=1 will not append ;\" to lines/\n\
+         !_TAG_FILE_SORTED\t1\t/0=unsorted, 1=sorted, 2=foldcase/\n",
+    );
+    out.push_str(&format!(
+        "!_TAG_PROGRAM_NAME\tcargo-qp\t//\n!_TAG_PROGRAM_VERSION\t{}\t//\n",
+        env!("CARGO_PKG_VERSION")
+    ));
+    for line in &lines {
+        out.push_str(line);
+        out.push('\n');
+    }
+    std::fs::write(&path, out).with_context(|| format!("failed to write {}", path.display()))?;
+    eprintln!("wrote {} ({} tags)", path.display(), lines.len());
+    Ok(())
+}
+
+/// `name<TAB>file<TAB>/^line$/;"<TAB>kind<TAB>line:N[<TAB>scope:Parent]`
+fn tag(d: &index::Def, file: &str, line: usize, text: &str) -> String {
+    let kind = KINDS
+        .iter()
+        .find(|(name, _)| *name == d.kind)
+        .map_or('v', |(_, letter)| *letter);
+    let pattern = text
+        .trim_end_matches('\r')
+        .replace('\\', "\\\\")
+        .replace('/', "\\/");
+    let mut tag = format!("{}\t{file}\t/^{pattern}$/;\"\t{kind}\tline:{line}", d.name);
+    if let (Some(scope), Some(parent)) = (d.scope, &d.parent) {
+        tag.push_str(&format!("\t{scope}:{parent}"));
+    }
+    tag
+}
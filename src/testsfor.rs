@@ -0,0 +1,155 @@
+//! `--with-tests-for PATH` — restrict the snapshot to source files and the
+//! tests that exercise them ("fix this and keep its tests passing").
+//! * PATH is a file or a directory (its `.rs` files), relative to the root;
+//!   repeatable.
+//! * Unit tests in the file itself come with it; out-of-line test modules it
+//!   declares under `#[cfg(test)]` (`mod tests;`) are added.
+//! * Integration tests (`tests/` of the same crate) naming the crate and the
+//!   module (just the crate, for a crate root) are added.
+//! * So are files of the crate with a test function whose name mentions a
+//!   type PATH defines (`fn config_rejects_empty` for `Config`).
+//! * The result narrows the selection like `--users-of`; filters still
+//!   apply.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use syn::visit::{self, Visit};
+
+use crate::{syntax, users, Ctx};
+
+pub fn select(ctx: &Ctx, targets: &[PathBuf]) -> Result<HashSet<PathBuf>> {
+    let sources = users::sources(ctx)?;
+    let mut selected = HashSet::new();
+    for target in targets {
+        let path = ctx.root.join(target);
+        let files: Vec<PathBuf> = if path.is_dir() {
+            sources
+                .iter()
+                .filter(|p| p.starts_with(&path))
+                .cloned()
+                .collect()
+        } else {
+            anyhow::ensure!(
+                path.is_file(),
+                "--with-tests-for {} is not a file or directory under {}",
+                target.display(),
+                ctx.root.display()
+            );
+            vec![path]
+        };
+        for file in files {
+            let tests = tests_for(ctx, &file, &sources);
+            selected.insert(file);
+            selected.extend(tests);
+        }
+    }
+    Ok(selected)
+}
+
+/// Files holding tests of the source file `path`.
+fn tests_for(ctx: &Ctx, path: &Path, sources: &[PathBuf]) -> HashSet<PathBuf> {
+    let mut tests = HashSet::new();
+    let Some(dir) = ctx.crate_dir(path) else {
+        return tests;
+    };
+    let Ok(src) = ctx.read(path) else {
+        return tests;
+    };
+    let parsed = syn::parse_file(&src).ok();
+
+    // `#[cfg(test)] mod tests;` next to the file
+    let base = match path.file_stem().and_then(|s| s.to_str()) {
+        Some("lib" | "main" | "mod") => path.parent().map(Path::to_path_buf),
+        Some(stem) => path.parent().map(|p| p.join(stem)),
+        None => None,
+    };
+    for item in parsed.iter().flat_map(|f| &f.items) {
+        let syn::Item::Mod(m) = item else { continue };
+        if m.content.is_none() && m.attrs.iter().any(is_cfg_test) {
+            let name = m.ident.to_string();
+            let flat = base.iter().map(|b| b.join(format!("{name}.rs")));
+            let nested = base.iter().map(|b| b.join(&name).join("mod.rs"));
+            tests.extend(flat.chain(nested).filter(|p| sources.contains(p)));
+        }
+    }
+
+    let krate = ctx.owner(path).0.replace('-', "_");
+    let module = users::module_name(dir, path);
+    let types: Vec<String> = syntax::definitions(&src)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|d| snake_case(&d.name))
+        .collect();
+    for file in sources {
+        if file == path || ctx.crate_dir(file) != Some(dir) {
+            continue;
+        }
+        let Ok(text) = ctx.read(file) else {
+            continue;
+        };
+        let integration = file.starts_with(dir.join("tests"));
+        let imports = integration
+            && syntax::mentions(&text, &krate)
+            && module.is_none_or(|m| syntax::mentions(&text, m));
+        let named = !types.is_empty()
+            && test_names(&text)
+                .iter()
+                .any(|name| types.iter().any(|t| name.contains(t.as_str())));
+        if imports || named {
+            tests.insert(file.clone());
+        }
+    }
+    tests
+}
+
+fn is_cfg_test(attr: &syn::Attribute) -> bool {
+    attr.path().is_ident("cfg")
+        && attr
+            .parse_args::<syn::Ident>()
+            .is_ok_and(|ident| ident == "test")
+}
+
+/// Names of the `#[test]` functions (`#[tokio::test]` and the like too).
+fn test_names(src: &str) -> Vec<String> {
+    if !src.contains("test") {
+        return Vec::new();
+    }
+    let Ok(file) = syn::parse_file(src) else {
+        return Vec::new();
+    };
+    let mut names = TestNames::default();
+    names.visit_file(&file);
+    names.0
+}
+
+#[derive(Default)]
+struct TestNames(Vec<String>);
+
+impl<'ast> Visit<'ast> for TestNames {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        let test = item
+            .attrs
+            .iter()
+            .any(|a| a.path().segments.last().is_some_and(|s| s.ident == "test"));
+        if test {
+            self.0.push(item.sig.ident.to_string());
+        }
+        visit::visit_item_fn(self, item);
+    }
+}
+
+/// `ParseConfig` → `parse_config`.
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.char_indices() {
+        if c.is_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
+}
@@ -0,0 +1,286 @@
+//! Line diffs (Myers), unified-diff rendering for apply previews, and
+//! three-way merges for files that drifted since their snapshot.
+
+/// One step of an edit script, as indices into the old/new line slices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Shortest edit script turning `a` into `b`.
+pub fn diff_lines(a: &[&str], b: &[&str]) -> Vec<Edit> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    let off = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max as isize {
+        trace.push(v.clone());
+        let mut k = -d;
+        while k <= d {
+            let i = (k + off) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    // walk the trace backwards to recover the script
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let v = &trace[d as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[(k - 1 + off) as usize] < v[(k + 1 + off) as usize])
+        {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + off) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal(x as usize, y as usize));
+        }
+        if x == prev_x {
+            y -= 1;
+            edits.push(Edit::Insert(y as usize));
+        } else {
+            x -= 1;
+            edits.push(Edit::Delete(x as usize));
+        }
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        edits.push(Edit::Equal(x as usize, y as usize));
+    }
+    edits.reverse();
+    edits
+}
+
+/// A unified-diff hunk; `lines` carry their ` `/`-`/`+` prefix.
+#[derive(Debug, Clone)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<String>,
+}
+
+impl Hunk {
+    pub fn header(&self) -> String {
+        format!(
+            "@@ -{},{} +{},{} @@",
+            self.old_start, self.old_len, self.new_start, self.new_len
+        )
+    }
+}
+
+/// Groups an edit script into hunks with `context` unchanged lines around
+/// each change (1-based line numbers, like `diff -u`).
+pub fn hunks(a: &[&str], b: &[&str], context: usize) -> Vec<Hunk> {
+    let edits = diff_lines(a, b);
+    let changed: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| !matches!(e, Edit::Equal(..)))
+        .map(|(i, _)| i)
+        .collect();
+    let Some(&first) = changed.first() else {
+        return Vec::new();
+    };
+
+    // ranges of edit indices, merged when their context overlaps
+    let mut ranges = vec![(first.saturating_sub(context), first + context)];
+    for &i in &changed[1..] {
+        let last = ranges.last_mut().unwrap();
+        if i.saturating_sub(context) <= last.1 + 1 {
+            last.1 = i + context;
+        } else {
+            ranges.push((i.saturating_sub(context), i + context));
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(lo, hi)| {
+            let slice = &edits[lo..=hi.min(edits.len() - 1)];
+            // position of the first edit in old/new coordinates
+            let (mut old_pos, mut new_pos) = (0, 0);
+            for e in &edits[..lo] {
+                match e {
+                    Edit::Equal(..) => {
+                        old_pos += 1;
+                        new_pos += 1;
+                    }
+                    Edit::Delete(_) => old_pos += 1,
+                    Edit::Insert(_) => new_pos += 1,
+                }
+            }
+            let mut hunk = Hunk {
+                old_start: old_pos + 1,
+                old_len: 0,
+                new_start: new_pos + 1,
+                new_len: 0,
+                lines: Vec::new(),
+            };
+            for e in slice {
+                match *e {
+                    Edit::Equal(i, _) => {
+                        hunk.old_len += 1;
+                        hunk.new_len += 1;
+                        hunk.lines.push(format!(" {}", a[i]));
+                    }
+                    Edit::Delete(i) => {
+                        hunk.old_len += 1;
+                        hunk.lines.push(format!("-{}", a[i]));
+                    }
+                    Edit::Insert(j) => {
+                        hunk.new_len += 1;
+                        hunk.lines.push(format!("+{}", b[j]));
+                    }
+                }
+            }
+            // `diff -u` numbers an empty side from the line before it
+            if hunk.old_len == 0 {
+                hunk.old_start -= 1;
+            }
+            if hunk.new_len == 0 {
+                hunk.new_start -= 1;
+            }
+            hunk
+        })
+        .collect()
+}
+
+/// Full unified diff of two texts; empty when they are equal.
+pub fn unified(old_name: &str, new_name: &str, old: &str, new: &str) -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let hunks = hunks(&a, &b, 3);
+    if hunks.is_empty() {
+        return String::new();
+    }
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+    for h in hunks {
+        out.push_str(&h.header());
+        out.push('\n');
+        for l in &h.lines {
+            out.push_str(l);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Three-way line merge of `ours` and `theirs`, both derived from `base`.
+/// Regions both sides changed differently get diff3-style conflict markers
+/// labelled with `labels`; returns the text and the number of conflicts.
+pub fn merge3(base: &str, ours: &str, theirs: &str, labels: (&str, &str)) -> (String, usize) {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+    // base line → matching line on each side
+    let matches = |side: &[&str]| {
+        let mut map = vec![None; base.len()];
+        for edit in diff_lines(&base, side) {
+            if let Edit::Equal(i, j) = edit {
+                map[i] = Some(j);
+            }
+        }
+        map
+    };
+    let (to_ours, to_theirs) = (matches(&ours), matches(&theirs));
+
+    let mut out = String::new();
+    let mut conflicts = 0;
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    loop {
+        while i < base.len() && to_ours[i] == Some(j) && to_theirs[i] == Some(k) {
+            out.push_str(base[i]);
+            (i, j, k) = (i + 1, j + 1, k + 1);
+        }
+        if i == base.len() && j == ours.len() && k == theirs.len() {
+            break;
+        }
+        // next base line both sides kept
+        let (i2, j2, k2) = (i..base.len())
+            .find_map(|n| Some((n, to_ours[n]?, to_theirs[n]?)))
+            .unwrap_or((base.len(), ours.len(), theirs.len()));
+        let (b, o, t) = (&base[i..i2], &ours[j..j2], &theirs[k..k2]);
+        if o == b || o == t {
+            out.extend(t.iter().copied());
+        } else if t == b {
+            out.extend(o.iter().copied());
+        } else {
+            conflicts += 1;
+            let block = |out: &mut String, marker: String, lines: &[&str]| {
+                if !out.is_empty() && !out.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str(&marker);
+                out.push('\n');
+                out.extend(lines.iter().copied());
+            };
+            block(&mut out, format!("<<<<<<< {}", labels.0), o);
+            block(&mut out, "||||||| snapshot".to_string(), b);
+            block(&mut out, "=======".to_string(), t);
+            block(&mut out, format!(">>>>>>> {}", labels.1), &[]);
+        }
+        (i, j, k) = (i2, j2, k2);
+    }
+    (out, conflicts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LABELS: (&str, &str) = ("local", "answer");
+
+    #[test]
+    fn merge3_takes_both_sides() {
+        let base = "a\nb\nc\nd\ne\n";
+        let ours = "a\nB\nc\nd\ne\n";
+        let theirs = "a\nb\nc\nd\nE\nf\n";
+        assert_eq!(
+            merge3(base, ours, theirs, LABELS),
+            ("a\nB\nc\nd\nE\nf\n".to_string(), 0)
+        );
+    }
+
+    #[test]
+    fn merge3_same_change_is_clean() {
+        let (text, conflicts) = merge3("a\nb\n", "a\nX\n", "a\nX\n", LABELS);
+        assert_eq!((text.as_str(), conflicts), ("a\nX\n", 0));
+    }
+
+    #[test]
+    fn merge3_conflict() {
+        let (text, conflicts) = merge3("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n", LABELS);
+        assert_eq!(conflicts, 1);
+        assert_eq!(
+            text,
+            "a\n<<<<<<< local\nours\n||||||| snapshot\nb\n=======\ntheirs\n>>>>>>> answer\nc\n"
+        );
+    }
+}
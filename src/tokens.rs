@@ -0,0 +1,78 @@
+//! Token estimates.
+//! * Deliberately crude (~4 bytes per token for source code); good enough for
+//!   budgeting without shipping tokenizer data.
+
+pub fn estimate(text: &str) -> usize {
+    estimate_bytes(text.len())
+}
+
+/// `estimate` for a text of `len` bytes.
+pub fn estimate_bytes(len: usize) -> usize {
+    len.div_ceil(4)
+}
+
+/// `1234` → `1.2k`, `56` → `56`.
+pub fn human(n: usize) -> String {
+    match n {
+        0..=999 => n.to_string(),
+        1_000..=999_999 => format!("{:.1}k", n as f64 / 1e3),
+        _ => format!("{:.1}M", n as f64 / 1e6),
+    }
+}
+
+/// `30000`, `30k` or `1.5M` → a token count; for `value_parser`.
+pub fn parse(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let (num, scale) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1e3),
+        Some((i, 'm' | 'M')) => (&s[..i], 1e6),
+        _ => (s, 1.0),
+    };
+    match num.parse::<f64>() {
+        Ok(n) if n >= 0.0 => Ok((n * scale).round() as usize),
+        _ => Err(format!(
+            "`{s}` is not a token count (e.g. 30000, 30k, 1.5M)"
+        )),
+    }
+}
+
+/// Context windows of popular models, used to size multi-part snapshots.
+pub const MODELS: &[(&str, usize)] = &[
+    ("gpt-4o", 128_000),
+    ("claude-sonnet", 200_000),
+    ("o3", 200_000),
+    ("gemini-pro", 1_000_000),
+];
+
+/// Context window of `model`: the longest `MODELS` name it starts with, so
+/// versioned and dated ids (`claude-sonnet-4-5-20250929`) count too.
+pub fn context_window(model: &str) -> Option<usize> {
+    MODELS
+        .iter()
+        .filter(|(name, _)| model.starts_with(name))
+        .max_by_key(|(name, _)| name.len())
+        .map(|&(_, window)| window)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_counts() {
+        assert_eq!(parse("30k"), Ok(30_000));
+        assert_eq!(parse("1.5M"), Ok(1_500_000));
+        assert_eq!(parse(" 1200 "), Ok(1200));
+        assert!(parse("30 kilotokens").is_err());
+        assert!(parse("-5").is_err());
+    }
+
+    #[test]
+    fn model_windows() {
+        assert_eq!(context_window("claude-sonnet-4-5-20250929"), Some(200_000));
+        assert_eq!(context_window("gpt-4o"), Some(128_000));
+        assert_eq!(context_window("o3-mini"), Some(200_000));
+        assert_eq!(context_window("claude"), None);
+        assert_eq!(context_window("gpt"), None);
+    }
+}
@@ -0,0 +1,366 @@
+//! Transform pipeline: rewrites applied to every file body after decoding,
+//! in order, recorded per file in the manifest.
+//! * Built in: `normalize-eol` (`--normalize-eol`), `truncate-fixture`
+//!   (`--fixtures truncate`), `minify-manifest` (`--minify-manifests`),
+//!   `scrub-pii` (`--scrub-pii`, see `pii`), `cfg-prune` (`--target`,
+//!   `--cfg-prune`, see `cfg`; also a filter).
+//! * External hooks from `[[transform]]` tables in `.cargo-qp.toml`: an
+//!   executable that reads `{"path", "body"}` JSON on stdin and writes
+//!   `{"body"}` JSON on stdout. A failing hook aborts the run rather than
+//!   letting an unscrubbed file through.
+//! * `[[plugin]]` WebAssembly modules (see `plugin`) can act as a `Filter`
+//!   on selected paths, a `Transform`, or both; they run after the hooks.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use globset::GlobSet;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cfg,
+    config::{self, Config},
+    groups::{self, Fixtures},
+    pii, Opts,
+};
+
+/// A file on its way into the snapshot.
+#[derive(Serialize)]
+pub struct FileEntry {
+    /// Relative to the snapshot root.
+    pub path: PathBuf,
+    pub body: String,
+}
+
+pub trait Transform: Send + Sync {
+    /// Name recorded in the manifest when the transform changed a file.
+    fn name(&self) -> &str;
+    fn apply(&self, file: &mut FileEntry) -> Result<()>;
+    /// Everything besides the body the output depends on, so cached bodies
+    /// are only reused under the same pipeline; the name by default.
+    fn identity(&self) -> String {
+        self.name().to_string()
+    }
+}
+
+/// Decides whether a selected path goes into the snapshot at all.
+pub trait Filter: Send + Sync {
+    fn name(&self) -> &str;
+    /// `rel` is relative to the snapshot root.
+    fn keep(&self, rel: &Path) -> Result<bool>;
+}
+
+/// Filters and transforms, each in the order they run.
+#[derive(Default)]
+pub struct Stages {
+    pub filters: Vec<Arc<dyn Filter>>,
+    pub transforms: Vec<Arc<dyn Transform>>,
+}
+
+impl Stages {
+    /// Hash of every transform's identity, in order: the bodies one pipeline
+    /// produces from the same bytes.
+    pub fn identity(&self) -> String {
+        let ids: Vec<String> = self.transforms.iter().map(|t| t.identity()).collect();
+        blake3::hash(ids.join("\n").as_bytes()).to_hex().to_string()
+    }
+}
+
+/// Built-in transforms selected by `opts`, then the config's hooks, then its
+/// plugins.
+pub fn pipeline(root: &Path, config: &Config, opts: &Opts) -> Result<Stages> {
+    let mut stages = Stages::default();
+    if opts.normalize_eol {
+        stages.transforms.push(Arc::new(NormalizeEol));
+    }
+    if opts.fixtures == Some(Fixtures::Truncate) {
+        stages.transforms.push(Arc::new(TruncateFixtures {
+            fixtures: groups::fixture_set()?,
+            lines: opts.fixture_lines,
+        }));
+    }
+    if opts.minify_manifests || opts.manifests_only {
+        stages.transforms.push(Arc::new(MinifyManifest));
+    }
+    if opts.scrub_pii {
+        stages.transforms.push(Arc::new(ScrubPii));
+    }
+    if opts.target.is_some() || opts.cfg_prune {
+        let target = cfg::Target::load(opts.target.as_deref())?;
+        let prune = Arc::new(cfg::CfgPrune::new(root, target));
+        stages.filters.push(prune.clone());
+        stages.transforms.push(prune);
+    }
+    for hook in &config.transform {
+        stages.transforms.push(Arc::new(Hook::new(root, hook)?));
+    }
+    for plugin in &config.plugin {
+        add_plugin(&mut stages, root, plugin)?;
+    }
+    Ok(stages)
+}
+
+#[cfg(feature = "wasm")]
+fn add_plugin(stages: &mut Stages, root: &Path, plugin: &config::PluginConfig) -> Result<()> {
+    let plugin = Arc::new(crate::plugin::WasmPlugin::load(root, plugin)?);
+    if plugin.has_filter() {
+        stages.filters.push(plugin.clone());
+    }
+    if plugin.has_transform() {
+        stages.transforms.push(plugin);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "wasm"))]
+fn add_plugin(_: &mut Stages, _: &Path, plugin: &config::PluginConfig) -> Result<()> {
+    anyhow::bail!(
+        "plugin {} needs cargo-qp built with the `wasm` feature",
+        plugin.path
+    )
+}
+
+/// Strips a leading BOM and turns CRLF into LF.
+pub struct NormalizeEol;
+
+impl Transform for NormalizeEol {
+    fn name(&self) -> &str {
+        "normalize-eol"
+    }
+
+    fn apply(&self, file: &mut FileEntry) -> Result<()> {
+        let text = &file.body;
+        let body = text.strip_prefix('\u{feff}').unwrap_or(text);
+        if body.len() != text.len() || body.contains("\r\n") {
+            file.body = body.replace("\r\n", "\n");
+        }
+        Ok(())
+    }
+}
+
+/// `--fixtures truncate`: keeps the first `lines` lines of each fixture.
+struct TruncateFixtures {
+    fixtures: GlobSet,
+    lines: usize,
+}
+
+impl Transform for TruncateFixtures {
+    fn name(&self) -> &str {
+        "truncate-fixture"
+    }
+
+    fn identity(&self) -> String {
+        format!("truncate-fixture {}", self.lines)
+    }
+
+    fn apply(&self, file: &mut FileEntry) -> Result<()> {
+        if !self.fixtures.is_match(&file.path) {
+            return Ok(());
+        }
+        let total = file.body.lines().count();
+        if total > self.lines {
+            let mut kept: String = file.body.split_inclusive('\n').take(self.lines).collect();
+            kept.push_str(&format!("… ({} more lines)\n", total - self.lines));
+            file.body = kept;
+        }
+        Ok(())
+    }
+}
+
+/// Tables `MinifyManifest` keeps; `workspace` keeps a virtual manifest's
+/// members and shared dependencies.
+const MANIFEST_TABLES: &[&str] = &[
+    "package",
+    "workspace",
+    "dependencies",
+    "features",
+    "lib",
+    "bin",
+];
+
+/// `Cargo.toml` reduced to what matters for reading the code: line-based, so
+/// the kept tables stay as written, minus comments and blank runs.
+struct MinifyManifest;
+
+impl Transform for MinifyManifest {
+    fn name(&self) -> &str {
+        "minify-manifest"
+    }
+
+    fn apply(&self, file: &mut FileEntry) -> Result<()> {
+        if file.path.file_name() != Some("Cargo.toml".as_ref()) {
+            return Ok(());
+        }
+        let mut out = String::new();
+        let mut keep = true;
+        let mut in_table = false;
+        for line in file.body.lines() {
+            let code = strip_comment(line).trim_end();
+            let trimmed = code.trim_start();
+            if trimmed.is_empty() && !line.trim().is_empty() {
+                continue; // a comment line
+            }
+            if let Some(header) = trimmed.strip_prefix('[') {
+                let name = header.trim_start_matches('[').trim_end_matches(']').trim();
+                keep = keep_table(name);
+                in_table = true;
+            } else if trimmed.is_empty() {
+                if keep && !out.is_empty() && !out.ends_with("\n\n") {
+                    out.push('\n');
+                }
+                continue;
+            } else if !in_table && !keep_table(trimmed) {
+                // dotted keys before the first header, e.g. `profile.dev.opt-level`
+                continue;
+            }
+            if keep {
+                out.push_str(code);
+                out.push('\n');
+            }
+        }
+        file.body = format!("{}\n", out.trim_end());
+        Ok(())
+    }
+}
+
+/// Whether the table (or dotted key) `name` survives minification.
+fn keep_table(name: &str) -> bool {
+    let mut parts = name.split('.').map(|p| p.trim().trim_matches('"'));
+    let top = parts.next().unwrap_or_default();
+    let second = parts.next().unwrap_or_default();
+    let second = second.split(['=', ' ']).next().unwrap_or_default();
+    MANIFEST_TABLES.contains(&top) && !matches!(second, "metadata" | "lints")
+}
+
+/// `line` without a trailing `# comment`; `#` inside strings is kept.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' => return &line[..i],
+            None => {}
+        }
+    }
+    line
+}
+
+/// `--scrub-pii`: masks emails, IP addresses and phone numbers.
+struct ScrubPii;
+
+impl Transform for ScrubPii {
+    fn name(&self) -> &str {
+        "scrub-pii"
+    }
+
+    fn apply(&self, file: &mut FileEntry) -> Result<()> {
+        if let Some(body) = pii::scrub(&file.path, &file.body) {
+            file.body = body;
+        }
+        Ok(())
+    }
+}
+
+/// An external `[[transform]]` command.
+struct Hook {
+    name: String,
+    /// Resolved against the root when it contains a path separator.
+    program: PathBuf,
+    args: Vec<String>,
+    root: PathBuf,
+    /// Only files matching these globs are passed through (all when empty).
+    include: Option<GlobSet>,
+    /// The `[[transform]]` table, for `identity`.
+    table: String,
+}
+
+#[derive(Deserialize)]
+struct HookOutput {
+    body: String,
+}
+
+impl Hook {
+    fn new(root: &Path, hook: &config::TransformHook) -> Result<Self> {
+        let program = if hook.command.contains(['/', '\\']) {
+            root.join(&hook.command)
+        } else {
+            PathBuf::from(&hook.command)
+        };
+        let include = if hook.include.is_empty() {
+            None
+        } else {
+            Some(config::glob_set(&hook.include, "transform include")?)
+        };
+        Ok(Hook {
+            name: hook.name.clone().unwrap_or_else(|| hook.command.clone()),
+            program,
+            args: hook.args.clone(),
+            root: root.to_path_buf(),
+            include,
+            table: format!("{hook:?}"),
+        })
+    }
+}
+
+impl Transform for Hook {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn identity(&self) -> String {
+        self.table.clone()
+    }
+
+    fn apply(&self, file: &mut FileEntry) -> Result<()> {
+        if self
+            .include
+            .as_ref()
+            .is_some_and(|g| !g.is_match(&file.path))
+        {
+            return Ok(());
+        }
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .current_dir(&self.root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to run transform `{}`", self.name))?;
+        let mut stdin = child.stdin.take().context("transform has no stdin")?;
+        let input = serde_json::to_vec(&*file)?;
+        // feed stdin from a thread so a full stdout pipe can't deadlock us
+        let feeder = std::thread::spawn(move || stdin.write_all(&input));
+        let output = child.wait_with_output()?;
+        // a hook may exit without reading all of its input
+        let _ = feeder.join();
+        if !output.status.success() {
+            anyhow::bail!(
+                "transform `{}` failed on {} (exit {:?}): {}",
+                self.name,
+                file.path.display(),
+                output.status.code(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let out: HookOutput = serde_json::from_slice(&output.stdout).with_context(|| {
+            format!(
+                "transform `{}` printed invalid JSON for {} (expected {{\"body\": \"…\"}})",
+                self.name,
+                file.path.display()
+            )
+        })?;
+        file.body = out.body;
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! `--users-of <PATH|SYMBOL>` — restrict the snapshot to what uses a file or
+//! an item, plus the target itself ("show me everything that calls this").
+//! * A PATH (an existing file, relative to the root) is a module: its users
+//!   are files of the same crate naming the module, and files of other
+//!   crates naming both the crate and the module. A crate root's users are
+//!   the files naming the crate.
+//! * A SYMBOL's users are the files where the identifier occurs, or with
+//!   `--resolver rust-analyzer` the files holding a reference to an item of
+//!   that name.
+//! * The result narrows the selection like `--against`; filters still apply.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+use crate::{analyzer::Analyzer, defs::Resolver, git, syntax, Ctx};
+
+pub fn select(ctx: &Ctx, target: &str, resolver: Resolver) -> Result<HashSet<PathBuf>> {
+    let path = ctx.root.join(target);
+    let users = if path.is_file() {
+        module_users(ctx, &path)?
+    } else {
+        match resolver {
+            Resolver::Syn => sources(ctx)?
+                .into_iter()
+                .filter(|p| ctx.read(p).is_ok_and(|src| syntax::mentions(&src, target)))
+                .collect(),
+            Resolver::RustAnalyzer => references(ctx, target)?,
+        }
+    };
+    if users.is_empty() {
+        eprintln!("warning: nothing found for --users-of {target}");
+    }
+    Ok(users)
+}
+
+/// Workspace `.rs` files not excluded by a glob.
+pub fn sources(ctx: &Ctx) -> Result<Vec<PathBuf>> {
+    let files = match &ctx.rev {
+        Some(rev) => git::ls_tree(&ctx.root, rev)?,
+        None => git::ls_files(&ctx.root)?,
+    };
+    Ok(files
+        .into_iter()
+        .filter(|rel| rel.ends_with(".rs") && !ctx.excludes.is_match(rel))
+        .map(|rel| ctx.root.join(rel))
+        .filter(|p| ctx.crate_dir(p).is_some())
+        .collect())
+}
+
+fn module_users(ctx: &Ctx, path: &Path) -> Result<HashSet<PathBuf>> {
+    let mut users = HashSet::from([path.to_path_buf()]);
+    let Some(dir) = ctx.crate_dir(path) else {
+        return Ok(users);
+    };
+    let krate = ctx.owner(path).0.replace('-', "_");
+    let module = module_name(dir, path);
+    for file in sources(ctx)? {
+        let Ok(src) = ctx.read(&file) else {
+            continue;
+        };
+        let same_crate = ctx.crate_dir(&file) == Some(dir);
+        let uses = match module {
+            Some(module) if same_crate => syntax::mentions(&src, module),
+            Some(module) => syntax::mentions(&src, &krate) && syntax::mentions(&src, module),
+            None => !same_crate && syntax::mentions(&src, &krate),
+        };
+        if uses {
+            users.insert(file);
+        }
+    }
+    Ok(users)
+}
+
+/// The name other files use for the module at `path` in the crate at
+/// `dir`; `None` for the crate root.
+pub fn module_name<'p>(dir: &Path, path: &'p Path) -> Option<&'p str> {
+    match path.file_stem().and_then(|s| s.to_str()) {
+        Some("lib" | "main") if path.parent() == Some(&dir.join("src")) => None,
+        Some("mod") => path.parent().and_then(|p| p.file_name()?.to_str()),
+        stem => stem,
+    }
+}
+
+fn references(ctx: &Ctx, symbol: &str) -> Result<HashSet<PathBuf>> {
+    if ctx.rev.is_some() {
+        anyhow::bail!("--resolver rust-analyzer works on the working tree; drop --rev");
+    }
+    let mut ra = Analyzer::start(&ctx.root)?;
+    let mut users = HashSet::new();
+    for item in ra.symbols(symbol)? {
+        for loc in ra.references(&item)? {
+            if loc.path.starts_with(&ctx.root) {
+                users.insert(loc.path);
+            }
+        }
+    }
+    Ok(users)
+}
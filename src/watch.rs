@@ -0,0 +1,89 @@
+//! `cargo qp watch` — regenerate the snapshot whenever a selected file changes.
+//! * Events are debounced: after the first one we wait for a quiet period.
+//! * Only changes to paths that are (or become) part of the selection count,
+//!   so builds writing to `target/` don't trigger a rerun.
+//! * `--notify` pops a desktop notification after every regeneration.
+
+use std::{
+    collections::BTreeSet,
+    path::PathBuf,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use notify::{RecursiveMode, Watcher};
+
+use crate::{default_mode, desktop, emit::Sink, tokens, write_intro, Ctx, Opts};
+
+pub fn run(ctx: &mut Ctx, opts: &Opts, debounce_ms: u64) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(ev) = res {
+            let _ = tx.send(ev.paths);
+        }
+    })
+    .context("failed to start file watcher")?;
+    watcher
+        .watch(&ctx.root, RecursiveMode::Recursive)
+        .context("failed to watch directory")?;
+
+    ctx.cache.get_mut().unwrap().keep_bodies();
+    let debounce = Duration::from_millis(debounce_ms);
+    let mut changed = BTreeSet::new();
+    loop {
+        let started = Instant::now();
+        ctx.emitted.clear();
+        let mut out = Sink::open(opts)?;
+        ctx.begin_output(&mut out)?;
+        write_intro(ctx, opts, &mut out)?;
+        default_mode(ctx, opts, &mut out)?;
+        ctx.finish_output(&mut out)?;
+        let copying = out.is_clipboard();
+        let clipboard_failed = out.finish()?;
+        ctx.report_read_errors();
+        ctx.report_unknown_crates()?;
+        ctx.cache.get_mut().unwrap().save()?;
+        let selected: BTreeSet<PathBuf> = ctx.selected()?.into_iter().collect();
+        let total = ctx.emitted.iter().map(|e| e.tokens).sum();
+        if opts.notify {
+            desktop::snapshot_ready(total, copying && !clipboard_failed);
+        }
+
+        let rels: Vec<String> = changed
+            .iter()
+            .map(|p: &PathBuf| ctx.rel(p).display().to_string())
+            .collect();
+        if rels.is_empty() {
+            eprint!("[qp] watching {} files", selected.len());
+        } else {
+            eprint!("[qp] {} changed: {}", rels.len(), rels.join(", "));
+        }
+        eprintln!(
+            " — snapshot ~{} tokens ({} ms)",
+            tokens::human(total),
+            started.elapsed().as_millis()
+        );
+
+        // block for the first relevant event, then drain until quiet
+        changed.clear();
+        while changed.is_empty() {
+            let paths = rx.recv().context("file watcher stopped")?;
+            changed.extend(paths.into_iter().filter(|p| relevant(ctx, &selected, p)));
+        }
+        while let Ok(paths) = rx.recv_timeout(debounce) {
+            changed.extend(paths.into_iter().filter(|p| relevant(ctx, &selected, p)));
+        }
+    }
+}
+
+/// Part of the current selection, or a new file the filters would pick up.
+fn relevant(ctx: &Ctx, selected: &BTreeSet<PathBuf>, p: &PathBuf) -> bool {
+    if selected.contains(p) {
+        return true;
+    }
+    let rel = ctx.rel(p);
+    !rel.components()
+        .any(|c| c.as_os_str() == ".git" || c.as_os_str() == "target")
+        && ctx.wants(p)
+}